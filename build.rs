@@ -0,0 +1,127 @@
+#[cfg(feature = "vendored")]
+extern crate cmake;
+extern crate pkg_config;
+
+use std::env;
+#[cfg(not(feature = "vendored"))]
+use std::fs;
+use std::path::Path;
+
+// the libgroove versions the FFI declarations in src/c_api were written
+// for, matching what Groove::new checks at run time
+#[cfg(not(feature = "libgroove5"))]
+const VERSION_MAJOR: i32 = 4;
+#[cfg(not(feature = "libgroove5"))]
+const MIN_VERSION_MINOR: i32 = 3;
+#[cfg(feature = "libgroove5")]
+const VERSION_MAJOR: i32 = 5;
+#[cfg(feature = "libgroove5")]
+const MIN_VERSION_MINOR: i32 = 0;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=GROOVE_LIB_DIR");
+
+    // the mock feature needs nothing linked
+    if env::var_os("CARGO_FEATURE_MOCK").is_some() {
+        return;
+    }
+
+    #[cfg(feature = "vendored")]
+    build_vendored();
+    #[cfg(not(feature = "vendored"))]
+    find_system();
+}
+
+// link the system libgroove, found through pkg-config if it has a .pc
+// file, which not every package of libgroove 4 installs
+#[cfg(not(feature = "vendored"))]
+fn find_system() {
+    if let Ok(dir) = env::var("GROOVE_LIB_DIR") {
+        match version_in_dir(Path::new(&dir)) {
+            Some(version) => check_version(&version, &format!("GROOVE_LIB_DIR holds libgroove {}", version)),
+            None => println!("cargo:warning=the libgroove in GROOVE_LIB_DIR has no version in its file name; \
+                              Groove::new will check it is {}.{} or a later {}.x",
+                             VERSION_MAJOR, MIN_VERSION_MINOR, VERSION_MAJOR),
+        }
+        println!("cargo:rustc-link-search=native={}", dir);
+        println!("cargo:rustc-link-lib=groove");
+        return;
+    }
+
+    for name in &["groove", "libgroove"] {
+        if let Ok(library) = pkg_config::probe_library(name) {
+            check_version(&library.version, &format!("pkg-config found libgroove {}", library.version));
+            return;
+        }
+    }
+
+    println!("cargo:warning=libgroove was not found by pkg-config; linking with -lgroove. \
+              if that fails, install libgroove {}.{} or later, set GROOVE_LIB_DIR, or enable the `vendored` feature",
+             VERSION_MAJOR, MIN_VERSION_MINOR);
+    println!("cargo:rustc-link-lib=groove");
+}
+
+// `found` says where `version` came from, for the message
+#[cfg(not(feature = "vendored"))]
+fn check_version(version: &str, found: &str) {
+    let mut parts = version.split('.').map(|part| part.parse::<i32>().ok());
+    let major = parts.next().and_then(|part| part);
+    let minor = parts.next().and_then(|part| part).unwrap_or(0);
+    if major != Some(VERSION_MAJOR) || minor < MIN_VERSION_MINOR {
+        panic!("\n\n{}, but this crate needs libgroove {}.{} or a later {}.x.\n\
+                set GROOVE_LIB_DIR to the directory of another build, or enable the `vendored` feature.\n\n",
+               found, VERSION_MAJOR, MIN_VERSION_MINOR, VERSION_MAJOR);
+    }
+}
+
+// the version libgroove's cmake build puts in the names of its shared
+// libraries, libgroove.so.4.3.0 or libgroove.4.3.0.dylib, taking the
+// longest when there are symlinks such as libgroove.so.4 too
+#[cfg(not(feature = "vendored"))]
+fn version_in_dir(dir: &Path) -> Option<String> {
+    let entries = fs::read_dir(dir).ok()?;
+    entries.filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| {
+            let rest = name.strip_prefix("libgroove.")?;
+            let version = rest.strip_prefix("so.").or_else(|| rest.strip_suffix(".dylib"))?;
+            let numeric = version.split('.').all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+            if numeric { Some(version.to_string()) } else { None }
+        })
+        .max_by_key(|version| version.len())
+}
+
+// build the copy of libgroove in vendor/libgroove and link it statically.
+// libav itself is still linked from the system.
+#[cfg(feature = "vendored")]
+fn build_vendored() {
+    let source = Path::new("vendor/libgroove");
+    if !source.join("CMakeLists.txt").exists() {
+        panic!("\n\nthe `vendored` feature builds libgroove from vendor/libgroove, which has no CMakeLists.txt.\n\
+                put the libgroove {}.{} sources there before building or packaging the crate, for example with\n\
+                \x20   git clone --branch {}.{}.0 https://github.com/andrewrk/libgroove vendor/libgroove\n\
+                libav is not vendored: its development packages must be installed either way.\n\n",
+               VERSION_MAJOR, MIN_VERSION_MINOR, VERSION_MAJOR, MIN_VERSION_MINOR);
+    }
+    println!("cargo:rerun-if-changed=vendor/libgroove");
+
+    let dst = cmake::Config::new(source)
+        .define("BUILD_STATIC_LIBS", "ON")
+        .define("BUILD_EXAMPLE_PROGRAMS", "OFF")
+        .define("BUILD_TESTS", "OFF")
+        .build();
+    println!("cargo:rustc-link-search=native={}", dst.join("lib").display());
+    println!("cargo:rustc-link-lib=static=groove");
+
+    let mut libraries = vec!["libavformat", "libavcodec", "libavfilter", "libavutil", "libswresample"];
+    // libgroove 5 describes audio formats with libsoundio's types
+    if cfg!(feature = "libgroove5") {
+        libraries.push("libsoundio");
+    }
+    for library in libraries {
+        if let Err(err) = pkg_config::probe_library(library) {
+            panic!("\n\nthe vendored libgroove needs {} from the system: {}\n\n", library, err);
+        }
+    }
+    println!("cargo:rustc-link-lib=pthread");
+}
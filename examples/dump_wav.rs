@@ -0,0 +1,43 @@
+extern crate groove;
+
+use std::env;
+use std::fs;
+use std::io::BufWriter;
+use std::path::Path;
+
+// decode a file and write it out as 16 bit stereo WAV
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <input> <output.wav>", args[0]);
+        std::process::exit(1);
+    }
+
+    let groove = groove::Groove::new().expect("error initializing libgroove");
+    groove.set_logging(groove::Log::Info);
+
+    let format = groove::AudioFormat {
+        sample_rate: 44100,
+        channel_layout: groove::ChannelLayout::LayoutStereo,
+        sample_fmt: groove::SampleFormat {
+            sample_type: groove::SampleType::S16,
+            planar: false,
+        },
+    };
+
+    let mut playlist = groove::Playlist::new(&groove);
+    let sink = groove::Sink::new(&groove);
+    sink.set_audio_format(format);
+    sink.attach(&playlist).expect("error attaching sink");
+
+    let file = groove::File::open(&groove, Path::new(&args[1])).expect("could not open file");
+    playlist.append(file, 1.0, 1.0);
+
+    let output = fs::File::create(&args[2]).expect("could not create output file");
+    let mut wav = groove::WavWriter::new(BufWriter::new(output), format).expect("write error");
+    while let Some(decoded_buffer) = sink.buffer_get_blocking() {
+        wav.write_decoded(&decoded_buffer).expect("write error");
+    }
+    wav.finish().expect("write error");
+}
@@ -1,6 +1,6 @@
 extern crate libc;
 
-use libc::{uint64_t, c_int};
+use libc::c_int;
 
 use c_api::{
     GrooveAudioFormat,
@@ -20,9 +20,9 @@ use c_api::{
     CH_FRONT_RIGHT,
     CH_LAYOUT_MONO,
     CH_LAYOUT_STEREO,
-    groove_channel_layout_default,
-    groove_channel_layout_count,
-    groove_sample_format_bytes_per_sample,
+    channel_layout_default,
+    channel_layout_count,
+    sample_format_bytes_per_sample,
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -37,17 +37,23 @@ pub enum ChannelLayout {
 impl ChannelLayout {
     /// get the default channel layout based on the channel count
     pub fn default(count: i32) -> Self {
-        let x = unsafe { groove_channel_layout_default(count) };
+        let x = unsafe { channel_layout_default(count) };
         ChannelLayout::from_groove(x)
     }
 
     /// Get the channel count for the channel layout
     pub fn count(&self) -> i32 {
-        unsafe { groove_channel_layout_count(self.to_groove()) as i32 }
+        unsafe { channel_layout_count(self.to_groove()) }
     }
 
-    fn to_groove(&self) -> uint64_t {
-        match *self {
+    /// the speaker positions in this layout, as a bit mask. the bits match
+    /// those of the WAVE_FORMAT_EXTENSIBLE channel mask.
+    pub fn mask(&self) -> u64 {
+        self.to_groove()
+    }
+
+    fn to_groove(self) -> u64 {
+        match self {
             ChannelLayout::FrontLeft    => CH_FRONT_LEFT,
             ChannelLayout::FrontRight   => CH_FRONT_RIGHT,
             ChannelLayout::FrontCenter  => CH_FRONT_CENTER,
@@ -56,7 +62,7 @@ impl ChannelLayout {
         }
     }
 
-    fn from_groove(x: uint64_t) -> Self {
+    fn from_groove(x: u64) -> Self {
         match x {
             CH_FRONT_LEFT     => ChannelLayout::FrontLeft,
             CH_FRONT_RIGHT    => ChannelLayout::FrontRight,
@@ -68,14 +74,14 @@ impl ChannelLayout {
 }
 
 /// how to organize bits which represent audio samples
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct SampleFormat {
     pub sample_type: SampleType,
     /// planar means non-interleaved
     pub planar: bool,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SampleType {
     NoType,
     /// unsigned 8 bits
@@ -91,7 +97,7 @@ pub enum SampleType {
 }
 
 impl SampleFormat {
-    fn to_groove(&self) -> i32 {
+    fn to_groove(self) -> i32 {
         match (self.sample_type, self.planar) {
             (SampleType::NoType, false) => SAMPLE_FMT_NONE,
             (SampleType::U8,     false) => SAMPLE_FMT_U8,
@@ -129,11 +135,36 @@ impl SampleFormat {
     }
 
     pub fn bytes_per_sample(&self) -> u32 {
-        unsafe { groove_sample_format_bytes_per_sample(self.to_groove()) as u32 }
+        unsafe { sample_format_bytes_per_sample(self.to_groove()) as u32 }
     }
 }
 
-#[derive(Clone, Copy)]
+/// a Rust type which holds one audio sample
+pub trait Sample: Copy {
+    fn sample_type() -> SampleType;
+}
+
+impl Sample for u8 {
+    fn sample_type() -> SampleType { SampleType::U8 }
+}
+
+impl Sample for i16 {
+    fn sample_type() -> SampleType { SampleType::S16 }
+}
+
+impl Sample for i32 {
+    fn sample_type() -> SampleType { SampleType::S32 }
+}
+
+impl Sample for f32 {
+    fn sample_type() -> SampleType { SampleType::Flt }
+}
+
+impl Sample for f64 {
+    fn sample_type() -> SampleType { SampleType::Dbl }
+}
+
+#[derive(Clone, Copy, Debug)]
 pub struct AudioFormat {
     pub sample_rate: i32,
     pub channel_layout: ChannelLayout,
@@ -141,14 +172,37 @@ pub struct AudioFormat {
 }
 
 impl AudioFormat {
+    /// number of separate data planes: one per channel for planar formats,
+    /// otherwise one
+    pub fn plane_count(&self) -> usize {
+        if self.sample_fmt.planar {
+            self.channel_layout.count() as usize
+        } else {
+            1
+        }
+    }
+
+    /// bytes taken up by one frame within one plane
+    pub(crate) fn bytes_per_frame_per_plane(&self) -> usize {
+        let samples = if self.sample_fmt.planar { 1 } else { self.channel_layout.count() as usize };
+        samples * self.sample_fmt.bytes_per_sample() as usize
+    }
+
+    /// whether the two formats have identically laid out sample data
+    pub(crate) fn same_layout(&self, other: &AudioFormat) -> bool {
+        self.sample_rate == other.sample_rate &&
+            self.channel_layout.to_groove() == other.channel_layout.to_groove() &&
+            self.sample_fmt.to_groove() == other.sample_fmt.to_groove()
+    }
+
     pub(crate) fn from_groove(groove_audio_format: &GrooveAudioFormat) -> Self {
         AudioFormat {
-            sample_rate: groove_audio_format.sample_rate as i32,
+            sample_rate: groove_audio_format.sample_rate,
             channel_layout: ChannelLayout::from_groove(groove_audio_format.channel_layout),
             sample_fmt: SampleFormat::from_groove(groove_audio_format.sample_fmt),
         }
     }
-    pub(crate) fn to_groove(&self) -> GrooveAudioFormat {
+    pub(crate) fn to_groove(self) -> GrooveAudioFormat {
         GrooveAudioFormat {
             sample_rate: self.sample_rate as c_int,
             channel_layout: self.channel_layout.to_groove(),
@@ -1,21 +1,162 @@
-use std::mem::transmute;
+use std::mem;
 use std::slice;
 
 use c_api::{
     GrooveBuffer,
+    GroovePlaylistItem,
     BUFFER_NO,
     BUFFER_YES,
     BUFFER_END,
+    buffer_audio_format,
+    encoder_buffer_get,
     groove_buffer_unref,
-    groove_channel_layout_count,
-    groove_encoder_buffer_get,
-    groove_sink_buffer_get
+    sink_buffer_get
 };
 
-use audio_format::{SampleFormat, SampleType};
+use audio_format::{AudioFormat, SampleFormat, SampleType};
 use encoder::Encoder;
 use sink::Sink;
 
+// the public sample accessors of DecodedBuffer and OwnedAudioBuffer,
+// which both keep their samples in `Planes`
+macro_rules! sample_accessors {
+    () => {
+        /// returns a vector of f64
+        /// panics if the buffer is not planar
+        /// panics if the buffer is not SampleType::Dbl
+        pub fn channel_as_slice_f64(&self, channel_index: u32) -> &[f64] {
+            self.channel_of(channel_index, Some(SampleType::Dbl))
+        }
+
+        /// returns a vector of f32
+        /// panics if the buffer is not planar
+        /// panics if the buffer is not SampleType::Flt
+        pub fn channel_as_slice_f32(&self, channel_index: u32) -> &[f32] {
+            self.channel_of(channel_index, Some(SampleType::Flt))
+        }
+
+        /// returns a vector of i32
+        /// panics if the buffer is not planar
+        /// panics if the buffer is not SampleType::S32
+        pub fn channel_as_slice_i32(&self, channel_index: u32) -> &[i32] {
+            self.channel_of(channel_index, Some(SampleType::S32))
+        }
+
+        /// returns a vector of i16
+        /// panics if the buffer is not planar
+        /// panics if the buffer is not SampleType::S16
+        pub fn channel_as_slice_i16(&self, channel_index: u32) -> &[i16] {
+            self.channel_of(channel_index, Some(SampleType::S16))
+        }
+
+        /// returns a vector of u8
+        /// panics if the buffer is not planar
+        /// panics if the buffer is not SampleType::U8
+        pub fn channel_as_slice_u8(&self, channel_index: u32) -> &[u8] {
+            self.channel_of(channel_index, Some(SampleType::U8))
+        }
+
+        /// returns all the bytes of a single channel
+        /// panics if the buffer is not planar
+        pub fn channel_as_slice_raw(&self, channel_index: u32) -> &[u8] {
+            self.channel_of(channel_index, None)
+        }
+
+        /// returns a vector of f64
+        /// panics if the buffer is planar
+        /// panics if the buffer is not SampleType::Dbl
+        pub fn as_slice_f64(&self) -> &[f64] {
+            self.interleaved(Some(SampleType::Dbl))
+        }
+
+        /// returns a vector of f32
+        /// panics if the buffer is planar
+        /// panics if the buffer is not SampleType::Flt
+        pub fn as_slice_f32(&self) -> &[f32] {
+            self.interleaved(Some(SampleType::Flt))
+        }
+
+        /// returns a vector of i32
+        /// panics if the buffer is planar
+        /// panics if the buffer is not SampleType::S32
+        pub fn as_slice_i32(&self) -> &[i32] {
+            self.interleaved(Some(SampleType::S32))
+        }
+
+        /// returns a vector of i16
+        /// panics if the buffer is planar
+        /// panics if the buffer is not SampleType::S16
+        pub fn as_slice_i16(&self) -> &[i16] {
+            self.interleaved(Some(SampleType::S16))
+        }
+
+        /// returns a vector of u8
+        /// panics if the buffer is planar
+        /// panics if the buffer is not SampleType::U8
+        pub fn as_slice_u8(&self) -> &[u8] {
+            self.interleaved(Some(SampleType::U8))
+        }
+
+        /// returns all the buffer data as [u8]
+        /// panics if the buffer is planar
+        pub fn as_slice_raw(&self) -> &[u8] {
+            self.interleaved(None)
+        }
+    };
+}
+
+// samples kept as one plane of bytes per channel, or a single plane of
+// interleaved frames
+trait Planes {
+    fn planes_format(&self) -> AudioFormat;
+
+    fn plane(&self, index: usize) -> &[u8];
+
+    // one channel of a planar buffer, as samples of `sample_type`, or as
+    // bytes if that is None
+    fn channel_of<T>(&self, channel_index: u32, sample_type: Option<SampleType>) -> &[T] {
+        let format = self.planes_format();
+        check_sample_type(format.sample_fmt.sample_type, sample_type);
+        if !format.sample_fmt.planar {
+            panic!("expected planar buffer");
+        }
+        if channel_index >= format.channel_layout.count() as u32 {
+            panic!("invalid channel index");
+        }
+        cast(self.plane(channel_index as usize))
+    }
+
+    // all the samples of an interleaved buffer, likewise
+    fn interleaved<T>(&self, sample_type: Option<SampleType>) -> &[T] {
+        let format = self.planes_format();
+        check_sample_type(format.sample_fmt.sample_type, sample_type);
+        if format.sample_fmt.planar {
+            panic!("as_slice works for interleaved buffers only");
+        }
+        cast(self.plane(0))
+    }
+}
+
+fn check_sample_type(actual: SampleType, expected: Option<SampleType>) {
+    let name = match expected {
+        Some(expected) if expected != actual => match expected {
+            SampleType::U8 => "u8",
+            SampleType::S16 => "i16",
+            SampleType::S32 => "i32",
+            SampleType::Flt => "f32",
+            _ => "f64",
+        },
+        _ => return,
+    };
+    panic!("buffer not in {} format", name);
+}
+
+// planes are aligned for any sample type: libav's are, and so are the u64
+// words of OwnedAudioBuffer's
+fn cast<T>(bytes: &[u8]) -> &[T] {
+    unsafe { slice::from_raw_parts(bytes.as_ptr() as *const T, bytes.len() / mem::size_of::<T>()) }
+}
+
 /// A buffer which contains encoded audio data
 pub struct EncodedBuffer {
     pub(crate) groove_buffer: *mut GrooveBuffer,
@@ -32,10 +173,12 @@ impl Drop for EncodedBuffer {
 }
 
 impl EncodedBuffer {
-    pub(crate) fn from_encoder(encoder: &Encoder) -> Result<Option<EncodedBuffer>, ()> {
+    /// `Err` if there is no buffer yet and `block` is false, or if the
+    /// wait was aborted
+    pub(crate) fn from_encoder(encoder: &Encoder, block: bool) -> Result<Option<EncodedBuffer>, ()> {
         let mut groove_buffer: *mut GrooveBuffer = ::std::ptr::null_mut();
         let return_code = unsafe {
-            groove_encoder_buffer_get(encoder.groove_encoder, &mut groove_buffer, 1)
+            encoder_buffer_get(encoder.groove_encoder, &mut groove_buffer, block)
         };
 
         match return_code {
@@ -46,6 +189,22 @@ impl EncodedBuffer {
         }
     }
 
+    /// position in seconds, within the playlist item, of the audio this
+    /// buffer was encoded from. 0 for format headers and trailers.
+    pub fn pos(&self) -> f64 {
+        unsafe {
+            (*self.groove_buffer).pos
+        }
+    }
+
+    /// the playlist item this audio was encoded from, or null for a format
+    /// header or trailer
+    pub(crate) fn groove_playlist_item(&self) -> *mut GroovePlaylistItem {
+        unsafe {
+            (*self.groove_buffer).item
+        }
+    }
+
     pub fn as_vec(&self) -> &[u8] {
         unsafe {
             let data = *(*self.groove_buffer).data;
@@ -57,15 +216,22 @@ impl EncodedBuffer {
 
 /// A buffer which contains raw samples
 pub struct DecodedBuffer {
-    pub(crate) groove_buffer: *mut GrooveBuffer,
+    audio: Decoded,
 }
 unsafe impl Sync for DecodedBuffer {}
 unsafe impl Send for DecodedBuffer {}
 
+enum Decoded {
+    Groove(*mut GrooveBuffer),
+    /// what a sink's filters made of libgroove's audio. the item and
+    /// position are those of the audio which went in.
+    Filtered { buffer: OwnedAudioBuffer, item: *mut GroovePlaylistItem, pos: f64 },
+}
+
 impl Drop for DecodedBuffer {
     fn drop(&mut self) {
-        unsafe {
-            groove_buffer_unref(self.groove_buffer);
+        if let Decoded::Groove(groove_buffer) = self.audio {
+            unsafe { groove_buffer_unref(groove_buffer) }
         }
     }
 }
@@ -74,166 +240,292 @@ impl DecodedBuffer {
     pub(crate) fn from_sink(sink: &Sink) -> Result<Option<DecodedBuffer>, ()> {
         let mut groove_buffer: *mut GrooveBuffer = ::std::ptr::null_mut();
         let return_code = unsafe {
-            groove_sink_buffer_get(sink.groove_sink, &mut groove_buffer, 1)
+            sink_buffer_get(sink.groove_sink, &mut groove_buffer, true)
         };
 
         match return_code {
             c if c == BUFFER_NO => Err(()),
-            c if c == BUFFER_YES => Ok(Some(DecodedBuffer { groove_buffer })),
+            c if c == BUFFER_YES => Ok(Some(DecodedBuffer { audio: Decoded::Groove(groove_buffer) })),
             c if c == BUFFER_END => Ok(None),
             _ => panic!("unexpected buffer result"),
         }
     }
 
-    /// returns a vector of f64
-    /// panics if the buffer is not planar
-    /// panics if the buffer is not SampleType::Dbl
-    pub fn channel_as_slice_f64(&self, channel_index: u32) -> &[f64] {
-        match self.sample_format().sample_type {
-            SampleType::Dbl => self.channel_as_slice_generic(channel_index),
-            _ => panic!("buffer not in f64 format"),
+    pub(crate) fn filtered(buffer: OwnedAudioBuffer, item: *mut GroovePlaylistItem, pos: f64) -> DecodedBuffer {
+        DecodedBuffer { audio: Decoded::Filtered { buffer, item, pos } }
+    }
+
+    sample_accessors!();
+
+    pub fn sample_format(&self) -> SampleFormat {
+        match self.audio {
+            Decoded::Groove(groove_buffer) => unsafe {
+                SampleFormat::from_groove(buffer_audio_format(groove_buffer).sample_fmt)
+            },
+            Decoded::Filtered { ref buffer, .. } => buffer.sample_format(),
         }
     }
 
-    /// returns a vector of f32
-    /// panics if the buffer is not planar
-    /// panics if the buffer is not SampleType::Flt
-    pub fn channel_as_slice_f32(&self, channel_index: u32) -> &[f32] {
-        match self.sample_format().sample_type {
-            SampleType::Flt => self.channel_as_slice_generic(channel_index),
-            _ => panic!("buffer not in f32 format"),
+    pub fn audio_format(&self) -> AudioFormat {
+        match self.audio {
+            Decoded::Groove(groove_buffer) => unsafe {
+                AudioFormat::from_groove(&buffer_audio_format(groove_buffer))
+            },
+            Decoded::Filtered { ref buffer, .. } => buffer.audio_format(),
         }
     }
 
-    /// returns a vector of i32
-    /// panics if the buffer is not planar
-    /// panics if the buffer is not SampleType::S32
-    pub fn channel_as_slice_i32(&self, channel_index: u32) -> &[i32] {
-        match self.sample_format().sample_type {
-            SampleType::S32 => self.channel_as_slice_generic(channel_index),
-            _ => panic!("buffer not in i32 format"),
+    /// number of audio frames described by this buffer
+    pub fn frame_count(&self) -> usize {
+        match self.audio {
+            Decoded::Groove(groove_buffer) => unsafe { (*groove_buffer).frame_count as usize },
+            Decoded::Filtered { ref buffer, .. } => buffer.frame_count(),
         }
     }
 
-    /// returns a vector of i16
-    /// panics if the buffer is not planar
-    /// panics if the buffer is not SampleType::S16
-    pub fn channel_as_slice_i16(&self, channel_index: u32) -> &[i16] {
-        match self.sample_format().sample_type {
-            SampleType::S16 => self.channel_as_slice_generic(channel_index),
-            _ => panic!("buffer not in i16 format"),
+    /// position in seconds, within its playlist item, of the first frame
+    /// of this buffer. for filtered audio, this is where the audio which
+    /// went into the filters started.
+    pub fn pos(&self) -> f64 {
+        match self.audio {
+            Decoded::Groove(groove_buffer) => unsafe { (*groove_buffer).pos },
+            Decoded::Filtered { pos, .. } => pos,
         }
     }
 
-    /// returns a vector of u8
-    /// panics if the buffer is not planar
-    /// panics if the buffer is not SampleType::U8
-    pub fn channel_as_slice_u8(&self, channel_index: u32) -> &[u8] {
-        match self.sample_format().sample_type {
-            SampleType::U8 => self.channel_as_slice_generic(channel_index),
-            _ => panic!("buffer not in u8 format"),
+    /// the playlist item this audio was decoded from
+    pub(crate) fn groove_playlist_item(&self) -> *mut GroovePlaylistItem {
+        match self.audio {
+            Decoded::Groove(groove_buffer) => unsafe { (*groove_buffer).item },
+            Decoded::Filtered { item, .. } => item,
         }
     }
 
-    pub fn sample_format(&self) -> SampleFormat {
+    /// all the bytes of one plane. interleaved buffers have a single plane.
+    pub(crate) fn plane_as_slice_raw(&self, plane_index: usize) -> &[u8] {
+        let groove_buffer = match self.audio {
+            Decoded::Groove(groove_buffer) => groove_buffer,
+            Decoded::Filtered { ref buffer, .. } => return buffer.plane_as_slice_raw(plane_index),
+        };
+        let format = self.audio_format();
+        let len = self.frame_count() * format.bytes_per_frame_per_plane();
         unsafe {
-            SampleFormat::from_groove((*self.groove_buffer).format.sample_fmt)
+            let data = *((*groove_buffer).data.add(plane_index));
+            slice::from_raw_parts(data, len)
         }
     }
 
-    fn channel_as_slice_generic<T>(&self, channel_index: u32) -> &[T] {
+    /// the samples as interleaved floats, nominally in -1.0 to 1.0
+    pub(crate) fn to_f64(&self) -> Vec<f64> {
+        let format = self.audio_format();
+        let planes: Vec<&[u8]> = (0..format.plane_count()).map(|index| self.plane_as_slice_raw(index)).collect();
+        samples_to_f64(format, &planes, self.frame_count())
+    }
+}
+
+impl Planes for DecodedBuffer {
+    fn planes_format(&self) -> AudioFormat {
+        self.audio_format()
+    }
+
+    fn plane(&self, index: usize) -> &[u8] {
+        self.plane_as_slice_raw(index)
+    }
+}
+
+/// A buffer of raw samples which is owned by Rust rather than libgroove.
+/// Interleaved audio is kept in a single plane; planar audio has one plane
+/// per channel.
+pub struct OwnedAudioBuffer {
+    format: AudioFormat,
+    frame_count: usize,
+    planes: Vec<Plane>,
+}
+
+// u64 backing storage keeps every plane aligned for any sample type
+struct Plane {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Plane {
+    fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.words.as_ptr() as *const u8, self.len) }
+    }
+
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        let new_len = self.len + bytes.len();
+        self.words.resize(new_len.div_ceil(8), 0);
         unsafe {
-            let sample_fmt = self.sample_format();
-            if !sample_fmt.planar {
-                panic!("expected planar buffer");
-            }
-            let channel_count = groove_channel_layout_count(
-                (*self.groove_buffer).format.channel_layout) as u32;
-            if channel_index >= channel_count {
-                panic!("invalid channel index");
-            }
-            let data = *((*self.groove_buffer).data.offset(channel_index as isize));
-            let frame_count = (*self.groove_buffer).frame_count as usize;
-            let raw_slice = slice::from_raw_parts(data, frame_count);
-            transmute(raw_slice)
+            let dest = (self.words.as_mut_ptr() as *mut u8).add(self.len);
+            ::std::ptr::copy_nonoverlapping(bytes.as_ptr(), dest, bytes.len());
         }
+        self.len = new_len;
     }
 
-    /// returns a single channel and always returns [u8]
-    /// panics if the buffer is not planar
-    pub fn channel_as_slice_raw(&self, channel_index: u32) -> &[u8] {
-        self.channel_as_slice_generic(channel_index)
+    fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.len = len;
+            self.words.truncate(len.div_ceil(8));
+        }
     }
+}
 
-    /// returns a vector of f64
-    /// panics if the buffer is planar
-    /// panics if the buffer is not SampleType::Dbl
-    pub fn as_slice_f64(&self) -> &[f64] {
-        match self.sample_format().sample_type {
-            SampleType::Dbl => self.as_slice_generic(),
-            _ => panic!("buffer not in f64 format"),
+impl OwnedAudioBuffer {
+    /// an empty buffer in the given format
+    pub fn new(format: AudioFormat) -> Self {
+        let plane_count = format.plane_count();
+        OwnedAudioBuffer {
+            format,
+            frame_count: 0,
+            planes: (0..plane_count).map(|_| Plane { words: Vec::new(), len: 0 }).collect(),
         }
     }
 
-    /// returns a vector of f32
-    /// panics if the buffer is planar
-    /// panics if the buffer is not SampleType::Flt
-    pub fn as_slice_f32(&self) -> &[f32] {
-        match self.sample_format().sample_type {
-            SampleType::Flt => self.as_slice_generic(),
-            _ => panic!("buffer not in f32 format"),
+    pub fn audio_format(&self) -> AudioFormat {
+        self.format
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.format.sample_fmt
+    }
+
+    /// number of audio frames held by this buffer
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// length of the audio in seconds
+    pub fn duration(&self) -> f64 {
+        self.frame_count as f64 / self.format.sample_rate as f64
+    }
+
+    /// append frames `start..end` of `buffer`.
+    /// panics if `buffer` is not in this buffer's audio format.
+    pub fn extend_from_decoded(&mut self, buffer: &DecodedBuffer, start: usize, end: usize) {
+        if !buffer.audio_format().same_layout(&self.format) {
+            panic!("buffer audio format does not match");
         }
+        let end = end.min(buffer.frame_count());
+        if start >= end {
+            return;
+        }
+        let frame_size = self.format.bytes_per_frame_per_plane();
+        for (index, plane) in self.planes.iter_mut().enumerate() {
+            let bytes = buffer.plane_as_slice_raw(index);
+            plane.extend_from_slice(&bytes[start * frame_size..end * frame_size]);
+        }
+        self.frame_count += end - start;
     }
 
-    /// returns a vector of i32
-    /// panics if the buffer is planar
-    /// panics if the buffer is not SampleType::S32
-    pub fn as_slice_i32(&self) -> &[i32] {
-        match self.sample_format().sample_type {
-            SampleType::S32 => self.as_slice_generic(),
-            _ => panic!("buffer not in i32 format"),
+    /// append `frame_count` frames of silence
+    pub fn extend_silence(&mut self, frame_count: usize) {
+        // unsigned 8 bit audio is centred on 128 rather than 0
+        let byte = match self.sample_format().sample_type {
+            SampleType::U8 => 0x80,
+            _ => 0,
+        };
+        let silence = vec![byte; frame_count * self.format.bytes_per_frame_per_plane()];
+        for plane in &mut self.planes {
+            plane.extend_from_slice(&silence);
         }
+        self.frame_count += frame_count;
     }
 
-    /// returns a vector of i16
-    /// panics if the buffer is planar
-    /// panics if the buffer is not SampleType::S16
-    pub fn as_slice_i16(&self) -> &[i16] {
-        match self.sample_format().sample_type {
-            SampleType::S16 => self.as_slice_generic(),
-            _ => panic!("buffer not in i16 format"),
+    /// append interleaved float samples, nominally in -1.0 to 1.0,
+    /// converting them to the buffer's sample format
+    pub(crate) fn extend_from_f64(&mut self, samples: &[f64]) {
+        let channel_count = self.format.channel_layout.count() as usize;
+        let sample_fmt = self.format.sample_fmt;
+        if sample_fmt.planar {
+            for (channel, plane) in self.planes.iter_mut().enumerate() {
+                let mut bytes = Vec::with_capacity(samples.len() / channel_count * 8);
+                for value in samples.iter().skip(channel).step_by(channel_count) {
+                    push_sample(&mut bytes, sample_fmt.sample_type, *value);
+                }
+                plane.extend_from_slice(&bytes);
+            }
+        } else {
+            let mut bytes = Vec::with_capacity(samples.len() * 8);
+            for value in samples {
+                push_sample(&mut bytes, sample_fmt.sample_type, *value);
+            }
+            self.planes[0].extend_from_slice(&bytes);
         }
+        self.frame_count += samples.len() / channel_count;
     }
 
-    /// returns a vector of u8
-    /// panics if the buffer is planar
-    /// panics if the buffer is not SampleType::U8
-    pub fn as_slice_u8(&self) -> &[u8] {
-        match self.sample_format().sample_type {
-            SampleType::U8 => self.as_slice_generic(),
-            _ => panic!("buffer not in u8 format"),
+    /// shorten the buffer to `frame_count` frames
+    pub fn truncate(&mut self, frame_count: usize) {
+        if frame_count < self.frame_count {
+            let frame_size = self.format.bytes_per_frame_per_plane();
+            for plane in &mut self.planes {
+                plane.truncate(frame_count * frame_size);
+            }
+            self.frame_count = frame_count;
         }
     }
 
-    /// returns all the buffer data as [u8]
-    /// panics if the buffer is planar
-    pub fn as_slice_raw(&self) -> &[u8] {
-        self.as_slice_generic()
+    /// all the bytes of one plane. interleaved buffers have a single plane.
+    pub(crate) fn plane_as_slice_raw(&self, plane_index: usize) -> &[u8] {
+        self.planes[plane_index].as_slice()
     }
 
-    fn as_slice_generic<T>(&self) -> &[T] {
-        unsafe {
-            let sample_fmt = (*self.groove_buffer).format.sample_fmt;
-            if SampleFormat::from_groove(sample_fmt).planar {
-                panic!("as_vec works for interleaved buffers only");
-            }
-            let channel_count = groove_channel_layout_count(
-                (*self.groove_buffer).format.channel_layout) as usize;
-            let frame_count = (*self.groove_buffer).frame_count as usize;
-            let data = *(*self.groove_buffer).data;
-            let len = channel_count * frame_count;
-            let raw_slice = slice::from_raw_parts(data, len);
-            transmute(raw_slice)
+    sample_accessors!();
+}
+
+impl Planes for OwnedAudioBuffer {
+    fn planes_format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn plane(&self, index: usize) -> &[u8] {
+        self.planes[index].as_slice()
+    }
+}
+
+/// read `frame_count` frames of samples in `format` from `planes` as
+/// interleaved floats
+pub(crate) fn samples_to_f64(format: AudioFormat, planes: &[&[u8]], frame_count: usize) -> Vec<f64> {
+    let channel_count = format.channel_layout.count() as usize;
+    let sample_type = format.sample_fmt.sample_type;
+    let size = format.sample_fmt.bytes_per_sample() as usize;
+    let mut samples = Vec::with_capacity(frame_count * channel_count);
+    for frame in 0..frame_count {
+        for channel in 0..channel_count {
+            let (plane, index) = if format.sample_fmt.planar {
+                (planes[channel], frame)
+            } else {
+                (planes[0], frame * channel_count + channel)
+            };
+            samples.push(read_sample(sample_type, &plane[index * size..(index + 1) * size]));
         }
     }
-}
\ No newline at end of file
+    samples
+}
+
+fn read_sample(sample_type: SampleType, bytes: &[u8]) -> f64 {
+    let mut array = [0u8; 8];
+    array[..bytes.len()].copy_from_slice(bytes);
+    match sample_type {
+        SampleType::U8 => (f64::from(bytes[0]) - 128.0) / 128.0,
+        SampleType::S16 => f64::from(i16::from_ne_bytes([array[0], array[1]])) / 32768.0,
+        SampleType::S32 => f64::from(i32::from_ne_bytes([array[0], array[1], array[2], array[3]])) / 2147483648.0,
+        SampleType::Flt => f64::from(f32::from_ne_bytes([array[0], array[1], array[2], array[3]])),
+        SampleType::Dbl => f64::from_ne_bytes(array),
+        SampleType::NoType => 0.0,
+    }
+}
+
+fn push_sample(bytes: &mut Vec<u8>, sample_type: SampleType, value: f64) {
+    match sample_type {
+        SampleType::U8 => bytes.push((value * 128.0 + 128.0).round().clamp(0.0, 255.0) as u8),
+        SampleType::S16 => bytes.extend_from_slice(
+            &((value * 32768.0).round().clamp(-32768.0, 32767.0) as i16).to_ne_bytes()),
+        SampleType::S32 => bytes.extend_from_slice(
+            &((value * 2147483648.0).round().clamp(-2147483648.0, 2147483647.0) as i32).to_ne_bytes()),
+        SampleType::Flt => bytes.extend_from_slice(&(value as f32).to_ne_bytes()),
+        SampleType::Dbl => bytes.extend_from_slice(&value.to_ne_bytes()),
+        SampleType::NoType => {},
+    }
+}
@@ -1,168 +1,117 @@
 extern crate libc;
 
-use libc::{c_int, uint64_t, c_char, c_void, c_double, uint8_t};
-
-#[repr(C)]
-pub struct GrooveSink {
-    pub audio_format: GrooveAudioFormat,
-    pub disable_resample: c_int,
-    /// If you leave this to its default of 0, frames pulled from the sink
-    /// will have sample count determined by efficiency.
-    /// If you set this to a positive number, frames pulled from the sink
-    /// will always have this number of samples.
-    pub buffer_sample_count: c_int,
-
-    /// how big the buffer queue should be, in sample frames.
-    /// groove_sink_create defaults this to 8192
-    pub buffer_size: c_int,
-
-    /// This volume adjustment only applies to this sink.
-    /// It is recommended that you leave this at 1.0 and instead adjust the
-    /// gain of the playlist.
-    /// If you want to change this value after you have already attached the
-    /// sink to the playlist, you must use groove_sink_set_gain.
-    /// float format. Defaults to 1.0
-    pub gain: c_double,
-
-    /// set to whatever you want
-    pub userdata: *mut c_void,
-    /// called when the audio queue is flushed. For example, if you seek to a
-    /// different location in the song.
-    pub flush: extern fn(sink: *mut GrooveSink),
-    /// called when a playlist item is deleted. Take this opportunity to remove
-    /// all your references to the GroovePlaylistItem.
-    pub purge: extern fn(sink: *mut GrooveSink, item: *mut GroovePlaylistItem),
-    /// called when the playlist is paused
-    pub pause: extern fn(sink: *mut GrooveSink),
-    /// called when the playlist is played
-    pub play: extern fn(sink: *mut GrooveSink),
-
-    /// read-only. set when you call groove_sink_attach. cleared when you call
-    /// groove_sink_detach
-    pub playlist: *mut GroovePlaylist,
-
-    /// read-only. automatically computed from audio_format when you call
-    /// groove_sink_attach
-    pub bytes_per_sec: c_int,
-}
-
-/// all fields read-only
-#[repr(C)]
-pub struct GrooveBuffer {
-    /// for interleaved audio, data[0] is the buffer.
-    /// for planar audio, each channel has a separate data pointer.
-    /// for encoded audio, data[0] is the encoded buffer.
-    pub data: *mut *mut uint8_t,
-
-    pub format: GrooveAudioFormat,
-
-    /// number of audio frames described by this buffer
-    /// for encoded audio, this is unknown and set to 0.
-    pub frame_count: c_int,
-
-    /// when encoding, if item is NULL, this is a format header or trailer.
-    /// otherwise, this is encoded audio for the item specified.
-    /// when decoding, item is never NULL.
-    pub item: *mut GroovePlaylistItem,
-    pub pos: c_double,
-
-    /// total number of bytes contained in this buffer
-    pub size: c_int,
-
-    /// presentation time stamp of the buffer
-    pub pts: uint64_t,
-}
-// Read-only structs are Sync
-unsafe impl Sync for GrooveBuffer {}
-// Promise rust that nothing points to a GrooveBuffer
-// when it destructs
-unsafe impl Send for GrooveBuffer {}
-
-/// all fields are read-only. modify with methods
-#[repr(C)]
-pub struct GroovePlaylistItem {
-    pub file: *mut GrooveFile,
-
-    pub gain: c_double,
-    pub peak: c_double,
-
-    /// A GroovePlaylist is a doubly linked list. Use these fields to
-    /// traverse the list.
-    pub prev: *mut GroovePlaylistItem,
-    pub next: *mut GroovePlaylistItem,
-}
-
-/// a GroovePlaylist keeps its sinks full.
-/// all fields are read-only. modify using methods.
-#[repr(C)]
-pub struct GroovePlaylist {
-    /// doubly linked list which is the playlist
-    pub head: *mut GroovePlaylistItem,
-    pub tail: *mut GroovePlaylistItem,
-
-    pub gain: c_double,
-}
-
-#[repr(C)]
-pub struct GrooveFile {
-    pub dirty: c_int,
-    pub filename: *const c_char,
-}
-
-unsafe impl Send for GrooveFile {}
-
-#[repr(C)]
-pub struct GrooveAudioFormat {
-    pub sample_rate: c_int,
-    pub channel_layout: uint64_t,
-    pub sample_fmt: c_int,
-}
-
-#[repr(C)]
-pub struct GrooveEncoder {
-    pub target_audio_format: GrooveAudioFormat,
-    pub bit_rate: c_int,
-    pub format_short_name: *const c_char,
-    pub codec_short_name: *const c_char,
-    pub filename: *const c_char,
-    pub mime_type: *const c_char,
-
-    /// how big the sink buffer should be, in sample frames.
-    /// groove_encoder_create defaults this to 8192
-    pub sink_buffer_size: c_int,
-
-    /// how big the encoded audio buffer should be, in bytes
-    /// groove_encoder_create defaults this to 16384
-    pub encoded_buffer_size: c_int,
-
-    /// This volume adjustment to make to this player.
-    /// It is recommended that you leave this at 1.0 and instead adjust the
-    /// gain of the underlying playlist.
-    /// If you want to change this value after you have already attached the
-    /// sink to the playlist, you must use groove_encoder_set_gain.
-    /// float format. Defaults to 1.0
-    pub gain: c_double,
-
-    /// read-only. set when attached and cleared when detached
-    pub playlist: *mut GroovePlaylist,
-
-    pub actual_audio_format: GrooveAudioFormat,
-}
+// the FFI declarations for the libgroove major version picked by cargo
+// feature, and the version-neutral layer over them which is all the rest of
+// the crate sees. libgroove 4's conventions are the neutral ones: audio
+// formats are a libav channel mask and sample format. libgroove 5's
+// libsoundio formats, C bools and `struct Groove` context are converted and
+// hidden here.
+
+use libc::c_int;
+
+#[cfg(all(feature = "libgroove4", feature = "libgroove5"))]
+compile_error!("the libgroove4 and libgroove5 features can't be enabled together");
+
+#[cfg(not(any(feature = "libgroove4", feature = "libgroove5")))]
+compile_error!("enable the libgroove4 or the libgroove5 feature");
+
+#[cfg(all(feature = "mock", feature = "libgroove5"))]
+compile_error!("the mock stands in for libgroove 4; build it with the default libgroove4 feature");
+
+#[cfg(feature = "libgroove4")]
+mod v4;
+
+#[cfg(feature = "libgroove5")]
+mod v5;
+
+#[cfg(feature = "mock")]
+mod mock;
+
+// where the libgroove functions come from
+#[cfg(all(feature = "libgroove4", not(feature = "mock")))]
+use self::v4 as raw;
+#[cfg(feature = "mock")]
+use self::mock as raw;
+#[cfg(feature = "libgroove5")]
+use self::v5 as raw;
+
+// what every version declares the same way
+pub use self::raw::{
+    GrooveBuffer,
+    GrooveCustomIo,
+    GrooveEncoder,
+    GrooveFile,
+    GroovePlaylist,
+    GroovePlaylistItem,
+    GrooveSink,
+    VERSION_MAJOR,
+    MIN_VERSION_MINOR,
+    groove_set_logging,
+    groove_version_major,
+    groove_version_minor,
+    groove_version_patch,
+    groove_version,
+    groove_file_duration,
+    groove_file_metadata_get,
+    groove_file_metadata_set,
+    groove_file_save,
+    groove_file_short_names,
+    groove_tag_key,
+    groove_tag_value,
+    groove_playlist_insert,
+    groove_playlist_destroy,
+    groove_playlist_remove,
+    groove_playlist_position,
+    groove_playlist_clear,
+    groove_playlist_set_fill_mode,
+    groove_playlist_seek,
+    groove_playlist_play,
+    groove_playlist_pause,
+    groove_encoder_destroy,
+    groove_encoder_metadata_set,
+    groove_encoder_attach,
+    groove_encoder_detach,
+    groove_buffer_unref,
+    groove_sink_destroy,
+    groove_sink_attach,
+    groove_sink_detach,
+    file_codec_info,
+    file_select_audio_stream,
+};
+
+#[cfg(feature = "log")]
+pub use self::raw::file_log_contexts;
+
+#[cfg(feature = "mock")]
+pub(crate) use self::mock::{inject_fault, clear_faults, open_files, times_opened};
+#[cfg(feature = "mock")]
+pub use self::mock::Fault;
+
+#[cfg(all(feature = "mock", feature = "log"))]
+pub use self::mock::{av_log_format_line, av_log_get_level, av_log_set_callback};
+
+pub use self::neutral::*;
 
 pub const EVERY_SINK_FULL: c_int = 0;
 pub const ANY_SINK_FULL:   c_int = 1;
 
 pub const TAG_MATCH_CASE: c_int = 1;
 
+pub const AVERROR_EOF: c_int = -0x20464F45;
+#[cfg(feature = "libgroove4")]
+pub const AVERROR_STREAM_NOT_FOUND: c_int = -0x525453F8;
+/// passed to GrooveCustomIo's seek as `whence` to ask for the stream size
+pub const AVSEEK_SIZE: c_int = 0x10000;
+
 pub const BUFFER_NO:  c_int = 0;
 pub const BUFFER_YES: c_int = 1;
 pub const BUFFER_END: c_int = 2;
 
-pub const CH_FRONT_LEFT    :uint64_t = 0x00000001;
-pub const CH_FRONT_RIGHT   :uint64_t = 0x00000002;
-pub const CH_FRONT_CENTER  :uint64_t = 0x00000004;
-pub const CH_LAYOUT_MONO   :uint64_t = CH_FRONT_CENTER;
-pub const CH_LAYOUT_STEREO :uint64_t = CH_FRONT_LEFT|CH_FRONT_RIGHT;
+pub const CH_FRONT_LEFT    :u64 = 0x00000001;
+pub const CH_FRONT_RIGHT   :u64 = 0x00000002;
+pub const CH_FRONT_CENTER  :u64 = 0x00000004;
+pub const CH_LAYOUT_MONO   :u64 = CH_FRONT_CENTER;
+pub const CH_LAYOUT_STEREO :u64 = CH_FRONT_LEFT|CH_FRONT_RIGHT;
 
 pub const SAMPLE_FMT_NONE: i32 = -1;
 pub const SAMPLE_FMT_U8:   i32 =  0;
@@ -177,55 +126,369 @@ pub const SAMPLE_FMT_S32P: i32 =  7;
 pub const SAMPLE_FMT_FLTP: i32 =  8;
 pub const SAMPLE_FMT_DBLP: i32 =  9;
 
-#[link(name="groove")]
-extern {
-    pub fn groove_init() -> c_int;
-    pub fn groove_finish();
-    pub fn groove_set_logging(level: c_int);
-    pub fn groove_channel_layout_count(channel_layout: uint64_t) -> c_int;
-    pub fn groove_channel_layout_default(count: c_int) -> uint64_t;
-    pub fn groove_sample_format_bytes_per_sample(format: c_int) -> c_int;
-    pub fn groove_version_major() -> c_int;
-    pub fn groove_version_minor() -> c_int;
-    pub fn groove_version_patch() -> c_int;
-    pub fn groove_version() -> *const c_char;
-
-    pub fn groove_file_open(filename: *const c_char) -> *mut GrooveFile;
-    pub fn groove_file_close(file: *mut GrooveFile);
-    pub fn groove_file_duration(file: *mut GrooveFile) -> c_double;
-    pub fn groove_file_metadata_get(file: *mut GrooveFile, key: *const c_char,
-                                prev: *const c_void, flags: c_int) -> *mut c_void;
-    pub fn groove_file_metadata_set(file: *mut GrooveFile, key: *const c_char,
-                                value: *const c_char, flags: c_int) -> c_int;
-    pub fn groove_file_save(file: *mut GrooveFile) -> c_int;
-    pub fn groove_file_audio_format(file: *mut GrooveFile, audio_format: *mut GrooveAudioFormat);
-
-    pub fn groove_tag_key(tag: *mut c_void) -> *const c_char;
-    pub fn groove_tag_value(tag: *mut c_void) -> *const c_char;
-
-    pub fn groove_playlist_create() -> *mut GroovePlaylist;
-    pub fn groove_playlist_insert(playlist: *mut GroovePlaylist, file: *mut GrooveFile,
-                              gain: c_double, peak: c_double,
-                              next: *mut GroovePlaylistItem) -> *mut GroovePlaylistItem;
-    pub fn groove_playlist_destroy(playlist: *mut GroovePlaylist);
-    pub fn groove_playlist_clear(playlist: *mut GroovePlaylist);
-    pub fn groove_playlist_set_fill_mode(playlist: *mut GroovePlaylist, mode: c_int);
-
-    pub fn groove_encoder_create() -> *mut GrooveEncoder;
-    pub fn groove_encoder_destroy(encoder: *mut GrooveEncoder);
-    pub fn groove_encoder_metadata_set(encoder: *mut GrooveEncoder, key: *const c_char,
-                                   value: *const c_char, flags: c_int) -> c_int;
-    pub fn groove_encoder_attach(encoder: *mut GrooveEncoder, playlist: *mut GroovePlaylist) -> c_int;
-    pub fn groove_encoder_detach(encoder: *mut GrooveEncoder) -> c_int;
-    pub fn groove_encoder_buffer_get(encoder: *mut GrooveEncoder, buffer: *mut *mut GrooveBuffer,
-                                 block: c_int) -> c_int;
-
-    pub fn groove_buffer_unref(buffer: *mut GrooveBuffer);
-
-    pub fn groove_sink_create() -> *mut GrooveSink;
-    pub fn groove_sink_destroy(sink: *mut GrooveSink);
-    pub fn groove_sink_attach(sink: *mut GrooveSink, playlist: *mut GroovePlaylist) -> c_int;
-    pub fn groove_sink_detach(sink: *mut GrooveSink) -> c_int;
-    pub fn groove_sink_buffer_get(sink: *mut GrooveSink, buffer: *mut *mut GrooveBuffer,
-                              block: c_int) -> c_int;
-}
\ No newline at end of file
+// libgroove 4 already works the neutral way
+#[cfg(feature = "libgroove4")]
+mod neutral {
+    use std::mem;
+
+    use libc::{c_char, c_int};
+
+    use super::raw;
+    use super::{GrooveBuffer, GrooveCustomIo, GrooveEncoder, GrooveFile, GroovePlaylist, GrooveSink};
+
+    pub use super::v4::GrooveAudioFormat;
+
+    pub unsafe fn init() -> c_int {
+        raw::groove_init()
+    }
+
+    pub unsafe fn finish() {
+        raw::groove_finish()
+    }
+
+    pub unsafe fn channel_layout_count(channel_layout: u64) -> c_int {
+        raw::groove_channel_layout_count(channel_layout)
+    }
+
+    pub unsafe fn channel_layout_default(count: c_int) -> u64 {
+        raw::groove_channel_layout_default(count)
+    }
+
+    pub unsafe fn sample_format_bytes_per_sample(format: c_int) -> c_int {
+        raw::groove_sample_format_bytes_per_sample(format)
+    }
+
+    pub unsafe fn file_open(filename: *const c_char) -> *mut GrooveFile {
+        raw::groove_file_open(filename)
+    }
+
+    pub unsafe fn file_open_custom(custom_io: *mut GrooveCustomIo, filename_hint: *const c_char) -> *mut GrooveFile {
+        raw::groove_file_open_custom(custom_io, filename_hint)
+    }
+
+    pub unsafe fn file_close(file: *mut GrooveFile) {
+        raw::groove_file_close(file)
+    }
+
+    pub unsafe fn file_dirty(file: *mut GrooveFile) -> bool {
+        (*file).dirty != 0
+    }
+
+    pub unsafe fn file_audio_format(file: *mut GrooveFile) -> GrooveAudioFormat {
+        let mut format = mem::zeroed();
+        raw::groove_file_audio_format(file, &mut format);
+        format
+    }
+
+    pub unsafe fn playlist_create() -> *mut GroovePlaylist {
+        raw::groove_playlist_create()
+    }
+
+    pub unsafe fn playlist_playing(playlist: *mut GroovePlaylist) -> bool {
+        raw::groove_playlist_playing(playlist) != 0
+    }
+
+    pub unsafe fn sink_create() -> *mut GrooveSink {
+        raw::groove_sink_create()
+    }
+
+    pub unsafe fn sink_set_audio_format(sink: *mut GrooveSink, format: GrooveAudioFormat) {
+        (*sink).audio_format = format;
+    }
+
+    pub unsafe fn sink_set_disable_resample(sink: *mut GrooveSink, disabled: bool) {
+        (*sink).disable_resample = disabled as c_int;
+    }
+
+    pub unsafe fn sink_buffer_get(sink: *mut GrooveSink, buffer: *mut *mut GrooveBuffer, block: bool) -> c_int {
+        raw::groove_sink_buffer_get(sink, buffer, block as c_int)
+    }
+
+    pub unsafe fn encoder_create() -> *mut GrooveEncoder {
+        raw::groove_encoder_create()
+    }
+
+    pub unsafe fn encoder_target_audio_format(encoder: *mut GrooveEncoder) -> GrooveAudioFormat {
+        (*encoder).target_audio_format
+    }
+
+    pub unsafe fn encoder_set_target_audio_format(encoder: *mut GrooveEncoder, format: GrooveAudioFormat) {
+        (*encoder).target_audio_format = format;
+    }
+
+    pub unsafe fn encoder_actual_audio_format(encoder: *mut GrooveEncoder) -> GrooveAudioFormat {
+        (*encoder).actual_audio_format
+    }
+
+    pub unsafe fn encoder_buffer_get(encoder: *mut GrooveEncoder, buffer: *mut *mut GrooveBuffer,
+                                     block: bool) -> c_int {
+        raw::groove_encoder_buffer_get(encoder, buffer, block as c_int)
+    }
+
+    pub unsafe fn buffer_audio_format(buffer: *mut GrooveBuffer) -> GrooveAudioFormat {
+        (*buffer).format
+    }
+}
+
+// libgroove 5 creates everything from one context, and describes audio the
+// way libsoundio does
+#[cfg(feature = "libgroove5")]
+mod neutral {
+    use std::mem;
+    use std::ptr;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+
+    use libc::{c_char, c_int};
+
+    use super::v5::{self, Groove, SoundIoChannelLayout};
+    use super::v5::{
+        SOUNDIO_CHANNEL_ID_LAST_LIBAV,
+        SOUNDIO_FORMAT_INVALID,
+        SOUNDIO_FORMAT_U8,
+        SOUNDIO_FORMAT_S16NE,
+        SOUNDIO_FORMAT_S32NE,
+        SOUNDIO_FORMAT_FLOAT32NE,
+        SOUNDIO_FORMAT_FLOAT64NE,
+        SOUNDIO_MAX_CHANNELS,
+    };
+    use super::{GrooveBuffer, GrooveCustomIo, GrooveEncoder, GrooveFile, GroovePlaylist, GrooveSink};
+    use super::{
+        SAMPLE_FMT_NONE,
+        SAMPLE_FMT_U8,
+        SAMPLE_FMT_S16,
+        SAMPLE_FMT_S32,
+        SAMPLE_FMT_FLT,
+        SAMPLE_FMT_DBL,
+        SAMPLE_FMT_U8P,
+        SAMPLE_FMT_S16P,
+        SAMPLE_FMT_S32P,
+        SAMPLE_FMT_FLTP,
+        SAMPLE_FMT_DBLP,
+    };
+
+    /// the libgroove 4 shape of an audio format
+    #[derive(Clone, Copy)]
+    pub struct GrooveAudioFormat {
+        pub sample_rate: c_int,
+        pub channel_layout: u64,
+        pub sample_fmt: c_int,
+    }
+
+    // libav's packed sample formats, each with the native-endian libsoundio
+    // format it matches. the planar ones follow the packed ones in the same
+    // order.
+    const SAMPLE_FORMATS: [(c_int, c_int); 5] = [
+        (SAMPLE_FMT_U8, SOUNDIO_FORMAT_U8),
+        (SAMPLE_FMT_S16, SOUNDIO_FORMAT_S16NE),
+        (SAMPLE_FMT_S32, SOUNDIO_FORMAT_S32NE),
+        (SAMPLE_FMT_FLT, SOUNDIO_FORMAT_FLOAT32NE),
+        (SAMPLE_FMT_DBL, SOUNDIO_FORMAT_FLOAT64NE),
+    ];
+    const PLANAR_OFFSET: c_int = SAMPLE_FMT_U8P - SAMPLE_FMT_U8;
+
+    // the context groove_create made. Groove::new only initializes libgroove
+    // when no other Groove is alive, so there is never more than one.
+    static GROOVE: AtomicPtr<Groove> = AtomicPtr::new(ptr::null_mut());
+
+    fn groove() -> *mut Groove {
+        GROOVE.load(Ordering::SeqCst)
+    }
+
+    fn to_raw(format: GrooveAudioFormat) -> v5::GrooveAudioFormat {
+        let mut layout = SoundIoChannelLayout {
+            name: ptr::null(),
+            channel_count: 0,
+            channels: [0; SOUNDIO_MAX_CHANNELS],
+        };
+        for bit in 0..SOUNDIO_CHANNEL_ID_LAST_LIBAV {
+            if format.channel_layout & (1 << bit) != 0 {
+                layout.channels[layout.channel_count as usize] = bit + 1;
+                layout.channel_count += 1;
+            }
+        }
+        let is_planar = format.sample_fmt >= SAMPLE_FMT_U8P;
+        let packed = if is_planar { format.sample_fmt - PLANAR_OFFSET } else { format.sample_fmt };
+        let format_id = SAMPLE_FORMATS.iter()
+            .find(|&&(libav, _)| libav == packed)
+            .map_or(SOUNDIO_FORMAT_INVALID, |&(_, soundio)| soundio);
+        v5::GrooveAudioFormat { sample_rate: format.sample_rate, layout, format: format_id, is_planar }
+    }
+
+    // channels and sample formats libav has no name for become an empty
+    // layout and SAMPLE_FMT_NONE
+    fn from_raw(format: &v5::GrooveAudioFormat) -> GrooveAudioFormat {
+        let count = (format.layout.channel_count.max(0) as usize).min(SOUNDIO_MAX_CHANNELS);
+        let channel_layout = format.layout.channels[..count].iter()
+            .filter(|&&id| (1..=SOUNDIO_CHANNEL_ID_LAST_LIBAV).contains(&id))
+            .fold(0, |mask, &id| mask | 1 << (id - 1));
+        let sample_fmt = match SAMPLE_FORMATS.iter().find(|&&(_, soundio)| soundio == format.format) {
+            Some(&(libav, _)) if format.is_planar => libav + PLANAR_OFFSET,
+            Some(&(libav, _)) => libav,
+            None => SAMPLE_FMT_NONE,
+        };
+        GrooveAudioFormat { sample_rate: format.sample_rate, channel_layout, sample_fmt }
+    }
+
+    pub unsafe fn init() -> c_int {
+        let mut groove = ptr::null_mut();
+        let err_code = v5::groove_create(&mut groove);
+        if err_code >= 0 {
+            GROOVE.store(groove, Ordering::SeqCst);
+        }
+        err_code
+    }
+
+    pub unsafe fn finish() {
+        v5::groove_destroy(GROOVE.swap(ptr::null_mut(), Ordering::SeqCst))
+    }
+
+    pub unsafe fn channel_layout_count(channel_layout: u64) -> c_int {
+        channel_layout.count_ones() as c_int
+    }
+
+    // what libav's av_get_default_channel_layout picks
+    pub unsafe fn channel_layout_default(count: c_int) -> u64 {
+        match count {
+            1 => 0x004,
+            2 => 0x003,
+            3 => 0x007,
+            4 => 0x033,
+            5 => 0x607,
+            6 => 0x60f,
+            7 => 0x637,
+            8 => 0x63f,
+            _ => 0,
+        }
+    }
+
+    pub unsafe fn sample_format_bytes_per_sample(format: c_int) -> c_int {
+        match format {
+            SAMPLE_FMT_U8 | SAMPLE_FMT_U8P => 1,
+            SAMPLE_FMT_S16 | SAMPLE_FMT_S16P => 2,
+            SAMPLE_FMT_S32 | SAMPLE_FMT_S32P | SAMPLE_FMT_FLT | SAMPLE_FMT_FLTP => 4,
+            SAMPLE_FMT_DBL | SAMPLE_FMT_DBLP => 8,
+            _ => 0,
+        }
+    }
+
+    // create a file and open it with `open`, or destroy it again
+    unsafe fn file_create(open: impl FnOnce(*mut GrooveFile) -> c_int) -> *mut GrooveFile {
+        let file = v5::groove_file_create(groove());
+        if file.is_null() || open(file) >= 0 {
+            return file;
+        }
+        v5::groove_file_destroy(file);
+        ptr::null_mut()
+    }
+
+    pub unsafe fn file_open(filename: *const c_char) -> *mut GrooveFile {
+        file_create(|file| v5::groove_file_open(file, filename, filename))
+    }
+
+    pub unsafe fn file_open_custom(custom_io: *mut GrooveCustomIo, filename_hint: *const c_char) -> *mut GrooveFile {
+        file_create(|file| v5::groove_file_open_custom(file, custom_io, filename_hint))
+    }
+
+    pub unsafe fn file_close(file: *mut GrooveFile) {
+        v5::groove_file_close(file);
+        v5::groove_file_destroy(file)
+    }
+
+    pub unsafe fn file_dirty(file: *mut GrooveFile) -> bool {
+        (*file).dirty
+    }
+
+    pub unsafe fn file_audio_format(file: *mut GrooveFile) -> GrooveAudioFormat {
+        let mut format = mem::zeroed();
+        v5::groove_file_audio_format(file, &mut format);
+        from_raw(&format)
+    }
+
+    pub unsafe fn playlist_create() -> *mut GroovePlaylist {
+        v5::groove_playlist_create(groove())
+    }
+
+    pub unsafe fn playlist_playing(playlist: *mut GroovePlaylist) -> bool {
+        v5::groove_playlist_playing(playlist)
+    }
+
+    pub unsafe fn sink_create() -> *mut GrooveSink {
+        v5::groove_sink_create(groove())
+    }
+
+    pub unsafe fn sink_set_audio_format(sink: *mut GrooveSink, format: GrooveAudioFormat) {
+        (*sink).audio_format = to_raw(format);
+    }
+
+    pub unsafe fn sink_set_disable_resample(sink: *mut GrooveSink, disabled: bool) {
+        (*sink).disable_resample = disabled;
+    }
+
+    pub unsafe fn sink_buffer_get(sink: *mut GrooveSink, buffer: *mut *mut GrooveBuffer, block: bool) -> c_int {
+        v5::groove_sink_buffer_get(sink, buffer, block)
+    }
+
+    pub unsafe fn encoder_create() -> *mut GrooveEncoder {
+        v5::groove_encoder_create(groove())
+    }
+
+    pub unsafe fn encoder_target_audio_format(encoder: *mut GrooveEncoder) -> GrooveAudioFormat {
+        from_raw(&(*encoder).target_audio_format)
+    }
+
+    pub unsafe fn encoder_set_target_audio_format(encoder: *mut GrooveEncoder, format: GrooveAudioFormat) {
+        (*encoder).target_audio_format = to_raw(format);
+    }
+
+    pub unsafe fn encoder_actual_audio_format(encoder: *mut GrooveEncoder) -> GrooveAudioFormat {
+        from_raw(&(*encoder).actual_audio_format)
+    }
+
+    pub unsafe fn encoder_buffer_get(encoder: *mut GrooveEncoder, buffer: *mut *mut GrooveBuffer,
+                                     block: bool) -> c_int {
+        v5::groove_encoder_buffer_get(encoder, buffer, block)
+    }
+
+    pub unsafe fn buffer_audio_format(buffer: *mut GrooveBuffer) -> GrooveAudioFormat {
+        from_raw(&(*buffer).format)
+    }
+}
+
+/// what the backend knows of the decoder behind a file, for `File::info`
+#[derive(Debug, Default)]
+pub struct CodecInfo {
+    /// libav's short and long names of the codec
+    pub name: Option<String>,
+    pub long_name: Option<String>,
+    /// how many streams of any type the container holds
+    pub stream_count: usize,
+    /// which of them is decoded
+    pub stream_index: Option<usize>,
+    /// bits per second, when the stream or its container says
+    pub bit_rate: Option<u64>,
+    /// bits per sample before decoding, for lossless codecs
+    pub bits_per_raw_sample: Option<u32>,
+}
+
+#[cfg(feature = "log")]
+use libc::{c_char, c_void};
+
+/// the leading fields of libavutil's AVClass, which every libav context
+/// points to first
+#[cfg(feature = "log")]
+#[repr(C)]
+pub struct AVClass {
+    pub class_name: *const c_char,
+    pub item_name: Option<unsafe extern "C" fn(ctx: *mut c_void) -> *const c_char>,
+}
+
+// the va_list arguments are pointers on the platforms libgroove supports
+#[cfg(all(feature = "log", not(feature = "mock")))]
+#[link(name="avutil")]
+extern "C" {
+    pub fn av_log_set_callback(callback: Option<unsafe extern "C" fn(avcl: *mut c_void, level: c_int,
+                                                                     fmt: *const c_char, vl: *mut c_void)>);
+    pub fn av_log_get_level() -> c_int;
+    pub fn av_log_format_line(avcl: *mut c_void, level: c_int, fmt: *const c_char, vl: *mut c_void,
+                              line: *mut c_char, line_size: c_int, print_prefix: *mut c_int);
+}
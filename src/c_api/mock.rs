@@ -0,0 +1,1855 @@
+// a pure-Rust stand-in for libgroove, used instead of the real library
+// when the `mock` feature is enabled. it shares the struct layouts of v4.rs
+// and keeps its private state after the public part of each struct, the way
+// libgroove does.
+//
+// what it can decode: WAV files (PCM and float, including
+// WAVE_FORMAT_EXTENSIBLE), files named `tone:<frequency>[:<seconds>
+// [:<sample rate>[:<channels>]]]`, which are sine waves, and WAV streams
+// read through GrooveCustomIo. the encoder writes WAV. MP4, Matroska,
+// FLAC and MP3 files open, with their streams listed, but play a constant
+// level instead of their audio.
+//
+// there is no decode thread: audio is decoded when a sink or encoder asks
+// for a buffer and has none queued, on the thread that asked.
+//
+// it logs through the libav log callback when a file fails to open, and
+// when a read error fault is hit, from a context of the file's own.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::f64::consts::PI;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use libc::{c_char, c_double, c_int, c_void};
+
+pub use super::v4::*;
+use super::{
+    CodecInfo,
+    AVERROR_STREAM_NOT_FOUND,
+    BUFFER_NO,
+    BUFFER_YES,
+    BUFFER_END,
+    CH_FRONT_CENTER,
+    CH_FRONT_LEFT,
+    CH_FRONT_RIGHT,
+    CH_LAYOUT_MONO,
+    CH_LAYOUT_STEREO,
+    SAMPLE_FMT_U8,
+    SAMPLE_FMT_S16,
+    SAMPLE_FMT_S32,
+    SAMPLE_FMT_FLT,
+    SAMPLE_FMT_DBL,
+    SAMPLE_FMT_U8P,
+    SAMPLE_FMT_S16P,
+    SAMPLE_FMT_S32P,
+    SAMPLE_FMT_FLTP,
+    SAMPLE_FMT_DBLP,
+    TAG_MATCH_CASE,
+};
+use audio_format::AudioFormat;
+use matroska;
+use mp4;
+use wav;
+
+// frames decoded at a time, in the file's own sample rate
+const CHUNK_FRAMES: u64 = 1024;
+
+// AVERROR(EINVAL)
+const EINVAL: c_int = -22;
+
+// how long the containers the mock can't decode play for, unless they say
+const PLACEHOLDER_SECONDS: f64 = 1.0;
+
+const VERSION: &[u8] = b"4.3.0\0";
+
+/// a failure for the mock to simulate. see `groove::mock::inject_fault`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// creating a Groove fails with this error code
+    Init(i32),
+    /// opening a file fails
+    Open,
+    /// attaching a sink or encoder fails with this error code
+    Attach(i32),
+    /// setting metadata on a file or encoder fails with this error code
+    MetadataSet(i32),
+    /// saving a file fails with this error code
+    Save(i32),
+    /// the next file opened hits a read error after this many frames. like
+    /// libgroove, the playlist then moves on as if the file ended there.
+    ReadError { after_frames: u64 },
+}
+
+thread_local! {
+    static FAULTS: RefCell<Vec<Fault>> = const { RefCell::new(Vec::new()) };
+}
+
+pub(crate) fn inject_fault(fault: Fault) {
+    FAULTS.with(|faults| faults.borrow_mut().push(fault));
+}
+
+pub(crate) fn clear_faults() {
+    FAULTS.with(|faults| faults.borrow_mut().clear());
+}
+
+// how many handles are open on each file name
+static OPEN_FILES: Mutex<BTreeMap<Vec<u8>, usize>> = Mutex::new(BTreeMap::new());
+
+// how many times each file name has been opened
+static OPENINGS: Mutex<BTreeMap<Vec<u8>, usize>> = Mutex::new(BTreeMap::new());
+
+pub(crate) fn open_files(filename: &[u8]) -> usize {
+    OPEN_FILES.lock().unwrap().get(filename).cloned().unwrap_or(0)
+}
+
+pub(crate) fn times_opened(filename: &[u8]) -> usize {
+    OPENINGS.lock().unwrap().get(filename).cloned().unwrap_or(0)
+}
+
+// remove and return the first injected fault `matches` accepts
+fn take_fault<T, F: Fn(&Fault) -> Option<T>>(matches: F) -> Option<T> {
+    FAULTS.with(|faults| {
+        let mut faults = faults.borrow_mut();
+        let index = faults.iter().position(|fault| matches(fault).is_some())?;
+        matches(&faults.remove(index))
+    })
+}
+
+// ---------------------------------------------------------------------------
+// library
+
+static LOG_LEVEL: AtomicI32 = AtomicI32::new(-8);
+
+pub unsafe extern "C" fn groove_init() -> c_int {
+    take_fault(|fault| match *fault { Fault::Init(code) => Some(code), _ => None }).unwrap_or(0)
+}
+
+pub unsafe extern "C" fn groove_finish() {}
+
+pub unsafe extern "C" fn groove_set_logging(level: c_int) {
+    LOG_LEVEL.store(level, Ordering::SeqCst);
+}
+
+pub unsafe extern "C" fn groove_channel_layout_count(channel_layout: u64) -> c_int {
+    channel_layout.count_ones() as c_int
+}
+
+pub unsafe extern "C" fn groove_channel_layout_default(count: c_int) -> u64 {
+    default_layout(count)
+}
+
+fn default_layout(count: c_int) -> u64 {
+    match count {
+        1 => CH_LAYOUT_MONO,
+        2 => CH_LAYOUT_STEREO,
+        3 => 0x7,
+        4 => 0x107,
+        5 => 0x37,
+        6 => 0x3f,
+        7 => 0x13f,
+        8 => 0x63f,
+        _ => 0,
+    }
+}
+
+pub unsafe extern "C" fn groove_sample_format_bytes_per_sample(format: c_int) -> c_int {
+    bytes_per_sample(format) as c_int
+}
+
+fn bytes_per_sample(format: c_int) -> usize {
+    match format {
+        SAMPLE_FMT_U8 | SAMPLE_FMT_U8P => 1,
+        SAMPLE_FMT_S16 | SAMPLE_FMT_S16P => 2,
+        SAMPLE_FMT_S32 | SAMPLE_FMT_S32P | SAMPLE_FMT_FLT | SAMPLE_FMT_FLTP => 4,
+        SAMPLE_FMT_DBL | SAMPLE_FMT_DBLP => 8,
+        _ => 0,
+    }
+}
+
+fn is_planar(format: c_int) -> bool {
+    format >= SAMPLE_FMT_U8P
+}
+
+fn packed(format: c_int) -> c_int {
+    if is_planar(format) { format - SAMPLE_FMT_U8P } else { format }
+}
+
+pub unsafe extern "C" fn groove_version_major() -> c_int { 4 }
+pub unsafe extern "C" fn groove_version_minor() -> c_int { 3 }
+pub unsafe extern "C" fn groove_version_patch() -> c_int { 0 }
+
+pub unsafe extern "C" fn groove_version() -> *const c_char {
+    VERSION.as_ptr() as *const c_char
+}
+
+// ---------------------------------------------------------------------------
+// samples
+
+#[derive(Clone, Copy, PartialEq)]
+enum Codec {
+    U8,
+    S16,
+    S24,
+    S32,
+    F32,
+    F64,
+}
+
+impl Codec {
+    fn bytes(self) -> usize {
+        match self {
+            Codec::U8 => 1,
+            Codec::S16 => 2,
+            Codec::S24 => 3,
+            Codec::S32 | Codec::F32 => 4,
+            Codec::F64 => 8,
+        }
+    }
+
+    // libav's short and long names for the codec
+    fn names(self) -> (&'static str, &'static str) {
+        match self {
+            Codec::U8 => ("pcm_u8", "PCM unsigned 8-bit"),
+            Codec::S16 => ("pcm_s16le", "PCM signed 16-bit little-endian"),
+            Codec::S24 => ("pcm_s24le", "PCM signed 24-bit little-endian"),
+            Codec::S32 => ("pcm_s32le", "PCM signed 32-bit little-endian"),
+            Codec::F32 => ("pcm_f32le", "PCM 32-bit floating point little-endian"),
+            Codec::F64 => ("pcm_f64le", "PCM 64-bit floating point little-endian"),
+        }
+    }
+
+    // the sample format libav decodes this to
+    fn sample_fmt(self) -> c_int {
+        match self {
+            Codec::U8 => SAMPLE_FMT_U8,
+            Codec::S16 => SAMPLE_FMT_S16,
+            Codec::S24 | Codec::S32 => SAMPLE_FMT_S32,
+            Codec::F32 => SAMPLE_FMT_FLT,
+            Codec::F64 => SAMPLE_FMT_DBL,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f64 {
+        match self {
+            Codec::U8 => (f64::from(bytes[0]) - 128.0) / 128.0,
+            Codec::S16 => f64::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32768.0,
+            Codec::S24 => f64::from(i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) / 8388608.0,
+            Codec::S32 => f64::from(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])) / 2147483648.0,
+            Codec::F32 => f64::from(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            Codec::F64 => {
+                let mut array = [0u8; 8];
+                array.copy_from_slice(&bytes[..8]);
+                f64::from_le_bytes(array)
+            },
+        }
+    }
+}
+
+// append one sample in a libgroove sample format
+fn put_sample(out: &mut Vec<u8>, sample_fmt: c_int, value: f64, little_endian: bool) {
+    macro_rules! put {
+        ($value:expr) => {
+            if little_endian {
+                out.extend_from_slice(&$value.to_le_bytes())
+            } else {
+                out.extend_from_slice(&$value.to_ne_bytes())
+            }
+        };
+    }
+    match packed(sample_fmt) {
+        SAMPLE_FMT_U8 => out.push((value * 128.0 + 128.0).round().clamp(0.0, 255.0) as u8),
+        SAMPLE_FMT_S16 => put!((value * 32768.0).round().clamp(-32768.0, 32767.0) as i16),
+        SAMPLE_FMT_S32 => put!((value * 2147483648.0).round().clamp(-2147483648.0, 2147483647.0) as i32),
+        SAMPLE_FMT_FLT => put!(value as f32),
+        _ => put!(value),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// WAV
+
+struct WavHeader {
+    codec: Codec,
+    channels: usize,
+    sample_rate: u32,
+    channel_layout: u64,
+    fmt_chunk: Vec<u8>,
+    metadata: Vec<(String, String)>,
+    data_offset: usize,
+    // None when the stream does not say
+    data_len: Option<u64>,
+}
+
+enum Parse<T> {
+    Done(T),
+    NeedMore,
+    Invalid,
+}
+
+const INFO_KEYS: [(&[u8; 4], &str); 10] = [
+    (b"INAM", "title"),
+    (b"IART", "artist"),
+    (b"IPRD", "album"),
+    (b"ICMT", "comment"),
+    (b"ICRD", "date"),
+    (b"IGNR", "genre"),
+    (b"IPRT", "track"),
+    (b"ICOP", "copyright"),
+    (b"ISFT", "encoder"),
+    (b"IENG", "engineer"),
+];
+
+fn le_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+// parse everything up to the start of the audio
+fn parse_wav_header(data: &[u8]) -> Parse<WavHeader> {
+    if data.len() < 12 {
+        return Parse::NeedMore;
+    }
+    if (&data[0..4] != b"RIFF" && &data[0..4] != b"RF64") || &data[8..12] != b"WAVE" {
+        return Parse::Invalid;
+    }
+    let rf64 = &data[0..4] == b"RF64";
+    let mut ds64_data_len = None;
+    let mut format = None;
+    let mut metadata = Vec::new();
+    let mut pos = 12;
+    loop {
+        if data.len() < pos + 8 {
+            return Parse::NeedMore;
+        }
+        let id = &data[pos..pos + 4];
+        let size = le_u32(data, pos + 4);
+        let body_start = pos + 8;
+        if id == b"data" {
+            let (codec, channels, sample_rate, channel_layout, fmt_chunk) = match format {
+                Some(format) => format,
+                None => return Parse::Invalid,
+            };
+            let data_len = if rf64 { ds64_data_len } else if size == u32::MAX { None } else { Some(u64::from(size)) };
+            return Parse::Done(WavHeader {
+                codec,
+                channels,
+                sample_rate,
+                channel_layout,
+                fmt_chunk,
+                metadata,
+                data_offset: body_start,
+                data_len,
+            });
+        }
+        let body_end = body_start + size as usize;
+        if data.len() < body_end {
+            return Parse::NeedMore;
+        }
+        let body = &data[body_start..body_end];
+        if id == b"fmt " {
+            format = match parse_fmt(body) {
+                Some((codec, channels, sample_rate, channel_layout)) =>
+                    Some((codec, channels, sample_rate, channel_layout, body.to_vec())),
+                None => return Parse::Invalid,
+            };
+        } else if id == b"ds64" && body.len() >= 16 {
+            ds64_data_len = Some(u64::from(le_u32(body, 8)) | (u64::from(le_u32(body, 12)) << 32));
+        } else if id == b"LIST" && body.len() >= 4 && &body[0..4] == b"INFO" {
+            metadata = parse_info(&body[4..]);
+        }
+        pos = body_end + (size as usize & 1);
+    }
+}
+
+fn parse_fmt(body: &[u8]) -> Option<(Codec, usize, u32, u64)> {
+    if body.len() < 16 {
+        return None;
+    }
+    let mut tag = le_u16(body, 0);
+    let channels = le_u16(body, 2) as usize;
+    let sample_rate = le_u32(body, 4);
+    let bits = le_u16(body, 14);
+    let mut channel_layout = 0;
+    if tag == 0xfffe && body.len() >= 40 {
+        channel_layout = u64::from(le_u32(body, 20));
+        tag = le_u16(body, 24);
+    }
+    let codec = match (tag, bits) {
+        (1, 8) => Codec::U8,
+        (1, 16) => Codec::S16,
+        (1, 24) => Codec::S24,
+        (1, 32) => Codec::S32,
+        (3, 32) => Codec::F32,
+        (3, 64) => Codec::F64,
+        _ => return None,
+    };
+    if channels == 0 || sample_rate == 0 {
+        return None;
+    }
+    if channel_layout.count_ones() as usize != channels {
+        channel_layout = default_layout(channels as c_int);
+    }
+    Some((codec, channels, sample_rate, channel_layout))
+}
+
+fn parse_info(mut data: &[u8]) -> Vec<(String, String)> {
+    let mut metadata = Vec::new();
+    while data.len() >= 8 {
+        let id = &data[0..4];
+        let size = le_u32(data, 4) as usize;
+        let value = match data.get(8..8 + size) {
+            Some(value) => value,
+            None => break,
+        };
+        let end = value.iter().position(|&byte| byte == 0).unwrap_or(value.len());
+        let key = INFO_KEYS.iter()
+            .find(|(info_id, _)| &info_id[..] == id)
+            .map_or_else(|| String::from_utf8_lossy(id).into_owned(), |(_, key)| key.to_string());
+        metadata.push((key, String::from_utf8_lossy(&value[..end]).into_owned()));
+        data = data.get(8 + size + (size & 1)..).unwrap_or(&[]);
+    }
+    metadata
+}
+
+fn info_chunk(metadata: &[(String, String)]) -> Vec<u8> {
+    let mut body = b"INFO".to_vec();
+    for (key, value) in metadata {
+        let id = match INFO_KEYS.iter().find(|(_, info_key)| info_key.eq_ignore_ascii_case(key)) {
+            Some((id, _)) => **id,
+            None if key.len() == 4 && key.is_ascii() => {
+                let mut id = [0u8; 4];
+                id.copy_from_slice(key.as_bytes());
+                id
+            },
+            None => continue,
+        };
+        let size = value.len() + 1;
+        body.extend_from_slice(&id);
+        body.extend_from_slice(&(size as u32).to_le_bytes());
+        body.extend_from_slice(value.as_bytes());
+        body.push(0);
+        if size % 2 == 1 {
+            body.push(0);
+        }
+    }
+    if body.len() == 4 {
+        return Vec::new();
+    }
+    let mut chunk = b"LIST".to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(&body);
+    chunk
+}
+
+// ---------------------------------------------------------------------------
+// files
+
+#[repr(C)]
+struct FilePrivate {
+    public: GrooveFile,
+    filename: CString,
+    short_names: CString,
+    state: Mutex<FileState>,
+    // stands in for the libav contexts which log about the file. it has no
+    // AVClass, so the pointer to one it starts with is null.
+    log_context: Box<*const c_void>,
+}
+
+impl FilePrivate {
+    fn log_context(&self) -> *mut c_void {
+        &*self.log_context as *const *const c_void as *mut c_void
+    }
+}
+
+struct MockTag {
+    key: CString,
+    value: CString,
+}
+
+enum Source {
+    // everything was decoded when the file was opened
+    Memory,
+    Custom {
+        io: *mut GrooveCustomIo,
+        // byte offset of the first frame in the stream
+        data_offset: u64,
+        pending: Vec<u8>,
+        data_remaining: Option<u64>,
+    },
+}
+
+struct FileState {
+    source: Source,
+    path: Option<PathBuf>,
+    codec: Codec,
+    channels: usize,
+    sample_rate: u32,
+    channel_layout: u64,
+    // decoded so far, interleaved, starting from `first_frame`
+    samples: Vec<f64>,
+    // where the stream was last seeked to
+    first_frame: u64,
+    // frames in the whole file, when known
+    total_frames: Option<u64>,
+    complete: bool,
+    read_error_after: Option<u64>,
+    // boxed, since tags are handed out by address
+    #[allow(clippy::vec_box)]
+    metadata: Vec<Box<MockTag>>,
+    // set for containers the mock only pretends to decode
+    placeholder: Option<Placeholder>,
+    stream_index: usize,
+}
+
+// a container the mock recognizes, but whose codecs it can't decode. it
+// plays a constant level in the format of the chosen stream, 0.1 for the
+// container's first stream, 0.2 for the second and so on, so that which is
+// playing can be told apart.
+struct Placeholder {
+    // libav's short and long names for the codec
+    codec_names: (&'static str, &'static str),
+    bits_per_raw_sample: Option<u32>,
+    // the audio streams, with their indexes among all the streams
+    streams: Vec<PlaceholderStream>,
+    stream_count: usize,
+    seconds: f64,
+    // the file as it was opened, which saving writes back the way libav
+    // remuxes from what it has open
+    bytes: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+struct PlaceholderStream {
+    index: usize,
+    sample_rate: u32,
+    channels: usize,
+}
+
+impl FileState {
+    // switch a placeholder container to another of its streams
+    fn select_stream(&mut self, index: usize) -> bool {
+        let (stream, seconds) = match self.placeholder {
+            Some(ref placeholder) => match placeholder.streams.iter().find(|stream| stream.index == index) {
+                Some(&stream) => (stream, placeholder.seconds),
+                None => return false,
+            },
+            None => return index == self.stream_index,
+        };
+        let frames = (seconds * f64::from(stream.sample_rate)).round() as u64;
+        self.channels = stream.channels;
+        self.sample_rate = stream.sample_rate;
+        self.channel_layout = default_layout(stream.channels as c_int);
+        self.samples = vec![0.1 * (index + 1) as f64; frames as usize * stream.channels];
+        self.first_frame = 0;
+        self.total_frames = Some(frames);
+        self.stream_index = index;
+        true
+    }
+
+    // the end of what has been decoded so far
+    fn frames(&self) -> u64 {
+        self.first_frame + (self.samples.len() / self.channels) as u64
+    }
+
+    fn frame(&self, index: u64) -> &[f64] {
+        let start = (index - self.first_frame) as usize * self.channels;
+        &self.samples[start..start + self.channels]
+    }
+
+    // move a custom stream to `frame` through its seek callback, the way
+    // libav would. returns false if the stream can't seek, in which case
+    // only what was decoded already can be played again.
+    unsafe fn seek(&mut self, frame: u64) -> bool {
+        let frame_len = (self.codec.bytes() * self.channels) as u64;
+        let (io, data_offset) = match self.source {
+            Source::Custom { io, data_offset, .. } => (io, data_offset),
+            Source::Memory => return true,
+        };
+        let offset = data_offset + frame * frame_len;
+        if ((*io).seek)(io, offset as i64, libc::SEEK_SET) != offset as i64 {
+            return false;
+        }
+        if let Source::Custom { ref mut pending, ref mut data_remaining, .. } = self.source {
+            pending.clear();
+            *data_remaining = self.total_frames.map(|total| total.saturating_sub(frame) * frame_len);
+        }
+        self.samples.clear();
+        self.first_frame = frame;
+        self.complete = false;
+        true
+    }
+
+    // decode until at least `frames` frames are available or the file
+    // ends, and return how many can be played
+    unsafe fn ensure(&mut self, frames: u64) -> u64 {
+        while self.frames() < frames && !self.complete {
+            self.read_more();
+        }
+        let available = self.frames();
+        self.read_error_after.map_or(available, |after| available.min(after))
+    }
+
+    unsafe fn read_more(&mut self) {
+        let frame_len = self.codec.bytes() * self.channels;
+        let (io, pending, data_remaining) = match self.source {
+            Source::Custom { io, ref mut pending, ref mut data_remaining, .. } => (io, pending, data_remaining),
+            Source::Memory => {
+                self.complete = true;
+                return;
+            },
+        };
+        if *data_remaining == Some(0) {
+            self.complete = true;
+            return;
+        }
+        let mut buf = vec![0u8; 4096];
+        let count = ((*io).read_packet)(io, buf.as_mut_ptr(), buf.len() as c_int);
+        if count <= 0 {
+            self.complete = true;
+            return;
+        }
+        let mut bytes = &buf[..count as usize];
+        if let Some(ref mut remaining) = *data_remaining {
+            let take = (*remaining).min(bytes.len() as u64);
+            *remaining -= take;
+            bytes = &bytes[..take as usize];
+        }
+        pending.extend_from_slice(bytes);
+        let whole = pending.len() / frame_len * frame_len;
+        for sample in pending[..whole].chunks(self.codec.bytes()) {
+            self.samples.push(self.codec.decode(sample));
+        }
+        pending.drain(..whole);
+    }
+
+    fn find_tag(&self, key: &str, after: *const c_void, match_case: bool) -> *mut c_void {
+        let start = if after.is_null() {
+            0
+        } else {
+            match self.metadata.iter().position(|tag| &**tag as *const MockTag as *const c_void == after) {
+                Some(index) => index + 1,
+                None => return ptr::null_mut(),
+            }
+        };
+        self.metadata[start..].iter()
+            .find(|tag| {
+                let tag_key = tag.key.to_string_lossy();
+                key.is_empty() || if match_case { tag_key == key } else { tag_key.eq_ignore_ascii_case(key) }
+            })
+            .map_or(ptr::null_mut(), |tag| &**tag as *const MockTag as *mut c_void)
+    }
+
+    fn metadata_pairs(&self) -> Vec<(String, String)> {
+        self.metadata.iter()
+            .map(|tag| (tag.key.to_string_lossy().into_owned(), tag.value.to_string_lossy().into_owned()))
+            .collect()
+    }
+}
+
+#[allow(clippy::vec_box)]
+fn set_tag(metadata: &mut Vec<Box<MockTag>>, key: &str, value: Option<&str>, match_case: bool) {
+    let index = metadata.iter().position(|tag| {
+        let tag_key = tag.key.to_string_lossy();
+        if match_case { tag_key == key } else { tag_key.eq_ignore_ascii_case(key) }
+    });
+    match (index, value) {
+        (Some(index), Some(value)) => metadata[index].value = CString::new(value).unwrap(),
+        (Some(index), None) => {
+            metadata.remove(index);
+        },
+        (None, Some(value)) => metadata.push(Box::new(MockTag {
+            key: CString::new(key).unwrap(),
+            value: CString::new(value).unwrap(),
+        })),
+        (None, None) => {},
+    }
+}
+
+fn new_file(filename: &[u8], short_names: &str, state: FileState) -> *mut GrooveFile {
+    let read_error_after = take_fault(|fault| match *fault {
+        Fault::ReadError { after_frames } => Some(after_frames),
+        _ => None,
+    });
+    let mut file = Box::new(FilePrivate {
+        public: GrooveFile { dirty: 0, filename: ptr::null() },
+        filename: CString::new(filename).unwrap(),
+        short_names: CString::new(short_names).unwrap(),
+        state: Mutex::new(FileState { read_error_after, ..state }),
+        log_context: Box::new(ptr::null()),
+    });
+    file.public.filename = file.filename.as_ptr();
+    *OPEN_FILES.lock().unwrap().entry(filename.to_vec()).or_insert(0) += 1;
+    *OPENINGS.lock().unwrap().entry(filename.to_vec()).or_insert(0) += 1;
+    Box::into_raw(file) as *mut GrooveFile
+}
+
+fn file_state(source: Source, path: Option<PathBuf>, header: &WavHeader) -> FileState {
+    let mut state = FileState {
+        source,
+        path,
+        codec: header.codec,
+        channels: header.channels,
+        sample_rate: header.sample_rate,
+        channel_layout: header.channel_layout,
+        samples: Vec::new(),
+        first_frame: 0,
+        total_frames: header.data_len.map(|len| len / (header.codec.bytes() * header.channels) as u64),
+        complete: false,
+        read_error_after: None,
+        metadata: Vec::new(),
+        placeholder: None,
+        stream_index: 0,
+    };
+    for (key, value) in &header.metadata {
+        set_tag(&mut state.metadata, key, Some(value), false);
+    }
+    state
+}
+
+fn open_wav(path: &Path) -> Option<FileState> {
+    let data = fs::read(path).ok()?;
+    let header = match parse_wav_header(&data) {
+        Parse::Done(header) => header,
+        _ => return None,
+    };
+    let frame_len = header.codec.bytes() * header.channels;
+    let available = data.len() - header.data_offset;
+    let data_len = header.data_len.map_or(available, |len| (len as usize).min(available));
+    let audio = &data[header.data_offset..header.data_offset + data_len / frame_len * frame_len];
+
+    let mut state = file_state(Source::Memory, Some(path.to_path_buf()), &header);
+    state.samples = audio.chunks(header.codec.bytes()).map(|sample| header.codec.decode(sample)).collect();
+    state.total_frames = Some(state.frames());
+    state.complete = true;
+    Some(state)
+}
+
+// tone:<frequency>[:<seconds>[:<sample rate>[:<channels>]]]
+fn open_tone(spec: &str) -> Option<FileState> {
+    let fields: Vec<&str> = spec.split(':').collect();
+    let frequency: f64 = fields.first()?.parse().ok()?;
+    let seconds: f64 = fields.get(1).map_or(Some(1.0), |field| field.parse().ok())?;
+    let sample_rate: u32 = fields.get(2).map_or(Some(44100), |field| field.parse().ok())?;
+    let channels: usize = fields.get(3).map_or(Some(2), |field| field.parse().ok())?;
+    if sample_rate == 0 || !(1..=8).contains(&channels) || seconds < 0.0 {
+        return None;
+    }
+
+    let frames = (seconds * f64::from(sample_rate)).round() as u64;
+    let mut samples = Vec::with_capacity(frames as usize * channels);
+    for frame in 0..frames {
+        let value = 0.5 * (2.0 * PI * frequency * frame as f64 / f64::from(sample_rate)).sin();
+        samples.extend((0..channels).map(|_| value));
+    }
+    Some(FileState {
+        source: Source::Memory,
+        path: None,
+        codec: Codec::F32,
+        channels,
+        sample_rate,
+        channel_layout: default_layout(channels as c_int),
+        samples,
+        first_frame: 0,
+        total_frames: Some(frames),
+        complete: true,
+        read_error_after: None,
+        metadata: Vec::new(),
+        placeholder: None,
+        stream_index: 0,
+    })
+}
+
+// MP4, Matroska, FLAC and MP3 files, with the short names libav gives
+// their formats
+fn open_placeholder(path: &Path) -> Option<(FileState, &'static str)> {
+    let data = fs::read(path).ok()?;
+    let (mut placeholder, short_names) = if data.get(4..8) == Some(b"ftyp") {
+        (placeholder_mp4(&data)?, "mov,mp4,m4a,3gp,3g2,mj2")
+    } else if data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        (placeholder_matroska(&data)?, "matroska,webm")
+    } else if data.starts_with(b"fLaC") {
+        (placeholder_flac(&data)?, "flac")
+    } else if data.starts_with(b"ID3") || (data.len() >= 2 && data[0] == 0xff && data[1] & 0xe0 == 0xe0) {
+        (placeholder_mp3(&data), "mp3")
+    } else {
+        return None;
+    };
+    // the stream libav would pick
+    let first = placeholder.streams.first()?.index;
+    placeholder.bytes = data;
+    let mut state = FileState {
+        source: Source::Memory,
+        path: Some(path.to_path_buf()),
+        codec: if placeholder.bits_per_raw_sample.is_some() { Codec::S16 } else { Codec::F32 },
+        channels: 0,
+        sample_rate: 0,
+        channel_layout: 0,
+        samples: Vec::new(),
+        first_frame: 0,
+        total_frames: None,
+        complete: true,
+        read_error_after: None,
+        metadata: Vec::new(),
+        placeholder: Some(placeholder),
+        stream_index: 0,
+    };
+    state.select_stream(first);
+    Some((state, short_names))
+}
+
+fn placeholder_mp4(data: &[u8]) -> Option<Placeholder> {
+    let moov = mp4::find(data, &[b"moov"])?;
+    let streams = mp4::audio_streams(moov).iter()
+        .filter_map(|stream| Some(PlaceholderStream {
+            index: stream.index?,
+            sample_rate: stream.sample_rate.unwrap_or(44100),
+            channels: stream.channel_count.unwrap_or(2) as usize,
+        }))
+        .collect();
+    let seconds = mp4::find(moov, &[b"mvhd"]).and_then(|mvhd| {
+        let (timescale, duration) = if *mvhd.first()? == 1 {
+            (mp4::u32_at(mvhd, 20)?, mp4::u64_at(mvhd, 24)?)
+        } else {
+            (mp4::u32_at(mvhd, 12)?, u64::from(mp4::u32_at(mvhd, 16)?))
+        };
+        if timescale > 0 { Some(duration as f64 / f64::from(timescale)) } else { None }
+    });
+    Some(Placeholder {
+        codec_names: ("aac", "AAC (Advanced Audio Coding)"),
+        bits_per_raw_sample: None,
+        streams,
+        stream_count: mp4::Boxes::new(moov).filter(|&(kind, _)| kind == b"trak").count(),
+        seconds: seconds.unwrap_or(PLACEHOLDER_SECONDS),
+        bytes: Vec::new(),
+    })
+}
+
+fn placeholder_matroska(data: &[u8]) -> Option<Placeholder> {
+    const INFO: u32 = 0x1549A966;
+    const TIMECODE_SCALE: u32 = 0x2AD7B1;
+    const DURATION: u32 = 0x4489;
+
+    let segment = matroska::Elements::new(data).find(|&(id, _)| id == matroska::SEGMENT)?.1;
+    let tracks = matroska::child(segment, matroska::TRACKS)?;
+    let streams = matroska::audio_streams(tracks).iter()
+        .filter_map(|stream| Some(PlaceholderStream {
+            index: stream.index?,
+            sample_rate: stream.sample_rate.unwrap_or(8000),
+            channels: stream.channel_count.unwrap_or(1) as usize,
+        }))
+        .collect();
+    let seconds = matroska::child(segment, INFO).and_then(|info| {
+        let scale = matroska::child(info, TIMECODE_SCALE).map_or(1_000_000, matroska::uint);
+        matroska::child(info, DURATION).and_then(matroska::float).map(|duration| duration * scale as f64 / 1e9)
+    });
+    Some(Placeholder {
+        codec_names: ("vorbis", "Vorbis"),
+        bits_per_raw_sample: None,
+        streams,
+        stream_count: matroska::Elements::new(tracks).filter(|&(id, _)| id == matroska::TRACK_ENTRY).count(),
+        seconds: seconds.unwrap_or(PLACEHOLDER_SECONDS),
+        bytes: Vec::new(),
+    })
+}
+
+fn placeholder_flac(data: &[u8]) -> Option<Placeholder> {
+    // STREAMINFO is always the first metadata block
+    let info = data.get(8..26)?;
+    let sample_rate = (u32::from(info[10]) << 12) | (u32::from(info[11]) << 4) | (u32::from(info[12]) >> 4);
+    let channels = ((info[12] >> 1) & 0x7) as usize + 1;
+    let bits = ((u32::from(info[12]) & 0x1) << 4 | u32::from(info[13]) >> 4) + 1;
+    let frames = (u64::from(info[13]) & 0xf) << 32 | u64::from(mp4::u32_at(info, 14)?);
+    if sample_rate == 0 {
+        return None;
+    }
+    Some(Placeholder {
+        codec_names: ("flac", "FLAC (Free Lossless Audio Codec)"),
+        bits_per_raw_sample: Some(bits),
+        streams: vec![PlaceholderStream { index: 0, sample_rate, channels }],
+        stream_count: 1,
+        seconds: if frames > 0 { frames as f64 / f64::from(sample_rate) } else { PLACEHOLDER_SECONDS },
+        bytes: Vec::new(),
+    })
+}
+
+fn placeholder_mp3(data: &[u8]) -> Placeholder {
+    // MPEG-1 layer III, as far as the mock cares
+    let id3_len = if data.starts_with(b"ID3") && data.len() >= 10 {
+        10 + data[6..10].iter().fold(0, |size, &byte| (size << 7) | usize::from(byte & 0x7f))
+    } else {
+        0
+    };
+    let (sample_rate, channels) = match data.get(id3_len..id3_len + 4) {
+        Some(header) if header[0] == 0xff && header[1] & 0xe0 == 0xe0 => {
+            let sample_rate = [44100, 48000, 32000].get(usize::from((header[2] >> 2) & 0x3)).cloned();
+            (sample_rate.unwrap_or(44100), if header[3] & 0xc0 == 0xc0 { 1 } else { 2 })
+        },
+        _ => (44100, 2),
+    };
+    Placeholder {
+        codec_names: ("mp3", "MP3 (MPEG audio layer 3)"),
+        bits_per_raw_sample: None,
+        streams: vec![PlaceholderStream { index: 0, sample_rate, channels }],
+        stream_count: 1,
+        seconds: PLACEHOLDER_SECONDS,
+        bytes: Vec::new(),
+    }
+}
+
+pub unsafe extern "C" fn groove_file_open(filename: *const c_char) -> *mut GrooveFile {
+    let filename = CStr::from_ptr(filename);
+    let name = filename.to_string_lossy();
+    if take_fault(|fault| if *fault == Fault::Open { Some(()) } else { None }).is_some() {
+        log(ptr::null_mut(), AV_LOG_ERROR, &format!("unable to open {}", name));
+        return ptr::null_mut();
+    }
+    let opened = match name.strip_prefix("tone:") {
+        Some(spec) => open_tone(spec).map(|state| (state, "lavfi")),
+        None => open_wav(Path::new(&*name)).map(|state| (state, "wav"))
+            .or_else(|| open_placeholder(Path::new(&*name))),
+    };
+    match opened {
+        Some((state, short_names)) => new_file(filename.to_bytes(), short_names, state),
+        None => {
+            log(ptr::null_mut(), AV_LOG_ERROR, &format!("unable to open {}", name));
+            ptr::null_mut()
+        },
+    }
+}
+
+pub unsafe extern "C" fn groove_file_open_custom(custom_io: *mut GrooveCustomIo,
+                                                 filename_hint: *const c_char) -> *mut GrooveFile {
+    if take_fault(|fault| if *fault == Fault::Open { Some(()) } else { None }).is_some() {
+        return ptr::null_mut();
+    }
+    // read until the header is complete
+    let mut data = Vec::new();
+    let header = loop {
+        match parse_wav_header(&data) {
+            Parse::Done(header) => break header,
+            Parse::Invalid => return ptr::null_mut(),
+            Parse::NeedMore => {},
+        }
+        let mut buf = vec![0u8; 4096];
+        let count = ((*custom_io).read_packet)(custom_io, buf.as_mut_ptr(), buf.len() as c_int);
+        if count <= 0 {
+            return ptr::null_mut();
+        }
+        data.extend_from_slice(&buf[..count as usize]);
+    };
+
+    let mut pending = data.split_off(header.data_offset);
+    if let Some(len) = header.data_len {
+        pending.truncate(len as usize);
+    }
+    let data_remaining = header.data_len.map(|len| len - pending.len() as u64);
+    let source = Source::Custom {
+        io: custom_io,
+        data_offset: header.data_offset as u64,
+        pending: Vec::new(),
+        data_remaining,
+    };
+    let mut state = file_state(source, None, &header);
+    // decode what came along with the header
+    let codec = state.codec;
+    let whole = pending.len() / (codec.bytes() * state.channels) * codec.bytes() * state.channels;
+    state.samples = pending[..whole].chunks(codec.bytes()).map(|sample| codec.decode(sample)).collect();
+    if let Source::Custom { pending: ref mut rest, .. } = state.source {
+        rest.extend_from_slice(&pending[whole..]);
+    }
+    new_file(CStr::from_ptr(filename_hint).to_bytes(), "wav", state)
+}
+
+pub unsafe extern "C" fn groove_file_close(file: *mut GrooveFile) {
+    let file = Box::from_raw(file as *mut FilePrivate);
+    let mut open_files = OPEN_FILES.lock().unwrap();
+    let filename = file.filename.as_bytes();
+    if let Some(count) = open_files.get_mut(filename) {
+        *count -= 1;
+        if *count == 0 {
+            open_files.remove(filename);
+        }
+    }
+}
+
+unsafe fn file_private<'a>(file: *mut GrooveFile) -> &'a FilePrivate {
+    &*(file as *const FilePrivate)
+}
+
+pub unsafe extern "C" fn groove_file_duration(file: *mut GrooveFile) -> c_double {
+    let state = file_private(file).state.lock().unwrap();
+    state.total_frames.map_or(0.0, |frames| frames as f64 / f64::from(state.sample_rate))
+}
+
+pub unsafe extern "C" fn groove_file_metadata_get(file: *mut GrooveFile, key: *const c_char,
+                                                  prev: *const c_void, flags: c_int) -> *mut c_void {
+    let state = file_private(file).state.lock().unwrap();
+    state.find_tag(&CStr::from_ptr(key).to_string_lossy(), prev, flags & TAG_MATCH_CASE != 0)
+}
+
+pub unsafe extern "C" fn groove_file_metadata_set(file: *mut GrooveFile, key: *const c_char,
+                                                  value: *const c_char, flags: c_int) -> c_int {
+    if let Some(code) = take_fault(|fault| match *fault { Fault::MetadataSet(code) => Some(code), _ => None }) {
+        return code;
+    }
+    let value = if value.is_null() { None } else { Some(CStr::from_ptr(value).to_string_lossy()) };
+    let mut state = file_private(file).state.lock().unwrap();
+    set_tag(&mut state.metadata, &CStr::from_ptr(key).to_string_lossy(), value.as_deref(),
+            flags & TAG_MATCH_CASE != 0);
+    (*file).dirty = 1;
+    0
+}
+
+pub unsafe extern "C" fn groove_file_save(file: *mut GrooveFile) -> c_int {
+    if let Some(code) = take_fault(|fault| match *fault { Fault::Save(code) => Some(code), _ => None }) {
+        return code;
+    }
+    let state = file_private(file).state.lock().unwrap();
+    let path = match state.path {
+        Some(ref path) => path.clone(),
+        None => return EINVAL,
+    };
+    let saved = match state.placeholder {
+        Some(ref placeholder) => replace_file(&path, &placeholder.bytes),
+        None => save_wav(&path, &state.metadata_pairs()),
+    };
+    if saved.is_none() {
+        return EINVAL;
+    }
+    (*file).dirty = 0;
+    0
+}
+
+// rewrite a WAV file with new LIST INFO metadata
+fn save_wav(path: &Path, metadata: &[(String, String)]) -> Option<()> {
+    let data = fs::read(path).ok()?;
+    let header = match parse_wav_header(&data) {
+        Parse::Done(header) => header,
+        _ => return None,
+    };
+    let audio_len = header.data_len.map_or(data.len() - header.data_offset, |len| len as usize);
+    let audio = data.get(header.data_offset..header.data_offset + audio_len)?;
+
+    let mut body = b"WAVE".to_vec();
+    body.extend_from_slice(b"fmt ");
+    body.extend_from_slice(&(header.fmt_chunk.len() as u32).to_le_bytes());
+    body.extend_from_slice(&header.fmt_chunk);
+    body.extend_from_slice(&info_chunk(metadata));
+    body.extend_from_slice(b"data");
+    body.extend_from_slice(&(audio.len() as u32).to_le_bytes());
+    body.extend_from_slice(audio);
+    if audio.len() % 2 == 1 {
+        body.push(0);
+    }
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    replace_file(path, &out)
+}
+
+// write a file next to `path` and rename it over it, as libav saves
+fn replace_file(path: &Path, bytes: &[u8]) -> Option<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".mock-save");
+    fs::File::create(&temp_path).and_then(|mut file| file.write_all(bytes)).ok()?;
+    fs::rename(&temp_path, path).ok()
+}
+
+pub unsafe extern "C" fn groove_file_short_names(file: *mut GrooveFile) -> *const c_char {
+    file_private(file).short_names.as_ptr()
+}
+
+pub unsafe fn file_codec_info(file: *mut GrooveFile) -> CodecInfo {
+    let state = file_private(file).state.lock().unwrap();
+    if let Some(ref placeholder) = state.placeholder {
+        return CodecInfo {
+            name: Some(placeholder.codec_names.0.to_string()),
+            long_name: Some(placeholder.codec_names.1.to_string()),
+            stream_count: placeholder.stream_count,
+            stream_index: Some(state.stream_index),
+            bit_rate: None,
+            bits_per_raw_sample: placeholder.bits_per_raw_sample,
+        };
+    }
+    let (name, long_name) = state.codec.names();
+    let bits = state.codec.bytes() as u32 * 8;
+    CodecInfo {
+        name: Some(name.to_string()),
+        long_name: Some(long_name.to_string()),
+        stream_count: 1,
+        stream_index: Some(0),
+        bit_rate: Some(u64::from(state.sample_rate) * state.channels as u64 * u64::from(bits)),
+        bits_per_raw_sample: Some(bits),
+    }
+}
+
+pub unsafe fn file_select_audio_stream(file: *mut GrooveFile, index: usize) -> c_int {
+    if file_private(file).state.lock().unwrap().select_stream(index) { 0 } else { AVERROR_STREAM_NOT_FOUND }
+}
+
+pub unsafe extern "C" fn groove_file_audio_format(file: *mut GrooveFile, audio_format: *mut GrooveAudioFormat) {
+    let state = file_private(file).state.lock().unwrap();
+    *audio_format = GrooveAudioFormat {
+        sample_rate: state.sample_rate as c_int,
+        channel_layout: state.channel_layout,
+        sample_fmt: state.codec.sample_fmt(),
+    };
+}
+
+pub unsafe extern "C" fn groove_tag_key(tag: *mut c_void) -> *const c_char {
+    (*(tag as *const MockTag)).key.as_ptr()
+}
+
+pub unsafe extern "C" fn groove_tag_value(tag: *mut c_void) -> *const c_char {
+    (*(tag as *const MockTag)).value.as_ptr()
+}
+
+// ---------------------------------------------------------------------------
+// buffers
+
+#[repr(C)]
+struct BufferPrivate {
+    public: GrooveBuffer,
+    // u64 backing keeps every sample type aligned
+    planes: Vec<Vec<u64>>,
+    plane_ptrs: Vec<*mut u8>,
+}
+
+fn new_buffer(planes: Vec<Vec<u8>>, format: GrooveAudioFormat, frame_count: usize,
+              item: *mut GroovePlaylistItem, pos: f64) -> *mut GrooveBuffer {
+    let size = planes.iter().map(|plane| plane.len()).sum::<usize>();
+    let mut planes: Vec<Vec<u64>> = planes.iter()
+        .map(|plane| {
+            let mut words = vec![0u64; plane.len().div_ceil(8)];
+            unsafe { ptr::copy_nonoverlapping(plane.as_ptr(), words.as_mut_ptr() as *mut u8, plane.len()) };
+            words
+        })
+        .collect();
+    let plane_ptrs = planes.iter_mut().map(|plane| plane.as_mut_ptr() as *mut u8).collect();
+    let mut buffer = Box::new(BufferPrivate {
+        public: GrooveBuffer {
+            data: ptr::null_mut(),
+            format,
+            frame_count: frame_count as c_int,
+            item,
+            pos,
+            size: size as c_int,
+            pts: 0,
+        },
+        planes,
+        plane_ptrs,
+    });
+    buffer.public.data = buffer.plane_ptrs.as_mut_ptr();
+    Box::into_raw(buffer) as *mut GrooveBuffer
+}
+
+pub unsafe extern "C" fn groove_buffer_unref(buffer: *mut GrooveBuffer) {
+    drop(Box::from_raw(buffer as *mut BufferPrivate));
+}
+
+enum Entry {
+    Buffer(*mut GrooveBuffer),
+    End,
+}
+
+fn free_entries(queue: &mut VecDeque<Entry>) {
+    for entry in queue.drain(..) {
+        if let Entry::Buffer(buffer) = entry {
+            unsafe { groove_buffer_unref(buffer) }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// converting decoded audio for a sink or encoder
+
+// a run of decoded frames, [start, end) of the item's file
+struct Chunk<'a> {
+    item: *mut GroovePlaylistItem,
+    file: &'a FileState,
+    start: u64,
+    end: u64,
+    // playlist gain times item gain
+    gain: f64,
+}
+
+impl<'a> Chunk<'a> {
+    fn pos(&self) -> f64 {
+        self.start as f64 / f64::from(self.file.sample_rate)
+    }
+}
+
+// where in the current item the next output frame comes from
+#[derive(Default)]
+struct Cursor {
+    item: usize,
+    // source frame where output frame 0 was taken
+    base: u64,
+    // output frames produced since then
+    produced: u64,
+}
+
+impl Cursor {
+    // produce the output frames whose source position falls in the chunk,
+    // in `format`, as planes of bytes
+    fn convert(&mut self, chunk: &Chunk, format: &GrooveAudioFormat, gain: f64,
+               little_endian: bool) -> (Vec<Vec<u8>>, usize) {
+        let Chunk { item, file, start, end, .. } = *chunk;
+        let gain = gain * chunk.gain;
+        if self.item != item as usize || start < self.base {
+            *self = Cursor { item: item as usize, base: start, produced: 0 };
+        }
+        let ratio = f64::from(file.sample_rate) / f64::from(format.sample_rate);
+        let out_channels = format.channel_layout.count_ones() as usize;
+        let planar = is_planar(format.sample_fmt);
+        let mut planes = vec![Vec::new(); if planar { out_channels } else { 1 }];
+        let available = file.frames();
+
+        let mut frame_count = 0;
+        loop {
+            let position = self.base as f64 + self.produced as f64 * ratio;
+            if position >= end as f64 {
+                break;
+            }
+            let index = position.floor() as u64;
+            let fraction = position - index as f64;
+            let current = file.frame(index);
+            let next = if index + 1 < available { file.frame(index + 1) } else { current };
+            for channel in 0..out_channels {
+                let bit = nth_channel(format.channel_layout, channel);
+                let a = map_channel(current, file.channel_layout, bit);
+                let b = map_channel(next, file.channel_layout, bit);
+                let value = (a + (b - a) * fraction) * gain;
+                let plane = if planar { &mut planes[channel] } else { &mut planes[0] };
+                put_sample(plane, format.sample_fmt, value, little_endian);
+            }
+            self.produced += 1;
+            frame_count += 1;
+        }
+        (planes, frame_count)
+    }
+}
+
+fn nth_channel(layout: u64, n: usize) -> u64 {
+    let mut bits = layout;
+    for _ in 0..n {
+        bits &= bits - 1;
+    }
+    bits & bits.wrapping_neg()
+}
+
+// the value of the channel `bit` from a frame laid out as `layout`,
+// downmixing or upmixing between mono and stereo
+fn map_channel(frame: &[f64], layout: u64, bit: u64) -> f64 {
+    let channel_of = |bit: u64| {
+        if layout & bit == 0 {
+            None
+        } else {
+            Some((layout & (bit - 1)).count_ones() as usize)
+        }
+    };
+    if let Some(index) = channel_of(bit) {
+        return frame[index];
+    }
+    match (bit, channel_of(CH_FRONT_LEFT), channel_of(CH_FRONT_RIGHT), channel_of(CH_FRONT_CENTER)) {
+        (CH_FRONT_CENTER, Some(left), Some(right), _) => (frame[left] + frame[right]) / 2.0,
+        (CH_FRONT_LEFT, _, _, Some(center)) | (CH_FRONT_RIGHT, _, _, Some(center)) => frame[center],
+        _ => 0.0,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// playlists
+
+#[repr(C)]
+struct PlaylistPrivate {
+    public: GroovePlaylist,
+    state: Mutex<PlaylistState>,
+}
+
+struct PlaylistState {
+    // the item being decoded, null at the end of the playlist
+    decode_head: *mut GroovePlaylistItem,
+    decode_pos: u64,
+    sent_end: bool,
+    // only tells the sinks: decoding carries on, as it does in libgroove
+    // until the sinks fill up
+    paused: bool,
+    sinks: Vec<*mut SinkPrivate>,
+    encoders: Vec<*mut EncoderPrivate>,
+}
+
+unsafe fn playlist_private<'a>(playlist: *mut GroovePlaylist) -> &'a PlaylistPrivate {
+    &*(playlist as *const PlaylistPrivate)
+}
+
+pub unsafe extern "C" fn groove_playlist_create() -> *mut GroovePlaylist {
+    let playlist = Box::new(PlaylistPrivate {
+        public: GroovePlaylist { head: ptr::null_mut(), tail: ptr::null_mut(), gain: 1.0 },
+        state: Mutex::new(PlaylistState {
+            decode_head: ptr::null_mut(),
+            decode_pos: 0,
+            sent_end: false,
+            paused: false,
+            sinks: Vec::new(),
+            encoders: Vec::new(),
+        }),
+    });
+    Box::into_raw(playlist) as *mut GroovePlaylist
+}
+
+pub unsafe extern "C" fn groove_playlist_insert(playlist: *mut GroovePlaylist, file: *mut GrooveFile,
+                                                gain: c_double, peak: c_double,
+                                                next: *mut GroovePlaylistItem) -> *mut GroovePlaylistItem {
+    let mut state = playlist_private(playlist).state.lock().unwrap();
+    let prev = if next.is_null() { (*playlist).tail } else { (*next).prev };
+    let item = Box::into_raw(Box::new(GroovePlaylistItem { file, gain, peak, prev, next }));
+    if prev.is_null() {
+        (*playlist).head = item;
+    } else {
+        (*prev).next = item;
+    }
+    if next.is_null() {
+        (*playlist).tail = item;
+    } else {
+        (*next).prev = item;
+    }
+    if state.decode_head.is_null() {
+        state.decode_head = item;
+        state.decode_pos = 0;
+        state.sent_end = false;
+    }
+    item
+}
+
+pub unsafe extern "C" fn groove_playlist_remove(playlist: *mut GroovePlaylist, item: *mut GroovePlaylistItem) {
+    let mut state = playlist_private(playlist).state.lock().unwrap();
+    let (prev, next) = ((*item).prev, (*item).next);
+    if prev.is_null() {
+        (*playlist).head = next;
+    } else {
+        (*prev).next = next;
+    }
+    if next.is_null() {
+        (*playlist).tail = prev;
+    } else {
+        (*next).prev = prev;
+    }
+    if state.decode_head == item {
+        state.advance();
+    }
+    state.for_each_queue(|queue, _| {
+        queue.retain(|entry| match *entry {
+            Entry::Buffer(buffer) if (*buffer).item == item => {
+                groove_buffer_unref(buffer);
+                false
+            },
+            _ => true,
+        });
+    });
+    for &sink in &state.sinks {
+        ((*sink).public.purge)(sink as *mut GrooveSink, item);
+    }
+    drop(Box::from_raw(item));
+}
+
+pub unsafe extern "C" fn groove_playlist_position(playlist: *mut GroovePlaylist, item: *mut *mut GroovePlaylistItem,
+                                                  seconds: *mut c_double) {
+    let state = playlist_private(playlist).state.lock().unwrap();
+    if !item.is_null() {
+        *item = state.decode_head;
+    }
+    if !seconds.is_null() {
+        *seconds = if state.decode_head.is_null() {
+            -1.0
+        } else {
+            let file = file_private((*state.decode_head).file).state.lock().unwrap();
+            state.decode_pos as f64 / f64::from(file.sample_rate)
+        };
+    }
+}
+
+pub unsafe extern "C" fn groove_playlist_clear(playlist: *mut GroovePlaylist) {
+    let mut state = playlist_private(playlist).state.lock().unwrap();
+    let mut item = (*playlist).head;
+    while !item.is_null() {
+        let next = (*item).next;
+        drop(Box::from_raw(item));
+        item = next;
+    }
+    (*playlist).head = ptr::null_mut();
+    (*playlist).tail = ptr::null_mut();
+    state.decode_head = ptr::null_mut();
+    state.decode_pos = 0;
+    // nothing queued may refer to the items any more
+    state.for_each_queue(|queue, _| {
+        queue.retain(|entry| matches!(*entry, Entry::End));
+    });
+}
+
+pub unsafe extern "C" fn groove_playlist_destroy(playlist: *mut GroovePlaylist) {
+    groove_playlist_clear(playlist);
+    drop(Box::from_raw(playlist as *mut PlaylistPrivate));
+}
+
+pub unsafe extern "C" fn groove_playlist_set_fill_mode(_playlist: *mut GroovePlaylist, _mode: c_int) {
+    // the mock decodes on demand, so no sink ever fills up
+}
+
+pub unsafe extern "C" fn groove_playlist_seek(playlist: *mut GroovePlaylist, item: *mut GroovePlaylistItem,
+                                              seconds: c_double) {
+    let mut state = playlist_private(playlist).state.lock().unwrap();
+    let mut file = file_private((*item).file).state.lock().unwrap();
+    let mut frame = (seconds.max(0.0) * f64::from(file.sample_rate)).round() as u64;
+    if !file.seek(frame) {
+        frame = frame.max(file.first_frame);
+    }
+    state.decode_head = item;
+    state.decode_pos = frame;
+    state.sent_end = false;
+    drop(file);
+    state.for_each_queue(|queue, cursor| {
+        free_entries(queue);
+        *cursor = Cursor::default();
+    });
+    for &sink in &state.sinks {
+        ((*sink).public.flush)(sink as *mut GrooveSink);
+    }
+}
+
+pub unsafe extern "C" fn groove_playlist_play(playlist: *mut GroovePlaylist) {
+    set_paused(playlist, false);
+}
+
+pub unsafe extern "C" fn groove_playlist_pause(playlist: *mut GroovePlaylist) {
+    set_paused(playlist, true);
+}
+
+unsafe fn set_paused(playlist: *mut GroovePlaylist, paused: bool) {
+    let mut state = playlist_private(playlist).state.lock().unwrap();
+    if state.paused == paused {
+        return;
+    }
+    state.paused = paused;
+    for &sink in &state.sinks {
+        let callback = if paused { (*sink).public.pause } else { (*sink).public.play };
+        callback(sink as *mut GrooveSink);
+    }
+}
+
+pub unsafe extern "C" fn groove_playlist_playing(playlist: *mut GroovePlaylist) -> c_int {
+    if playlist_private(playlist).state.lock().unwrap().paused { 0 } else { 1 }
+}
+
+impl PlaylistState {
+    unsafe fn for_each_queue<F: FnMut(&mut VecDeque<Entry>, &mut Cursor)>(&self, mut f: F) {
+        for &sink in &self.sinks {
+            let mut sink_state = (*sink).state.lock().unwrap();
+            let sink_state = &mut *sink_state;
+            f(&mut sink_state.queue, &mut sink_state.cursor);
+        }
+        for &encoder in &self.encoders {
+            let mut encoder_state = (*encoder).state.lock().unwrap();
+            let encoder_state = &mut *encoder_state;
+            f(&mut encoder_state.queue, &mut encoder_state.cursor);
+        }
+    }
+
+    // move on to the next item, which libgroove always starts from the
+    // beginning, seeking its file there even if it was never read
+    unsafe fn advance(&mut self) {
+        self.decode_head = (*self.decode_head).next;
+        self.decode_pos = 0;
+        if !self.decode_head.is_null() {
+            let mut file = file_private((*self.decode_head).file).state.lock().unwrap();
+            if !file.seek(0) {
+                // nothing before the last seek can be played again
+                self.decode_pos = file.first_frame;
+            }
+        }
+    }
+
+    // decode the next chunk for every sink and encoder. returns false if
+    // there is nothing left to do.
+    unsafe fn decode_step(&mut self, playlist: *mut GroovePlaylist) -> bool {
+        loop {
+            let item = self.decode_head;
+            if item.is_null() {
+                if self.sent_end {
+                    return false;
+                }
+                self.sent_end = true;
+                for &sink in &self.sinks {
+                    (*sink).state.lock().unwrap().queue.push_back(Entry::End);
+                }
+                for &encoder in &self.encoders {
+                    let mut encoder_state = (*encoder).state.lock().unwrap();
+                    encoder_state.queue.push_back(Entry::End);
+                    encoder_state.header_pending = true;
+                }
+                return true;
+            }
+
+            let mut file = file_private((*item).file).state.lock().unwrap();
+            let start = self.decode_pos;
+            let end = file.ensure(start + CHUNK_FRAMES).min(start + CHUNK_FRAMES);
+            if end <= start {
+                if file.read_error_after.is_some_and(|after| start >= after) {
+                    log(file_private((*item).file).log_context(), AV_LOG_ERROR, "error reading frames");
+                }
+                drop(file);
+                self.advance();
+                continue;
+            }
+            let chunk = Chunk { item, file: &file, start, end, gain: (*playlist).gain * (*item).gain };
+            for &sink in &self.sinks {
+                (*sink).push(&chunk);
+            }
+            for &encoder in &self.encoders {
+                (*encoder).push(&chunk);
+            }
+            self.decode_pos = end;
+            return true;
+        }
+    }
+}
+
+// pull the next entry from a sink or encoder queue, decoding more when
+// it is empty
+unsafe fn buffer_get<F: Fn() -> Option<Entry>>(playlist: *mut GroovePlaylist, pop: F,
+                                               buffer: *mut *mut GrooveBuffer, block: c_int) -> c_int {
+    loop {
+        match pop() {
+            Some(Entry::Buffer(popped)) => {
+                *buffer = popped;
+                return BUFFER_YES;
+            },
+            Some(Entry::End) => {
+                *buffer = ptr::null_mut();
+                return BUFFER_END;
+            },
+            None => {},
+        }
+        let decoded = !playlist.is_null() &&
+            playlist_private(playlist).state.lock().unwrap().decode_step(playlist);
+        if !decoded {
+            *buffer = ptr::null_mut();
+            // libgroove would block forever here; end instead, so that a
+            // test which reads too far fails rather than hangs
+            return if block != 0 { BUFFER_END } else { BUFFER_NO };
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// sinks
+
+#[repr(C)]
+struct SinkPrivate {
+    public: GrooveSink,
+    state: Mutex<SinkState>,
+}
+
+struct SinkState {
+    queue: VecDeque<Entry>,
+    cursor: Cursor,
+}
+
+extern "C" fn ignore_sink(_sink: *mut GrooveSink) {}
+extern "C" fn ignore_purge(_sink: *mut GrooveSink, _item: *mut GroovePlaylistItem) {}
+
+impl SinkPrivate {
+    fn push(&self, chunk: &Chunk) {
+        let file = chunk.file;
+        let mut format = GrooveAudioFormat { ..self.public.audio_format };
+        if self.public.disable_resample != 0 {
+            format = GrooveAudioFormat {
+                sample_rate: file.sample_rate as c_int,
+                channel_layout: file.channel_layout,
+                sample_fmt: file.codec.sample_fmt(),
+            };
+        }
+        let mut state = self.state.lock().unwrap();
+        let (planes, frame_count) = state.cursor.convert(chunk, &format, self.public.gain, false);
+        if frame_count > 0 {
+            state.queue.push_back(Entry::Buffer(new_buffer(planes, format, frame_count, chunk.item, chunk.pos())));
+        }
+    }
+}
+
+pub unsafe extern "C" fn groove_sink_create() -> *mut GrooveSink {
+    let sink = Box::new(SinkPrivate {
+        public: GrooveSink {
+            audio_format: GrooveAudioFormat {
+                sample_rate: 44100,
+                channel_layout: CH_LAYOUT_STEREO,
+                sample_fmt: SAMPLE_FMT_S16,
+            },
+            disable_resample: 0,
+            buffer_sample_count: 0,
+            buffer_size: 8192,
+            gain: 1.0,
+            userdata: ptr::null_mut(),
+            flush: ignore_sink,
+            purge: ignore_purge,
+            pause: ignore_sink,
+            play: ignore_sink,
+            playlist: ptr::null_mut(),
+            bytes_per_sec: 0,
+        },
+        state: Mutex::new(SinkState { queue: VecDeque::new(), cursor: Cursor::default() }),
+    });
+    Box::into_raw(sink) as *mut GrooveSink
+}
+
+pub unsafe extern "C" fn groove_sink_destroy(sink: *mut GrooveSink) {
+    let sink = Box::from_raw(sink as *mut SinkPrivate);
+    free_entries(&mut sink.state.lock().unwrap().queue);
+}
+
+pub unsafe extern "C" fn groove_sink_attach(sink: *mut GrooveSink, playlist: *mut GroovePlaylist) -> c_int {
+    if let Some(code) = take_fault(|fault| match *fault { Fault::Attach(code) => Some(code), _ => None }) {
+        return code;
+    }
+    let format = &(*sink).audio_format;
+    if format.sample_rate <= 0 || format.channel_layout == 0 || bytes_per_sample(format.sample_fmt) == 0 {
+        return EINVAL;
+    }
+    (*sink).bytes_per_sec = format.sample_rate * format.channel_layout.count_ones() as c_int *
+        bytes_per_sample(format.sample_fmt) as c_int;
+    playlist_private(playlist).state.lock().unwrap().sinks.push(sink as *mut SinkPrivate);
+    (*sink).playlist = playlist;
+    0
+}
+
+pub unsafe extern "C" fn groove_sink_detach(sink: *mut GrooveSink) -> c_int {
+    let playlist = (*sink).playlist;
+    if playlist.is_null() {
+        return EINVAL;
+    }
+    playlist_private(playlist).state.lock().unwrap().sinks.retain(|&attached| attached != sink as *mut SinkPrivate);
+    let sink_private = &*(sink as *const SinkPrivate);
+    let mut state = sink_private.state.lock().unwrap();
+    free_entries(&mut state.queue);
+    state.cursor = Cursor::default();
+    (*sink).playlist = ptr::null_mut();
+    0
+}
+
+pub unsafe extern "C" fn groove_sink_buffer_get(sink: *mut GrooveSink, buffer: *mut *mut GrooveBuffer,
+                                                block: c_int) -> c_int {
+    let sink_private = &*(sink as *const SinkPrivate);
+    buffer_get((*sink).playlist, || sink_private.state.lock().unwrap().queue.pop_front(), buffer, block)
+}
+
+// ---------------------------------------------------------------------------
+// encoders
+
+#[repr(C)]
+struct EncoderPrivate {
+    public: GrooveEncoder,
+    state: Mutex<EncoderState>,
+}
+
+struct EncoderState {
+    queue: VecDeque<Entry>,
+    cursor: Cursor,
+    #[allow(clippy::vec_box)]
+    metadata: Vec<Box<MockTag>>,
+    // a header is due before the next audio
+    header_pending: bool,
+}
+
+impl EncoderPrivate {
+    fn push(&self, chunk: &Chunk) {
+        let format = GrooveAudioFormat { ..self.public.actual_audio_format };
+        let mut state = self.state.lock().unwrap();
+        if state.header_pending {
+            state.header_pending = false;
+            let mut header = wav::streaming_header(AudioFormat::from_groove(&format));
+            let data_chunk = header.split_off(header.len() - 8);
+            let metadata: Vec<(String, String)> = state.metadata.iter()
+                .map(|tag| (tag.key.to_string_lossy().into_owned(), tag.value.to_string_lossy().into_owned()))
+                .collect();
+            header.extend_from_slice(&info_chunk(&metadata));
+            header.extend_from_slice(&data_chunk);
+            let header_format = GrooveAudioFormat { ..format };
+            state.queue.push_back(Entry::Buffer(new_buffer(vec![header], header_format, 0, ptr::null_mut(), 0.0)));
+        }
+        let (planes, frame_count) = state.cursor.convert(chunk, &format, self.public.gain, true);
+        if frame_count > 0 {
+            state.queue.push_back(Entry::Buffer(new_buffer(planes, format, 0, chunk.item, chunk.pos())));
+        }
+    }
+}
+
+pub unsafe extern "C" fn groove_encoder_create() -> *mut GrooveEncoder {
+    let format = GrooveAudioFormat {
+        sample_rate: 44100,
+        channel_layout: CH_LAYOUT_STEREO,
+        sample_fmt: SAMPLE_FMT_S16,
+    };
+    let encoder = Box::new(EncoderPrivate {
+        public: GrooveEncoder {
+            target_audio_format: GrooveAudioFormat { ..format },
+            bit_rate: 256000,
+            format_short_name: ptr::null(),
+            codec_short_name: ptr::null(),
+            filename: ptr::null(),
+            mime_type: ptr::null(),
+            sink_buffer_size: 8192,
+            encoded_buffer_size: 16384,
+            gain: 1.0,
+            playlist: ptr::null_mut(),
+            actual_audio_format: format,
+        },
+        state: Mutex::new(EncoderState {
+            queue: VecDeque::new(),
+            cursor: Cursor::default(),
+            metadata: Vec::new(),
+            header_pending: true,
+        }),
+    });
+    Box::into_raw(encoder) as *mut GrooveEncoder
+}
+
+pub unsafe extern "C" fn groove_encoder_destroy(encoder: *mut GrooveEncoder) {
+    let encoder = Box::from_raw(encoder as *mut EncoderPrivate);
+    free_entries(&mut encoder.state.lock().unwrap().queue);
+}
+
+pub unsafe extern "C" fn groove_encoder_metadata_set(encoder: *mut GrooveEncoder, key: *const c_char,
+                                                     value: *const c_char, flags: c_int) -> c_int {
+    if let Some(code) = take_fault(|fault| match *fault { Fault::MetadataSet(code) => Some(code), _ => None }) {
+        return code;
+    }
+    let value = if value.is_null() { None } else { Some(CStr::from_ptr(value).to_string_lossy()) };
+    let encoder_private = &*(encoder as *const EncoderPrivate);
+    let mut state = encoder_private.state.lock().unwrap();
+    set_tag(&mut state.metadata, &CStr::from_ptr(key).to_string_lossy(), value.as_deref(),
+            flags & TAG_MATCH_CASE != 0);
+    0
+}
+
+unsafe fn optional_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() { None } else { CStr::from_ptr(string).to_str().ok() }
+}
+
+pub unsafe extern "C" fn groove_encoder_attach(encoder: *mut GrooveEncoder, playlist: *mut GroovePlaylist) -> c_int {
+    if let Some(code) = take_fault(|fault| match *fault { Fault::Attach(code) => Some(code), _ => None }) {
+        return code;
+    }
+    // the mock can only write WAV
+    let format_ok = match optional_str((*encoder).format_short_name) {
+        Some(name) => name == "wav",
+        None => optional_str((*encoder).filename)
+            .is_none_or(|filename| filename.to_ascii_lowercase().ends_with(".wav")),
+    };
+    if !format_ok {
+        return EINVAL;
+    }
+    let target = GrooveAudioFormat { ..(*encoder).target_audio_format };
+    let sample_fmt = match optional_str((*encoder).codec_short_name) {
+        None => packed(target.sample_fmt),
+        Some("pcm_u8") => SAMPLE_FMT_U8,
+        Some("pcm_s16le") => SAMPLE_FMT_S16,
+        Some("pcm_s32le") => SAMPLE_FMT_S32,
+        Some("pcm_f32le") => SAMPLE_FMT_FLT,
+        Some("pcm_f64le") => SAMPLE_FMT_DBL,
+        Some(_) => return EINVAL,
+    };
+    if target.sample_rate <= 0 || target.channel_layout == 0 || bytes_per_sample(sample_fmt) == 0 {
+        return EINVAL;
+    }
+    (*encoder).actual_audio_format = GrooveAudioFormat { sample_fmt, ..target };
+
+    let encoder_private = &*(encoder as *const EncoderPrivate);
+    encoder_private.state.lock().unwrap().header_pending = true;
+    playlist_private(playlist).state.lock().unwrap().encoders.push(encoder as *mut EncoderPrivate);
+    (*encoder).playlist = playlist;
+    0
+}
+
+pub unsafe extern "C" fn groove_encoder_detach(encoder: *mut GrooveEncoder) -> c_int {
+    let playlist = (*encoder).playlist;
+    if playlist.is_null() {
+        return EINVAL;
+    }
+    playlist_private(playlist).state.lock().unwrap().encoders
+        .retain(|&attached| attached != encoder as *mut EncoderPrivate);
+    let encoder_private = &*(encoder as *const EncoderPrivate);
+    let mut state = encoder_private.state.lock().unwrap();
+    free_entries(&mut state.queue);
+    state.cursor = Cursor::default();
+    (*encoder).playlist = ptr::null_mut();
+    0
+}
+
+pub unsafe extern "C" fn groove_encoder_buffer_get(encoder: *mut GrooveEncoder, buffer: *mut *mut GrooveBuffer,
+                                                   block: c_int) -> c_int {
+    let encoder_private = &*(encoder as *const EncoderPrivate);
+    buffer_get((*encoder).playlist, || encoder_private.state.lock().unwrap().queue.pop_front(), buffer, block)
+}
+
+// ---------------------------------------------------------------------------
+// libavutil logging
+
+const AV_LOG_ERROR: c_int = 16;
+
+#[cfg(feature = "log")]
+type LogCallback = unsafe extern "C" fn(*mut c_void, c_int, *const c_char, *mut c_void);
+
+#[cfg(feature = "log")]
+static LOG_CALLBACK: Mutex<Option<LogCallback>> = Mutex::new(None);
+
+// log a whole line about `context`, as a format string with nothing to
+// format, which av_log_format_line below hands back as it is
+#[cfg(feature = "log")]
+unsafe fn log(context: *mut c_void, level: c_int, message: &str) {
+    let callback = *LOG_CALLBACK.lock().unwrap();
+    if let Some(callback) = callback {
+        let line = CString::new(format!("{}\n", message)).unwrap();
+        callback(context, level, line.as_ptr(), ptr::null_mut());
+    }
+}
+
+#[cfg(not(feature = "log"))]
+unsafe fn log(_context: *mut c_void, _level: c_int, _message: &str) {}
+
+#[cfg(feature = "log")]
+pub unsafe fn file_log_contexts(file: *mut GrooveFile) -> Vec<*mut c_void> {
+    vec![file_private(file).log_context()]
+}
+
+#[cfg(feature = "log")]
+pub unsafe extern "C" fn av_log_set_callback(callback: Option<LogCallback>) {
+    *LOG_CALLBACK.lock().unwrap() = callback;
+}
+
+#[cfg(feature = "log")]
+pub unsafe extern "C" fn av_log_get_level() -> c_int {
+    LOG_LEVEL.load(Ordering::SeqCst)
+}
+
+#[cfg(feature = "log")]
+pub unsafe extern "C" fn av_log_format_line(_avcl: *mut c_void, _level: c_int, fmt: *const c_char,
+                                            _vl: *mut c_void, line: *mut c_char, line_size: c_int,
+                                            _print_prefix: *mut c_int) {
+    if line_size <= 0 {
+        return;
+    }
+    let fmt = CStr::from_ptr(fmt).to_bytes();
+    let len = fmt.len().min(line_size as usize - 1);
+    ptr::copy_nonoverlapping(fmt.as_ptr() as *const c_char, line, len);
+    *line.add(len) = 0;
+}
@@ -0,0 +1,492 @@
+// bindings to the libgroove 4 API, checked against its 4.3 headers
+
+#[cfg(not(feature = "mock"))]
+use std::ffi::CStr;
+use std::mem;
+#[cfg(not(feature = "mock"))]
+use std::ptr;
+
+#[cfg(not(feature = "mock"))]
+use libc::c_uint;
+use libc::{c_int, c_char, c_void, c_double};
+
+#[cfg(not(feature = "mock"))]
+use super::{CodecInfo, AVERROR_STREAM_NOT_FOUND};
+
+#[repr(C)]
+pub struct GrooveSink {
+    pub audio_format: GrooveAudioFormat,
+    pub disable_resample: c_int,
+    /// If you leave this to its default of 0, frames pulled from the sink
+    /// will have sample count determined by efficiency.
+    /// If you set this to a positive number, frames pulled from the sink
+    /// will always have this number of samples.
+    pub buffer_sample_count: c_int,
+
+    /// how big the buffer queue should be, in sample frames.
+    /// groove_sink_create defaults this to 8192
+    pub buffer_size: c_int,
+
+    /// This volume adjustment only applies to this sink.
+    /// It is recommended that you leave this at 1.0 and instead adjust the
+    /// gain of the playlist.
+    /// If you want to change this value after you have already attached the
+    /// sink to the playlist, you must use groove_sink_set_gain.
+    /// float format. Defaults to 1.0
+    pub gain: c_double,
+
+    /// set to whatever you want
+    pub userdata: *mut c_void,
+    /// called when the audio queue is flushed. For example, if you seek to a
+    /// different location in the song.
+    pub flush: extern "C" fn(sink: *mut GrooveSink),
+    /// called when a playlist item is deleted. Take this opportunity to remove
+    /// all your references to the GroovePlaylistItem.
+    pub purge: extern "C" fn(sink: *mut GrooveSink, item: *mut GroovePlaylistItem),
+    /// called when the playlist is paused
+    pub pause: extern "C" fn(sink: *mut GrooveSink),
+    /// called when the playlist is played
+    pub play: extern "C" fn(sink: *mut GrooveSink),
+
+    /// read-only. set when you call groove_sink_attach. cleared when you call
+    /// groove_sink_detach
+    pub playlist: *mut GroovePlaylist,
+
+    /// read-only. automatically computed from audio_format when you call
+    /// groove_sink_attach
+    pub bytes_per_sec: c_int,
+}
+
+/// all fields read-only
+#[repr(C)]
+pub struct GrooveBuffer {
+    /// for interleaved audio, data[0] is the buffer.
+    /// for planar audio, each channel has a separate data pointer.
+    /// for encoded audio, data[0] is the encoded buffer.
+    pub data: *mut *mut u8,
+
+    pub format: GrooveAudioFormat,
+
+    /// number of audio frames described by this buffer
+    /// for encoded audio, this is unknown and set to 0.
+    pub frame_count: c_int,
+
+    /// when encoding, if item is NULL, this is a format header or trailer.
+    /// otherwise, this is encoded audio for the item specified.
+    /// when decoding, item is never NULL.
+    pub item: *mut GroovePlaylistItem,
+    pub pos: c_double,
+
+    /// total number of bytes contained in this buffer
+    pub size: c_int,
+
+    /// presentation time stamp of the buffer
+    pub pts: u64,
+}
+// Read-only structs are Sync
+unsafe impl Sync for GrooveBuffer {}
+// Promise rust that nothing points to a GrooveBuffer
+// when it destructs
+unsafe impl Send for GrooveBuffer {}
+
+/// all fields are read-only. modify with methods
+#[repr(C)]
+pub struct GroovePlaylistItem {
+    pub file: *mut GrooveFile,
+
+    pub gain: c_double,
+    pub peak: c_double,
+
+    /// A GroovePlaylist is a doubly linked list. Use these fields to
+    /// traverse the list.
+    pub prev: *mut GroovePlaylistItem,
+    pub next: *mut GroovePlaylistItem,
+}
+
+/// a GroovePlaylist keeps its sinks full.
+/// all fields are read-only. modify using methods.
+#[repr(C)]
+pub struct GroovePlaylist {
+    /// doubly linked list which is the playlist
+    pub head: *mut GroovePlaylistItem,
+    pub tail: *mut GroovePlaylistItem,
+
+    pub gain: c_double,
+}
+
+#[repr(C)]
+pub struct GrooveFile {
+    pub dirty: c_int,
+    pub filename: *const c_char,
+}
+
+unsafe impl Send for GrooveFile {}
+
+/// lets libgroove read a file through callbacks rather than from disk
+#[repr(C)]
+pub struct GrooveCustomIo {
+    /// set to whatever you want
+    pub userdata: *mut c_void,
+    /// return the number of bytes read, or a negative error code.
+    /// AVERROR_EOF at the end of the stream.
+    pub read_packet: extern "C" fn(io: *mut GrooveCustomIo, buf: *mut u8, buf_size: c_int) -> c_int,
+    pub write_packet: extern "C" fn(io: *mut GrooveCustomIo, buf: *mut u8, buf_size: c_int) -> c_int,
+    /// whence can be AVSEEK_SIZE, in which case return the stream size, or
+    /// a negative error code if unknown
+    pub seek: extern "C" fn(io: *mut GrooveCustomIo, offset: i64, whence: c_int) -> i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GrooveAudioFormat {
+    pub sample_rate: c_int,
+    pub channel_layout: u64,
+    pub sample_fmt: c_int,
+}
+
+#[repr(C)]
+pub struct GrooveEncoder {
+    pub target_audio_format: GrooveAudioFormat,
+    pub bit_rate: c_int,
+    pub format_short_name: *const c_char,
+    pub codec_short_name: *const c_char,
+    pub filename: *const c_char,
+    pub mime_type: *const c_char,
+
+    /// how big the sink buffer should be, in sample frames.
+    /// groove_encoder_create defaults this to 8192
+    pub sink_buffer_size: c_int,
+
+    /// how big the encoded audio buffer should be, in bytes
+    /// groove_encoder_create defaults this to 16384
+    pub encoded_buffer_size: c_int,
+
+    /// This volume adjustment to make to this player.
+    /// It is recommended that you leave this at 1.0 and instead adjust the
+    /// gain of the underlying playlist.
+    /// If you want to change this value after you have already attached the
+    /// sink to the playlist, you must use groove_encoder_set_gain.
+    /// float format. Defaults to 1.0
+    pub gain: c_double,
+
+    /// read-only. set when attached and cleared when detached
+    pub playlist: *mut GroovePlaylist,
+
+    pub actual_audio_format: GrooveAudioFormat,
+}
+
+/// the versions these declarations were written for. build.rs checks
+/// the same at build time.
+pub const VERSION_MAJOR: c_int = 4;
+pub const MIN_VERSION_MINOR: c_int = 3;
+
+// linked by build.rs. the mock feature provides these in mock.rs instead.
+#[cfg(not(feature = "mock"))]
+extern "C" {
+    pub fn groove_init() -> c_int;
+    pub fn groove_finish();
+    pub fn groove_set_logging(level: c_int);
+    pub fn groove_channel_layout_count(channel_layout: u64) -> c_int;
+    pub fn groove_channel_layout_default(count: c_int) -> u64;
+    pub fn groove_sample_format_bytes_per_sample(format: c_int) -> c_int;
+    pub fn groove_version_major() -> c_int;
+    pub fn groove_version_minor() -> c_int;
+    pub fn groove_version_patch() -> c_int;
+    pub fn groove_version() -> *const c_char;
+
+    pub fn groove_file_open(filename: *const c_char) -> *mut GrooveFile;
+    pub fn groove_file_open_custom(custom_io: *mut GrooveCustomIo,
+                                   filename_hint: *const c_char) -> *mut GrooveFile;
+    pub fn groove_file_close(file: *mut GrooveFile);
+    pub fn groove_file_duration(file: *mut GrooveFile) -> c_double;
+    pub fn groove_file_metadata_get(file: *mut GrooveFile, key: *const c_char,
+                                prev: *const c_void, flags: c_int) -> *mut c_void;
+    pub fn groove_file_metadata_set(file: *mut GrooveFile, key: *const c_char,
+                                value: *const c_char, flags: c_int) -> c_int;
+    pub fn groove_file_save(file: *mut GrooveFile) -> c_int;
+    pub fn groove_file_short_names(file: *mut GrooveFile) -> *const c_char;
+    pub fn groove_file_audio_format(file: *mut GrooveFile, audio_format: *mut GrooveAudioFormat);
+
+    pub fn groove_tag_key(tag: *mut c_void) -> *const c_char;
+    pub fn groove_tag_value(tag: *mut c_void) -> *const c_char;
+
+    pub fn groove_playlist_create() -> *mut GroovePlaylist;
+    pub fn groove_playlist_insert(playlist: *mut GroovePlaylist, file: *mut GrooveFile,
+                              gain: c_double, peak: c_double,
+                              next: *mut GroovePlaylistItem) -> *mut GroovePlaylistItem;
+    pub fn groove_playlist_destroy(playlist: *mut GroovePlaylist);
+    pub fn groove_playlist_remove(playlist: *mut GroovePlaylist, item: *mut GroovePlaylistItem);
+    pub fn groove_playlist_position(playlist: *mut GroovePlaylist, item: *mut *mut GroovePlaylistItem,
+                                seconds: *mut c_double);
+    pub fn groove_playlist_clear(playlist: *mut GroovePlaylist);
+    pub fn groove_playlist_set_fill_mode(playlist: *mut GroovePlaylist, mode: c_int);
+    pub fn groove_playlist_seek(playlist: *mut GroovePlaylist, item: *mut GroovePlaylistItem,
+                            seconds: c_double);
+    pub fn groove_playlist_play(playlist: *mut GroovePlaylist);
+    pub fn groove_playlist_pause(playlist: *mut GroovePlaylist);
+    pub fn groove_playlist_playing(playlist: *mut GroovePlaylist) -> c_int;
+
+    pub fn groove_encoder_create() -> *mut GrooveEncoder;
+    pub fn groove_encoder_destroy(encoder: *mut GrooveEncoder);
+    pub fn groove_encoder_metadata_set(encoder: *mut GrooveEncoder, key: *const c_char,
+                                   value: *const c_char, flags: c_int) -> c_int;
+    pub fn groove_encoder_attach(encoder: *mut GrooveEncoder, playlist: *mut GroovePlaylist) -> c_int;
+    pub fn groove_encoder_detach(encoder: *mut GrooveEncoder) -> c_int;
+    pub fn groove_encoder_buffer_get(encoder: *mut GrooveEncoder, buffer: *mut *mut GrooveBuffer,
+                                 block: c_int) -> c_int;
+
+    pub fn groove_buffer_unref(buffer: *mut GrooveBuffer);
+
+    pub fn groove_sink_create() -> *mut GrooveSink;
+    pub fn groove_sink_destroy(sink: *mut GrooveSink);
+    pub fn groove_sink_attach(sink: *mut GrooveSink, playlist: *mut GroovePlaylist) -> c_int;
+    pub fn groove_sink_detach(sink: *mut GrooveSink) -> c_int;
+    pub fn groove_sink_buffer_get(sink: *mut GrooveSink, buffer: *mut *mut GrooveBuffer,
+                              block: c_int) -> c_int;
+}
+// libgroove 4 keeps the decoder to itself, behind the public GrooveFile.
+// these are the leading fields of its GrooveFilePrivate (src/file.h) in
+// libgroove 4.3.0, and of the libav structs it points to. nothing here is
+// read unless `private_layout_known` says those are the versions loaded.
+#[cfg(not(feature = "mock"))]
+#[repr(C)]
+struct GrooveFilePrivate {
+    externals: GrooveFile,
+    audio_stream_index: c_int,
+    abort_request: c_int,
+    ic: *mut AVFormatContext,
+    decoder: *mut AVCodec,
+    audio_st: *mut AVStream,
+}
+
+#[cfg(not(feature = "mock"))]
+#[repr(C)]
+struct AVFormatContext {
+    av_class: *const c_void,
+    iformat: *const c_void,
+    oformat: *const c_void,
+    priv_data: *mut c_void,
+    pb: *mut c_void,
+    ctx_flags: c_int,
+    nb_streams: c_uint,
+    streams: *mut *mut AVStream,
+}
+
+#[cfg(not(feature = "mock"))]
+#[repr(C)]
+struct AVCodec {
+    name: *const c_char,
+    long_name: *const c_char,
+}
+
+#[cfg(not(feature = "mock"))]
+#[repr(C)]
+struct AVStream {
+    index: c_int,
+    id: c_int,
+    /// the AVCodecContext, which later fields are read from by name
+    codec: *mut c_void,
+}
+
+#[cfg(not(feature = "mock"))]
+#[link(name="avutil")]
+extern "C" {
+    fn av_opt_get_int(obj: *mut c_void, name: *const c_char, search_flags: c_int, out_val: *mut i64) -> c_int;
+}
+
+#[cfg(not(feature = "mock"))]
+#[link(name="avformat")]
+extern "C" {
+    fn avformat_version() -> c_uint;
+    fn av_find_best_stream(ic: *mut AVFormatContext, media_type: c_int, wanted_stream_nb: c_int,
+                           related_stream: c_int, decoder_ret: *mut *mut AVCodec, flags: c_int) -> c_int;
+}
+
+#[cfg(not(feature = "mock"))]
+#[link(name="avcodec")]
+extern "C" {
+    fn avcodec_version() -> c_uint;
+    fn avcodec_open2(avctx: *mut c_void, codec: *const AVCodec, options: *mut *mut c_void) -> c_int;
+    fn avcodec_close(avctx: *mut c_void) -> c_int;
+}
+
+#[cfg(not(feature = "mock"))]
+const AVMEDIA_TYPE_AUDIO: c_int = 1;
+#[cfg(not(feature = "mock"))]
+const AVDISCARD_DEFAULT: c_int = 0;
+#[cfg(not(feature = "mock"))]
+const AVDISCARD_ALL: c_int = 48;
+
+// whether the structs above match what is loaded: libgroove 4.3.0, the
+// last libgroove 4, with a libavformat and libavcodec of the same release
+// from 55 to 58. the codec context left AVStream in 59.
+#[cfg(not(feature = "mock"))]
+unsafe fn private_layout_known() -> bool {
+    let libgroove = (groove_version_major(), groove_version_minor(), groove_version_patch());
+    let libavformat = avformat_version() >> 16;
+    libgroove == (4, 3, 0) && (55..=58).contains(&libavformat) && avcodec_version() >> 16 == libavformat
+}
+
+// AVStream.discard moved when libavformat 57 dropped AVStream.pts. only
+// called once `private_layout_known`.
+#[cfg(not(feature = "mock"))]
+unsafe fn stream_discard(stream: *mut AVStream) -> *mut c_int {
+    let offset = if avformat_version() >> 16 <= 56 { 84 } else { 60 };
+    (stream as *mut u8).add(offset) as *mut c_int
+}
+
+/// make libgroove decode the audio stream at `index` among all of `file`'s
+/// streams instead of the one it picked. `file` must not be in a playlist.
+/// -ENOSYS with any libgroove or libav other than `private_layout_known`.
+#[cfg(not(feature = "mock"))]
+pub unsafe fn file_select_audio_stream(file: *mut GrooveFile, index: usize) -> c_int {
+    if !private_layout_known() {
+        return -libc::ENOSYS;
+    }
+    let private = &mut *(file as *mut GrooveFilePrivate);
+    let ic = private.ic;
+    if ic.is_null() || index >= (*ic).nb_streams as usize {
+        return AVERROR_STREAM_NOT_FOUND;
+    }
+    if private.audio_stream_index == index as c_int {
+        return 0;
+    }
+    let old_stream = private.audio_st;
+    let new_stream = *(*ic).streams.add(index);
+    // fails unless the stream is audio and has a decoder
+    let mut decoder = ptr::null_mut();
+    let found = av_find_best_stream(ic, AVMEDIA_TYPE_AUDIO, index as c_int, -1, &mut decoder, 0);
+    if found < 0 {
+        return found;
+    }
+    let err = avcodec_open2((*new_stream).codec, decoder, ptr::null_mut());
+    if err < 0 {
+        return err;
+    }
+    avcodec_close((*old_stream).codec);
+    *stream_discard(old_stream) = AVDISCARD_ALL;
+    *stream_discard(new_stream) = AVDISCARD_DEFAULT;
+    private.audio_stream_index = index as c_int;
+    private.decoder = decoder;
+    private.audio_st = new_stream;
+    0
+}
+
+/// describe the decoder libgroove opened `file` with. nothing is known
+/// with any libgroove or libav other than `private_layout_known`.
+#[cfg(not(feature = "mock"))]
+pub unsafe fn file_codec_info(file: *mut GrooveFile) -> CodecInfo {
+    if !private_layout_known() {
+        return CodecInfo::default();
+    }
+    let private = &*(file as *const GrooveFilePrivate);
+    let string = |ptr: *const c_char| if ptr.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    };
+    // an option of the codec context, if it is set to something positive
+    let option = |name: &[u8]| {
+        let codec_context = if private.audio_st.is_null() { return None } else { (*private.audio_st).codec };
+        let mut value = 0;
+        if codec_context.is_null() || av_opt_get_int(codec_context, name.as_ptr() as *const c_char, 0, &mut value) < 0 {
+            return None;
+        }
+        if value > 0 { Some(value) } else { None }
+    };
+
+    let mut info = CodecInfo {
+        stream_index: if private.audio_stream_index >= 0 { Some(private.audio_stream_index as usize) } else { None },
+        bit_rate: option(b"b\0").map(|rate| rate as u64),
+        bits_per_raw_sample: option(b"bits_per_raw_sample\0").map(|bits| bits as u32),
+        ..CodecInfo::default()
+    };
+    if !private.ic.is_null() {
+        info.stream_count = (*private.ic).nb_streams as usize;
+    }
+    if !private.decoder.is_null() {
+        info.name = string((*private.decoder).name);
+        info.long_name = string((*private.decoder).long_name);
+    }
+    info
+}
+
+/// the libav contexts which log about `file` for as long as it is open:
+/// its format and I/O contexts, and the codec contexts of its streams
+#[cfg(all(feature = "log", not(feature = "mock")))]
+pub unsafe fn file_log_contexts(file: *mut GrooveFile) -> Vec<*mut c_void> {
+    if !private_layout_known() {
+        return Vec::new();
+    }
+    let ic = (*(file as *const GrooveFilePrivate)).ic;
+    if ic.is_null() {
+        return Vec::new();
+    }
+    let mut contexts = vec![ic as *mut c_void, (*ic).pb];
+    for index in 0..(*ic).nb_streams as usize {
+        contexts.push((**(*ic).streams.add(index)).codec);
+    }
+    contexts.retain(|context| !context.is_null());
+    contexts
+}
+
+// the layouts the libgroove 4 headers give on 64-bit targets, so that
+// editing a struct above can't silently break the ABI
+#[cfg(target_pointer_width = "64")]
+const _: () = {
+    assert!(mem::size_of::<GrooveAudioFormat>() == 24);
+    assert!(mem::offset_of!(GrooveAudioFormat, channel_layout) == 8);
+    assert!(mem::offset_of!(GrooveAudioFormat, sample_fmt) == 16);
+
+    assert!(mem::size_of::<GrooveSink>() == 104);
+    assert!(mem::offset_of!(GrooveSink, disable_resample) == 24);
+    assert!(mem::offset_of!(GrooveSink, buffer_size) == 32);
+    assert!(mem::offset_of!(GrooveSink, gain) == 40);
+    assert!(mem::offset_of!(GrooveSink, userdata) == 48);
+    assert!(mem::offset_of!(GrooveSink, flush) == 56);
+    assert!(mem::offset_of!(GrooveSink, play) == 80);
+    assert!(mem::offset_of!(GrooveSink, playlist) == 88);
+    assert!(mem::offset_of!(GrooveSink, bytes_per_sec) == 96);
+
+    assert!(mem::size_of::<GrooveBuffer>() == 72);
+    assert!(mem::offset_of!(GrooveBuffer, format) == 8);
+    assert!(mem::offset_of!(GrooveBuffer, frame_count) == 32);
+    assert!(mem::offset_of!(GrooveBuffer, item) == 40);
+    assert!(mem::offset_of!(GrooveBuffer, pos) == 48);
+    assert!(mem::offset_of!(GrooveBuffer, size) == 56);
+    assert!(mem::offset_of!(GrooveBuffer, pts) == 64);
+
+    assert!(mem::size_of::<GroovePlaylistItem>() == 40);
+    assert!(mem::offset_of!(GroovePlaylistItem, gain) == 8);
+    assert!(mem::offset_of!(GroovePlaylistItem, next) == 32);
+
+    assert!(mem::size_of::<GroovePlaylist>() == 24);
+    assert!(mem::offset_of!(GroovePlaylist, gain) == 16);
+
+    assert!(mem::size_of::<GrooveFile>() == 16);
+    assert!(mem::offset_of!(GrooveFile, filename) == 8);
+
+    assert!(mem::size_of::<GrooveCustomIo>() == 32);
+    assert!(mem::offset_of!(GrooveCustomIo, seek) == 24);
+
+    assert!(mem::size_of::<GrooveEncoder>() == 112);
+    assert!(mem::offset_of!(GrooveEncoder, bit_rate) == 24);
+    assert!(mem::offset_of!(GrooveEncoder, format_short_name) == 32);
+    assert!(mem::offset_of!(GrooveEncoder, mime_type) == 56);
+    assert!(mem::offset_of!(GrooveEncoder, sink_buffer_size) == 64);
+    assert!(mem::offset_of!(GrooveEncoder, gain) == 72);
+    assert!(mem::offset_of!(GrooveEncoder, playlist) == 80);
+    assert!(mem::offset_of!(GrooveEncoder, actual_audio_format) == 88);
+};
+
+#[cfg(all(target_pointer_width = "64", not(feature = "mock")))]
+const _: () = {
+    assert!(mem::offset_of!(GrooveFilePrivate, audio_stream_index) == 16);
+    assert!(mem::offset_of!(GrooveFilePrivate, ic) == 24);
+    assert!(mem::offset_of!(GrooveFilePrivate, decoder) == 32);
+    assert!(mem::offset_of!(GrooveFilePrivate, audio_st) == 40);
+    assert!(mem::offset_of!(AVFormatContext, nb_streams) == 44);
+    assert!(mem::offset_of!(AVFormatContext, streams) == 48);
+    assert!(mem::offset_of!(AVCodec, long_name) == 8);
+    assert!(mem::offset_of!(AVStream, codec) == 8);
+};
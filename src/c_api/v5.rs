@@ -0,0 +1,244 @@
+// bindings to the libgroove 5 API, checked against the groove.h and
+// encoder.h of its 5.0 development headers, and libsoundio 1.1's
+// soundio.h, which libgroove 5 describes audio formats with.
+//
+// libgroove 5 creates everything from a `struct Groove` context, opens
+// files into a GrooveFile created beforehand, and takes and returns C bools
+// where libgroove 4 used ints. the version-neutral layer in c_api.rs makes
+// up the difference.
+
+use libc::{c_int, c_char, c_void, c_double};
+
+use super::CodecInfo;
+
+/// the most channels a libsoundio layout holds
+pub const SOUNDIO_MAX_CHANNELS: usize = 24;
+
+/// libsoundio's channel ids. 1 to 18 are the channels of libav's mask
+/// bits 0 to 17, in the same order; the rest have no libav equivalent.
+pub const SOUNDIO_CHANNEL_ID_LAST_LIBAV: c_int = 18;
+
+/// libsoundio's sample formats, as used in GrooveAudioFormat. like
+/// soundio.h, only the native-endian ones are named here.
+pub const SOUNDIO_FORMAT_INVALID: c_int = 0;
+pub const SOUNDIO_FORMAT_U8: c_int = 2;
+#[cfg(target_endian = "little")]
+pub const SOUNDIO_FORMAT_S16NE: c_int = 3;
+#[cfg(target_endian = "big")]
+pub const SOUNDIO_FORMAT_S16NE: c_int = 4;
+#[cfg(target_endian = "little")]
+pub const SOUNDIO_FORMAT_S32NE: c_int = 11;
+#[cfg(target_endian = "big")]
+pub const SOUNDIO_FORMAT_S32NE: c_int = 12;
+#[cfg(target_endian = "little")]
+pub const SOUNDIO_FORMAT_FLOAT32NE: c_int = 15;
+#[cfg(target_endian = "big")]
+pub const SOUNDIO_FORMAT_FLOAT32NE: c_int = 16;
+#[cfg(target_endian = "little")]
+pub const SOUNDIO_FORMAT_FLOAT64NE: c_int = 17;
+#[cfg(target_endian = "big")]
+pub const SOUNDIO_FORMAT_FLOAT64NE: c_int = 18;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SoundIoChannelLayout {
+    pub name: *const c_char,
+    pub channel_count: c_int,
+    pub channels: [c_int; SOUNDIO_MAX_CHANNELS],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GrooveAudioFormat {
+    pub sample_rate: c_int,
+    pub layout: SoundIoChannelLayout,
+    /// a libsoundio format
+    pub format: c_int,
+    pub is_planar: bool,
+}
+
+/// the library context everything else is created from
+pub enum Groove {}
+
+#[repr(C)]
+pub struct GrooveSink {
+    pub audio_format: GrooveAudioFormat,
+    pub disable_resample: bool,
+    /// If you leave this to its default of 0, frames pulled from the sink
+    /// will have sample count determined by efficiency.
+    pub buffer_sample_count: c_int,
+    /// how big the buffer queue should be, in bytes.
+    /// groove_sink_create defaults this to 64KB
+    pub buffer_size_bytes: c_int,
+    /// This volume adjustment only applies to this sink.
+    pub gain: c_double,
+    /// set to whatever you want
+    pub userdata: *mut c_void,
+    pub flush: extern "C" fn(sink: *mut GrooveSink),
+    pub purge: extern "C" fn(sink: *mut GrooveSink, item: *mut GroovePlaylistItem),
+    pub pause: extern "C" fn(sink: *mut GrooveSink),
+    pub play: extern "C" fn(sink: *mut GrooveSink),
+    /// read-only. set when attached and cleared when detached
+    pub playlist: *mut GroovePlaylist,
+    /// read-only. computed from audio_format when you call
+    /// groove_sink_attach
+    pub bytes_per_sec: c_int,
+}
+
+/// all fields read-only
+#[repr(C)]
+pub struct GrooveBuffer {
+    pub data: *mut *mut u8,
+    pub format: GrooveAudioFormat,
+    pub frame_count: c_int,
+    pub item: *mut GroovePlaylistItem,
+    pub pos: c_double,
+    pub size: c_int,
+    pub pts: u64,
+}
+// Read-only structs are Sync
+unsafe impl Sync for GrooveBuffer {}
+// Promise rust that nothing points to a GrooveBuffer
+// when it destructs
+unsafe impl Send for GrooveBuffer {}
+
+/// all fields are read-only. modify with methods
+#[repr(C)]
+pub struct GroovePlaylistItem {
+    pub file: *mut GrooveFile,
+    pub gain: c_double,
+    pub peak: c_double,
+    pub prev: *mut GroovePlaylistItem,
+    pub next: *mut GroovePlaylistItem,
+}
+
+/// all fields are read-only. modify using methods.
+#[repr(C)]
+pub struct GroovePlaylist {
+    pub head: *mut GroovePlaylistItem,
+    pub tail: *mut GroovePlaylistItem,
+    pub gain: c_double,
+}
+
+#[repr(C)]
+pub struct GrooveFile {
+    pub dirty: bool,
+    pub filename: *const c_char,
+}
+
+unsafe impl Send for GrooveFile {}
+
+/// lets libgroove read a file through callbacks rather than from disk
+#[repr(C)]
+pub struct GrooveCustomIo {
+    /// set to whatever you want
+    pub userdata: *mut c_void,
+    pub read_packet: extern "C" fn(io: *mut GrooveCustomIo, buf: *mut u8, buf_size: c_int) -> c_int,
+    pub write_packet: extern "C" fn(io: *mut GrooveCustomIo, buf: *mut u8, buf_size: c_int) -> c_int,
+    pub seek: extern "C" fn(io: *mut GrooveCustomIo, offset: i64, whence: c_int) -> i64,
+}
+
+#[repr(C)]
+pub struct GrooveEncoder {
+    pub target_audio_format: GrooveAudioFormat,
+    pub bit_rate: c_int,
+    pub format_short_name: *const c_char,
+    pub codec_short_name: *const c_char,
+    pub filename: *const c_char,
+    pub mime_type: *const c_char,
+    /// how big the sink buffer should be, in bytes
+    pub sink_buffer_size_bytes: c_int,
+    /// how big the encoded audio buffer should be, in bytes
+    pub encoded_buffer_size: c_int,
+    pub gain: c_double,
+    /// read-only. set when attached and cleared when detached
+    pub playlist: *mut GroovePlaylist,
+    pub actual_audio_format: GrooveAudioFormat,
+}
+
+/// the versions these declarations were written for. build.rs checks
+/// the same at build time.
+pub const VERSION_MAJOR: c_int = 5;
+pub const MIN_VERSION_MINOR: c_int = 0;
+
+// linked by build.rs
+extern "C" {
+    pub fn groove_create(groove: *mut *mut Groove) -> c_int;
+    pub fn groove_destroy(groove: *mut Groove);
+    pub fn groove_set_logging(level: c_int);
+    pub fn groove_version_major() -> c_int;
+    pub fn groove_version_minor() -> c_int;
+    pub fn groove_version_patch() -> c_int;
+    pub fn groove_version() -> *const c_char;
+
+    pub fn groove_file_create(groove: *mut Groove) -> *mut GrooveFile;
+    pub fn groove_file_destroy(file: *mut GrooveFile);
+    pub fn groove_file_open(file: *mut GrooveFile, filename: *const c_char, filename_hint: *const c_char) -> c_int;
+    pub fn groove_file_open_custom(file: *mut GrooveFile, custom_io: *mut GrooveCustomIo,
+                                   filename_hint: *const c_char) -> c_int;
+    pub fn groove_file_close(file: *mut GrooveFile);
+    pub fn groove_file_duration(file: *mut GrooveFile) -> c_double;
+    pub fn groove_file_metadata_get(file: *mut GrooveFile, key: *const c_char,
+                                    prev: *const c_void, flags: c_int) -> *mut c_void;
+    pub fn groove_file_metadata_set(file: *mut GrooveFile, key: *const c_char,
+                                    value: *const c_char, flags: c_int) -> c_int;
+    pub fn groove_file_save(file: *mut GrooveFile) -> c_int;
+    pub fn groove_file_short_names(file: *mut GrooveFile) -> *const c_char;
+    pub fn groove_file_audio_format(file: *mut GrooveFile, audio_format: *mut GrooveAudioFormat);
+
+    pub fn groove_tag_key(tag: *mut c_void) -> *const c_char;
+    pub fn groove_tag_value(tag: *mut c_void) -> *const c_char;
+
+    pub fn groove_playlist_create(groove: *mut Groove) -> *mut GroovePlaylist;
+    pub fn groove_playlist_insert(playlist: *mut GroovePlaylist, file: *mut GrooveFile,
+                                  gain: c_double, peak: c_double,
+                                  next: *mut GroovePlaylistItem) -> *mut GroovePlaylistItem;
+    pub fn groove_playlist_destroy(playlist: *mut GroovePlaylist);
+    pub fn groove_playlist_remove(playlist: *mut GroovePlaylist, item: *mut GroovePlaylistItem);
+    pub fn groove_playlist_position(playlist: *mut GroovePlaylist, item: *mut *mut GroovePlaylistItem,
+                                    seconds: *mut c_double);
+    pub fn groove_playlist_clear(playlist: *mut GroovePlaylist);
+    pub fn groove_playlist_set_fill_mode(playlist: *mut GroovePlaylist, mode: c_int);
+    pub fn groove_playlist_seek(playlist: *mut GroovePlaylist, item: *mut GroovePlaylistItem,
+                                seconds: c_double);
+    pub fn groove_playlist_play(playlist: *mut GroovePlaylist);
+    pub fn groove_playlist_pause(playlist: *mut GroovePlaylist);
+    pub fn groove_playlist_playing(playlist: *mut GroovePlaylist) -> bool;
+
+    pub fn groove_encoder_create(groove: *mut Groove) -> *mut GrooveEncoder;
+    pub fn groove_encoder_destroy(encoder: *mut GrooveEncoder);
+    pub fn groove_encoder_metadata_set(encoder: *mut GrooveEncoder, key: *const c_char,
+                                       value: *const c_char, flags: c_int) -> c_int;
+    pub fn groove_encoder_attach(encoder: *mut GrooveEncoder, playlist: *mut GroovePlaylist) -> c_int;
+    pub fn groove_encoder_detach(encoder: *mut GrooveEncoder) -> c_int;
+    pub fn groove_encoder_buffer_get(encoder: *mut GrooveEncoder, buffer: *mut *mut GrooveBuffer,
+                                     block: bool) -> c_int;
+
+    pub fn groove_buffer_unref(buffer: *mut GrooveBuffer);
+
+    pub fn groove_sink_create(groove: *mut Groove) -> *mut GrooveSink;
+    pub fn groove_sink_destroy(sink: *mut GrooveSink);
+    pub fn groove_sink_attach(sink: *mut GrooveSink, playlist: *mut GroovePlaylist) -> c_int;
+    pub fn groove_sink_detach(sink: *mut GrooveSink) -> c_int;
+    pub fn groove_sink_buffer_get(sink: *mut GrooveSink, buffer: *mut *mut GrooveBuffer,
+                                  block: bool) -> c_int;
+}
+
+// libgroove 5's private structs have not been mapped the way v4.rs maps
+// libgroove 4's, so what can only be found there is unknown
+
+/// describe the decoder libgroove opened `file` with
+pub unsafe fn file_codec_info(_file: *mut GrooveFile) -> CodecInfo {
+    CodecInfo::default()
+}
+
+/// make libgroove decode another of `file`'s streams
+pub unsafe fn file_select_audio_stream(_file: *mut GrooveFile, _index: usize) -> c_int {
+    -libc::ENOSYS
+}
+
+/// the libav contexts which log about `file` for as long as it is open
+#[cfg(feature = "log")]
+pub unsafe fn file_log_contexts(_file: *mut GrooveFile) -> Vec<*mut c_void> {
+    Vec::new()
+}
@@ -0,0 +1,87 @@
+// chapters, read from and written to the file on disk as libgroove does
+// not expose them: Nero and QuickTime chapters in MP4, Matroska chapters,
+// and ID3v2 CHAP frames at the start of MP3s
+
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::Path;
+use std::time::Duration;
+
+use id3;
+use matroska;
+use mp4;
+use picture::{self, is_format};
+
+/// a named part of a file, such as a chapter of an audiobook or podcast.
+/// see `File::chapters`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub end: Duration,
+    /// empty if the chapter has none
+    pub title: String,
+}
+
+/// a chapter as its container gives it, which may not say where it ends
+pub(crate) struct Mark {
+    pub(crate) start: Duration,
+    pub(crate) end: Option<Duration>,
+    pub(crate) title: String,
+}
+
+/// read the chapters of a file on disk, given the short names of its
+/// container format and how long it lasts. chapters come in order, and
+/// those which don't say where they end run up to the next one, or the end
+/// of the file.
+pub(crate) fn read(path: &Path, format_names: &[String], duration: Duration) -> io::Result<Vec<Chapter>> {
+    let mut marks = if is_format(format_names, "mp4") || is_format(format_names, "mov") {
+        match mp4::read_moov(path)? {
+            Some(moov) => match mp4::nero_chapters(&moov) {
+                Some(marks) => marks,
+                None => mp4::track_chapters(path, &moov)?,
+            },
+            None => Vec::new(),
+        }
+    } else if is_format(format_names, "matroska") || is_format(format_names, "webm") {
+        matroska::read_segment_child(path, matroska::CHAPTERS)?
+            .map(|chapters| matroska::chapters(&chapters))
+            .unwrap_or_default()
+    } else {
+        id3::read(&mut fs::File::open(path)?)?
+            .map(|tag| id3::chapters(&tag))
+            .unwrap_or_default()
+    };
+
+    marks.sort_by_key(|mark| mark.start);
+    let next_starts: Vec<Duration> = marks.iter().skip(1).map(|mark| mark.start).chain(iter::once(duration)).collect();
+    Ok(marks.into_iter()
+        .zip(next_starts)
+        .map(|(mark, next_start)| Chapter {
+            start: mark.start,
+            end: mark.end.unwrap_or(next_start).max(mark.start),
+            title: mark.title,
+        })
+        .collect())
+}
+
+/// whether `write` can replace the chapters of this container format
+pub(crate) fn can_write(format_names: &[String]) -> bool {
+    ["mp4", "mov", "matroska", "webm"].iter().any(|name| is_format(format_names, name))
+}
+
+/// replace all the chapters of a file on disk, given the short names of
+/// its container format: Nero chapters in MP4 and M4B, and a single
+/// edition of Matroska chapters. the file is rewritten to a temporary file
+/// next to it, which is then renamed over it.
+pub(crate) fn write(path: &Path, format_names: &[String], chapters: &[Chapter]) -> io::Result<()> {
+    let mut file = fs::File::open(path)?;
+    let parts = if is_format(format_names, "mp4") || is_format(format_names, "mov") {
+        mp4::with_moov(&mut file, path, |moov| mp4::with_chapters(moov, chapters))?
+    } else if is_format(format_names, "matroska") || is_format(format_names, "webm") {
+        matroska::with_chapters(&mut file, chapters)?
+    } else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "can't write chapters to this format"));
+    };
+    picture::replace(path, &mut file, &parts)
+}
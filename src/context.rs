@@ -0,0 +1,99 @@
+use std::error;
+use std::fmt;
+use std::sync::Mutex;
+
+use libc::c_int;
+
+use c_api::{
+    VERSION_MAJOR,
+    MIN_VERSION_MINOR,
+    init,
+    finish,
+    groove_set_logging,
+    groove_version_major,
+    groove_version_minor,
+};
+#[cfg(feature = "log")]
+use log_bridge;
+use Log;
+
+// libgroove keeps its state in globals, so it is set up when the first
+// Groove is created and torn down when the last one is dropped
+static INSTANCES: Mutex<usize> = Mutex::new(0);
+
+/// the library itself. files, playlists, sinks and encoders are created
+/// with a reference to one of these and borrow it, so libgroove can't be
+/// shut down while they are in use.
+pub struct Groove {
+    _private: (),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// the libgroove loaded at run time is not a version these bindings
+    /// were written for
+    UnsupportedVersion { major: i32, minor: i32 },
+    /// groove_init failed with this error code
+    Init(i32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnsupportedVersion { major, minor } =>
+                write!(f, "libgroove {}.{} is not supported, {}.{} or a later {}.x is needed",
+                       major, minor, VERSION_MAJOR, MIN_VERSION_MINOR, VERSION_MAJOR),
+            Error::Init(err_code) => write!(f, "could not initialize libgroove (error {})", err_code),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl Groove {
+    /// initialize libgroove. logging starts out as `Log::Quiet`.
+    pub fn new() -> Result<Groove, Error> {
+        let (major, minor) = unsafe { (groove_version_major(), groove_version_minor()) };
+        if major != VERSION_MAJOR || minor < MIN_VERSION_MINOR {
+            return Err(Error::UnsupportedVersion { major, minor });
+        }
+
+        let mut instances = INSTANCES.lock().unwrap();
+        if *instances == 0 {
+            let err_code = unsafe { init() };
+            if err_code < 0 {
+                return Err(Error::Init(err_code));
+            }
+            #[cfg(feature = "log")]
+            log_bridge::install();
+        }
+        *instances += 1;
+        Ok(Groove { _private: () })
+    }
+
+    /// enable/disable logging of errors. libav prints to stderr, unless
+    /// the `log` feature is enabled, in which case messages up to this
+    /// level go to the `log` crate instead. this setting is shared by every
+    /// Groove in the process.
+    pub fn set_logging(&self, level: Log) {
+        let c_level: c_int = match level {
+            Log::Quiet   => -8,
+            Log::Error   => 16,
+            Log::Warning => 24,
+            Log::Info    => 32,
+            Log::Verbose => 40,
+            Log::Debug   => 48,
+        };
+        unsafe { groove_set_logging(c_level) }
+    }
+}
+
+impl Drop for Groove {
+    fn drop(&mut self) {
+        let mut instances = INSTANCES.lock().unwrap();
+        *instances -= 1;
+        if *instances == 0 {
+            unsafe { finish() }
+        }
+    }
+}
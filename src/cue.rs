@@ -0,0 +1,172 @@
+// cue sheets, which split a file, or a few, into tracks. they come as a
+// file of their own beside a whole-album rip, or embedded in it as a
+// CUESHEET tag. times in them count CD frames, 75 to the second.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use playlist_file;
+
+/// a cue sheet, for `Playlist::load_cue` and `Playlist::append_cue`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueSheet {
+    /// the album
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    /// the FILE the track is in, as the sheet names it
+    pub file: String,
+    pub title: Option<String>,
+    /// the track's own performer, if it has one other than the sheet's
+    pub performer: Option<String>,
+    /// where INDEX 01 puts the track in its file. any pregap before it
+    /// plays as the end of the track before.
+    pub start: Duration,
+    /// where the next track in the same file starts, or `None` to play to
+    /// the end of the file
+    pub end: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CueError {
+    /// this line, counting from 1, couldn't be read
+    Line(usize),
+    /// this track has no INDEX 01 to start from
+    NoStart(u32),
+}
+
+impl fmt::Display for CueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CueError::Line(line) => write!(f, "unable to read cue sheet line {}", line),
+            CueError::NoStart(number) => write!(f, "cue sheet track {} has no INDEX 01", number),
+        }
+    }
+}
+
+impl error::Error for CueError {}
+
+impl CueSheet {
+    /// read a cue sheet. commands which don't say where tracks are or what
+    /// they're called are skipped.
+    pub fn parse(text: &str) -> Result<CueSheet, CueError> {
+        let mut sheet = CueSheet::default();
+        let mut file = None;
+        let mut starts = Vec::new();
+        for (number, line) in (1..).zip(text.lines()) {
+            let words = words(line);
+            let command = match words.first() {
+                Some(command) => command.to_ascii_uppercase(),
+                None => continue,
+            };
+            let argument = words.get(1).cloned();
+            let in_track = !sheet.tracks.is_empty();
+            match command.as_str() {
+                "FILE" => file = Some(argument.ok_or(CueError::Line(number))?),
+                "TRACK" => {
+                    let track_number = argument.and_then(|argument| argument.parse().ok());
+                    let (track_number, file) = match (track_number, &file) {
+                        (Some(track_number), Some(file)) => (track_number, file.clone()),
+                        _ => return Err(CueError::Line(number)),
+                    };
+                    sheet.tracks.push(CueTrack {
+                        number: track_number,
+                        file,
+                        title: None,
+                        performer: None,
+                        start: Duration::from_secs(0),
+                        end: None,
+                    });
+                    starts.push(None);
+                },
+                "INDEX" if in_track => {
+                    let index: u32 = argument.and_then(|argument| argument.parse().ok())
+                        .ok_or(CueError::Line(number))?;
+                    let time = words.get(2).and_then(|time| time_of(time)).ok_or(CueError::Line(number))?;
+                    if index == 1 {
+                        *starts.last_mut().unwrap() = Some(time);
+                    }
+                },
+                "TITLE" | "PERFORMER" => {
+                    let (title, performer) = match sheet.tracks.last_mut() {
+                        Some(track) => (&mut track.title, &mut track.performer),
+                        None => (&mut sheet.title, &mut sheet.performer),
+                    };
+                    let field = if command == "TITLE" { title } else { performer };
+                    *field = argument.filter(|argument| !argument.is_empty());
+                },
+                _ => {},
+            }
+        }
+
+        for (track, start) in sheet.tracks.iter_mut().zip(&starts) {
+            track.start = start.ok_or(CueError::NoStart(track.number))?;
+        }
+        for index in 1..sheet.tracks.len() {
+            if sheet.tracks[index].file == sheet.tracks[index - 1].file {
+                sheet.tracks[index - 1].end = Some(sheet.tracks[index].start);
+            }
+        }
+        Ok(sheet)
+    }
+
+    /// read a cue sheet file, in UTF-8 or Latin-1. sheets which can't be
+    /// parsed fail as `InvalidData`, with the `CueError` inside.
+    pub fn read(path: &Path) -> io::Result<CueSheet> {
+        let text = playlist_file::text(&fs::read(path)?);
+        CueSheet::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// the tags a playlist item playing `track` has
+    pub(crate) fn tags(&self, track: &CueTrack) -> Vec<(String, String)> {
+        let tags = [
+            ("title", track.title.as_ref()),
+            ("artist", track.performer.as_ref().or(self.performer.as_ref())),
+            ("album", self.title.as_ref()),
+        ];
+        let mut tags: Vec<(String, String)> = tags.iter()
+            .filter_map(|&(key, value)| value.map(|value| (key.to_string(), value.clone())))
+            .collect();
+        tags.push(("track".to_string(), track.number.to_string()));
+        tags
+    }
+}
+
+// the words of a line, with "quoted strings" as one
+fn words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            words.push(quoted[..end].to_string());
+            rest = quoted.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            words.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    words
+}
+
+// mm:ss:ff, where there are 75 frames to the second
+fn time_of(text: &str) -> Option<Duration> {
+    let parts: Vec<u64> = text.split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    match parts[..] {
+        [minutes, seconds, frames] if seconds < 60 && frames < 75 => {
+            Some(Duration::from_secs(minutes * 60 + seconds) + Duration::from_nanos(frames * 1_000_000_000 / 75))
+        },
+        _ => None,
+    }
+}
@@ -1,6 +1,8 @@
 extern crate libc;
 
+use std::cell::{Cell, RefCell};
 use std::ffi::CString;
+use std::marker::PhantomData;
 
 use libc::c_int;
 
@@ -9,21 +11,55 @@ use c_api::{
     TAG_MATCH_CASE,
     groove_encoder_attach,
     groove_encoder_detach,
-    groove_encoder_create,
+    encoder_actual_audio_format,
+    encoder_create,
+    encoder_set_target_audio_format,
+    encoder_target_audio_format,
     groove_encoder_destroy,
     groove_encoder_metadata_set,
 };
 use audio_format::AudioFormat;
 use buffer::EncodedBuffer;
+use context::Groove;
+use file::File;
 use playlist::Playlist;
 
+/// how an Encoder chooses the tags of the streams it writes, for
+/// `Encoder::tag_stream`. each stream starts with a format header carrying
+/// the encoder's metadata at that point, and ends with a trailer once the
+/// playlist runs out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MetadataMode {
+    /// tag every stream with the metadata of the first item's file
+    #[default]
+    FirstItem,
+    /// tag each stream with the metadata of the file it starts with. let
+    /// the playlist run out before appending each item to give every item
+    /// a stream, and tags, of its own. only concatenate the streams into
+    /// one output with formats which allow it, such as Ogg or MP3.
+    PerItem,
+    /// write no tags at all
+    None,
+}
+
 /// attach an Encoder to a playlist to keep a buffer of encoded audio full.
 /// for example you could use it to implement an http audio stream
-pub struct Encoder {
+pub struct Encoder<'g> {
     pub(crate) groove_encoder: *mut GrooveEncoder,
+    // libgroove keeps the pointers we hand it, so the strings live here
+    format_short_name: Option<CString>,
+    codec_short_name: Option<CString>,
+    filename: Option<CString>,
+    mime_type: Option<CString>,
+    // keys given to metadata_set, so that metadata_clear can remove them
+    metadata_keys: RefCell<Vec<String>>,
+    metadata_mode: Cell<MetadataMode>,
+    // whether tag_stream has tagged a stream since the mode was set
+    tagged: Cell<bool>,
+    _groove: PhantomData<&'g Groove>,
 }
 
-impl Drop for Encoder {
+impl<'g> Drop for Encoder<'g> {
     fn drop(&mut self) {
         unsafe {
             if !(*self.groove_encoder).playlist.is_null() {
@@ -34,11 +70,20 @@ impl Drop for Encoder {
     }
 }
 
-impl Encoder {
-    pub fn new() -> Self {
-        super::init();
+impl<'g> Encoder<'g> {
+    pub fn new(_groove: &'g Groove) -> Self {
         unsafe {
-            Encoder { groove_encoder: groove_encoder_create() }
+            Encoder {
+                groove_encoder: encoder_create(),
+                format_short_name: None,
+                codec_short_name: None,
+                filename: None,
+                mime_type: None,
+                metadata_keys: RefCell::new(Vec::new()),
+                metadata_mode: Cell::new(MetadataMode::default()),
+                tagged: Cell::new(false),
+                _groove: PhantomData,
+            }
         }
     }
 
@@ -49,12 +94,12 @@ impl Encoder {
     /// used instead. actual_audio_format is set to the actual values.
     pub fn set_target_audio_format(&self, target_audio_format: AudioFormat) {
         unsafe {
-            (*self.groove_encoder).target_audio_format = target_audio_format.to_groove();
+            encoder_set_target_audio_format(self.groove_encoder, target_audio_format.to_groove());
         }
     }
     pub fn get_target_audio_format(&self) -> AudioFormat {
         unsafe {
-            AudioFormat::from_groove(&(*self.groove_encoder).target_audio_format)
+            AudioFormat::from_groove(&encoder_target_audio_format(self.groove_encoder))
         }
     }
 
@@ -77,39 +122,43 @@ impl Encoder {
     /// optional - choose a short name for the format
     /// to help libgroove guess which format to use
     /// use `avconv -formats` to get a list of possibilities
-    pub fn set_format_short_name(&self, format: &str) {
+    pub fn set_format_short_name(&mut self, format: &str) {
         let format_c_str = CString::new(format).unwrap();
         unsafe {
             (*self.groove_encoder).format_short_name = format_c_str.as_ptr();
         }
+        self.format_short_name = Some(format_c_str);
     }
 
     /// optional - choose a short name for the codec
     /// to help libgroove guess which codec to use
     /// use `avconv -codecs` to get a list of possibilities
-    pub fn set_codec_short_name(&self, codec: &str) {
+    pub fn set_codec_short_name(&mut self, codec: &str) {
         let codec_c_str = CString::new(codec).unwrap();
         unsafe {
             (*self.groove_encoder).codec_short_name = codec_c_str.as_ptr();
         }
+        self.codec_short_name = Some(codec_c_str);
     }
 
     /// optional - provide an example filename
     /// to help libgroove guess which format/codec to use
-    pub fn set_filename(&self, filename: &str) {
+    pub fn set_filename(&mut self, filename: &str) {
         let filename_c_str = CString::new(filename).unwrap();
         unsafe {
             (*self.groove_encoder).filename = filename_c_str.as_ptr();
         }
+        self.filename = Some(filename_c_str);
     }
 
     /// optional - provide a mime type string
     /// to help libgroove guess which format/codec to use
-    pub fn set_mime_type(&self, mime_type: &str) {
+    pub fn set_mime_type(&mut self, mime_type: &str) {
         let mime_type_c_str = CString::new(mime_type).unwrap();
         unsafe {
             (*self.groove_encoder).mime_type = mime_type_c_str.as_ptr();
         }
+        self.mime_type = Some(mime_type_c_str);
     }
 
     /// set to the actual format you get when you attach to a
@@ -117,18 +166,21 @@ impl Encoder {
     /// not be.
     pub fn get_actual_audio_format(&self) -> AudioFormat {
         unsafe {
-            AudioFormat::from_groove(&(*self.groove_encoder).actual_audio_format)
+            AudioFormat::from_groove(&encoder_actual_audio_format(self.groove_encoder))
         }
     }
 
-    /// see docs for file::metadata_set
-    pub fn metadata_set(&self, key: &str, value: &str, case_sensitive: bool) -> Result<(), i32> {
+    fn _metadata_set(&self, key: &str, value: Option<&str>, case_sensitive: bool) -> Result<(), i32> {
         let flags: c_int = if case_sensitive {TAG_MATCH_CASE} else {0};
         let c_tag_key = CString::new(key).unwrap();
-        let c_tag_value = CString::new(value).unwrap();
+        let c_tag_value = value.map(|value| CString::new(value).unwrap());
+        let c_tag_value_ptr = match c_tag_value {
+            Some(ref value) => value.as_ptr(),
+            None => ::std::ptr::null(),
+        };
         unsafe {
             let err_code = groove_encoder_metadata_set(self.groove_encoder, c_tag_key.as_ptr(),
-                                                       c_tag_value.as_ptr(), flags);
+                                                       c_tag_value_ptr, flags);
             if err_code >= 0 {
                 Result::Ok(())
             } else {
@@ -137,6 +189,85 @@ impl Encoder {
         }
     }
 
+    /// see docs for file::metadata_set
+    pub fn metadata_set(&self, key: &str, value: &str, case_sensitive: bool) -> Result<(), i32> {
+        self._metadata_set(key, Some(value), case_sensitive)?;
+        let mut keys = self.metadata_keys.borrow_mut();
+        if !keys.iter().any(|k| k == key) {
+            keys.push(key.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn metadata_delete(&self, key: &str, case_sensitive: bool) -> Result<(), i32> {
+        self._metadata_set(key, None, case_sensitive)?;
+        self.metadata_keys.borrow_mut().retain(|k| if case_sensitive {
+            k != key
+        } else {
+            !k.eq_ignore_ascii_case(key)
+        });
+        Ok(())
+    }
+
+    /// remove every tag set with metadata_set
+    pub fn metadata_clear(&self) -> Result<(), i32> {
+        let keys = self.metadata_keys.replace(Vec::new());
+        for key in &keys {
+            self._metadata_set(key, None, true)?;
+        }
+        Ok(())
+    }
+
+    /// copy every tag of `file` into the encoder's metadata
+    pub fn copy_metadata_from(&self, file: &File) -> Result<(), i32> {
+        self.copy_metadata_from_with(file, |key, value| Some((key.to_string(), value.to_string())))
+    }
+
+    /// copy the tags of `file` into the encoder's metadata, passing each
+    /// key and value through `map` first. return None from `map` to skip
+    /// a tag, or a new key and value to rename or rewrite it.
+    /// tags which are not valid UTF-8 are skipped.
+    pub fn copy_metadata_from_with<F>(&self, file: &File, mut map: F) -> Result<(), i32>
+        where F: FnMut(&str, &str) -> Option<(String, String)>
+    {
+        for tag in file.metadata_iter() {
+            let (key, value) = match (tag.key(), tag.value()) {
+                (Ok(key), Ok(value)) => (key, value),
+                _ => continue,
+            };
+            if let Some((key, value)) = map(key, value) {
+                self.metadata_set(&key, &value, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// how `tag_stream` tags each stream. defaults to `FirstItem`.
+    pub fn set_metadata_mode(&self, mode: MetadataMode) {
+        self.metadata_mode.set(mode);
+        self.tagged.set(false);
+    }
+
+    pub fn metadata_mode(&self) -> MetadataMode {
+        self.metadata_mode.get()
+    }
+
+    /// set the metadata for a stream which starts with `file`, as the
+    /// metadata mode says, replacing tags set with `metadata_set`. call
+    /// this before appending the item the stream starts with to a playlist
+    /// which has run out, or which the encoder is yet to be attached to.
+    pub fn tag_stream(&self, file: &File) -> Result<(), i32> {
+        let first = !self.tagged.replace(true);
+        match self.metadata_mode.get() {
+            MetadataMode::FirstItem if !first => Ok(()),
+            MetadataMode::FirstItem | MetadataMode::PerItem => {
+                self.metadata_clear()?;
+                self.copy_metadata_from(file)
+            },
+            MetadataMode::None => self.metadata_clear(),
+        }
+    }
+
     /// at playlist begin, format headers are generated. when end of playlist is
     /// reached, format trailers are generated.
     pub fn attach(&self, playlist: &Playlist) -> Result<(), i32> {
@@ -159,6 +290,6 @@ impl Encoder {
     /// returns None on end of playlist, Some<EncodedBuffer> when there is a buffer
     /// blocks the thread until a buffer or end is found
     pub fn buffer_get_blocking(&self) -> Option<EncodedBuffer> {
-        EncodedBuffer::from_encoder(self).expect("buffer aborted or not ready")
+        EncodedBuffer::from_encoder(self, true).expect("buffer aborted or not ready")
     }
 }
\ No newline at end of file
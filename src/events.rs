@@ -0,0 +1,217 @@
+// telling subscribers what a sink has played. libgroove only says which
+// item each buffer came from, so the sink watches for that changing as
+// buffers are handed out, and counts the audio it has handed out to time
+// what it sees. seeks, pauses and plays come from libgroove's sink
+// callbacks.
+
+use c_api::{GroovePlaylist, GroovePlaylistItem, GrooveSink};
+
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use buffer::DecodedBuffer;
+use laps::Laps;
+use playlist::ItemId;
+
+/// something a sink played, or was told, while attached to a playlist
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaylistEvent {
+    /// the first audio of an item. an item repeated with `RepeatMode::One`,
+    /// or looped A-B, starts once.
+    ItemStarted(ItemId),
+    /// the last audio of an item, just before the next item starts or the
+    /// playlist ends. an item removed while playing still finishes.
+    ItemFinished(ItemId),
+    /// the first audio after a seek
+    Seeked,
+    Paused,
+    Played,
+    /// the end of the playlist, after the last item finished
+    EndOfPlaylist,
+}
+
+/// an event, with when the sink saw it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Notification {
+    pub event: PlaylistEvent,
+    /// how much audio the sink had handed out before the event, from
+    /// when it was created. for a sink feeding a device, that is how far
+    /// into its playback the event is heard.
+    pub timestamp: Duration,
+}
+
+// what happened, before the item is looked up in each subscriber's
+// playlist
+#[derive(Clone, Copy)]
+enum Seen {
+    Started(*mut GroovePlaylistItem),
+    Finished,
+    Other(PlaylistEvent),
+}
+
+struct Subscriber {
+    playlist: *mut GroovePlaylist,
+    laps: Arc<Mutex<Laps>>,
+    sender: Sender<Notification>,
+    /// the item last said to start, looked up when it did, so it finishes
+    /// as that even once removed
+    started: Mutex<Option<ItemId>>,
+}
+
+// the playlist is only compared against, never followed
+unsafe impl Send for Subscriber {}
+unsafe impl Sync for Subscriber {}
+
+#[derive(Default)]
+struct State {
+    /// seconds of audio handed out
+    elapsed: f64,
+    /// the item the last buffer came from
+    current: Option<*mut GroovePlaylistItem>,
+    /// flushed since the last buffer
+    seeked: bool,
+    /// flushed since the sink's filters last asked
+    filters_flushed: bool,
+    /// the end was handed out, and nothing since
+    ended: bool,
+    subscribers: Vec<Arc<Subscriber>>,
+}
+
+/// the events of one sink, which its libgroove callbacks point at
+#[derive(Default)]
+pub(crate) struct Events {
+    state: Mutex<State>,
+}
+
+impl Events {
+    /// set `sink`'s callbacks to report to this
+    pub(crate) unsafe fn hook(&self, sink: *mut GrooveSink) {
+        (*sink).userdata = self as *const Events as *mut _;
+        (*sink).flush = flushed;
+        (*sink).pause = paused;
+        (*sink).play = played;
+    }
+
+    /// events from the sink while attached to `playlist`
+    pub(crate) fn subscribe(&self, playlist: *mut GroovePlaylist, laps: Arc<Mutex<Laps>>) -> Receiver<Notification> {
+        let (sender, receiver) = mpsc::channel();
+        let subscriber = Subscriber { playlist, laps, sender, started: Mutex::new(None) };
+        self.state.lock().unwrap().subscribers.push(Arc::new(subscriber));
+        receiver
+    }
+
+    /// whether the sink was flushed since this was last asked, so what its
+    /// filters hold back is from before a seek
+    pub(crate) fn take_flush(&self) -> bool {
+        mem::replace(&mut self.state.lock().unwrap().filters_flushed, false)
+    }
+
+    /// the sink attached to `playlist` handed out `buffer`, or the end of
+    /// the playlist
+    pub(crate) fn observe(&self, playlist: *mut GroovePlaylist, buffer: Option<&DecodedBuffer>) {
+        let mut seen = Vec::new();
+        let timestamp = {
+            let mut state = self.state.lock().unwrap();
+            let timestamp = state.elapsed;
+            if state.seeked {
+                state.seeked = false;
+                seen.push(Seen::Other(PlaylistEvent::Seeked));
+            }
+            match buffer {
+                Some(buffer) => {
+                    let item = buffer.groove_playlist_item();
+                    if state.current != Some(item) {
+                        if state.current.is_some() {
+                            seen.push(Seen::Finished);
+                        }
+                        seen.push(Seen::Started(item));
+                        state.current = Some(item);
+                    }
+                    state.ended = false;
+                    let format = buffer.audio_format();
+                    state.elapsed += buffer.frame_count() as f64 / f64::from(format.sample_rate);
+                },
+                // asking again after the end gets the end again
+                None if !state.ended => {
+                    if state.current.take().is_some() {
+                        seen.push(Seen::Finished);
+                    }
+                    seen.push(Seen::Other(PlaylistEvent::EndOfPlaylist));
+                    state.ended = true;
+                },
+                None => {},
+            }
+            timestamp
+        };
+        // not under the lock: finding items takes the playlist's laps,
+        // which are held while calling into libgroove, which may be
+        // calling back into this
+        self.notify(playlist, timestamp, &seen);
+    }
+
+    fn notify(&self, playlist: *mut GroovePlaylist, timestamp: f64, seen: &[Seen]) {
+        if seen.is_empty() {
+            return;
+        }
+        let subscribers = self.state.lock().unwrap().subscribers.clone();
+        let timestamp = Duration::from_secs_f64(timestamp);
+        let mut gone = Vec::new();
+        for subscriber in subscribers.iter().filter(|subscriber| subscriber.playlist == playlist) {
+            for &seen in seen {
+                let event = match seen {
+                    Seen::Other(event) => Some(event),
+                    Seen::Started(item) => {
+                        // an item removed before it was heard has no id
+                        let id = subscriber.laps.lock().unwrap().key_of(item).map(ItemId);
+                        *subscriber.started.lock().unwrap() = id;
+                        id.map(PlaylistEvent::ItemStarted)
+                    },
+                    Seen::Finished => subscriber.started.lock().unwrap().take().map(PlaylistEvent::ItemFinished),
+                };
+                let notification = match event {
+                    Some(event) => Notification { event, timestamp },
+                    None => continue,
+                };
+                if subscriber.sender.send(notification).is_err() {
+                    gone.push(subscriber.clone());
+                    break;
+                }
+            }
+        }
+        if !gone.is_empty() {
+            self.state.lock().unwrap().subscribers
+                .retain(|subscriber| !gone.iter().any(|gone| Arc::ptr_eq(gone, subscriber)));
+        }
+    }
+
+    // a libgroove callback for `event`
+    fn callback(sink: *mut GrooveSink, event: Option<PlaylistEvent>) {
+        let (events, playlist) = unsafe { (&*((*sink).userdata as *const Events), (*sink).playlist) };
+        let timestamp = {
+            let mut state = events.state.lock().unwrap();
+            // a seek shows from the next buffer on
+            if event.is_none() {
+                state.seeked = true;
+                state.filters_flushed = true;
+            }
+            state.elapsed
+        };
+        if let Some(event) = event {
+            events.notify(playlist, timestamp, &[Seen::Other(event)]);
+        }
+    }
+}
+
+extern "C" fn flushed(sink: *mut GrooveSink) {
+    Events::callback(sink, None)
+}
+
+extern "C" fn paused(sink: *mut GrooveSink) {
+    Events::callback(sink, Some(PlaylistEvent::Paused))
+}
+
+extern "C" fn played(sink: *mut GrooveSink) {
+    Events::callback(sink, Some(PlaylistEvent::Played))
+}
@@ -1,7 +1,10 @@
 extern crate libc;
 
+use std::io;
 use std::str;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
 use std::os::unix::ffi::OsStrExt;
 use std::ffi::{CStr, CString, OsStr};
 
@@ -13,66 +16,223 @@ use libc::{
 
 use c_api::{
     GrooveFile,
-    GrooveAudioFormat,
     TAG_MATCH_CASE,
-    groove_file_open,
-    groove_file_close,
+    file_audio_format,
+    file_close,
+    file_dirty,
+    file_open,
     groove_file_duration,
     groove_file_metadata_get,
     groove_file_metadata_set,
     groove_file_save,
-    groove_file_audio_format,
+    groove_file_short_names,
+    file_codec_info,
+    file_select_audio_stream,
     groove_tag_key,
     groove_tag_value
 };
 
 use audio_format::AudioFormat;
+use buffer::OwnedAudioBuffer;
+use chapter::{self, Chapter};
+use context::Groove;
+use cue::CueSheet;
+use info::{self, AudioStream, FileInfo};
+use matroska;
+use mp4;
+#[cfg(feature = "log")]
+use log_bridge::{FileLog, LogMessage};
+use pcm_source::PcmReader;
+use picture::{self, is_format, Picture};
+use playlist::Playlist;
+use sink::Sink;
 
 fn err_code_result(err_code: i32) -> Result<(), i32> {
     if err_code >= 0 { Ok(()) } else { Err(err_code) }
 }
 
-pub struct File {
-    pub(crate) groove_file: *mut GrooveFile,
+// report I/O errors the way libav does, as a negated errno
+fn io_err_code(err: io::Error) -> i32 {
+    -err.raw_os_error().unwrap_or(libc::EIO)
 }
 
-impl Drop for File {
+pub struct File<'g> {
+    groove: &'g Groove,
+    groove_file: Cell<*mut GrooveFile>,
+    // handles given up when the file was reopened after a save, kept open
+    // while a playlist holds the file, as it may still be decoding from them
+    retired: RefCell<Vec<*mut GrooveFile>>,
+    in_playlist: Cell<bool>,
+    // set when the file reads from a PcmSource rather than from disk
+    pcm_reader: Option<Box<PcmReader>>,
+    // pictures to write on the next save, if they were edited
+    pictures: RefCell<Option<Vec<Picture>>>,
+    // and chapters
+    chapters: RefCell<Option<Vec<Chapter>>>,
+    // set by select_audio_stream
+    stream: Option<usize>,
+    log: FileLog,
+}
+
+// without the log feature nothing is kept, so a File's log does nothing
+#[cfg(not(feature = "log"))]
+#[derive(Default)]
+struct FileLog {}
+
+#[cfg(not(feature = "log"))]
+impl FileLog {
+    fn capture<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        f()
+    }
+
+    fn watch(&self, _groove_file: *mut GrooveFile) {}
+
+    fn forget(&self) {}
+}
+
+/// where a file's audio can be decoded from again, apart from libgroove's
+/// playlist: its path, and the audio stream chosen in it
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Origin {
+    pub(crate) path: PathBuf,
+    pub(crate) stream: Option<usize>,
+}
+
+impl Origin {
+    /// open the file again, decoding the same stream
+    pub(crate) fn open<'g>(&self, groove: &'g Groove) -> Option<File<'g>> {
+        let mut file = File::open(groove, &self.path)?;
+        if let Some(stream) = self.stream {
+            file.select_audio_stream(stream).ok()?;
+        }
+        Some(file)
+    }
+}
+
+impl<'g> Drop for File<'g> {
     fn drop(&mut self) {
+        self.abort_io();
+        self.log.forget();
         unsafe {
-            groove_file_close(self.groove_file);
+            file_close(self.groove_file.get());
+            for &groove_file in self.retired.borrow().iter() {
+                file_close(groove_file);
+            }
         }
     }
 }
 
-impl File {
+impl<'g> File<'g> {
     /// open a file on disk and prepare to stream audio from it
-    pub fn open(filename: &Path) -> Option<File> {
-        super::init();
-        let filename_byte_vec = filename.as_os_str().as_bytes().to_vec();
-        let c_filename = CString::new(filename_byte_vec).unwrap();
+    pub fn open(groove: &'g Groove, filename: &Path) -> Option<File<'g>> {
+        File::open_with_log(groove, filename, FileLog::default()).ok()
+    }
 
-        unsafe {
-            let groove_file = groove_file_open(c_filename.as_ptr());
+    /// like `open`, but on failure returns the messages logged while
+    /// trying, which usually say why
+    #[cfg(feature = "log")]
+    pub fn open_logged(groove: &'g Groove, filename: &Path) -> Result<File<'g>, Vec<LogMessage>> {
+        File::open_with_log(groove, filename, FileLog::default()).map_err(|log| log.messages())
+    }
 
-            if groove_file.is_null() {
-                None
-            } else {
-                Some(File { groove_file })
-            }
+    // open a file, giving back its log if that fails
+    fn open_with_log(groove: &'g Groove, filename: &Path, log: FileLog) -> Result<File<'g>, FileLog> {
+        let groove_file = log.capture(|| open_groove_file(filename));
+        if groove_file.is_null() {
+            return Err(log);
+        }
+        log.watch(groove_file);
+        Ok(File {
+            groove,
+            groove_file: Cell::new(groove_file),
+            retired: RefCell::new(Vec::new()),
+            in_playlist: Cell::new(false),
+            pcm_reader: None,
+            pictures: RefCell::new(None),
+            chapters: RefCell::new(None),
+            stream: None,
+            log,
+        })
+    }
+
+    pub(crate) fn from_pcm_reader(groove: &'g Groove, groove_file: *mut GrooveFile,
+                                  pcm_reader: Box<PcmReader>) -> File<'g> {
+        let log = FileLog::default();
+        log.watch(groove_file);
+        File {
+            groove,
+            groove_file: Cell::new(groove_file),
+            retired: RefCell::new(Vec::new()),
+            in_playlist: Cell::new(false),
+            pcm_reader: Some(pcm_reader),
+            pictures: RefCell::new(None),
+            chapters: RefCell::new(None),
+            stream: None,
+            log,
+        }
+    }
+
+    /// the libgroove file, which a reopened file replaces
+    pub(crate) fn groove_file(&self) -> *mut GrooveFile {
+        self.groove_file.get()
+    }
+
+    /// set while a playlist holds the file. once it lets go, the handles
+    /// given up by saves are closed.
+    pub(crate) fn set_in_playlist(&self, in_playlist: bool) {
+        self.in_playlist.set(in_playlist);
+        if !in_playlist {
+            self.close_retired();
+        }
+    }
+
+    // close the handles given up by `reopen`, which nothing decodes from
+    fn close_retired(&self) {
+        let retired = self.retired.replace(Vec::new());
+        if retired.is_empty() {
+            return;
+        }
+        // watch only the handle still open
+        self.log.forget();
+        self.log.watch(self.groove_file.get());
+        for groove_file in retired {
+            unsafe { file_close(groove_file) };
+        }
+    }
+
+    /// wake up and fail any read blocked waiting for a PcmSource, so that
+    /// libgroove can let go of the file
+    pub(crate) fn abort_io(&self) {
+        if let Some(ref pcm_reader) = self.pcm_reader {
+            pcm_reader.close();
+        }
+    }
+
+    /// where the file can be opened again from, which files reading from a
+    /// PcmSource can't be
+    pub(crate) fn origin(&self) -> Option<Origin> {
+        if self.pcm_reader.is_some() {
+            return None;
         }
+        Some(Origin { path: self.filename().to_path_buf(), stream: self.stream })
     }
 
+    /// the path the file was opened from. files reading from a PcmSource
+    /// weren't opened from one, and give an empty path.
     pub fn filename(&self) -> &Path {
+        if self.pcm_reader.is_some() {
+            return Path::new("");
+        }
         unsafe {
-            let slice = CStr::from_ptr((*self.groove_file).filename).to_bytes();
+            let slice = CStr::from_ptr((*self.groove_file.get()).filename).to_bytes();
             Path::new(&*(slice as *const [u8] as *const OsStr))
         }
     }
 
     /// whether the file has pending edits
     pub fn is_dirty(&self) -> bool {
-        unsafe {
-            (*self.groove_file).dirty != 0
+        self.pictures.borrow().is_some() || self.chapters.borrow().is_some() || unsafe {
+            file_dirty(self.groove_file.get())
         }
     }
 
@@ -82,7 +242,7 @@ impl File {
     /// GrooveLoudnessDetector
     pub fn duration(&self) -> f64 {
         unsafe {
-            groove_file_duration(self.groove_file)
+            groove_file_duration(self.groove_file.get())
         }
     }
 
@@ -92,7 +252,7 @@ impl File {
 
         unsafe {
             let groove_tag = groove_file_metadata_get(
-                self.groove_file,
+                self.groove_file.get(),
                 c_tag_key.as_ptr(),
                 ::std::ptr::null(),
                 flags
@@ -106,23 +266,23 @@ impl File {
         }
     }
 
-    pub fn metadata_iter(&self) -> MetadataIterator {
-        MetadataIterator { file: self, curr: ::std::ptr::null() }
+    pub fn metadata_iter(&self) -> MetadataIterator<'_> {
+        MetadataIterator { _file: self, groove_file: self.groove_file.get(), curr: ::std::ptr::null() }
     }
 
     fn _metadata_set(&self, key: &str, value: Option<&str>, case_sensitive: bool) -> Result<(), i32> {
         let flags: c_int = if case_sensitive { TAG_MATCH_CASE } else { 0 };
 
         let c_tag_key = CString::new(key).unwrap();
-        let c_tag_value_ptr = if let Some(value) = value {
-            CString::new(value).unwrap().as_ptr()
-        } else {
-            ::std::ptr::null()
+        let c_tag_value = value.map(|value| CString::new(value).unwrap());
+        let c_tag_value_ptr = match c_tag_value {
+            Some(ref value) => value.as_ptr(),
+            None => ::std::ptr::null(),
         };
 
         let err_code = unsafe {
             groove_file_metadata_set(
-                self.groove_file,
+                self.groove_file.get(),
                 c_tag_key.as_ptr(),
                 c_tag_value_ptr,
                 flags
@@ -137,32 +297,375 @@ impl File {
     }
 
     pub fn metadata_delete(&self, key: &str, case_sensitive: bool) -> Result<(), i32> {
-        self._metadata_set(key, None, case_sensitive)        
+        self._metadata_set(key, None, case_sensitive)
     }
 
-    /// write changes made to metadata to disk.
+    /// write changes made to metadata, pictures and chapters to disk. after
+    /// writing pictures or chapters the file is opened again, so that later
+    /// saves start from them.
     pub fn save(&self) -> Result<(), i32> {
-        err_code_result(unsafe { groove_file_save(self.groove_file) })
+        let mut pictures = self.pictures.borrow_mut();
+        let mut chapters = self.chapters.borrow_mut();
+        let metadata_dirty = unsafe { file_dirty(self.groove_file.get()) };
+        if metadata_dirty || (pictures.is_none() && chapters.is_none()) {
+            let err_code = self.log.capture(|| unsafe { groove_file_save(self.groove_file.get()) });
+            err_code_result(err_code)?;
+        }
+        if let Some(ref new_pictures) = *pictures {
+            picture::write(self.filename(), &self.format_names(), new_pictures).map_err(io_err_code)?;
+            *pictures = None;
+            // libgroove would save from the file as it was before
+            self.reopen()?;
+        }
+        if let Some(ref new_chapters) = *chapters {
+            chapter::write(self.filename(), &self.format_names(), new_chapters).map_err(io_err_code)?;
+            *chapters = None;
+            self.reopen()?;
+        }
+        Ok(())
+    }
+
+    // open the file again after rewriting it, so that libgroove works from
+    // what is on disk now
+    fn reopen(&self) -> Result<(), i32> {
+        let groove_file = self.log.capture(|| open_groove_file(self.filename()));
+        if groove_file.is_null() {
+            return Err(-libc::EIO);
+        }
+        if let Some(stream) = self.stream {
+            let err_code = unsafe { file_select_audio_stream(groove_file, stream) };
+            if err_code < 0 {
+                unsafe { file_close(groove_file) };
+                return Err(err_code);
+            }
+        }
+        self.log.watch(groove_file);
+        self.retired.borrow_mut().push(self.groove_file.replace(groove_file));
+        if !self.in_playlist.get() {
+            self.close_retired();
+        }
+        Ok(())
     }
 
-    /// get the audio format of the main audio stream of a file
+    /// errors, warnings and informational messages libav logged about this
+    /// file while it was opened, saved or decoded, whatever the
+    /// `set_logging` level. kept for error reports. libav's contexts for
+    /// the file are found in libgroove's private state, so this is always
+    /// empty with the `libgroove5` feature, or with a libgroove or libav
+    /// that `select_audio_stream` can't work with either.
+    #[cfg(feature = "log")]
+    pub fn log_messages(&self) -> Vec<LogMessage> {
+        self.log.messages()
+    }
+
+    /// the images embedded in the file, such as cover art, read from the
+    /// file on disk: ID3v2 APIC frames, FLAC PICTURE blocks, MP4 `covr`
+    /// items and Matroska attachments. if pictures were edited since the
+    /// last save, this returns the edited pictures.
+    pub fn pictures(&self) -> Vec<Picture> {
+        if let Some(ref pictures) = *self.pictures.borrow() {
+            return pictures.clone();
+        }
+        if self.pcm_reader.is_some() {
+            return Vec::new();
+        }
+        picture::read(self.filename(), &self.format_names()).unwrap_or_default()
+    }
+
+    /// add a picture, replacing any of the same picture type. written to
+    /// disk by `save`. MP3, FLAC, MP4 and Matroska files can be edited; for
+    /// others this fails with `-ENOSYS`. MP4 keeps no picture types or
+    /// descriptions, and Matroska only whether a picture is the front cover.
+    pub fn set_picture(&self, picture: Picture) -> Result<(), i32> {
+        let mut pictures = self.edited_pictures()?;
+        pictures.retain(|existing| existing.picture_type != picture.picture_type);
+        pictures.push(picture);
+        *self.pictures.borrow_mut() = Some(pictures);
+        Ok(())
+    }
+
+    /// remove all pictures. written to disk by `save`. see `set_picture`
+    /// for the formats which can be edited.
+    pub fn remove_pictures(&self) -> Result<(), i32> {
+        self.edited_pictures()?;
+        *self.pictures.borrow_mut() = Some(Vec::new());
+        Ok(())
+    }
+
+    /// the chapters of the file, such as those of an audiobook or podcast,
+    /// read from the file on disk: Nero or QuickTime chapters in MP4 and
+    /// M4B, Matroska chapters, and ID3v2 CHAP frames at the start of MP3s.
+    /// chapters come in order; those which don't say where they end run up
+    /// to the next, or to the end of the file as `duration` puts it. if
+    /// chapters were edited since the last save, this returns those.
+    pub fn chapters(&self) -> Vec<Chapter> {
+        if let Some(ref chapters) = *self.chapters.borrow() {
+            return chapters.clone();
+        }
+        if self.pcm_reader.is_some() {
+            return Vec::new();
+        }
+        let seconds = self.duration();
+        let duration = if seconds.is_finite() && seconds > 0.0 {
+            Duration::from_secs_f64(seconds)
+        } else {
+            Duration::from_secs(0)
+        };
+        chapter::read(self.filename(), &self.format_names(), duration).unwrap_or_default()
+    }
+
+    /// replace all the chapters of the file. written to disk by `save`.
+    /// MP4 and Matroska files can be edited; for others this fails with
+    /// `-ENOSYS`. MP4 gets Nero chapters, which have no ends and can number
+    /// at most 255; more fail with `-EINVAL`.
+    pub fn set_chapters(&self, chapters: Vec<Chapter>) -> Result<(), i32> {
+        let format_names = self.format_names();
+        if self.pcm_reader.is_some() || !chapter::can_write(&format_names) {
+            return Err(-libc::ENOSYS);
+        }
+        let is_mp4 = is_format(&format_names, "mp4") || is_format(&format_names, "mov");
+        if is_mp4 && chapters.len() > usize::from(u8::MAX) {
+            return Err(-libc::EINVAL);
+        }
+        *self.chapters.borrow_mut() = Some(chapters);
+        Ok(())
+    }
+
+    /// the cue sheet embedded in the file's CUESHEET tag, as whole-album
+    /// rips carry. `None` if there isn't one, or it can't be read.
+    pub fn cue_sheet(&self) -> Option<CueSheet> {
+        let tag = self.metadata_get("cuesheet", false)?;
+        CueSheet::parse(tag.value().ok()?).ok()
+    }
+
+    fn edited_pictures(&self) -> Result<Vec<Picture>, i32> {
+        if self.pcm_reader.is_some() || !picture::can_write(&self.format_names()) {
+            return Err(-libc::ENOSYS);
+        }
+        Ok(self.pictures())
+    }
+
+    /// get the audio format of the audio stream being decoded
     pub fn audio_format(&self) -> AudioFormat {
-        let mut result = GrooveAudioFormat {
-            sample_rate: 0,
-            channel_layout: 0,
-            sample_fmt: 0,
+        AudioFormat::from_groove(&unsafe { file_audio_format(self.groove_file.get()) })
+    }
+
+    /// describe the container and audio stream. this reads the start of
+    /// the file from disk to find the encoder delay and padding of MP3s.
+    /// the codec names, stream count and index, bit rate and bits per raw
+    /// sample come from libgroove's private state, so they are only filled
+    /// in with libgroove 4.3.0, with libavformat and libavcodec 55 to 58;
+    /// otherwise, and always with the `libgroove5` feature, they are None,
+    /// or 0 streams.
+    pub fn info(&self) -> FileInfo {
+        let format_names = self.format_names();
+        let codec = unsafe { file_codec_info(self.groove_file.get()) };
+        let lossless = info::lossless_for(codec.name.as_deref(), &format_names);
+
+        let delay_padding = match self.metadata_get("iTunSMPB", false) {
+            Some(tag) => tag.value().ok().and_then(info::parse_itunsmpb),
+            None if self.pcm_reader.is_none() && format_names.iter().any(|name| name == "mp3") =>
+                info::read_lame_delay_padding(self.filename()),
+            None => None,
         };
 
+        FileInfo {
+            format_names,
+            duration: self.duration(),
+            audio_format: self.audio_format(),
+            codec_name: codec.name,
+            codec_long_name: codec.long_name,
+            stream_count: codec.stream_count,
+            stream_index: codec.stream_index,
+            bit_rate: codec.bit_rate,
+            bits_per_raw_sample: codec.bits_per_raw_sample,
+            lossless,
+            encoder_delay: delay_padding.map(|(delay, _)| delay),
+            encoder_padding: delay_padding.map(|(_, padding)| padding),
+        }
+    }
+
+    // the short names libav matched the container format with
+    fn format_names(&self) -> Vec<String> {
         unsafe {
-            groove_file_audio_format(self.groove_file, &mut result);
+            let short_names = groove_file_short_names(self.groove_file.get());
+            if short_names.is_null() {
+                Vec::new()
+            } else {
+                info::split_short_names(&CStr::from_ptr(short_names).to_string_lossy())
+            }
         }
+    }
+
+    /// list the audio streams of an MP4 or Matroska file, read from the
+    /// file on disk. for other formats, or if the container can't be read,
+    /// this describes only the stream being decoded.
+    pub fn audio_streams(&self) -> Vec<AudioStream> {
+        let format_names = self.format_names();
+        let is_format = |name| format_names.iter().any(|format_name| format_name == name);
+        let streams = if self.pcm_reader.is_some() {
+            Vec::new()
+        } else if is_format("mp4") || is_format("mov") {
+            mp4::read_moov(self.filename()).ok().and_then(|moov| moov)
+                .map(|moov| mp4::audio_streams(&moov))
+                .unwrap_or_default()
+        } else if is_format("matroska") || is_format("webm") {
+            matroska::read_segment_child(self.filename(), matroska::TRACKS).ok().and_then(|tracks| tracks)
+                .map(|tracks| matroska::audio_streams(&tracks))
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        if !streams.is_empty() {
+            return streams;
+        }
+
+        let audio_format = self.audio_format();
+        vec![AudioStream {
+            index: unsafe { file_codec_info(self.groove_file.get()) }.stream_index,
+            codec: None,
+            language: None,
+            name: None,
+            sample_rate: Some(audio_format.sample_rate as u32),
+            channel_count: Some(audio_format.channel_layout.count() as u32),
+            default: true,
+        }]
+    }
+
+    /// decode the audio stream at `index` among all of the container's
+    /// streams, as `AudioStream::index` gives it, instead of the one libav
+    /// picked when the file was opened. a file can't change streams once it
+    /// is in a playlist, so choose before adding it to one. fails with
+    /// `AVERROR_STREAM_NOT_FOUND` if there is no audio stream at `index`,
+    /// or with libav's error if its decoder can't be opened. this reaches
+    /// into libgroove's private state, so it fails with -ENOSYS unless
+    /// libgroove is 4.3.0, with libavformat and libavcodec 55 to 58, and
+    /// always with the `libgroove5` feature.
+    pub fn select_audio_stream(&mut self, index: usize) -> Result<(), i32> {
+        err_code_result(unsafe { file_select_audio_stream(self.groove_file.get(), index) })?;
+        self.stream = Some(index);
+        Ok(())
+    }
+
+    /// decode the whole file into memory, converted to `format`.
+    /// the file is opened again from disk to do this, so the File may be
+    /// in use by a playlist at the same time.
+    /// returns None if the file could not be decoded.
+    pub fn decode_all(&self, format: AudioFormat) -> Option<OwnedAudioBuffer> {
+        self.decode(Duration::from_secs(0), None, format)
+    }
+
+    /// decode the audio between `start` and `end` into memory, converted to
+    /// `format`. the result holds exactly `(end - start) * sample_rate`
+    /// frames, rounded to the nearest frame, unless the file ends before
+    /// `end`. see `decode_all`.
+    pub fn decode_range(&self, start: Duration, end: Duration, format: AudioFormat) -> Option<OwnedAudioBuffer> {
+        self.decode(start, Some(end), format)
+    }
 
-        AudioFormat::from_groove(&result)
+    fn decode(&self, start: Duration, end: Option<Duration>, format: AudioFormat) -> Option<OwnedAudioBuffer> {
+        let rate = format.sample_rate as f64;
+        let to_frame = |time: Duration| (time.as_secs_f64() * rate).round() as u64;
+        let start_frame = to_frame(start);
+        let frame_count = end.map(|end| to_frame(end).saturating_sub(start_frame)).unwrap_or(u64::MAX);
+
+        let mut decoder = RangeDecoder::new(self.groove, &self.origin()?, start_frame, format)?;
+        let mut output = OwnedAudioBuffer::new(format);
+        while (output.frame_count() as u64) < frame_count && decoder.read(&mut output) {}
+        output.truncate(frame_count.min(usize::MAX as u64) as usize);
+        Some(output)
     }
 }
 
+/// decodes a file from a given frame onwards. libgroove's seeks land near
+/// the time asked for rather than on it, so this lines buffers up by their
+/// positions to start exactly where it should.
+pub(crate) struct RangeDecoder<'g> {
+    // declared before the playlist so it is dropped, and detached, first
+    sink: Sink<'g>,
+    _playlist: Playlist<'g>,
+    rate: f64,
+    // how far a buffer may start before the frame asked for and still be
+    // taken for where the seek landed, rather than audio from before it
+    tolerance: u64,
+    // the frame the next buffer from the sink starts at, once known
+    cursor: Option<u64>,
+    // the next frame to hand out
+    next_frame: u64,
+}
+
+impl<'g> RangeDecoder<'g> {
+    /// open `origin` again and decode it in `format` from `start_frame`,
+    /// counted in `format`'s sample rate
+    pub(crate) fn new(groove: &'g Groove, origin: &Origin, start_frame: u64, format: AudioFormat) -> Option<Self> {
+        let file = origin.open(groove)?;
+        let rate = format.sample_rate as f64;
+        let tolerance = (rate / 10.0) as u64;
+        let mut playlist = Playlist::new(groove);
+        let sink = Sink::new(groove);
+        sink.set_audio_format(format);
+        let id = playlist.append(file, 1.0, 1.0);
+
+        // seek before attaching, so that nothing from before the seek
+        // reaches the sink
+        let seeking = start_frame > tolerance;
+        if seeking {
+            playlist.seek(id, start_frame as f64 / rate).ok()?;
+        }
+        sink.attach(&playlist).ok()?;
+
+        Some(RangeDecoder {
+            sink,
+            _playlist: playlist,
+            rate,
+            tolerance,
+            cursor: if seeking { None } else { Some(0) },
+            next_frame: start_frame,
+        })
+    }
+
+    /// append the next run of audio to `output`, which must be in the
+    /// decoder's format. returns false at the end of the file.
+    pub(crate) fn read(&mut self, output: &mut OwnedAudioBuffer) -> bool {
+        while let Some(buffer) = self.sink.buffer_get_blocking() {
+            let buffer_start = match self.cursor {
+                Some(cursor) => cursor,
+                None => {
+                    let pos_frame = (buffer.pos().max(0.0) * self.rate).round() as u64;
+                    if pos_frame + self.tolerance < self.next_frame {
+                        // decoded before the seek took effect
+                        continue;
+                    }
+                    pos_frame
+                },
+            };
+            let buffer_end = buffer_start + buffer.frame_count() as u64;
+            self.cursor = Some(buffer_end);
+
+            let take_from = buffer_start.max(self.next_frame);
+            if buffer_end <= take_from {
+                continue;
+            }
+            // a seek can land a little after the point asked for
+            output.extend_silence((take_from - self.next_frame) as usize);
+            output.extend_from_decoded(&buffer, (take_from - buffer_start) as usize, buffer.frame_count());
+            self.next_frame = buffer_end;
+            return true;
+        }
+        false
+    }
+}
+
+fn open_groove_file(filename: &Path) -> *mut GrooveFile {
+    let c_filename = CString::new(filename.as_os_str().as_bytes()).unwrap();
+    unsafe { file_open(c_filename.as_ptr()) }
+}
+
 pub struct MetadataIterator<'a> {
-    file: &'a File,
+    _file: &'a File<'a>,
+    // the handle iteration started with, which stays open even if the file
+    // is reopened
+    groove_file: *mut GrooveFile,
     curr: *const c_void,
 }
 
@@ -174,7 +677,7 @@ impl<'a> Iterator for MetadataIterator<'a> {
 
         unsafe {
             let groove_tag = groove_file_metadata_get(
-                self.file.groove_file,
+                self.groove_file,
                 c_tag_key.as_ptr(),
                 self.curr,
                 0
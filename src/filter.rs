@@ -0,0 +1,514 @@
+// audio filters for a single Sink's output. libgroove 4 resamples each sink
+// through a libav filter graph of its own making but gives no way to add to
+// it, so these run in Rust on every buffer the sink hands out, between
+// libgroove and Sink::buffer_get_blocking. they are modelled on the libav
+// filters of the same names.
+
+use std::collections::VecDeque;
+use std::error;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// one step of a FilterChain
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// multiply every sample by this gain
+    Volume(f64),
+    /// boost or cut by `gain_db` around `frequency` Hz. `q` sets the
+    /// width of the band; higher is narrower.
+    Equalizer { frequency: f64, q: f64, gain_db: f64 },
+    /// second order high pass at `frequency` Hz. a `q` of 0.707 gives a
+    /// Butterworth response.
+    Highpass { frequency: f64, q: f64 },
+    /// second order low pass at `frequency` Hz
+    Lowpass { frequency: f64, q: f64 },
+    /// play faster or slower without changing pitch, by a factor from 0.5
+    /// to 2.0
+    Tempo(f64),
+    /// echoes of the input: `in_gain` applies to the input, then each
+    /// `(delay in seconds, decay)` adds a delayed copy, and `out_gain`
+    /// applies to the sum
+    Echo { in_gain: f64, out_gain: f64, delays: Vec<(f64, f64)> },
+    /// dynamic range compression or expansion. the level of each channel
+    /// is followed with the `attack` and `release` times in seconds and
+    /// mapped through `points`, pairs of input and output level in dB,
+    /// which are joined by straight lines.
+    Compand { attack: f64, release: f64, points: Vec<(f64, f64)> },
+    /// mix the channels: output channel `i` is the sum of each input
+    /// channel `j` times `matrix[i][j]`. missing entries count as 0. there
+    /// are as many output channels as rows, which must be 1 or 2, so this
+    /// can mix stereo down to mono or spread mono across stereo.
+    Pan(Vec<Vec<f64>>),
+}
+
+/// why a filter can't go in a FilterChain
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterError {
+    /// a pan matrix had this many rows, not 1 or 2
+    PanChannels(usize),
+    /// a tempo factor outside 0.5 to 2.0
+    TempoFactor(f64),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FilterError::PanChannels(count) => write!(f, "a pan to {} channels, rather than 1 or 2", count),
+            FilterError::TempoFactor(factor) => write!(f, "tempo factor {} is not from 0.5 to 2.0", factor),
+        }
+    }
+}
+
+impl error::Error for FilterError {}
+
+/// filters applied in order to a Sink's output. see `Sink::set_filters`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FilterChain {
+    filters: Vec<Filter>,
+}
+
+impl FilterChain {
+    pub fn new() -> Self {
+        FilterChain::default()
+    }
+
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// add any filter. fails if it is a pan with other than 1 or 2 rows,
+    /// or a tempo change out of range.
+    pub fn push(self, filter: Filter) -> Result<Self, FilterError> {
+        match filter {
+            Filter::Pan(ref matrix) if !(1..=2).contains(&matrix.len()) =>
+                return Err(FilterError::PanChannels(matrix.len())),
+            Filter::Tempo(factor) if !(0.5..=2.0).contains(&factor) => return Err(FilterError::TempoFactor(factor)),
+            _ => {},
+        }
+        Ok(self.with(filter))
+    }
+
+    // add a filter which can't be out of range
+    fn with(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    pub fn volume(self, gain: f64) -> Self {
+        self.with(Filter::Volume(gain))
+    }
+
+    pub fn volume_db(self, db: f64) -> Self {
+        self.volume(db_to_gain(db))
+    }
+
+    pub fn equalizer(self, frequency: f64, q: f64, gain_db: f64) -> Self {
+        self.with(Filter::Equalizer { frequency, q, gain_db })
+    }
+
+    pub fn highpass(self, frequency: f64) -> Self {
+        self.with(Filter::Highpass { frequency, q: 0.707 })
+    }
+
+    pub fn lowpass(self, frequency: f64) -> Self {
+        self.with(Filter::Lowpass { frequency, q: 0.707 })
+    }
+
+    /// see `Filter::Tempo`. fails if `factor` is outside 0.5 to 2.0.
+    pub fn tempo(self, factor: f64) -> Result<Self, FilterError> {
+        self.push(Filter::Tempo(factor))
+    }
+
+    pub fn echo(self, in_gain: f64, out_gain: f64, delays: &[(f64, f64)]) -> Self {
+        self.with(Filter::Echo { in_gain, out_gain, delays: delays.to_vec() })
+    }
+
+    pub fn compand(self, attack: f64, release: f64, points: &[(f64, f64)]) -> Self {
+        self.with(Filter::Compand { attack, release, points: points.to_vec() })
+    }
+
+    /// see `Filter::Pan`. fails unless `matrix` has 1 or 2 rows.
+    pub fn pan(self, matrix: &[&[f64]]) -> Result<Self, FilterError> {
+        self.push(Filter::Pan(matrix.iter().map(|row| row.to_vec()).collect()))
+    }
+}
+
+fn db_to_gain(db: f64) -> f64 {
+    (db * 0.05 * 10f64.ln()).exp()
+}
+
+/// a FilterChain running on audio of one sample rate and channel count
+pub(crate) struct ChainState {
+    stages: Vec<Stage>,
+    /// how many channels come out, after any pans
+    channel_count: usize,
+    /// frames out for each frame in, after any tempo changes
+    stretch: f64,
+}
+
+impl ChainState {
+    pub(crate) fn new(chain: &FilterChain, sample_rate: i32, channel_count: usize) -> Self {
+        let sample_rate = f64::from(sample_rate);
+        let mut stages = Vec::with_capacity(chain.filters.len());
+        let mut channel_count = channel_count;
+        let mut stretch = 1.0;
+        for filter in &chain.filters {
+            stages.push(Stage::new(filter, sample_rate, channel_count));
+            match *filter {
+                Filter::Pan(ref matrix) => channel_count = matrix.len(),
+                Filter::Tempo(factor) => stretch /= factor,
+                _ => {},
+            }
+        }
+        ChainState { stages, channel_count, stretch }
+    }
+
+    pub(crate) fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    pub(crate) fn stretch(&self) -> f64 {
+        self.stretch
+    }
+
+    /// filter interleaved samples. stages which change the tempo may hold
+    /// some back, or return more than they were given.
+    pub(crate) fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+        self.stages.iter_mut().fold(samples, |samples, stage| stage.process(samples))
+    }
+
+    /// everything still held back, at the end of the audio
+    pub(crate) fn flush(&mut self) -> Vec<f64> {
+        let mut samples = Vec::new();
+        for stage in &mut self.stages {
+            samples = stage.process(samples);
+            samples.extend(stage.flush());
+        }
+        samples
+    }
+}
+
+enum Stage {
+    Volume(f64),
+    Biquad(Biquad),
+    Tempo(Tempo),
+    Echo(Echo),
+    Compand(Compand),
+    Pan { matrix: Vec<Vec<f64>>, channel_count: usize },
+}
+
+impl Stage {
+    fn new(filter: &Filter, sample_rate: f64, channel_count: usize) -> Stage {
+        match *filter {
+            Filter::Volume(gain) => Stage::Volume(gain),
+            Filter::Equalizer { frequency, q, gain_db } =>
+                Stage::Biquad(Biquad::peaking(frequency / sample_rate, q, gain_db, channel_count)),
+            Filter::Highpass { frequency, q } =>
+                Stage::Biquad(Biquad::pass(frequency / sample_rate, q, true, channel_count)),
+            Filter::Lowpass { frequency, q } =>
+                Stage::Biquad(Biquad::pass(frequency / sample_rate, q, false, channel_count)),
+            Filter::Tempo(factor) => Stage::Tempo(Tempo::new(factor, sample_rate, channel_count)),
+            Filter::Echo { in_gain, out_gain, ref delays } =>
+                Stage::Echo(Echo::new(in_gain, out_gain, delays, sample_rate, channel_count)),
+            Filter::Compand { attack, release, ref points } =>
+                Stage::Compand(Compand::new(attack, release, points, sample_rate, channel_count)),
+            Filter::Pan(ref matrix) => Stage::Pan { matrix: matrix.clone(), channel_count },
+        }
+    }
+
+    fn process(&mut self, mut samples: Vec<f64>) -> Vec<f64> {
+        match *self {
+            Stage::Volume(gain) => {
+                for sample in &mut samples {
+                    *sample *= gain;
+                }
+                samples
+            },
+            Stage::Biquad(ref mut biquad) => {
+                biquad.process(&mut samples);
+                samples
+            },
+            Stage::Tempo(ref mut tempo) => tempo.process(&samples),
+            Stage::Echo(ref mut echo) => {
+                echo.process(&mut samples);
+                samples
+            },
+            Stage::Compand(ref mut compand) => {
+                compand.process(&mut samples);
+                samples
+            },
+            Stage::Pan { ref matrix, channel_count } => {
+                let mut output = Vec::with_capacity(samples.len() / channel_count * matrix.len());
+                for frame in samples.chunks(channel_count) {
+                    output.extend(matrix.iter().map(|row| {
+                        frame.iter().zip(row).map(|(sample, gain)| sample * gain).sum::<f64>()
+                    }));
+                }
+                output
+            },
+        }
+    }
+
+    fn flush(&mut self) -> Vec<f64> {
+        match *self {
+            Stage::Tempo(ref mut tempo) => tempo.flush(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// a second order IIR filter, with coefficients from Robert
+// Bristow-Johnson's "Cookbook formulae for audio EQ biquad filter
+// coefficients"
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    // x[n-1], x[n-2], y[n-1], y[n-2] of each channel
+    history: Vec<[f64; 4]>,
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3], channel_count: usize) -> Biquad {
+        Biquad {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+            history: vec![[0.0; 4]; channel_count],
+        }
+    }
+
+    // `frequency` is relative to the sample rate
+    fn peaking(frequency: f64, q: f64, gain_db: f64, channel_count: usize) -> Biquad {
+        let amplitude = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency;
+        let alpha = w0.sin() / (2.0 * q);
+        Biquad::new([1.0 + alpha * amplitude, -2.0 * w0.cos(), 1.0 - alpha * amplitude],
+                    [1.0 + alpha / amplitude, -2.0 * w0.cos(), 1.0 - alpha / amplitude],
+                    channel_count)
+    }
+
+    fn pass(frequency: f64, q: f64, high: bool, channel_count: usize) -> Biquad {
+        let w0 = 2.0 * PI * frequency;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let b = if high {
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0]
+        } else {
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0]
+        };
+        Biquad::new(b, [1.0 + alpha, -2.0 * cos, 1.0 - alpha], channel_count)
+    }
+
+    fn process(&mut self, samples: &mut [f64]) {
+        let channel_count = self.history.len();
+        for frame in samples.chunks_mut(channel_count) {
+            for (sample, history) in frame.iter_mut().zip(&mut self.history) {
+                let [x1, x2, y1, y2] = *history;
+                let x = *sample;
+                let y = self.b[0] * x + self.b[1] * x1 + self.b[2] * x2 - self.a[0] * y1 - self.a[1] * y2;
+                *history = [x, x1, y, y1];
+                *sample = y;
+            }
+        }
+    }
+}
+
+// time stretching by overlap-add of Hann windowed grains, which are taken
+// from the input `factor` times further apart than they are laid down
+struct Tempo {
+    factor: f64,
+    channel_count: usize,
+    window: Vec<f64>,
+    hop: usize,
+    // input not yet used up, and the input frame index of its start
+    input: VecDeque<f64>,
+    input_start: u64,
+    // where the next grain is taken from, as an input frame index
+    next_grain: f64,
+    // output being built up; the first `hop` frames are complete once the
+    // next grain has been added
+    overlap: Vec<f64>,
+    frames_in: u64,
+    frames_out: u64,
+}
+
+impl Tempo {
+    fn new(factor: f64, sample_rate: f64, channel_count: usize) -> Tempo {
+        // grains of about 40ms
+        let hop = ((sample_rate * 0.02) as usize).max(1);
+        let window = (0..hop * 2).map(|n| 0.5 - 0.5 * (PI * n as f64 / hop as f64).cos()).collect();
+        Tempo {
+            factor,
+            channel_count,
+            window,
+            hop,
+            input: VecDeque::new(),
+            input_start: 0,
+            next_grain: 0.0,
+            overlap: vec![0.0; hop * 2 * channel_count],
+            frames_in: 0,
+            frames_out: 0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f64]) -> Vec<f64> {
+        self.input.extend(samples);
+        self.frames_in += (samples.len() / self.channel_count) as u64;
+        let mut output = Vec::new();
+        self.run(&mut output);
+        output
+    }
+
+    fn run(&mut self, output: &mut Vec<f64>) {
+        let channels = self.channel_count;
+        let grain_len = self.window.len();
+        loop {
+            let start = self.next_grain as u64;
+            let offset = (start - self.input_start) as usize;
+            if self.input.len() < (offset + grain_len) * channels {
+                break;
+            }
+            for n in 0..grain_len {
+                for channel in 0..channels {
+                    self.overlap[n * channels + channel] +=
+                        self.input[(offset + n) * channels + channel] * self.window[n];
+                }
+            }
+            let done = self.hop * channels;
+            output.extend(self.overlap.drain(..done));
+            self.overlap.resize(grain_len * channels, 0.0);
+            self.frames_out += self.hop as u64;
+
+            self.next_grain += self.hop as f64 * self.factor;
+            let consumed = (self.next_grain as u64 - self.input_start) as usize;
+            self.input.drain(..consumed * channels);
+            self.input_start += consumed as u64;
+        }
+    }
+
+    // pad the input with silence until as much has come out as the tempo
+    // change calls for
+    fn flush(&mut self) -> Vec<f64> {
+        let target = (self.frames_in as f64 / self.factor).round() as u64;
+        let mut output = Vec::new();
+        while self.frames_out < target {
+            let silence = self.window.len() * self.channel_count;
+            self.input.extend(std::iter::repeat_n(0.0, silence));
+            self.run(&mut output);
+        }
+        let excess = (self.frames_out - target) as usize * self.channel_count;
+        output.truncate(output.len().saturating_sub(excess));
+        self.frames_out = target;
+        output
+    }
+}
+
+struct Echo {
+    in_gain: f64,
+    out_gain: f64,
+    // delays in frames, with their decays
+    taps: Vec<(usize, f64)>,
+    channel_count: usize,
+    // the last `len` input frames, interleaved, as a ring
+    history: Vec<f64>,
+    len: usize,
+    position: usize,
+}
+
+impl Echo {
+    fn new(in_gain: f64, out_gain: f64, delays: &[(f64, f64)], sample_rate: f64, channel_count: usize) -> Echo {
+        let taps: Vec<(usize, f64)> = delays.iter()
+            .map(|&(delay, decay)| (((delay * sample_rate).round() as usize).max(1), decay))
+            .collect();
+        let len = taps.iter().map(|&(delay, _)| delay).max().unwrap_or(0) + 1;
+        Echo {
+            in_gain,
+            out_gain,
+            taps,
+            channel_count,
+            history: vec![0.0; len * channel_count],
+            len,
+            position: 0,
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f64]) {
+        let channels = self.channel_count;
+        for frame in samples.chunks_mut(channels) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                self.history[self.position * channels + channel] = *sample;
+                let mut sum = *sample * self.in_gain;
+                for &(delay, decay) in &self.taps {
+                    let index = (self.position + self.len - delay) % self.len;
+                    sum += self.history[index * channels + channel] * decay;
+                }
+                *sample = sum * self.out_gain;
+            }
+            self.position = (self.position + 1) % self.len;
+        }
+    }
+}
+
+struct Compand {
+    attack: f64,
+    release: f64,
+    points: Vec<(f64, f64)>,
+    // the followed level of each channel, as amplitude
+    levels: Vec<f64>,
+}
+
+impl Compand {
+    fn new(attack: f64, release: f64, points: &[(f64, f64)], sample_rate: f64, channel_count: usize) -> Compand {
+        let coefficient = |time: f64| if time > 0.0 { 1.0 - (-1.0 / (time * sample_rate)).exp() } else { 1.0 };
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Compand {
+            attack: coefficient(attack),
+            release: coefficient(release),
+            points,
+            levels: vec![0.0; channel_count],
+        }
+    }
+
+    // output level in dB for an input level in dB. beyond the first and
+    // last points the gain stays as it is there.
+    fn transfer(&self, db: f64) -> f64 {
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return db,
+        };
+        if db <= first.0 {
+            return db + first.1 - first.0;
+        }
+        if db >= last.0 {
+            return db + last.1 - last.0;
+        }
+        for pair in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if db <= x1 {
+                return if x1 > x0 { y0 + (db - x0) * (y1 - y0) / (x1 - x0) } else { y1 };
+            }
+        }
+        db
+    }
+
+    fn process(&mut self, samples: &mut [f64]) {
+        let channels = self.levels.len();
+        for frame in samples.chunks_mut(channels) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let amplitude = sample.abs();
+                let level = self.levels[channel];
+                let coefficient = if amplitude > level { self.attack } else { self.release };
+                let level = level + (amplitude - level) * coefficient;
+                self.levels[channel] = level;
+                if level > 1e-10 {
+                    let db = 20.0 * level.log10();
+                    *sample *= db_to_gain(self.transfer(db) - db);
+                }
+            }
+        }
+    }
+}
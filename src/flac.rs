@@ -0,0 +1,116 @@
+// reading and rewriting the metadata blocks at the start of a FLAC file
+
+use std::io::{self, Read};
+
+use picture::{Picture, PictureType};
+
+pub(crate) const PADDING: u8 = 1;
+pub(crate) const PICTURE: u8 = 6;
+
+// refuse to load absurd metadata into memory
+const MAX_METADATA_LEN: u64 = 64 * 1024 * 1024;
+
+pub(crate) struct Block {
+    pub(crate) kind: u8,
+    pub(crate) data: Vec<u8>,
+}
+
+/// the metadata blocks of a FLAC stream, with the length of everything up
+/// to the first audio frame, counting from the "fLaC" marker
+pub(crate) struct Metadata {
+    pub(crate) blocks: Vec<Block>,
+    pub(crate) len: u64,
+}
+
+/// read the metadata blocks following the "fLaC" marker at the current
+/// position of `reader`. Ok(None) if this is not a FLAC stream.
+pub(crate) fn read<R: Read>(reader: &mut R) -> io::Result<Option<Metadata>> {
+    let mut marker = [0u8; 4];
+    if reader.read_exact(&mut marker).is_err() || &marker != b"fLaC" {
+        return Ok(None);
+    }
+    let mut blocks = Vec::new();
+    let mut len = 4;
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header)?;
+        let last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let size = u64::from(u32::from_be_bytes([0, header[1], header[2], header[3]]));
+        len += 4 + size;
+        if len > MAX_METADATA_LEN {
+            return Ok(None);
+        }
+        let mut data = vec![0; size as usize];
+        reader.read_exact(&mut data)?;
+        blocks.push(Block { kind, data });
+        if last {
+            break;
+        }
+    }
+    Ok(Some(Metadata { blocks, len }))
+}
+
+/// serialise metadata blocks, "fLaC" marker included, followed by a
+/// padding block of `padding` bytes
+pub(crate) fn write(blocks: &[Block], padding: usize) -> Vec<u8> {
+    let mut out = b"fLaC".to_vec();
+    let padding_block = Block { kind: PADDING, data: vec![0; padding] };
+    let count = blocks.len() + 1;
+    for (index, block) in blocks.iter().chain(Some(&padding_block)).enumerate() {
+        let last = if index + 1 == count { 0x80 } else { 0 };
+        let size = (block.data.len() as u32).to_be_bytes();
+        out.push(last | block.kind);
+        out.extend_from_slice(&size[1..]);
+        out.extend_from_slice(&block.data);
+    }
+    out
+}
+
+/// decode the body of a PICTURE block
+pub(crate) fn parse_picture(data: &[u8]) -> Option<Picture> {
+    let mut pos = 0;
+    let mut next_u32 = || {
+        let value = be_u32(data, pos);
+        pos += 4;
+        value
+    };
+    let picture_type = next_u32()?;
+    let mime_len = next_u32()? as usize;
+    let mime_start = pos;
+    pos += mime_len;
+    let description_len = be_u32(data, pos)? as usize;
+    let description_start = pos + 4;
+    // width, height, colour depth and number of colours are skipped
+    let data_len_at = description_start + description_len + 16;
+    let data_len = be_u32(data, data_len_at)? as usize;
+    let data_start = data_len_at + 4;
+
+    Some(Picture {
+        picture_type: PictureType::from_id(picture_type.min(255) as u8),
+        mime_type: String::from_utf8_lossy(data.get(mime_start..mime_start + mime_len)?).into_owned(),
+        description: String::from_utf8_lossy(data.get(description_start..description_start + description_len)?)
+            .into_owned(),
+        data: data.get(data_start..data_start + data_len)?.to_vec(),
+    })
+}
+
+/// encode a PICTURE block. dimensions and colour depth are left as 0,
+/// meaning unknown.
+pub(crate) fn picture_block(picture: &Picture) -> Block {
+    let mut data = Vec::with_capacity(picture.data.len() + 64);
+    data.extend_from_slice(&u32::from(picture.picture_type.id()).to_be_bytes());
+    data.extend_from_slice(&(picture.mime_type.len() as u32).to_be_bytes());
+    data.extend_from_slice(picture.mime_type.as_bytes());
+    data.extend_from_slice(&(picture.description.len() as u32).to_be_bytes());
+    data.extend_from_slice(picture.description.as_bytes());
+    data.extend_from_slice(&[0; 16]);
+    data.extend_from_slice(&(picture.data.len() as u32).to_be_bytes());
+    data.extend_from_slice(&picture.data);
+    Block { kind: PICTURE, data }
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
@@ -0,0 +1,323 @@
+// reading and rewriting ID3v2 tags, for the frames libav does not turn
+// into metadata
+
+use std::io::{self, Read};
+use std::time::Duration;
+
+use chapter::Mark;
+use picture::{Picture, PictureType};
+
+/// a parsed ID3v2 tag
+pub(crate) struct Tag {
+    pub(crate) major_version: u8,
+    /// length of the whole tag in the file, header and footer included
+    pub(crate) len: u64,
+    pub(crate) frames: Vec<Frame>,
+}
+
+pub(crate) struct Frame {
+    pub(crate) id: String,
+    /// format flags as stored, always 0 for ID3v2.2
+    pub(crate) flags: u16,
+    /// the frame body, with unsynchronisation undone when the tag or
+    /// frame asked for it
+    pub(crate) data: Vec<u8>,
+}
+
+impl Frame {
+    /// compressed and encrypted frames can't be interpreted
+    pub(crate) fn is_readable(&self, major_version: u8) -> bool {
+        match major_version {
+            3 => self.flags & 0x00c0 == 0,
+            4 => self.flags & 0x000c == 0,
+            _ => true,
+        }
+    }
+
+    /// the frame body without the extra bytes some format flags add
+    pub(crate) fn body(&self, major_version: u8) -> &[u8] {
+        let skip = match major_version {
+            // group id
+            3 => usize::from(self.flags & 0x0020 != 0),
+            // group id and data length indicator
+            4 => usize::from(self.flags & 0x0040 != 0) + 4 * usize::from(self.flags & 0x0001 != 0),
+            _ => 0,
+        };
+        self.data.get(skip..).unwrap_or(&[])
+    }
+}
+
+/// read the ID3v2 tag at the start of `reader`. Ok(None) if there is
+/// none or it is not a version we understand.
+pub(crate) fn read<R: Read>(reader: &mut R) -> io::Result<Option<Tag>> {
+    let mut header = [0u8; 10];
+    if read_up_to(reader, &mut header)? < 10 || &header[0..3] != b"ID3" {
+        return Ok(None);
+    }
+    let major_version = header[3];
+    if !(2..=4).contains(&major_version) {
+        return Ok(None);
+    }
+    let flags = header[5];
+    let size = synchsafe(&header[6..10]);
+    let footer = if major_version == 4 && flags & 0x10 != 0 { 10 } else { 0 };
+
+    let mut body = vec![0; size as usize];
+    reader.read_exact(&mut body)?;
+    // before 2.4 unsynchronisation applies to the whole tag
+    if flags & 0x80 != 0 && major_version < 4 {
+        body = resynchronise(&body);
+    }
+
+    let mut pos = 0;
+    if flags & 0x40 != 0 {
+        pos = match major_version {
+            3 => 4 + be_u32(&body, 0).unwrap_or(0) as usize,
+            4 => body.get(0..4).map_or(0, synchsafe) as usize,
+            _ => 0,
+        };
+    }
+
+    Ok(Some(Tag {
+        major_version,
+        len: 10 + size + footer,
+        frames: frames(major_version, flags, &body, pos),
+    }))
+}
+
+// the frames in `body` from `pos`, given the tag's version and flags
+fn frames(major_version: u8, flags: u8, body: &[u8], mut pos: usize) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let (id_len, header_len) = if major_version == 2 { (3, 6) } else { (4, 10) };
+    while pos + header_len <= body.len() {
+        let frame_header = &body[pos..pos + header_len];
+        // padding
+        if frame_header[0] == 0 {
+            break;
+        }
+        let id = String::from_utf8_lossy(&frame_header[..id_len]).into_owned();
+        let (frame_size, frame_flags) = match major_version {
+            2 => ((u32::from(frame_header[3]) << 16) | (u32::from(frame_header[4]) << 8) |
+                  u32::from(frame_header[5]), 0),
+            3 => (be_u32(frame_header, 4).unwrap(), u16::from_be_bytes([frame_header[8], frame_header[9]])),
+            _ => (synchsafe(&frame_header[4..8]) as u32, u16::from_be_bytes([frame_header[8], frame_header[9]])),
+        };
+        let start = pos + header_len;
+        let end = start + frame_size as usize;
+        if end > body.len() {
+            break;
+        }
+        let mut data = body[start..end].to_vec();
+        if major_version == 4 && (frame_flags & 0x0002 != 0 || flags & 0x80 != 0) {
+            data = resynchronise(&data);
+        }
+        // the data now holds no unsynchronisation, whatever the flag said
+        let frame_flags = if major_version == 4 { frame_flags & !0x0002 } else { frame_flags };
+        frames.push(Frame { id, flags: frame_flags, data });
+        pos = end;
+    }
+
+    frames
+}
+
+/// serialise an ID3v2.3 or ID3v2.4 tag, without unsynchronisation
+pub(crate) fn write(major_version: u8, frames: &[Frame], padding: usize) -> Vec<u8> {
+    debug_assert!(major_version == 3 || major_version == 4);
+    let mut body = Vec::new();
+    for frame in frames {
+        body.extend_from_slice(frame.id.as_bytes());
+        let size = frame.data.len() as u32;
+        if major_version == 4 {
+            body.extend_from_slice(&to_synchsafe(size));
+        } else {
+            body.extend_from_slice(&size.to_be_bytes());
+        }
+        body.extend_from_slice(&frame.flags.to_be_bytes());
+        body.extend_from_slice(&frame.data);
+    }
+    body.resize(body.len() + padding, 0);
+
+    let mut tag = Vec::with_capacity(10 + body.len());
+    tag.extend_from_slice(b"ID3");
+    tag.push(major_version);
+    tag.push(0);
+    tag.push(0);
+    tag.extend_from_slice(&to_synchsafe(body.len() as u32));
+    tag.extend_from_slice(&body);
+    tag
+}
+
+pub(crate) fn is_picture_frame(id: &str) -> bool {
+    id == "APIC" || id == "PIC"
+}
+
+/// decode an APIC (ID3v2.3 and later) or PIC (ID3v2.2) frame body
+pub(crate) fn parse_picture(id: &str, body: &[u8]) -> Option<Picture> {
+    let encoding = *body.first()?;
+    let (mime_type, rest) = if id == "PIC" {
+        let format = String::from_utf8_lossy(body.get(1..4)?).to_ascii_lowercase();
+        let mime_type = match format.as_str() {
+            "jpg" => "image/jpeg".to_string(),
+            "-->" => "-->".to_string(),
+            other => format!("image/{}", other),
+        };
+        (mime_type, body.get(4..)?)
+    } else {
+        let rest = body.get(1..)?;
+        let end = rest.iter().position(|&byte| byte == 0)?;
+        (latin1(&rest[..end]), &rest[end + 1..])
+    };
+    let picture_type = PictureType::from_id(*rest.first()?);
+    let (description, data) = split_text(encoding, rest.get(1..)?)?;
+    Some(Picture {
+        picture_type,
+        mime_type,
+        description,
+        data: data.to_vec(),
+    })
+}
+
+/// encode an APIC frame for an ID3v2.3 or ID3v2.4 tag
+pub(crate) fn picture_frame(picture: &Picture, major_version: u8) -> Frame {
+    // UTF-8 only exists since ID3v2.4
+    let encoding = if picture.description.is_ascii() {
+        0
+    } else if major_version == 4 {
+        3
+    } else {
+        1
+    };
+    let mut data = Vec::with_capacity(picture.data.len() + 64);
+    data.push(encoding);
+    data.extend_from_slice(picture.mime_type.as_bytes());
+    data.push(0);
+    data.push(picture.picture_type.id());
+    match encoding {
+        1 => {
+            data.extend_from_slice(&[0xff, 0xfe]);
+            for unit in picture.description.encode_utf16() {
+                data.extend_from_slice(&unit.to_le_bytes());
+            }
+            data.extend_from_slice(&[0, 0]);
+        },
+        _ => {
+            data.extend_from_slice(picture.description.as_bytes());
+            data.push(0);
+        },
+    }
+    data.extend_from_slice(&picture.data);
+    Frame { id: "APIC".to_string(), flags: 0, data }
+}
+
+/// the chapters of CHAP frames (ID3v2.3 and later), each titled by the TIT2
+/// frame inside it
+pub(crate) fn chapters(tag: &Tag) -> Vec<Mark> {
+    tag.frames.iter()
+        .filter(|frame| frame.id == "CHAP" && frame.is_readable(tag.major_version))
+        .filter_map(|frame| {
+            // element id, start and end in milliseconds, start and end byte
+            // offsets, then frames of its own
+            let body = frame.body(tag.major_version);
+            let body = &body[body.iter().position(|&byte| byte == 0)? + 1..];
+            let (start, end) = (be_u32(body, 0)?, be_u32(body, 4)?);
+            let title = frames(tag.major_version, 0, body.get(16..)?, 0).iter()
+                .find(|frame| frame.id == "TIT2" && frame.is_readable(tag.major_version))
+                .and_then(|frame| text(frame.body(tag.major_version)))
+                .unwrap_or_default();
+            Some(Mark {
+                start: Duration::from_millis(u64::from(start)),
+                end: Some(Duration::from_millis(u64::from(end))),
+                title,
+            })
+        })
+        .collect()
+}
+
+// the first string of a text frame, which needn't be terminated
+fn text(body: &[u8]) -> Option<String> {
+    let (&encoding, text) = body.split_first()?;
+    let mut text = text.to_vec();
+    text.extend_from_slice(&[0, 0]);
+    split_text(encoding, &text).map(|(text, _)| text)
+}
+
+/// split a terminated string in `encoding` off the front of `data`
+pub(crate) fn split_text(encoding: u8, data: &[u8]) -> Option<(String, &[u8])> {
+    match encoding {
+        0 | 3 => {
+            let end = data.iter().position(|&byte| byte == 0)?;
+            let text = if encoding == 0 {
+                latin1(&data[..end])
+            } else {
+                String::from_utf8_lossy(&data[..end]).into_owned()
+            };
+            Some((text, &data[end + 1..]))
+        },
+        1 | 2 => {
+            let end = data.chunks(2).position(|pair| pair == [0, 0])? * 2;
+            Some((utf16(encoding, &data[..end]), &data[end + 2..]))
+        },
+        _ => None,
+    }
+}
+
+fn utf16(encoding: u8, data: &[u8]) -> String {
+    let (big_endian, data) = match data {
+        [0xfe, 0xff, rest @ ..] => (true, rest),
+        [0xff, 0xfe, rest @ ..] => (false, rest),
+        _ => (encoding == 2, data),
+    };
+    let units: Vec<u16> = data.chunks_exact(2)
+        .map(|pair| if big_endian {
+            u16::from_be_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_le_bytes([pair[0], pair[1]])
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn latin1(data: &[u8]) -> String {
+    data.iter().map(|&byte| byte as char).collect()
+}
+
+fn resynchronise(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut previous = 0;
+    for &byte in data {
+        if !(previous == 0xff && byte == 0) {
+            out.push(byte);
+        }
+        previous = byte;
+    }
+    out
+}
+
+fn synchsafe(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |value, &byte| (value << 7) | u64::from(byte & 0x7f))
+}
+
+fn to_synchsafe(value: u32) -> [u8; 4] {
+    [
+        ((value >> 21) & 0x7f) as u8,
+        ((value >> 14) & 0x7f) as u8,
+        ((value >> 7) & 0x7f) as u8,
+        (value & 0x7f) as u8,
+    ]
+}
+
+pub(crate) fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            count => len += count,
+        }
+    }
+    Ok(len)
+}
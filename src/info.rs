@@ -0,0 +1,176 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use audio_format::AudioFormat;
+
+/// what is known about a file's container and audio stream.
+/// see `File::info`.
+#[derive(Clone, Debug)]
+pub struct FileInfo {
+    /// short names libav matched the container format with, for example
+    /// `["mov", "mp4", "m4a", "3gp", "3g2", "mj2"]`
+    pub format_names: Vec<String>,
+    /// see `File::duration`
+    pub duration: f64,
+    /// see `File::audio_format`
+    pub audio_format: AudioFormat,
+    /// short name of the codec libav decodes the audio with, for example
+    /// `mp3` or `pcm_s16le`
+    pub codec_name: Option<String>,
+    /// descriptive name of the codec, for example
+    /// "MP3 (MPEG audio layer 3)"
+    pub codec_long_name: Option<String>,
+    /// number of streams in the container, whatever their type
+    pub stream_count: usize,
+    /// index of the stream being decoded, among all the container's streams
+    pub stream_index: Option<usize>,
+    /// bits per second of the decoded stream, as its codec or container
+    /// reports it. None when neither does.
+    pub bit_rate: Option<u64>,
+    /// bits per sample before decoding, which lossless codecs report.
+    /// `audio_format` gives the sample format after decoding.
+    pub bits_per_raw_sample: Option<u32>,
+    /// whether the codec is lossless, or failing that, whether the container
+    /// can only hold lossless or only lossy audio. None when unknown.
+    pub lossless: Option<bool>,
+    /// samples of silence the encoder added to the start of the audio,
+    /// as recorded in a LAME header or an iTunSMPB tag
+    pub encoder_delay: Option<u32>,
+    /// samples of silence the encoder added to the end of the audio,
+    /// as recorded in a LAME header or an iTunSMPB tag
+    pub encoder_padding: Option<u32>,
+}
+
+/// an audio stream inside a container. see `File::audio_streams`.
+#[derive(Clone, Debug)]
+pub struct AudioStream {
+    /// index of the stream among all the streams of the container,
+    /// whatever their type. None when unknown.
+    pub index: Option<usize>,
+    /// codec as the container names it: a fourcc such as `mp4a` for MP4,
+    /// a codec id such as `A_AAC` for Matroska
+    pub codec: Option<String>,
+    /// language tag, such as `eng` or `en-GB`. None when undetermined.
+    pub language: Option<String>,
+    /// track title, for example "Director's commentary"
+    pub name: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channel_count: Option<u32>,
+    /// whether the container marks this stream as enabled or default
+    pub default: bool,
+}
+
+impl FileInfo {
+    /// whether any of the container's short names is `name`
+    pub fn is_format(&self, name: &str) -> bool {
+        self.format_names.iter().any(|format_name| format_name == name)
+    }
+}
+
+pub(crate) fn split_short_names(short_names: &str) -> Vec<String> {
+    short_names.split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+pub(crate) fn lossless_for(codec_name: Option<&str>, format_names: &[String]) -> Option<bool> {
+    match codec_name {
+        Some(name) if name.starts_with("pcm_") => return Some(true),
+        Some("flac") | Some("alac") | Some("wavpack") | Some("ape") | Some("tta") | Some("tak")
+            | Some("shorten") | Some("mlp") | Some("truehd") => return Some(true),
+        Some("mp3") | Some("mp2") | Some("aac") | Some("vorbis") | Some("opus") | Some("ac3")
+            | Some("eac3") | Some("wmav2") | Some("dts") => return Some(false),
+        _ => {},
+    }
+    for name in format_names {
+        match name.as_str() {
+            "flac" | "wav" | "w64" | "aiff" | "wv" | "ape" | "tta" | "tak" | "shn" | "caf_pcm" =>
+                return Some(true),
+            "mp3" | "mp2" | "aac" | "ac3" | "eac3" | "amr" | "mpc" | "mpc8" | "dts" =>
+                return Some(false),
+            _ => {},
+        }
+    }
+    None
+}
+
+/// parse delay and padding from the value of an iTunSMPB tag:
+/// ` 00000000 00000840 000001CA 00000000003F31F6 ...` in hex
+pub(crate) fn parse_itunsmpb(value: &str) -> Option<(u32, u32)> {
+    let mut fields = value.split_whitespace().skip(1);
+    let delay = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let padding = u32::from_str_radix(fields.next()?, 16).ok()?;
+    Some((delay, padding))
+}
+
+/// find the delay and padding stored in the LAME extension of the Xing or
+/// Info header in the first frame of an MP3 file
+pub(crate) fn read_lame_delay_padding(path: &Path) -> Option<(u32, u32)> {
+    let mut file = fs::File::open(path).ok()?;
+    let start = id3v2_len(&mut file).ok()?;
+    file.seek(SeekFrom::Start(start)).ok()?;
+
+    let mut frame = [0u8; 512];
+    let len = read_up_to(&mut file, &mut frame).ok()?;
+    let frame = &frame[..len];
+
+    // frame sync, then find where the side information ends
+    if frame.len() < 4 || frame[0] != 0xff || frame[1] & 0xe0 != 0xe0 {
+        return None;
+    }
+    let mpeg1 = frame[1] & 0x18 == 0x18;
+    let mono = frame[3] & 0xc0 == 0xc0;
+    let side_info_len = match (mpeg1, mono) {
+        (true, true) => 17,
+        (true, false) => 32,
+        (false, true) => 9,
+        (false, false) => 17,
+    };
+
+    let xing = 4 + side_info_len;
+    let tag = frame.get(xing..xing + 4)?;
+    if tag != b"Xing" && tag != b"Info" {
+        return None;
+    }
+    let flags = u32::from_be_bytes([frame[xing + 4], frame[xing + 5], frame[xing + 6], frame[xing + 7]]);
+    let mut lame = xing + 8;
+    if flags & 0x1 != 0 { lame += 4; }
+    if flags & 0x2 != 0 { lame += 4; }
+    if flags & 0x4 != 0 { lame += 100; }
+    if flags & 0x8 != 0 { lame += 4; }
+
+    // 9 byte encoder name, then 12 more bytes before delay and padding
+    let encoder = frame.get(lame..lame + 4)?;
+    if encoder != b"LAME" && encoder != b"Lavf" && encoder != b"Lavc" {
+        return None;
+    }
+    let bytes = frame.get(lame + 21..lame + 24)?;
+    let delay = (u32::from(bytes[0]) << 4) | (u32::from(bytes[1]) >> 4);
+    let padding = ((u32::from(bytes[1]) & 0x0f) << 8) | u32::from(bytes[2]);
+    Some((delay, padding))
+}
+
+/// length of the ID3v2 tag at the start of `file`, 0 if there is none
+fn id3v2_len(file: &mut fs::File) -> io::Result<u64> {
+    let mut header = [0u8; 10];
+    if read_up_to(file, &mut header)? < 10 || &header[0..3] != b"ID3" {
+        return Ok(0);
+    }
+    let size = header[6..10].iter().fold(0u64, |size, &byte| (size << 7) | u64::from(byte & 0x7f));
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    Ok(10 + size + footer)
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            count => len += count,
+        }
+    }
+    Ok(len)
+}
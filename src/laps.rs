@@ -0,0 +1,242 @@
+// repeating a whole playlist. libgroove stops at the end of its list, so
+// while a playlist repeats, copies of its items follow its own: laps, which
+// play the same files again. new laps go on as the playlist reaches them,
+// and old ones come off once they have played.
+
+use c_api::{
+    GrooveFile,
+    GroovePlaylist,
+    GroovePlaylistItem,
+    groove_playlist_insert,
+    groove_playlist_position,
+    groove_playlist_remove,
+};
+
+use std::panic;
+use std::ptr;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+// laps kept ahead of the item being decoded
+const LAPS_AHEAD: usize = 2;
+
+/// what a playlist item plays
+#[derive(Clone, Copy)]
+pub(crate) struct Source {
+    pub(crate) key: u64,
+    /// the item itself in libgroove's list
+    pub(crate) item: *mut GroovePlaylistItem,
+    pub(crate) file: *mut GrooveFile,
+    pub(crate) gain: f64,
+    pub(crate) peak: f64,
+}
+
+#[derive(Clone, Copy)]
+struct Copied {
+    key: u64,
+    item: *mut GroovePlaylistItem,
+}
+
+pub(crate) struct Laps {
+    // None once the playlist is gone
+    playlist: Option<*mut GroovePlaylist>,
+    pub(crate) repeat: bool,
+    /// the playlist's own items, in order. changed along with libgroove's
+    /// list, under the same lock, so that whatever a buffer came from can
+    /// be found here.
+    pub(crate) sources: Vec<Source>,
+    // in libgroove's order, after the playlist's own items
+    copies: Vec<Copied>,
+}
+
+// only used under its mutex, and libgroove's playlist functions may be
+// called from any thread
+unsafe impl Send for Laps {}
+
+impl Laps {
+    pub(crate) fn new(playlist: *mut GroovePlaylist) -> Self {
+        Laps {
+            playlist: Some(playlist),
+            repeat: false,
+            sources: Vec::new(),
+            copies: Vec::new(),
+        }
+    }
+
+    /// the playlist is about to be destroyed
+    pub(crate) fn close(&mut self) {
+        self.playlist = None;
+        self.copies.clear();
+    }
+
+    /// libgroove cleared the playlist, copies and all
+    pub(crate) fn cleared(&mut self) {
+        self.copies.clear();
+    }
+
+    /// the first copy, which the playlist's own items go before
+    pub(crate) fn first_copy(&self) -> *mut GroovePlaylistItem {
+        self.copies.first().map_or(ptr::null_mut(), |copy| copy.item)
+    }
+
+    /// the playlist item a libgroove item plays, copies included
+    pub(crate) fn key_of(&self, item: *mut GroovePlaylistItem) -> Option<u64> {
+        self.sources.iter().find(|source| source.item == item).map(|source| source.key)
+            .or_else(|| self.copies.iter().find(|copy| copy.item == item).map(|copy| copy.key))
+    }
+
+    /// where the item a buffer came from is in the playlist
+    pub(crate) fn index_of(&self, item: *mut GroovePlaylistItem) -> Option<usize> {
+        let key = self.key_of(item)?;
+        self.sources.iter().position(|source| source.key == key)
+    }
+
+    /// take out the copies of an item, which is about to be removed
+    pub(crate) fn forget(&mut self, key: u64) {
+        let playlist = match self.playlist {
+            Some(playlist) => playlist,
+            None => return,
+        };
+        self.copies.retain(|copy| {
+            if copy.key == key {
+                unsafe { groove_playlist_remove(playlist, copy.item) };
+            }
+            copy.key != key
+        });
+    }
+
+    /// lay the laps out again after `sources` or `repeat` change: the lap
+    /// being played finishes in the new order, and those after it start
+    /// over
+    pub(crate) fn rebuild(&mut self) {
+        let playlist = match self.playlist {
+            Some(playlist) => playlist,
+            None => return,
+        };
+        let head = decode_head(playlist);
+        // at the end, or before the start, there are no laps to follow
+        if head.is_null() {
+            return;
+        }
+        let reached = self.copies.iter().position(|copy| copy.item == head);
+        let keep = reached.map_or(0, |index| index + 1);
+        for copy in self.copies.drain(keep..) {
+            unsafe { groove_playlist_remove(playlist, copy.item) };
+        }
+        if !self.repeat {
+            return;
+        }
+        if let Some(index) = reached {
+            let key = self.copies[index].key;
+            if let Some(position) = self.sources.iter().position(|source| source.key == key) {
+                let rest = self.sources[position + 1..].to_vec();
+                self.append(playlist, &rest);
+            }
+        }
+        self.extend();
+    }
+
+    /// keep enough laps ahead of the item being decoded, and take off
+    /// those long since played
+    pub(crate) fn extend(&mut self) {
+        let playlist = match self.playlist {
+            Some(playlist) if self.repeat && !self.sources.is_empty() => playlist,
+            _ => return,
+        };
+        let head = decode_head(playlist);
+        if head.is_null() {
+            return;
+        }
+        let reached = self.copies.iter().position(|copy| copy.item == head);
+        let ahead = self.copies.len() - reached.map_or(0, |index| index + 1);
+        for _ in ahead / self.sources.len()..LAPS_AHEAD {
+            let lap = self.sources.clone();
+            self.append(playlist, &lap);
+        }
+
+        // a lap behind may still be in the sinks' queues, and libgroove
+        // drops what is queued of an item it removes
+        let behind = reached.unwrap_or(0).saturating_sub(self.sources.len());
+        for copy in self.copies.drain(..behind) {
+            unsafe { groove_playlist_remove(playlist, copy.item) };
+        }
+    }
+
+    fn append(&mut self, playlist: *mut GroovePlaylist, sources: &[Source]) {
+        for source in sources {
+            let item = unsafe {
+                groove_playlist_insert(playlist, source.file, source.gain, source.peak, ptr::null_mut())
+            };
+            if item.is_null() {
+                panic!("out of memory");
+            }
+            self.copies.push(Copied { key: source.key, item });
+        }
+    }
+}
+
+/// runs `Laps::extend` on a thread of its own, for callers which libgroove
+/// may be waiting on. the playlist holds the laps while calling into
+/// libgroove, so those callers can't take them either. the thread starts
+/// with the first request, and `stop` waits for it to finish.
+pub(crate) struct Extender {
+    laps: Arc<Mutex<Laps>>,
+    thread: Mutex<ExtenderThread>,
+}
+
+#[derive(Default)]
+struct ExtenderThread {
+    requests: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+    stopped: bool,
+}
+
+impl Extender {
+    pub(crate) fn new(laps: &Arc<Mutex<Laps>>) -> Self {
+        Extender { laps: laps.clone(), thread: Mutex::new(ExtenderThread::default()) }
+    }
+
+    /// have the laps extended soon, unless stopped
+    pub(crate) fn request(&self) {
+        let mut thread = self.thread.lock().unwrap();
+        if thread.stopped {
+            return;
+        }
+        if thread.requests.is_none() {
+            let (sender, receiver) = mpsc::channel();
+            let laps = self.laps.clone();
+            thread.handle = Some(thread::spawn(move || {
+                while receiver.recv().is_ok() {
+                    // requests made meanwhile are answered by this extend
+                    receiver.try_iter().count();
+                    laps.lock().unwrap().extend();
+                }
+            }));
+            thread.requests = Some(sender);
+        }
+        let _ = thread.requests.as_ref().unwrap().send(());
+    }
+
+    /// refuse further requests, and wait for the thread to finish those
+    /// made so far. a panic on the thread carries on here.
+    pub(crate) fn stop(&self) {
+        let handle = {
+            let mut thread = self.thread.lock().unwrap();
+            thread.stopped = true;
+            thread.requests = None;
+            thread.handle.take()
+        };
+        if let Some(Err(panic)) = handle.map(JoinHandle::join) {
+            if !thread::panicking() {
+                panic::resume_unwind(panic);
+            }
+        }
+    }
+}
+
+fn decode_head(playlist: *mut GroovePlaylist) -> *mut GroovePlaylistItem {
+    let mut head = ptr::null_mut();
+    unsafe { groove_playlist_position(playlist, &mut head, ptr::null_mut()) };
+    head
+}
@@ -4,20 +4,35 @@ mod c_api;
 
 mod audio_format;
 mod buffer;
+mod chapter;
+mod context;
+mod cue;
 mod encoder;
+mod events;
 mod file;
+mod filter;
+mod flac;
+mod id3;
+mod info;
+mod laps;
+#[cfg(feature = "log")]
+mod log_bridge;
+mod matroska;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod mp4;
+mod pcm_source;
+mod picture;
 mod playlist;
+mod playlist_file;
+mod render;
 mod sink;
+mod transcode;
+mod wav;
 
-use std::sync::{Once, ONCE_INIT};
 use std::ffi::CStr;
 
-use libc::c_int;
-
 use c_api::{
-    groove_init,
-    groove_finish,
-    groove_set_logging,
     groove_version_major,
     groove_version_minor,
     groove_version_patch,
@@ -27,64 +42,88 @@ pub use audio_format::{
     ChannelLayout,
     SampleFormat,
     SampleType,
+    Sample,
     AudioFormat
 };
 pub use buffer::{
     EncodedBuffer,
-    DecodedBuffer
+    DecodedBuffer,
+    OwnedAudioBuffer
+};
+pub use chapter::Chapter;
+pub use context::{
+    Groove,
+    Error
+};
+pub use cue::{
+    CueError,
+    CueSheet,
+    CueTrack
+};
+pub use encoder::{
+    Encoder,
+    MetadataMode
+};
+pub use events::{
+    Notification,
+    PlaylistEvent
 };
-pub use encoder::Encoder;
 pub use file::{
     File,
     MetadataIterator,
     Tag
 };
+pub use filter::{
+    Filter,
+    FilterChain,
+    FilterError,
+};
+pub use info::{
+    AudioStream,
+    FileInfo
+};
+#[cfg(feature = "log")]
+pub use log_bridge::LogMessage;
+pub use pcm_source::PcmSource;
+pub use picture::{
+    Picture,
+    PictureType
+};
 pub use playlist::{
+    AbLoopError,
     Playlist,
     PlaylistItem,
-    FillMode
+    Fade,
+    FadeShape,
+    FillMode,
+    ItemId,
+    ItemOptions,
+    RepeatMode,
+    StaleItem,
+    Transition
+};
+pub use playlist_file::{
+    Loaded,
+    PlaylistEntry
 };
 pub use sink::Sink;
+pub use transcode::{
+    transcode,
+    TranscodeOptions,
+    TranscodeError,
+    Progress
+};
+pub use wav::WavWriter;
 
-fn init() {
-    static mut INIT: Once = ONCE_INIT;
-
-    unsafe {
-        INIT.call_once(|| {
-            let err_code = groove_init();
-            if err_code != 0 {
-                panic!("groove_init() failed");
-            }
-        });
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
+/// how much libav and libgroove log, from least to most
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Log {
     Quiet,
     Error,
     Warning,
     Info,
-}
-
-/// Call at the end of your program to clean up. After calling this you may no
-/// longer use this API. You may choose to never call this function, in which
-/// case the worst thing that can happen is valgrind may report a memory leak.
-pub fn finish() {
-    init();
-    unsafe { groove_finish() }
-}
-
-/// enable/disable logging of errors
-pub fn set_logging(level: Log) {
-    init();
-    let c_level: c_int = match level {
-        Log::Quiet   => -8,
-        Log::Error   => 16,
-        Log::Warning => 24,
-        Log::Info    => 32,
-    };
-    unsafe { groove_set_logging(c_level) }
+    Verbose,
+    Debug,
 }
 
 pub fn version_major() -> i32 {
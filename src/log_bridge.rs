@@ -0,0 +1,268 @@
+// forwards libav's log messages, which libav would otherwise print to
+// stderr, to the log crate, and keeps those about each File
+
+extern crate log;
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+use libc::{c_char, c_int, c_void};
+
+use c_api::{
+    AVClass,
+    GrooveFile,
+    av_log_format_line,
+    av_log_get_level,
+    av_log_set_callback,
+    file_log_contexts,
+};
+use Log;
+
+// keep error reports from growing without bound when a file is damaged
+const MAX_FILE_MESSAGES: usize = 256;
+// messages about watched contexts waiting to be sorted into file logs.
+// more are dropped until something reads a log.
+const MAX_QUEUED_MESSAGES: usize = 1024;
+
+/// a message libav or libgroove logged. see `File::log_messages`.
+#[derive(Clone, Debug)]
+pub struct LogMessage {
+    pub level: Log,
+    /// `groove::libav`, followed by the name of the libav component which
+    /// logged the message if there was one, for example
+    /// `groove::libav::mp3`
+    pub target: String,
+    pub message: String,
+}
+
+/// the messages about one File: those logged on its own thread while
+/// `capture` ran, and those its handles' libav contexts log later from
+/// any thread while `watch`ed.
+#[derive(Default)]
+pub(crate) struct FileLog {
+    messages: Arc<Mutex<Vec<LogMessage>>>,
+}
+
+// a libav context whose messages go to a file log, from the message
+// numbered `since` on, so that one logged by an earlier context at the
+// same address is not taken for its
+struct Watch {
+    context: usize,
+    since: u64,
+    messages: Weak<Mutex<Vec<LogMessage>>>,
+}
+
+// a message logged outside `capture`, numbered in the order it was logged
+struct Queued {
+    number: u64,
+    context: usize,
+    message: LogMessage,
+}
+
+struct Watches {
+    watches: Vec<Watch>,
+    queue: Receiver<Queued>,
+}
+
+// the callback only ever queues messages, so that it takes no locks which
+// file code might hold while calling into libav. they are sorted into file
+// logs under WATCHES when a log is read or changed.
+static QUEUE: OnceLock<SyncSender<Queued>> = OnceLock::new();
+static WATCHES: OnceLock<Mutex<Watches>> = OnceLock::new();
+static WATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+static NEXT_NUMBER: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // the start of a line libav has not finished yet
+    static PARTIAL_LINE: RefCell<String> = const { RefCell::new(String::new()) };
+    static CAPTURED: RefCell<Option<Vec<LogMessage>>> = const { RefCell::new(None) };
+}
+
+impl FileLog {
+    /// run `f`, adding what is logged on this thread meanwhile
+    pub(crate) fn capture<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        let (result, captured) = capture(f);
+        let mut messages = self.messages.lock().unwrap();
+        let room = MAX_FILE_MESSAGES.saturating_sub(messages.len());
+        messages.extend(captured.into_iter().take(room));
+        result
+    }
+
+    /// add what the libav contexts of `groove_file` log from now on, until
+    /// `forget` is called. `groove_file` must stay open until then.
+    pub(crate) fn watch(&self, groove_file: *mut GrooveFile) {
+        let mut watches = lock_watches();
+        let since = NEXT_NUMBER.load(Ordering::SeqCst);
+        for context in unsafe { file_log_contexts(groove_file) } {
+            watches.watches.push(Watch { context: context as usize, since, messages: Arc::downgrade(&self.messages) });
+        }
+        WATCH_COUNT.store(watches.watches.len(), Ordering::SeqCst);
+    }
+
+    /// stop watching every handle `watch` was given, before closing them,
+    /// so that contexts libav allocates at the same addresses later are
+    /// not taken for theirs
+    pub(crate) fn forget(&self) {
+        let mut watches = lock_watches();
+        let messages = Arc::downgrade(&self.messages);
+        watches.watches.retain(|watch| !watch.messages.ptr_eq(&messages));
+        WATCH_COUNT.store(watches.watches.len(), Ordering::SeqCst);
+    }
+
+    pub(crate) fn messages(&self) -> Vec<LogMessage> {
+        drop(lock_watches());
+        self.messages.lock().unwrap().clone()
+    }
+}
+
+impl Drop for FileLog {
+    fn drop(&mut self) {
+        self.forget();
+    }
+}
+
+// the watches, with the queued messages sorted into their file logs
+fn lock_watches() -> ::std::sync::MutexGuard<'static, Watches> {
+    let watches = WATCHES.get_or_init(|| {
+        let (sender, receiver) = mpsc::sync_channel(MAX_QUEUED_MESSAGES);
+        let _ = QUEUE.set(sender);
+        Mutex::new(Watches { watches: Vec::new(), queue: receiver })
+    }).lock().unwrap();
+    while let Ok(queued) = watches.queue.try_recv() {
+        let matching = watches.watches.iter()
+            .filter(|watch| watch.context == queued.context && watch.since <= queued.number);
+        for watch in matching {
+            if let Some(messages) = watch.messages.upgrade() {
+                let mut messages = messages.lock().unwrap();
+                if messages.len() < MAX_FILE_MESSAGES {
+                    messages.push(queued.message.clone());
+                }
+            }
+        }
+    }
+    watches
+}
+
+/// run `f`, collecting what is logged on this thread meanwhile
+pub(crate) fn capture<T, F: FnOnce() -> T>(f: F) -> (T, Vec<LogMessage>) {
+    let previous = CAPTURED.with(|captured| captured.borrow_mut().replace(Vec::new()));
+    let result = f();
+    let captured = CAPTURED.with(|captured| mem::replace(&mut *captured.borrow_mut(), previous));
+    (result, captured.unwrap_or_default())
+}
+
+/// route libav's logging through `callback`. called once, from `init`.
+pub(crate) fn install() {
+    drop(lock_watches());
+    unsafe { av_log_set_callback(Some(callback)) }
+}
+
+pub(crate) fn log_level(av_level: c_int) -> Log {
+    match av_level {
+        level if level <= 16 => Log::Error,
+        level if level <= 24 => Log::Warning,
+        level if level <= 32 => Log::Info,
+        level if level <= 40 => Log::Verbose,
+        _ => Log::Debug,
+    }
+}
+
+fn record_level(level: Log) -> log::Level {
+    match level {
+        Log::Quiet | Log::Error => log::Level::Error,
+        Log::Warning => log::Level::Warn,
+        Log::Info => log::Level::Info,
+        Log::Verbose => log::Level::Debug,
+        Log::Debug => log::Level::Trace,
+    }
+}
+
+// the va_list argument is passed as a pointer on the platforms libgroove
+// supports, so it is declared as one and handed straight back to libav
+unsafe extern "C" fn callback(avcl: *mut c_void, av_level: c_int, fmt: *const c_char, vl: *mut c_void) {
+    let level = log_level(av_level);
+    let forward = av_level <= av_log_get_level() && record_level(level) <= log::max_level();
+    let capturing = CAPTURED.with(|captured| captured.borrow().is_some());
+    let keep = level <= Log::Info && (capturing || WATCH_COUNT.load(Ordering::SeqCst) > 0);
+    if !forward && !keep {
+        return;
+    }
+
+    let mut line = [0 as c_char; 1024];
+    let mut print_prefix: c_int = 0;
+    av_log_format_line(avcl, av_level, fmt, vl, line.as_mut_ptr(), line.len() as c_int, &mut print_prefix);
+    let piece = CStr::from_ptr(line.as_ptr()).to_string_lossy();
+
+    // libav builds some lines up over several calls
+    let message = PARTIAL_LINE.with(|partial| {
+        let mut partial = partial.borrow_mut();
+        partial.push_str(&piece);
+        if partial.ends_with('\n') {
+            Some(mem::take(&mut *partial))
+        } else {
+            None
+        }
+    });
+    let message = match message {
+        Some(ref message) if !message.trim().is_empty() => message.trim_end().to_string(),
+        _ => return,
+    };
+    let message = LogMessage {
+        level,
+        target: target(avcl),
+        message,
+    };
+
+    if forward {
+        log::logger().log(&log::Record::builder()
+            .args(format_args!("{}", message.message))
+            .level(record_level(level))
+            .target(&message.target)
+            .module_path_static(Some("groove::libav"))
+            .build());
+    }
+    if keep {
+        keep_message(avcl, message);
+    }
+}
+
+unsafe fn target(avcl: *mut c_void) -> String {
+    if avcl.is_null() {
+        return "groove::libav".to_string();
+    }
+    // every libav context starts with a pointer to its class
+    let class = *(avcl as *const *const AVClass);
+    if class.is_null() {
+        return "groove::libav".to_string();
+    }
+    let name = match (*class).item_name {
+        Some(item_name) => item_name(avcl),
+        None => (*class).class_name,
+    };
+    if name.is_null() {
+        "groove::libav".to_string()
+    } else {
+        format!("groove::libav::{}", CStr::from_ptr(name).to_string_lossy())
+    }
+}
+
+fn keep_message(avcl: *mut c_void, message: LogMessage) {
+    let message = CAPTURED.with(|captured| {
+        match *captured.borrow_mut() {
+            Some(ref mut captured) => {
+                captured.push(message);
+                None
+            },
+            None => Some(message),
+        }
+    });
+    if let (Some(message), false, Some(queue)) = (message, avcl.is_null(), QUEUE.get()) {
+        let number = NEXT_NUMBER.fetch_add(1, Ordering::SeqCst);
+        // dropped if the queue is full
+        let _ = queue.try_send(Queued { number, context: avcl as usize, message });
+    }
+}
@@ -0,0 +1,576 @@
+// just enough of Matroska (MKV, MKA, WebM) to read metadata which
+// libgroove does not expose
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use chapter::{Chapter, Mark};
+use info::AudioStream;
+use picture::{Part, Picture, PictureType};
+
+pub(crate) const EBML: u32 = 0x1A45DFA3;
+pub(crate) const SEGMENT: u32 = 0x18538067;
+pub(crate) const SEEK_HEAD: u32 = 0x114D9B74;
+pub(crate) const SEEK: u32 = 0x4DBB;
+pub(crate) const SEEK_ID: u32 = 0x53AB;
+pub(crate) const SEEK_POSITION: u32 = 0x53AC;
+pub(crate) const CLUSTER: u32 = 0x1F43B675;
+pub(crate) const TRACKS: u32 = 0x1654AE6B;
+pub(crate) const TRACK_ENTRY: u32 = 0xAE;
+pub(crate) const TRACK_TYPE: u32 = 0x83;
+pub(crate) const FLAG_DEFAULT: u32 = 0x88;
+pub(crate) const CODEC_ID: u32 = 0x86;
+pub(crate) const LANGUAGE: u32 = 0x22B59C;
+pub(crate) const LANGUAGE_IETF: u32 = 0x22B59D;
+pub(crate) const NAME: u32 = 0x536E;
+pub(crate) const AUDIO: u32 = 0xE1;
+pub(crate) const SAMPLING_FREQUENCY: u32 = 0xB5;
+pub(crate) const CHANNELS: u32 = 0x9F;
+pub(crate) const ATTACHMENTS: u32 = 0x1941A469;
+pub(crate) const ATTACHED_FILE: u32 = 0x61A7;
+pub(crate) const FILE_DESCRIPTION: u32 = 0x467E;
+pub(crate) const FILE_NAME: u32 = 0x466E;
+pub(crate) const FILE_MIME_TYPE: u32 = 0x4660;
+pub(crate) const FILE_DATA: u32 = 0x465C;
+pub(crate) const CHAPTERS: u32 = 0x1043A770;
+pub(crate) const EDITION_ENTRY: u32 = 0x45B9;
+pub(crate) const EDITION_FLAG_DEFAULT: u32 = 0x45DB;
+pub(crate) const CHAPTER_ATOM: u32 = 0xB6;
+pub(crate) const CHAPTER_UID: u32 = 0x73C4;
+pub(crate) const CHAPTER_TIME_START: u32 = 0x91;
+pub(crate) const CHAPTER_TIME_END: u32 = 0x92;
+pub(crate) const CHAPTER_FLAG_HIDDEN: u32 = 0x98;
+pub(crate) const CHAPTER_FLAG_ENABLED: u32 = 0x4598;
+pub(crate) const CHAPTER_DISPLAY: u32 = 0x80;
+pub(crate) const CHAP_STRING: u32 = 0x85;
+
+pub(crate) const FILE_UID: u32 = 0x46AE;
+pub(crate) const CUES: u32 = 0x1C53BB6B;
+pub(crate) const CUE_POINT: u32 = 0xBB;
+pub(crate) const CUE_TRACK_POSITIONS: u32 = 0xB7;
+pub(crate) const CUE_CLUSTER_POSITION: u32 = 0xF1;
+pub(crate) const CRC_32: u32 = 0xBF;
+pub(crate) const VOID: u32 = 0xEC;
+
+const TRACK_TYPE_AUDIO: u64 = 2;
+
+// refuse to load absurd metadata elements into memory
+const MAX_ELEMENT_LEN: u64 = 16 * 1024 * 1024;
+
+/// the elements directly inside an element's payload
+pub(crate) struct Elements<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Elements<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Elements { data }
+    }
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = (u32, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, id_len) = match vint(self.data, true)? {
+            (Some(id), id_len) => (id, id_len),
+            (None, _) => return None,
+        };
+        let (size, size_len) = vint(&self.data[id_len..], false)?;
+        let start = id_len + size_len;
+        // an unknown size runs to the end of the parent
+        let end = match size {
+            Some(size) if size <= (self.data.len() - start) as u64 => start + size as usize,
+            Some(_) => return None,
+            None => self.data.len(),
+        };
+        let payload = &self.data[start..end];
+        self.data = &self.data[end..];
+        Some((id as u32, payload))
+    }
+}
+
+/// the payload of the first child with this id
+pub(crate) fn child(data: &[u8], id: u32) -> Option<&[u8]> {
+    Elements::new(data).find(|&(child_id, _)| child_id == id).map(|(_, payload)| payload)
+}
+
+pub(crate) fn uint(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0, |value, &byte| (value << 8) | u64::from(byte))
+}
+
+pub(crate) fn float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f64::from(f32::from_bits(uint(data) as u32))),
+        8 => Some(f64::from_bits(uint(data))),
+        _ => None,
+    }
+}
+
+pub(crate) fn string(data: &[u8]) -> String {
+    let end = data.iter().position(|&byte| byte == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// decode a variable length integer, returning it and its length in
+/// bytes. ids keep their length marker; sizes which are all ones mean
+/// "unknown" and come back as None.
+fn vint(data: &[u8], is_id: bool) -> Option<(Option<u64>, usize)> {
+    let first = *data.first()?;
+    if first == 0 {
+        return None;
+    }
+    let len = first.leading_zeros() as usize + 1;
+    if (is_id && len > 4) || data.len() < len {
+        return None;
+    }
+    let marker_mask = if is_id { 0xff } else { (0xffu32 >> len) as u8 };
+    let value = data[1..len].iter().fold(u64::from(first & marker_mask), |value, &byte| (value << 8) | u64::from(byte));
+    let all_ones = (1u64 << (7 * len)) - 1;
+    if !is_id && value == all_ones {
+        Some((None, len))
+    } else {
+        Some((Some(value), len))
+    }
+}
+
+// reads an element header from a file: id, payload size, header length
+fn read_header(file: &mut fs::File) -> io::Result<Option<(u32, Option<u64>, u64)>> {
+    let mut header = [0u8; 12];
+    let mut len = 0;
+    while len < header.len() {
+        match file.read(&mut header[len..])? {
+            0 => break,
+            count => len += count,
+        }
+    }
+    let header = &header[..len];
+    let (id, id_len) = match vint(header, true) {
+        Some((Some(id), id_len)) => (id, id_len),
+        _ => return Ok(None),
+    };
+    match vint(&header[id_len..], false) {
+        Some((size, size_len)) => Ok(Some((id as u32, size, (id_len + size_len) as u64))),
+        None => Ok(None),
+    }
+}
+
+/// the payload of the first element with this id directly inside the
+/// Segment, found through the SeekHead or by scanning up to the first
+/// Cluster
+pub(crate) fn read_segment_child(path: &Path, id: u32) -> io::Result<Option<Vec<u8>>> {
+    let mut file = fs::File::open(path)?;
+
+    // skip the EBML header
+    let ebml_len = match read_header(&mut file)? {
+        Some((EBML, Some(size), header_len)) => header_len + size,
+        _ => return Ok(None),
+    };
+    file.seek(SeekFrom::Start(ebml_len))?;
+    let (segment_start, segment_end) = match read_header(&mut file)? {
+        Some((SEGMENT, size, header_len)) => {
+            let start = ebml_len + header_len;
+            (start, size.map_or(u64::MAX, |size| start + size))
+        },
+        _ => return Ok(None),
+    };
+
+    let mut pos = segment_start;
+    let mut seek_target = None;
+    while pos < segment_end {
+        file.seek(SeekFrom::Start(pos))?;
+        let (child_id, size, header_len) = match read_header(&mut file)? {
+            Some((child_id, Some(size), header_len)) => (child_id, size, header_len),
+            _ => break,
+        };
+        if child_id == id || child_id == SEEK_HEAD {
+            if size > MAX_ELEMENT_LEN {
+                return Ok(None);
+            }
+            let mut payload = vec![0; size as usize];
+            file.read_exact(&mut payload)?;
+            if child_id == id {
+                return Ok(Some(payload));
+            }
+            seek_target = seek_target.or_else(|| seek_position(&payload, id));
+        } else if child_id == CLUSTER {
+            break;
+        }
+        pos += header_len + size;
+    }
+
+    // the element comes after the audio
+    if let Some(offset) = seek_target {
+        file.seek(SeekFrom::Start(segment_start + offset))?;
+        if let Some((child_id, Some(size), _)) = read_header(&mut file)? {
+            if child_id == id && size <= MAX_ELEMENT_LEN {
+                let mut payload = vec![0; size as usize];
+                file.read_exact(&mut payload)?;
+                return Ok(Some(payload));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn seek_position(seek_head: &[u8], id: u32) -> Option<u64> {
+    Elements::new(seek_head)
+        .filter(|&(seek_id, _)| seek_id == SEEK)
+        .find(|&(_, seek)| child(seek, SEEK_ID).map(uint) == Some(u64::from(id)))
+        .and_then(|(_, seek)| child(seek, SEEK_POSITION))
+        .map(uint)
+}
+
+pub(crate) fn audio_streams(tracks: &[u8]) -> Vec<AudioStream> {
+    Elements::new(tracks)
+        .filter(|&(id, _)| id == TRACK_ENTRY)
+        .enumerate()
+        .filter(|&(_, (_, entry))| child(entry, TRACK_TYPE).map(uint) == Some(TRACK_TYPE_AUDIO))
+        .map(|(index, (_, entry))| {
+            let audio = child(entry, AUDIO).unwrap_or(&[]);
+            // Matroska defaults the language to English
+            let language = child(entry, LANGUAGE_IETF)
+                .or_else(|| child(entry, LANGUAGE))
+                .map_or_else(|| "eng".to_string(), string);
+            AudioStream {
+                index: Some(index),
+                codec: child(entry, CODEC_ID).map(string),
+                language: Some(language).filter(|language| language != "und"),
+                name: child(entry, NAME).map(string),
+                sample_rate: child(audio, SAMPLING_FREQUENCY).and_then(float)
+                    .map(|rate| rate.round() as u32).or(Some(8000)),
+                channel_count: child(audio, CHANNELS).map(|channels| uint(channels) as u32).or(Some(1)),
+                default: child(entry, FLAG_DEFAULT).is_none_or(|flag| uint(flag) != 0),
+            }
+        })
+        .collect()
+}
+
+/// the attached images. Matroska has no picture types, but by convention
+/// cover art is attached as `cover.jpg` or `cover.png`.
+pub(crate) fn pictures(attachments: &[u8]) -> Vec<Picture> {
+    Elements::new(attachments)
+        .filter(|&(id, _)| id == ATTACHED_FILE)
+        .filter_map(|(_, attached_file)| {
+            let mime_type = string(child(attached_file, FILE_MIME_TYPE)?);
+            if !mime_type.starts_with("image/") {
+                return None;
+            }
+            let file_name = child(attached_file, FILE_NAME).map(string).unwrap_or_default().to_lowercase();
+            let picture_type = if file_name.starts_with("cover.") {
+                PictureType::FrontCover
+            } else {
+                PictureType::Other
+            };
+            Some(Picture {
+                picture_type,
+                mime_type,
+                description: child(attached_file, FILE_DESCRIPTION).map(string).unwrap_or_default(),
+                data: child(attached_file, FILE_DATA)?.to_vec(),
+            })
+        })
+        .collect()
+}
+
+/// the chapters of the default edition, or else the first, in nanoseconds.
+/// hidden and disabled chapters are left out, as are chapters nested in
+/// others.
+pub(crate) fn chapters(chapters: &[u8]) -> Vec<Mark> {
+    let editions: Vec<&[u8]> = Elements::new(chapters)
+        .filter(|&(id, _)| id == EDITION_ENTRY)
+        .map(|(_, edition)| edition)
+        .collect();
+    let edition = match editions.iter().find(|edition| child(edition, EDITION_FLAG_DEFAULT).map(uint) == Some(1))
+        .or_else(|| editions.first()) {
+        Some(edition) => edition,
+        None => return Vec::new(),
+    };
+    Elements::new(edition)
+        .filter(|&(id, _)| id == CHAPTER_ATOM)
+        .filter(|&(_, atom)| child(atom, CHAPTER_FLAG_HIDDEN).is_none_or(|flag| uint(flag) == 0))
+        .filter(|&(_, atom)| child(atom, CHAPTER_FLAG_ENABLED).is_none_or(|flag| uint(flag) != 0))
+        .filter_map(|(_, atom)| Some(Mark {
+            start: Duration::from_nanos(uint(child(atom, CHAPTER_TIME_START)?)),
+            end: child(atom, CHAPTER_TIME_END).map(|end| Duration::from_nanos(uint(end))),
+            title: child(atom, CHAPTER_DISPLAY).and_then(|display| child(display, CHAP_STRING))
+                .map(string)
+                .unwrap_or_default(),
+        }))
+        .collect()
+}
+
+/// an element holding `payload`, with its size written in 8 bytes so
+/// that it can be worked out before the payload is
+pub(crate) fn element(id: u32, payload: &[u8]) -> Vec<u8> {
+    let id_bytes = id.to_be_bytes();
+    let skip = id_bytes.iter().position(|&byte| byte != 0).unwrap_or(3);
+    let mut out = id_bytes[skip..].to_vec();
+    out.push(0x01);
+    out.extend_from_slice(&(payload.len() as u64).to_be_bytes()[1..]);
+    out.extend_from_slice(payload);
+    out
+}
+
+// an element directly inside the Segment, where it starts counting from
+// the Segment's payload
+struct SegmentChild {
+    id: u32,
+    offset: u64,
+    len: u64,
+}
+
+/// how to rewrite `file`, a Matroska file, so that its attachments are
+/// the ones it has which aren't images, and then `pictures`
+pub(crate) fn with_pictures(file: &mut fs::File, pictures: &[Picture]) -> io::Result<Vec<Part>> {
+    with_segment_child(file, ATTACHMENTS, |old| {
+        // everything but the pictures among the attachments stays
+        let mut attachments = Vec::new();
+        for (id, attached_file) in Elements::new(&old) {
+            let mime_type = child_string(attached_file, FILE_MIME_TYPE);
+            if id == ATTACHED_FILE && !mime_type.starts_with("image/") {
+                attachments.extend_from_slice(&element(id, attached_file));
+            }
+        }
+        for (index, picture) in pictures.iter().enumerate() {
+            attachments.extend_from_slice(&element(ATTACHED_FILE, &attached_picture(index, picture)));
+        }
+        attachments
+    })
+}
+
+/// how to rewrite `file`, a Matroska file, so that its chapters are
+/// `chapters`, in a single edition
+pub(crate) fn with_chapters(file: &mut fs::File, chapters: &[Chapter]) -> io::Result<Vec<Part>> {
+    with_segment_child(file, CHAPTERS, |_| {
+        let mut edition = Vec::new();
+        for (index, chapter) in chapters.iter().enumerate() {
+            let mut atom = element(CHAPTER_UID, &(index as u64 + 1).to_be_bytes());
+            atom.extend_from_slice(&element(CHAPTER_TIME_START, &(chapter.start.as_nanos() as u64).to_be_bytes()));
+            atom.extend_from_slice(&element(CHAPTER_TIME_END, &(chapter.end.as_nanos() as u64).to_be_bytes()));
+            if !chapter.title.is_empty() {
+                atom.extend_from_slice(&element(CHAPTER_DISPLAY, &element(CHAP_STRING, chapter.title.as_bytes())));
+            }
+            edition.extend_from_slice(&element(CHAPTER_ATOM, &atom));
+        }
+        if edition.is_empty() {
+            Vec::new()
+        } else {
+            element(EDITION_ENTRY, &edition)
+        }
+    })
+}
+
+// how to rewrite `file`, a Matroska file, so that the elements with this
+// id directly inside its Segment are replaced by one whose payload `build`
+// makes from theirs, or by none if that is empty. the new element goes at
+// the end of the Segment, and the SeekHead and Cues are rewritten to point
+// at where everything has moved to.
+fn with_segment_child<F>(file: &mut fs::File, id: u32, build: F) -> io::Result<Vec<Part>>
+    where F: FnOnce(Vec<u8>) -> Vec<u8>
+{
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+    let file_len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(0))?;
+    let ebml_len = match read_header(file)? {
+        Some((EBML, Some(size), header_len)) => header_len + size,
+        _ => return Err(invalid("not a Matroska file")),
+    };
+    file.seek(SeekFrom::Start(ebml_len))?;
+    let (segment_start, segment_size) = match read_header(file)? {
+        Some((SEGMENT, size, header_len)) => (ebml_len + header_len, size),
+        _ => return Err(invalid("no Matroska segment")),
+    };
+    let segment_end = segment_size.map_or(file_len, |size| segment_start + size).min(file_len);
+
+    let mut children = Vec::new();
+    let mut pos = segment_start;
+    while pos < segment_end {
+        file.seek(SeekFrom::Start(pos))?;
+        match read_header(file)? {
+            Some((id, Some(size), header_len)) => {
+                children.push(SegmentChild { id, offset: pos - segment_start, len: header_len + size });
+                pos += header_len + size;
+            },
+            _ => return Err(invalid("can't rewrite a Matroska segment whose elements have no size")),
+        }
+    }
+    let mut payload = |child: &SegmentChild| -> io::Result<Vec<u8>> {
+        if child.len > MAX_ELEMENT_LEN {
+            return Err(invalid("Matroska metadata too large"));
+        }
+        file.seek(SeekFrom::Start(segment_start + child.offset))?;
+        let (_, _, header_len) = read_header(file)?.ok_or_else(|| invalid("bad element header"))?;
+        file.seek(SeekFrom::Start(segment_start + child.offset + header_len))?;
+        let mut payload = vec![0; (child.len - header_len) as usize];
+        file.read_exact(&mut payload)?;
+        Ok(payload)
+    };
+
+    let mut old = Vec::new();
+    let mut rewritten = Vec::new();
+    for (index, child) in children.iter().enumerate() {
+        if child.id == id {
+            old.extend_from_slice(&payload(child)?);
+        } else if child.id == SEEK_HEAD || child.id == CUES {
+            rewritten.push((index, payload(child)?));
+        }
+    }
+    let new_child = build(old);
+    let new_child = Some(element(id, &new_child)).filter(|_| !new_child.is_empty());
+    let has_seek_head = children.iter().any(|child| child.id == SEEK_HEAD);
+
+    // the rewritten elements are the same size wherever things end up,
+    // so lay them out with dummy positions first
+    let is_start = |offset: u64| children.iter().any(|child| child.offset == offset && child.id != id);
+    let dummy = |offset: u64| if is_start(offset) { Some(0) } else { None };
+    let new_child_at = new_child.as_ref().map(|_| 0);
+    let rebuild = |child_id: u32, payload: &[u8], map: &dyn Fn(u64) -> Option<u64>, new_child_at: Option<u64>,
+                   first: bool| {
+        if child_id == SEEK_HEAD {
+            element(SEEK_HEAD, &seek_head(payload, id, map, if first { new_child_at } else { None }))
+        } else {
+            element(CUES, &cues(payload, map))
+        }
+    };
+    let new_seek_head = |new_child_at: Option<u64>| if has_seek_head {
+        None
+    } else {
+        new_child_at.map(|at| element(SEEK_HEAD, &seek_head(&[], id, &|_| None, Some(at))))
+    };
+
+    let mut new_offsets = Vec::new();
+    let mut offset = new_seek_head(new_child_at).map_or(0, |head| head.len() as u64);
+    let mut first_seek_head = true;
+    for (index, child) in children.iter().enumerate() {
+        new_offsets.push(offset);
+        if child.id == id {
+            continue;
+        }
+        offset += match rewritten.iter().find(|&&(rewritten_index, _)| rewritten_index == index) {
+            Some((_, payload)) => {
+                let first = child.id == SEEK_HEAD && first_seek_head;
+                first_seek_head &= child.id != SEEK_HEAD;
+                rebuild(child.id, payload, &dummy, new_child_at, first).len() as u64
+            },
+            None => child.len,
+        };
+    }
+    let new_child_at = new_child.as_ref().map(|_| offset);
+    let segment_len = offset + new_child.as_ref().map_or(0, |new_child| new_child.len() as u64);
+
+    let map = |old: u64| children.iter().position(|child| child.offset == old && child.id != id)
+        .map(|index| new_offsets[index]);
+    let mut parts = vec![Part::Copy(0, ebml_len)];
+    // a Segment of unknown size stays that way
+    let mut segment = SEGMENT.to_be_bytes().to_vec();
+    segment.push(0x01);
+    match segment_size {
+        Some(_) => segment.extend_from_slice(&segment_len.to_be_bytes()[1..]),
+        None => segment.extend_from_slice(&[0xff; 7]),
+    }
+    parts.push(Part::Bytes(segment));
+    if let Some(head) = new_seek_head(new_child_at) {
+        parts.push(Part::Bytes(head));
+    }
+    let mut first_seek_head = true;
+    for (index, child) in children.iter().enumerate() {
+        if child.id == id {
+            continue;
+        }
+        match rewritten.iter().find(|&&(rewritten_index, _)| rewritten_index == index) {
+            Some((_, payload)) => {
+                let first = child.id == SEEK_HEAD && first_seek_head;
+                first_seek_head &= child.id != SEEK_HEAD;
+                parts.push(Part::Bytes(rebuild(child.id, payload, &map, new_child_at, first)));
+            },
+            None => parts.push(Part::Copy(segment_start + child.offset, child.len)),
+        }
+    }
+    if let Some(new_child) = new_child {
+        parts.push(Part::Bytes(new_child));
+    }
+    if segment_end < file_len {
+        parts.push(Part::Copy(segment_end, file_len - segment_end));
+    }
+    Ok(parts)
+}
+
+fn child_string(data: &[u8], id: u32) -> String {
+    child(data, id).map(string).unwrap_or_default()
+}
+
+// an AttachedFile, named the way players look for cover art
+fn attached_picture(index: usize, picture: &Picture) -> Vec<u8> {
+    let extension = match picture.mime_type.as_str() {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/bmp" => "bmp",
+        _ => "bin",
+    };
+    let name = if picture.picture_type == PictureType::FrontCover {
+        format!("cover.{}", extension)
+    } else {
+        format!("picture{}.{}", index + 1, extension)
+    };
+    let mut out = Vec::new();
+    if !picture.description.is_empty() {
+        out.extend_from_slice(&element(FILE_DESCRIPTION, picture.description.as_bytes()));
+    }
+    out.extend_from_slice(&element(FILE_NAME, name.as_bytes()));
+    out.extend_from_slice(&element(FILE_MIME_TYPE, picture.mime_type.as_bytes()));
+    out.extend_from_slice(&element(FILE_DATA, &picture.data));
+    out.extend_from_slice(&element(FILE_UID, &(index as u64 + 1).to_be_bytes()));
+    out
+}
+
+// a SeekHead's payload with its positions passed through `map`, dropping
+// those it has no answer for and any for the elements with id `moved`, and
+// with an entry for the new one at `moved_at`
+fn seek_head(old: &[u8], moved: u32, map: &dyn Fn(u64) -> Option<u64>, moved_at: Option<u64>) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (id, payload) in Elements::new(old) {
+        match id {
+            SEEK => {
+                let seek_id = match child(payload, SEEK_ID) {
+                    Some(seek_id) if uint(seek_id) != u64::from(moved) => seek_id,
+                    _ => continue,
+                };
+                if let Some(position) = child(payload, SEEK_POSITION).map(uint).and_then(map) {
+                    out.extend_from_slice(&seek(seek_id, position));
+                }
+            },
+            CRC_32 | VOID => {},
+            _ => out.extend_from_slice(&element(id, payload)),
+        }
+    }
+    if let Some(position) = moved_at {
+        out.extend_from_slice(&seek(&moved.to_be_bytes(), position));
+    }
+    out
+}
+
+fn seek(seek_id: &[u8], position: u64) -> Vec<u8> {
+    let mut payload = element(SEEK_ID, seek_id);
+    payload.extend_from_slice(&element(SEEK_POSITION, &position.to_be_bytes()));
+    element(SEEK, &payload)
+}
+
+// Cues with their cluster positions passed through `map`
+fn cues(old: &[u8], map: &dyn Fn(u64) -> Option<u64>) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (id, payload) in Elements::new(old) {
+        match id {
+            CUE_POINT | CUE_TRACK_POSITIONS => out.extend_from_slice(&element(id, &cues(payload, map))),
+            CUE_CLUSTER_POSITION => {
+                let position = map(uint(payload)).unwrap_or_else(|| uint(payload));
+                out.extend_from_slice(&element(id, &position.to_be_bytes()));
+            },
+            CRC_32 => {},
+            _ => out.extend_from_slice(&element(id, payload)),
+        }
+    }
+    out
+}
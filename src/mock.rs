@@ -0,0 +1,40 @@
+//! control over the in-process stand-in for libgroove that the `mock`
+//! feature builds instead of linking the real library.
+//!
+//! the mock decodes WAV files and files named
+//! `tone:<frequency>[:<seconds>[:<sample rate>[:<channels>]]]`, which are
+//! half-scale sine waves (one second of 44100 Hz stereo by default). its
+//! encoder writes WAV. audio is decoded when a sink or encoder asks for a
+//! buffer, and a blocking get after the end of the playlist returns the end
+//! again instead of waiting for more. pausing a playlist tells its sinks, but
+//! does not stop it decoding.
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use c_api;
+
+pub use c_api::Fault;
+
+/// make the next matching libgroove call on this thread fail. faults are
+/// used up in the order they were injected.
+pub fn inject_fault(fault: Fault) {
+    c_api::inject_fault(fault)
+}
+
+/// forget the faults injected on this thread which have not happened yet
+pub fn clear_faults() {
+    c_api::clear_faults()
+}
+
+/// how many handles libgroove has open on `path`, on any thread, for
+/// checking that files are closed
+pub fn open_files(path: &Path) -> usize {
+    c_api::open_files(path.as_os_str().as_bytes())
+}
+
+/// how many times libgroove has opened `path`, on any thread, for checking
+/// that files aren't opened more often than they need to be
+pub fn times_opened(path: &Path) -> usize {
+    c_api::times_opened(path.as_os_str().as_bytes())
+}
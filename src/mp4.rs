@@ -0,0 +1,496 @@
+// just enough of the ISO base media file format (MP4, M4A, M4B, MOV) to
+// read metadata which libgroove does not expose
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use chapter::{Chapter, Mark};
+use info::AudioStream;
+use picture::{Part, Picture, PictureType};
+
+// refuse to load absurd moov boxes into memory
+const MAX_MOOV_LEN: u64 = 64 * 1024 * 1024;
+// and absurd chapter tracks
+const MAX_CHAPTERS: usize = 10_000;
+
+/// the boxes directly inside a box's payload
+pub(crate) struct Boxes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Boxes<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Boxes { data }
+    }
+}
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 8 {
+            return None;
+        }
+        let size = u32_at(self.data, 0)? as u64;
+        let kind = &self.data[4..8];
+        let (header_len, size) = match size {
+            0 => (8, self.data.len() as u64),
+            1 => (16, u64_at(self.data, 8)?),
+            size => (8, size),
+        };
+        if size < header_len || size > self.data.len() as u64 {
+            return None;
+        }
+        let payload = &self.data[header_len as usize..size as usize];
+        self.data = &self.data[size as usize..];
+        Some((kind, payload))
+    }
+}
+
+/// the payload of the first box at the end of `path`, for example
+/// `[b"mdia", b"minf", b"stbl"]`
+pub(crate) fn find<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let mut data = data;
+    for kind in path {
+        data = Boxes::new(data).find(|&(k, _)| k == &kind[..])?.1;
+    }
+    Some(data)
+}
+
+/// where the top level moov box is in `file`: its offset and its length,
+/// header included, and the length of its header
+pub(crate) fn locate_moov(file: &mut fs::File) -> io::Result<Option<(u64, u64, u64)>> {
+    let file_len = file.metadata()?.len();
+    let mut pos = 0;
+    while pos + 8 <= file_len {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8])?;
+        let mut header_len = 8;
+        let size = match u32_at(&header, 0).unwrap() {
+            0 => file_len - pos,
+            1 => {
+                file.read_exact(&mut header[8..16])?;
+                header_len = 16;
+                u64_at(&header, 8).unwrap()
+            },
+            size => size as u64,
+        };
+        if size < header_len {
+            return Ok(None);
+        }
+        if &header[4..8] == b"moov" {
+            return Ok(Some((pos, size, header_len)));
+        }
+        pos += size;
+    }
+    Ok(None)
+}
+
+/// the payload of the top level moov box, which holds everything but the
+/// media data
+pub(crate) fn read_moov(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut file = fs::File::open(path)?;
+    let (start, size, header_len) = match locate_moov(&mut file)? {
+        Some(moov) => moov,
+        None => return Ok(None),
+    };
+    let len = size - header_len;
+    if len > MAX_MOOV_LEN {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(start + header_len))?;
+    let mut moov = vec![0; len as usize];
+    file.read_exact(&mut moov)?;
+    Ok(Some(moov))
+}
+
+pub(crate) fn audio_streams(moov: &[u8]) -> Vec<AudioStream> {
+    let traks = Boxes::new(moov).filter(|&(kind, _)| kind == b"trak");
+    traks.enumerate().filter_map(|(index, (_, trak))| audio_stream(index, trak)).collect()
+}
+
+fn audio_stream(index: usize, trak: &[u8]) -> Option<AudioStream> {
+    let mdia = find(trak, &[b"mdia"])?;
+    let hdlr = find(mdia, &[b"hdlr"])?;
+    if hdlr.get(8..12)? != b"soun" {
+        return None;
+    }
+
+    let enabled = find(trak, &[b"tkhd"]).and_then(|tkhd| tkhd.get(3)).is_none_or(|flags| flags & 1 != 0);
+
+    let language = find(mdia, &[b"mdhd"]).and_then(|mdhd| {
+        let offset = if *mdhd.first()? == 1 { 32 } else { 20 };
+        let packed = u16_at(mdhd, offset)?;
+        let language: String = [10, 5, 0].iter()
+            .map(|shift| (((packed >> shift) & 0x1f) as u8 + 0x60) as char)
+            .collect();
+        Some(language)
+    }).filter(|language| language != "und" && language.chars().all(|c| c.is_ascii_lowercase()));
+
+    let stsd = find(mdia, &[b"minf", b"stbl", b"stsd"])?;
+    let entry = stsd.get(8..)?;
+    let codec = String::from_utf8_lossy(entry.get(4..8)?).trim().to_string();
+    let channel_count = u16_at(entry, 24).map(u32::from);
+    let sample_rate = u32_at(entry, 32).map(|rate| rate >> 16);
+
+    Some(AudioStream {
+        index: Some(index),
+        codec: Some(codec),
+        language,
+        name: None,
+        sample_rate: sample_rate.filter(|&rate| rate > 0),
+        channel_count: channel_count.filter(|&count| count > 0),
+        default: enabled,
+    })
+}
+
+/// cover art from the iTunes-style `covr` item
+pub(crate) fn pictures(moov: &[u8]) -> Vec<Picture> {
+    let meta = match find(moov, &[b"udta", b"meta"]) {
+        Some(meta) => meta,
+        None => return Vec::new(),
+    };
+    // meta is a full box in MP4 but a plain one in QuickTime
+    let meta = if meta.get(4..8) == Some(&b"hdlr"[..]) { meta } else { meta.get(4..).unwrap_or(&[]) };
+    let covr = match find(meta, &[b"ilst", b"covr"]) {
+        Some(covr) => covr,
+        None => return Vec::new(),
+    };
+    Boxes::new(covr)
+        .filter(|&(kind, _)| kind == b"data")
+        .filter_map(|(_, data)| {
+            let mime_type = match u32_at(data, 0)? & 0x00ff_ffff {
+                13 => "image/jpeg",
+                14 => "image/png",
+                27 => "image/bmp",
+                _ => "application/octet-stream",
+            };
+            Some(Picture {
+                picture_type: PictureType::FrontCover,
+                mime_type: mime_type.to_string(),
+                description: String::new(),
+                data: data.get(8..)?.to_vec(),
+            })
+        })
+        .collect()
+}
+
+/// how to rewrite `file`, an MP4 file opened from `path`, with its moov
+/// box's payload passed through `edit`. the chunk offsets of the media
+/// data after the moov box move with its end.
+pub(crate) fn with_moov<F>(file: &mut fs::File, path: &Path, edit: F) -> io::Result<Vec<Part>>
+    where F: FnOnce(&[u8]) -> io::Result<Vec<u8>>
+{
+    let file_len = file.metadata()?.len();
+    let (start, len, _) = locate_moov(file)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no moov box"))?;
+    let moov = read_moov(path)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "moov box too large"))?;
+    let moov = edit(&moov)?;
+    let delta = make_box(b"moov", &moov).len() as i64 - len as i64;
+    let moov = make_box(b"moov", &shift_chunk_offsets(&moov, start + len, delta)?);
+    Ok(vec![Part::Copy(0, start), Part::Bytes(moov), Part::Copy(start + len, file_len - start - len)])
+}
+
+/// a box holding `payload`, with a 64-bit size if it needs one
+pub(crate) fn make_box(kind: &[u8], payload: &[u8]) -> Vec<u8> {
+    let len = payload.len() as u64 + 8;
+    let mut out = if len > u64::from(u32::MAX) {
+        let mut out = 1u32.to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(&(len + 8).to_be_bytes());
+        out
+    } else {
+        let mut out = (len as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out
+    };
+    out.extend_from_slice(payload);
+    out
+}
+
+// the boxes of `data` with the first of `kind` given `payload` instead,
+// or added at the end if there is none, or removed if `payload` is None
+fn replace_box(data: &[u8], kind: &[u8; 4], payload: Option<&[u8]>) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut replaced = false;
+    for (box_kind, box_payload) in Boxes::new(data) {
+        if box_kind == kind && !replaced {
+            replaced = true;
+            if let Some(payload) = payload {
+                out.extend_from_slice(&make_box(kind, payload));
+            }
+        } else {
+            out.extend_from_slice(&make_box(box_kind, box_payload));
+        }
+    }
+    if let (false, Some(payload)) = (replaced, payload) {
+        out.extend_from_slice(&make_box(kind, payload));
+    }
+    out
+}
+
+/// the payload of a moov box with its `covr` item holding `pictures`, or
+/// without one if there are none. iTunes-style items have no picture
+/// types or descriptions, so those are lost.
+pub(crate) fn with_pictures(moov: &[u8], pictures: &[Picture]) -> Vec<u8> {
+    let udta = find(moov, &[b"udta"]).unwrap_or(&[]);
+    // keep the version and flags of an MP4 meta box, which QuickTime's lack
+    let (meta_header, children) = match find(udta, &[b"meta"]) {
+        Some(meta) if meta.get(4..8) == Some(&b"hdlr"[..]) => (&[][..], meta),
+        Some(meta) => meta.split_at(meta.len().min(4)),
+        None => (&[0u8; 4][..], &[][..]),
+    };
+    let mut children = children.to_vec();
+    if find(&children, &[b"hdlr"]).is_none() {
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"mdirappl");
+        hdlr.extend_from_slice(&[0; 9]);
+        let mut with_hdlr = make_box(b"hdlr", &hdlr);
+        with_hdlr.extend_from_slice(&children);
+        children = with_hdlr;
+    }
+
+    let mut covr = Vec::new();
+    for picture in pictures {
+        let data_type: u32 = match picture.mime_type.as_str() {
+            "image/jpeg" | "image/jpg" => 13,
+            "image/png" => 14,
+            "image/bmp" => 27,
+            _ => 0,
+        };
+        let mut data = data_type.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&picture.data);
+        covr.extend_from_slice(&make_box(b"data", &data));
+    }
+    let ilst = replace_box(find(&children, &[b"ilst"]).unwrap_or(&[]), b"covr",
+                           if pictures.is_empty() { None } else { Some(&covr) });
+    let mut meta = meta_header.to_vec();
+    meta.extend_from_slice(&replace_box(&children, b"ilst", Some(&ilst)));
+    let udta = replace_box(udta, b"meta", Some(&meta));
+    replace_box(moov, b"udta", Some(&udta))
+}
+
+/// the payload of a moov box with Nero chapters holding `chapters`, or
+/// without any if there are none. Nero chapters have no ends, and titles
+/// longer than 255 bytes are cut short. fails for more than 255 chapters.
+pub(crate) fn with_chapters(moov: &[u8], chapters: &[Chapter]) -> io::Result<Vec<u8>> {
+    if chapters.len() > usize::from(u8::MAX) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many chapters for MP4"));
+    }
+    // version 1, with four reserved bytes after the version and flags
+    let mut chpl = vec![1, 0, 0, 0, 0, 0, 0, 0, chapters.len() as u8];
+    for chapter in chapters {
+        let mut len = chapter.title.len().min(usize::from(u8::MAX));
+        while !chapter.title.is_char_boundary(len) {
+            len -= 1;
+        }
+        chpl.extend_from_slice(&((chapter.start.as_nanos() / 100) as u64).to_be_bytes());
+        chpl.push(len as u8);
+        chpl.extend_from_slice(&chapter.title.as_bytes()[..len]);
+    }
+    let udta = find(moov, &[b"udta"]).unwrap_or(&[]);
+    let udta = replace_box(udta, b"chpl", if chapters.is_empty() { None } else { Some(&chpl) });
+    Ok(replace_box(moov, b"udta", Some(&udta)))
+}
+
+/// the payload of a moov box with `delta` added to every chunk offset of
+/// its tracks at or after `from`, for when the media data moves
+pub(crate) fn shift_chunk_offsets(moov: &[u8], from: u64, delta: i64) -> io::Result<Vec<u8>> {
+    shift_offsets(moov, 0, from, delta)
+}
+
+fn shift_offsets(data: &[u8], depth: usize, from: u64, delta: i64) -> io::Result<Vec<u8>> {
+    const PATH: [&[u8; 4]; 4] = [b"trak", b"mdia", b"minf", b"stbl"];
+    let mut out = Vec::new();
+    for (kind, payload) in Boxes::new(data) {
+        let payload = if depth < PATH.len() && kind == PATH[depth] {
+            shift_offsets(payload, depth + 1, from, delta)?
+        } else if depth == PATH.len() && (kind == b"stco" || kind == b"co64") {
+            let entry_len = if kind == b"stco" { 4 } else { 8 };
+            let mut table = payload.to_vec();
+            let count = u32_at(payload, 4).unwrap_or(0) as usize;
+            for entry in 0..count.min(payload.len().saturating_sub(8) / entry_len) {
+                let at = 8 + entry * entry_len;
+                let offset = if entry_len == 4 {
+                    u64::from(u32_at(&table, at).unwrap())
+                } else {
+                    u64_at(&table, at).unwrap()
+                };
+                if offset < from {
+                    continue;
+                }
+                let offset = offset as i64 + delta;
+                if entry_len == 4 {
+                    if offset < 0 || offset > i64::from(u32::MAX) {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "chunk offset out of range"));
+                    }
+                    table[at..at + 4].copy_from_slice(&(offset as u32).to_be_bytes());
+                } else {
+                    table[at..at + 8].copy_from_slice(&(offset as u64).to_be_bytes());
+                }
+            }
+            table
+        } else {
+            payload.to_vec()
+        };
+        out.extend_from_slice(&make_box(kind, &payload));
+    }
+    Ok(out)
+}
+
+/// Nero chapters from the `chpl` box, which start in 100ns units and have
+/// no end. `None` if there are none.
+pub(crate) fn nero_chapters(moov: &[u8]) -> Option<Vec<Mark>> {
+    let chpl = find(moov, &[b"udta", b"chpl"])?;
+    // version 1 has four more bytes after the version and flags
+    let mut pos = if *chpl.first()? == 0 { 4 } else { 8 };
+    let count = *chpl.get(pos)?;
+    pos += 1;
+    let mut marks = Vec::new();
+    for _ in 0..count {
+        let start = u64_at(chpl, pos)?;
+        let len = *chpl.get(pos + 8)? as usize;
+        let title = chpl.get(pos + 9..pos + 9 + len)?;
+        marks.push(Mark {
+            start: Duration::from_nanos(start.saturating_mul(100)),
+            end: None,
+            title: String::from_utf8_lossy(title).into_owned(),
+        });
+        pos += 9 + len;
+    }
+    Some(marks).filter(|marks| !marks.is_empty())
+}
+
+/// QuickTime chapters, as iTunes writes them: a text track which another
+/// track points at with a `chap` reference, each of whose samples titles a
+/// chapter lasting as long as it. the samples are read from `path`.
+pub(crate) fn track_chapters(path: &Path, moov: &[u8]) -> io::Result<Vec<Mark>> {
+    let traks: Vec<&[u8]> = Boxes::new(moov).filter(|&(kind, _)| kind == b"trak").map(|(_, trak)| trak).collect();
+    let ids: Vec<u32> = traks.iter()
+        .filter_map(|trak| find(trak, &[b"tref", b"chap"]))
+        .flat_map(|chap| chap.chunks_exact(4).filter_map(|id| u32_at(id, 0)))
+        .collect();
+    let samples = traks.iter()
+        .find(|trak| track_id(trak).is_some_and(|id| ids.contains(&id)))
+        .and_then(|trak| chapter_samples(trak));
+    let samples = match samples {
+        Some(samples) => samples,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut file = fs::File::open(path)?;
+    let mut marks = Vec::new();
+    for (offset, size, start, end) in samples {
+        let mut data = vec![0; size.min(u32::from(u16::MAX) + 2) as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut data)?;
+        marks.push(Mark { start, end: Some(end), title: sample_text(&data) });
+    }
+    Ok(marks)
+}
+
+fn track_id(trak: &[u8]) -> Option<u32> {
+    let tkhd = find(trak, &[b"tkhd"])?;
+    u32_at(tkhd, if *tkhd.first()? == 1 { 20 } else { 12 })
+}
+
+// where each sample of a track is in the file, its size, and when it
+// starts and ends
+fn chapter_samples(trak: &[u8]) -> Option<Vec<(u64, u32, Duration, Duration)>> {
+    let mdia = find(trak, &[b"mdia"])?;
+    let mdhd = find(mdia, &[b"mdhd"])?;
+    let timescale = u32_at(mdhd, if *mdhd.first()? == 1 { 20 } else { 12 }).filter(|&scale| scale > 0)?;
+    let stbl = find(mdia, &[b"minf", b"stbl"])?;
+
+    // each table starts with a version, flags and an entry count
+    let table = |kind: &[u8; 4], entry_len: usize| -> Option<(&[u8], usize)> {
+        let table = find(stbl, &[kind])?;
+        Some((table, (u32_at(table, 4)? as usize).min(table.len() / entry_len)))
+    };
+    let (stts, count) = table(b"stts", 8)?;
+    let mut durations = Vec::new();
+    for entry in 0..count {
+        let samples = u32_at(stts, 8 + entry * 8)? as usize;
+        let delta = u32_at(stts, 12 + entry * 8)?;
+        durations.extend((0..samples.min(MAX_CHAPTERS - durations.len())).map(|_| delta));
+    }
+
+    let stsz = find(stbl, &[b"stsz"])?;
+    let fixed_size = u32_at(stsz, 4)?;
+    let sample_count = (u32_at(stsz, 8)? as usize).min(MAX_CHAPTERS);
+    let sizes: Vec<u32> = match fixed_size {
+        0 => (0..sample_count).map(|sample| u32_at(stsz, 12 + sample * 4)).collect::<Option<_>>()?,
+        size => vec![size; sample_count],
+    };
+
+    let offsets: Vec<u64> = match table(b"stco", 4) {
+        Some((stco, count)) => {
+            (0..count).map(|chunk| u32_at(stco, 8 + chunk * 4).map(u64::from)).collect::<Option<_>>()?
+        },
+        None => {
+            let (co64, count) = table(b"co64", 8)?;
+            (0..count).map(|chunk| u64_at(co64, 8 + chunk * 8)).collect::<Option<_>>()?
+        },
+    };
+    // runs of chunks with the same number of samples, from their first
+    // chunk, counting from 1
+    let (stsc, count) = table(b"stsc", 12)?;
+    let runs: Vec<(u32, u32)> = (0..count)
+        .map(|run| Some((u32_at(stsc, 8 + run * 12)?, u32_at(stsc, 12 + run * 12)?)))
+        .collect::<Option<_>>()?;
+
+    let mut positions = Vec::new();
+    for (chunk, &offset) in (1..).zip(&offsets) {
+        let per_chunk = runs.iter().rev().find(|&&(first, _)| first <= chunk)?.1;
+        let mut offset = offset;
+        for &size in sizes.iter().skip(positions.len()).take(per_chunk as usize) {
+            positions.push(offset);
+            offset += u64::from(size);
+        }
+    }
+
+    let time = |units: u64| Duration::from_nanos((u128::from(units) * 1_000_000_000 / u128::from(timescale)) as u64);
+    let mut start = 0;
+    Some(positions.into_iter().zip(sizes).zip(durations)
+        .map(|((offset, size), duration)| {
+            let end = start + u64::from(duration);
+            let sample = (offset, size, time(start), time(end));
+            start = end;
+            sample
+        })
+        .collect())
+}
+
+// a text sample: its length, then the text, in UTF-16 if it has a byte
+// order mark
+fn sample_text(data: &[u8]) -> String {
+    let len = u16_at(data, 0).unwrap_or(0) as usize;
+    match data.get(2..2 + len).unwrap_or(&[]) {
+        [0xfe, 0xff, text @ ..] => {
+            let units: Vec<u16> = text.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        },
+        text => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub(crate) fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    let high = u32_at(data, offset)? as u64;
+    let low = u32_at(data, offset + 4)? as u64;
+    Some((high << 32) | low)
+}
@@ -0,0 +1,491 @@
+extern crate libc;
+
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::slice;
+use std::sync::{Arc, Condvar, Mutex};
+
+use libc::{c_int, c_void};
+
+use c_api::{
+    GrooveCustomIo,
+    AVERROR_EOF,
+    AVSEEK_SIZE,
+    file_open_custom,
+};
+use audio_format::{AudioFormat, Sample};
+use context::Groove;
+use file::File;
+use wav;
+
+// one second of 44.1kHz stereo f32
+const DEFAULT_CAPACITY: usize = 352800;
+
+// may be or'ed into `whence`
+const AVSEEK_FORCE: c_int = 0x20000;
+
+/// audio produced by your program rather than read from disk. write
+/// samples in the declared format and call `open` to get a File which you
+/// can append to a Playlist like any other. the playlist decodes the
+/// samples as they arrive, so attached sinks and encoders see them too.
+///
+/// writes block while the internal queue is full, and the playlist
+/// blocks while it is empty, so write from a thread of your own. the
+/// stream ends when you call `finish` or drop the PcmSource.
+pub struct PcmSource {
+    format: AudioFormat,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+// starts a writer for a reader left waiting
+type Wake = Arc<dyn Fn() + Send + Sync>;
+
+struct State {
+    queue: Queue,
+    capacity: usize,
+    finished: bool,
+    /// the File reading from this source was closed
+    closed: bool,
+    opened: bool,
+    /// set for the sources playlists make to process items, whose writer
+    /// starts over wherever libgroove seeks to
+    seekable: bool,
+    header_len: u64,
+    frame_len: u64,
+    /// how far into the stream the reader is, in bytes
+    position: u64,
+    /// the frame the reader seeked to, until the writer picks it up
+    restart: Option<u64>,
+    /// a writer is running, or about to be
+    writing: bool,
+    /// the writer should start over after a seek, rather than stop
+    wanted: bool,
+    wake: Option<Wake>,
+}
+
+impl PcmSource {
+    /// a source of audio in `format`. 8 bit samples are unsigned, all
+    /// other integer samples are signed; floating point samples are
+    /// nominally in -1.0 to 1.0.
+    pub fn new(format: AudioFormat) -> Self {
+        let header = wav::streaming_header(format);
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                header_len: header.len() as u64,
+                frame_len: (format.channel_layout.count() as u32 * format.sample_fmt.bytes_per_sample()) as u64,
+                queue: Queue { bytes: header, start: 0 },
+                capacity: DEFAULT_CAPACITY,
+                finished: false,
+                closed: false,
+                opened: false,
+                seekable: false,
+                position: 0,
+                restart: None,
+                writing: false,
+                wanted: false,
+                wake: None,
+            }),
+            changed: Condvar::new(),
+        });
+        PcmSource { format, shared }
+    }
+
+    /// a source which lets libgroove seek. when it does, queued audio is
+    /// dropped and writes fail with `Interrupted` until the writer calls
+    /// `pass_ended` and starts again from the frame it returns.
+    pub(crate) fn seekable(format: AudioFormat) -> Self {
+        let source = PcmSource::new(format);
+        source.shared.state.lock().unwrap().seekable = true;
+        source
+    }
+
+    /// have `wake` called whenever the reader is left waiting with no
+    /// writer, to start one. it is called on the reader's thread, with the
+    /// source marked as being written.
+    pub(crate) fn on_demand(&self, wake: Wake) {
+        self.shared.state.lock().unwrap().wake = Some(wake);
+    }
+
+    /// mark the source as being written, returning whether a writer has to
+    /// be started for it: false if one is running already, or the File
+    /// reading from it was closed
+    pub(crate) fn claim(&self) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        state.wanted = true;
+        if state.writing || state.closed {
+            return false;
+        }
+        state.writing = true;
+        true
+    }
+
+    /// the frame a new writer starts from: where the reader last seeked
+    /// to, if it hasn't been picked up
+    pub(crate) fn take_restart(&self) -> Option<u64> {
+        self.shared.state.lock().unwrap().restart.take()
+    }
+
+    /// for the writer to call when a pass ends or is interrupted. gives the
+    /// frame to start over from if the reader has seeked and still wants
+    /// audio. otherwise the writer is done, and has to stop: another is
+    /// started when the reader next needs one.
+    pub(crate) fn pass_ended(&self) -> Option<u64> {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.closed && state.wanted {
+            if let Some(frame) = state.restart.take() {
+                return Some(frame);
+            }
+        }
+        state.writing = false;
+        self.shared.changed.notify_all();
+        None
+    }
+
+    /// stop writing, whatever the reader wants. for a writer which can't
+    /// carry on.
+    pub(crate) fn release(&self) {
+        self.shared.state.lock().unwrap().writing = false;
+        self.shared.changed.notify_all();
+    }
+
+    /// whether the File reading from this source was closed
+    pub(crate) fn closed(&self) -> bool {
+        self.shared.state.lock().unwrap().closed
+    }
+
+    /// have the writer start over from the first frame, as if the reader
+    /// had seeked there. for a reader which hasn't started on the audio
+    /// yet, or will seek before it does, so a running writer stops unless
+    /// `claim`ed again.
+    pub(crate) fn rewind(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        // whatever is left of the header still has to be read
+        let header_left = state.header_len.saturating_sub(state.position);
+        state.queue.truncate(header_left as usize);
+        state.position = state.position.min(state.header_len);
+        state.finished = false;
+        state.restart = Some(0);
+        state.wanted = false;
+        self.shared.changed.notify_all();
+    }
+
+    /// block until the reader has taken everything queued, failing like a
+    /// write would if it seeks or is closed first
+    pub(crate) fn wait_drained(&self) -> io::Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "pcm source file closed"));
+            }
+            if state.restart.is_some() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "pcm source seeked"));
+            }
+            if state.queue.is_empty() {
+                return Ok(());
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+
+    /// queue samples already laid out as this source's format expects
+    pub(crate) fn write_bytes(&self, bytes: &[u8]) -> io::Result<()> {
+        self.push(bytes)
+    }
+
+    pub fn audio_format(&self) -> AudioFormat {
+        self.format
+    }
+
+    /// how many bytes of audio may be queued before writes block.
+    /// defaults to one second of 44.1kHz stereo f32.
+    pub fn set_capacity(&self, bytes: usize) {
+        self.shared.state.lock().unwrap().capacity = bytes;
+        self.shared.changed.notify_all();
+    }
+
+    /// get a File which decodes the audio written to this source.
+    /// libgroove reads the start of the stream while opening it, so write
+    /// some audio first or be writing from another thread.
+    /// returns None if called a second time, or if libgroove rejects the
+    /// stream.
+    pub fn open<'g>(&self, groove: &'g Groove) -> Option<File<'g>> {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            if state.opened {
+                return None;
+            }
+            state.opened = true;
+        }
+
+        let mut reader = Box::new(PcmReader {
+            groove_custom_io: GrooveCustomIo {
+                userdata: ::std::ptr::null_mut(),
+                read_packet,
+                write_packet,
+                seek,
+            },
+            shared: self.shared.clone(),
+        });
+        let userdata: *mut PcmReader = &mut *reader;
+        reader.groove_custom_io.userdata = userdata as *mut c_void;
+
+        let hint = CString::new("pcm_source.wav").unwrap();
+        let groove_file = unsafe {
+            file_open_custom(&mut reader.groove_custom_io, hint.as_ptr())
+        };
+        if groove_file.is_null() {
+            reader.close();
+            None
+        } else {
+            Some(File::from_pcm_reader(groove, groove_file, reader))
+        }
+    }
+
+    /// queue interleaved samples: all channels of the first frame, then
+    /// all channels of the second frame, and so on.
+    /// panics if the format of this source is planar, if `T` does not match
+    /// its sample type, or if `samples` is not a whole number of frames.
+    /// fails with `BrokenPipe` once the File reading from this source has
+    /// been closed.
+    pub fn write_interleaved<T: Sample>(&self, samples: &[T]) -> io::Result<()> {
+        if self.format.sample_fmt.planar {
+            panic!("source expects planar samples");
+        }
+        self.check_sample_type::<T>();
+        let channel_count = self.format.channel_layout.count() as usize;
+        if !samples.len().is_multiple_of(channel_count) {
+            panic!("samples must be a whole number of frames");
+        }
+        self.push(as_bytes(samples))
+    }
+
+    /// queue planar samples, one slice per channel.
+    /// panics if the format of this source is not planar, if `T` does not
+    /// match its sample type, or if the channels differ in length.
+    /// fails with `BrokenPipe` once the File reading from this source has
+    /// been closed.
+    pub fn write_planar<T: Sample>(&self, channels: &[&[T]]) -> io::Result<()> {
+        if !self.format.sample_fmt.planar {
+            panic!("source expects interleaved samples");
+        }
+        self.check_sample_type::<T>();
+        if channels.len() != self.format.channel_layout.count() as usize {
+            panic!("wrong number of channels");
+        }
+        let frame_count = channels[0].len();
+        if channels.iter().any(|channel| channel.len() != frame_count) {
+            panic!("channels differ in length");
+        }
+
+        // the stream handed to libgroove is always interleaved
+        let mut interleaved = Vec::with_capacity(frame_count * channels.len());
+        for frame in 0..frame_count {
+            for channel in channels {
+                interleaved.push(channel[frame]);
+            }
+        }
+        self.push(as_bytes(&interleaved))
+    }
+
+    /// end the stream. the playlist moves on to its next item once it has
+    /// decoded everything written so far.
+    pub fn finish(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        // a seek since the writer got to the end has undone it
+        if state.restart.is_none() {
+            state.finished = true;
+        }
+        self.shared.changed.notify_all();
+    }
+
+    fn check_sample_type<T: Sample>(&self) {
+        if T::sample_type() != self.format.sample_fmt.sample_type {
+            panic!("sample type does not match the source's audio format");
+        }
+    }
+
+    fn push(&self, mut bytes: &[u8]) -> io::Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        while !bytes.is_empty() {
+            if state.closed {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "pcm source file closed"));
+            }
+            if state.restart.is_some() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "pcm source seeked"));
+            }
+            if state.finished {
+                return Err(io::Error::other("pcm source finished"));
+            }
+            let room = state.capacity.saturating_sub(state.queue.len());
+            if room == 0 {
+                state = self.shared.changed.wait(state).unwrap();
+                continue;
+            }
+            let count = room.min(bytes.len());
+            state.queue.push(&bytes[..count]);
+            bytes = &bytes[count..];
+            self.shared.changed.notify_all();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PcmSource {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+// bytes written and not yet read, which are appended and taken in bulk
+struct Queue {
+    bytes: Vec<u8>,
+    // where the unread bytes begin
+    start: usize,
+}
+
+impl Queue {
+    fn len(&self) -> usize {
+        self.bytes.len() - self.start
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        // move the unread bytes down once the read ones would be most of
+        // the buffer, rather than after every read
+        if self.start > 0 && self.start >= self.len() {
+            self.bytes.copy_within(self.start.., 0);
+            self.bytes.truncate(self.len());
+            self.start = 0;
+        }
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// move as many bytes as fit into `buf`, returning how many did
+    fn take(&mut self, buf: &mut [u8]) -> usize {
+        let count = buf.len().min(self.len());
+        buf[..count].copy_from_slice(&self.bytes[self.start..self.start + count]);
+        self.start += count;
+        if self.start == self.bytes.len() {
+            self.clear();
+        }
+        count
+    }
+
+    /// keep only the first `len` unread bytes
+    fn truncate(&mut self, len: usize) {
+        self.bytes.truncate(self.start + len);
+    }
+
+    fn clear(&mut self) {
+        self.bytes.clear();
+        self.start = 0;
+    }
+}
+
+/// the reading end of a PcmSource, owned by the File it was opened as
+pub(crate) struct PcmReader {
+    groove_custom_io: GrooveCustomIo,
+    shared: Arc<Shared>,
+}
+
+impl PcmReader {
+    /// stop blocking reads and refuse further writes
+    pub(crate) fn close(&self) {
+        let wake = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.closed = true;
+            state.wake.take()
+        };
+        self.shared.changed.notify_all();
+        // which may hold the last reference to what it starts
+        drop(wake);
+    }
+
+    fn read(&self, buf: &mut [u8]) -> c_int {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return AVERROR_EOF;
+            }
+            if !state.queue.is_empty() {
+                break;
+            }
+            if state.finished {
+                return AVERROR_EOF;
+            }
+            if !state.writing {
+                if let Some(wake) = state.wake.clone() {
+                    state.writing = true;
+                    state.wanted = true;
+                    drop(state);
+                    wake();
+                    state = self.shared.state.lock().unwrap();
+                    continue;
+                }
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+        let count = state.queue.take(buf);
+        state.position += count as u64;
+        self.shared.changed.notify_all();
+        count as c_int
+    }
+
+    fn seek(&self, offset: i64, whence: c_int) -> i64 {
+        let mut state = self.shared.state.lock().unwrap();
+        // the size is never known
+        if !state.seekable || whence & AVSEEK_SIZE != 0 {
+            return -1;
+        }
+        let target = match whence & !AVSEEK_FORCE {
+            libc::SEEK_SET => offset,
+            libc::SEEK_CUR => state.position as i64 + offset,
+            _ => return -1,
+        };
+        if target == state.position as i64 {
+            return target;
+        }
+        if target < state.header_len as i64 {
+            return -1;
+        }
+        let frame = (target as u64 - state.header_len) / state.frame_len;
+        state.queue.clear();
+        state.finished = false;
+        state.restart = Some(frame);
+        state.wanted = true;
+        state.position = state.header_len + frame * state.frame_len;
+        self.shared.changed.notify_all();
+        state.position as i64
+    }
+}
+
+extern "C" fn read_packet(io: *mut GrooveCustomIo, buf: *mut u8, buf_size: c_int) -> c_int {
+    unsafe {
+        let reader = &*((*io).userdata as *const PcmReader);
+        reader.read(slice::from_raw_parts_mut(buf, buf_size as usize))
+    }
+}
+
+extern "C" fn write_packet(_io: *mut GrooveCustomIo, _buf: *mut u8, _buf_size: c_int) -> c_int {
+    -1
+}
+
+extern "C" fn seek(io: *mut GrooveCustomIo, offset: i64, whence: c_int) -> i64 {
+    unsafe {
+        let reader = &*((*io).userdata as *const PcmReader);
+        reader.seek(offset, whence)
+    }
+}
+
+fn as_bytes<T: Sample>(samples: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(samples.as_ptr() as *const u8, mem::size_of_val(samples)) }
+}
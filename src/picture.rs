@@ -0,0 +1,214 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use flac;
+use id3;
+use matroska;
+use mp4;
+use transcode::temp_path_for;
+
+// room left after rewritten tags so later edits need not move the audio
+const ID3_PADDING: usize = 1024;
+const FLAC_PADDING: usize = 4096;
+
+/// what a picture shows, as numbered by ID3v2 and FLAC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PictureType {
+    Other,
+    /// 32x32 PNG file icon
+    FileIcon,
+    OtherFileIcon,
+    FrontCover,
+    BackCover,
+    Leaflet,
+    /// the disc or other medium itself
+    Media,
+    LeadArtist,
+    Artist,
+    Conductor,
+    Band,
+    Composer,
+    Lyricist,
+    RecordingLocation,
+    DuringRecording,
+    DuringPerformance,
+    ScreenCapture,
+    BrightColouredFish,
+    Illustration,
+    BandLogo,
+    PublisherLogo,
+}
+
+const PICTURE_TYPES: [PictureType; 21] = [
+    PictureType::Other,
+    PictureType::FileIcon,
+    PictureType::OtherFileIcon,
+    PictureType::FrontCover,
+    PictureType::BackCover,
+    PictureType::Leaflet,
+    PictureType::Media,
+    PictureType::LeadArtist,
+    PictureType::Artist,
+    PictureType::Conductor,
+    PictureType::Band,
+    PictureType::Composer,
+    PictureType::Lyricist,
+    PictureType::RecordingLocation,
+    PictureType::DuringRecording,
+    PictureType::DuringPerformance,
+    PictureType::ScreenCapture,
+    PictureType::BrightColouredFish,
+    PictureType::Illustration,
+    PictureType::BandLogo,
+    PictureType::PublisherLogo,
+];
+
+impl PictureType {
+    pub(crate) fn from_id(id: u8) -> PictureType {
+        PICTURE_TYPES.get(id as usize).cloned().unwrap_or(PictureType::Other)
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        PICTURE_TYPES.iter().position(|&picture_type| picture_type == self).unwrap() as u8
+    }
+}
+
+/// an image embedded in a file, such as cover art. see `File::pictures`.
+#[derive(Clone, Debug)]
+pub struct Picture {
+    pub picture_type: PictureType,
+    /// for example `image/jpeg` or `image/png`
+    pub mime_type: String,
+    pub description: String,
+    /// the encoded image
+    pub data: Vec<u8>,
+}
+
+pub(crate) fn is_format(format_names: &[String], name: &str) -> bool {
+    format_names.iter().any(|format_name| format_name == name)
+}
+
+/// read the pictures of a file on disk, given the short names of its
+/// container format
+pub(crate) fn read(path: &Path, format_names: &[String]) -> io::Result<Vec<Picture>> {
+    if is_format(format_names, "mp4") || is_format(format_names, "mov") {
+        return Ok(mp4::read_moov(path)?.map(|moov| mp4::pictures(&moov)).unwrap_or_default());
+    }
+    if is_format(format_names, "matroska") || is_format(format_names, "webm") {
+        return Ok(matroska::read_segment_child(path, matroska::ATTACHMENTS)?
+            .map(|attachments| matroska::pictures(&attachments))
+            .unwrap_or_default());
+    }
+
+    // anything else may start with an ID3v2 tag, FLAC included
+    let mut file = fs::File::open(path)?;
+    let tag = id3::read(&mut file)?;
+    let mut pictures = Vec::new();
+    if let Some(ref tag) = tag {
+        pictures.extend(tag.frames.iter()
+            .filter(|frame| id3::is_picture_frame(&frame.id) && frame.is_readable(tag.major_version))
+            .filter_map(|frame| id3::parse_picture(&frame.id, frame.body(tag.major_version))));
+    }
+    if is_format(format_names, "flac") {
+        file.seek(SeekFrom::Start(tag.map_or(0, |tag| tag.len)))?;
+        if let Some(metadata) = flac::read(&mut file)? {
+            pictures.extend(metadata.blocks.iter()
+                .filter(|block| block.kind == flac::PICTURE)
+                .filter_map(|block| flac::parse_picture(&block.data)));
+        }
+    }
+    Ok(pictures)
+}
+
+/// whether `write` can replace the pictures of this container format
+pub(crate) fn can_write(format_names: &[String]) -> bool {
+    ["mp3", "flac", "mp4", "mov", "matroska", "webm"].iter().any(|name| is_format(format_names, name))
+}
+
+/// a piece of a file being rewritten
+pub(crate) enum Part {
+    /// this many bytes of the old file from an offset
+    Copy(u64, u64),
+    Bytes(Vec<u8>),
+}
+
+/// replace all the pictures of a file on disk. the file is rewritten to a
+/// temporary file next to it, which is then renamed over it.
+pub(crate) fn write(path: &Path, format_names: &[String], pictures: &[Picture]) -> io::Result<()> {
+    let mut file = fs::File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let parts = if is_format(format_names, "mp4") || is_format(format_names, "mov") {
+        mp4::with_moov(&mut file, path, |moov| Ok(mp4::with_pictures(moov, pictures)))?
+    } else if is_format(format_names, "matroska") || is_format(format_names, "webm") {
+        matroska::with_pictures(&mut file, pictures)?
+    } else {
+        let tag = id3::read(&mut file)?;
+        let tag_len = tag.as_ref().map_or(0, |tag| tag.len);
+        if is_format(format_names, "flac") {
+            // keep any ID3v2 tag in front of the FLAC stream as it is
+            file.seek(SeekFrom::Start(tag_len))?;
+            let metadata = flac::read(&mut file)?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a FLAC stream"))?;
+            let mut blocks: Vec<flac::Block> = metadata.blocks.into_iter()
+                .filter(|block| block.kind != flac::PICTURE && block.kind != flac::PADDING)
+                .collect();
+            for picture in pictures {
+                let block = flac::picture_block(picture);
+                if block.data.len() >= 1 << 24 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "picture too large for FLAC"));
+                }
+                blocks.push(block);
+            }
+            let skip = tag_len + metadata.len;
+            vec![Part::Copy(0, tag_len), Part::Bytes(flac::write(&blocks, FLAC_PADDING)),
+                 Part::Copy(skip, file_len - skip)]
+        } else if is_format(format_names, "mp3") {
+            let (major_version, frames) = match tag {
+                Some(ref tag) if tag.major_version == 2 =>
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "can't rewrite an ID3v2.2 tag")),
+                Some(tag) => (tag.major_version, tag.frames),
+                None => (3, Vec::new()),
+            };
+            let mut frames: Vec<id3::Frame> = frames.into_iter()
+                .filter(|frame| !id3::is_picture_frame(&frame.id))
+                .collect();
+            frames.extend(pictures.iter().map(|picture| id3::picture_frame(picture, major_version)));
+            vec![Part::Bytes(id3::write(major_version, &frames, ID3_PADDING)), Part::Copy(tag_len, file_len - tag_len)]
+        } else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "can't write pictures to this format"));
+        }
+    };
+
+    replace(path, &mut file, &parts)
+}
+
+/// rewrite `file`, opened from `path`, as `parts`. they are written to a
+/// temporary file next to it, which is then renamed over it.
+pub(crate) fn replace(path: &Path, file: &mut fs::File, parts: &[Part]) -> io::Result<()> {
+    let temp_path = temp_path_for(path);
+    let result = rewrite(file, &temp_path, parts)
+        .and_then(|()| fs::set_permissions(&temp_path, file.metadata()?.permissions()))
+        .and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// write `parts` to `temp_path`, copying from `file`
+fn rewrite(file: &mut fs::File, temp_path: &Path, parts: &[Part]) -> io::Result<()> {
+    let mut out = fs::File::create(temp_path)?;
+    for part in parts {
+        match *part {
+            Part::Copy(start, len) => {
+                file.seek(SeekFrom::Start(start))?;
+                if io::copy(&mut Read::by_ref(file).take(len), &mut out)? != len {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file changed while rewriting it"));
+                }
+            },
+            Part::Bytes(ref bytes) => out.write_all(bytes)?,
+        }
+    }
+    out.sync_all()
+}
@@ -3,21 +3,59 @@ use c_api::{
     GroovePlaylist,
     ANY_SINK_FULL,
     EVERY_SINK_FULL,
-    groove_playlist_create,
     groove_playlist_destroy,
     groove_playlist_insert,
+    groove_playlist_remove,
+    groove_playlist_position,
     groove_playlist_clear,
     groove_playlist_set_fill_mode,
+    groove_playlist_seek,
+    groove_playlist_play,
+    groove_playlist_pause,
+    playlist_create,
+    playlist_playing,
 };
 
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use buffer::DecodedBuffer;
+use context::Groove;
+use cue::{CueSheet, CueTrack};
+use events::Notification;
 use file::File;
+use laps::{Extender, Laps, Source};
+use playlist_file::{self, Format, Loaded, PlaylistEntry};
+use render::{self, Decoders, Entry, Lineup, Region, Rendered};
+use sink::Sink;
+
+// shared by every playlist, so that an id can't be mistaken for an item
+// of another
+static NEXT_KEY: AtomicU64 = AtomicU64::new(0);
 
-pub struct PlaylistItem {
-    groove_playlist_item: *mut GroovePlaylistItem,
-    file: File
+pub struct PlaylistItem<'g> {
+    pub(crate) groove_playlist_item: *mut GroovePlaylistItem,
+    file: File<'g>,
+    // what libgroove plays in place of `file` when the playlist renders
+    // the item itself
+    rendered: Option<Rendered<'g>>,
+    // tells the item apart in the lineup, and is its ItemId
+    key: u64,
+    region: Region,
+    // what the item says about itself over its file's tags, like the title
+    // of a cue sheet track or chapter
+    tags: Vec<(String, String)>,
 }
 
-impl PlaylistItem {
+impl<'g> PlaylistItem<'g> {
     /// A volume adjustment in float format to apply to the file when it plays.
     /// This is typically used for loudness compensation, for example ReplayGain.
     /// To convert from dB to float, use exp(log(10) * 0.05 * dB_value)
@@ -38,32 +76,210 @@ impl PlaylistItem {
         }
     }
 
-    pub fn file(&self) -> &File {
+    pub fn file(&self) -> &File<'g> {
         &self.file
     }
+
+    pub fn id(&self) -> ItemId {
+        ItemId(self.key)
+    }
+
+    /// where in the file the item starts playing
+    pub fn start(&self) -> Duration {
+        self.region.start
+    }
+
+    /// where in the file the item stops playing, if before the end
+    pub fn end(&self) -> Option<Duration> {
+        self.region.end
+    }
+
+    /// why the playlist's own decoding of the item, for its `ItemOptions`,
+    /// transitions or repeats, last stopped short of the end, if it did:
+    /// say if its file could not be opened again. the item then ends where
+    /// the decoding stopped, and the playlist moves on.
+    pub fn render_error(&self) -> Option<io::Error> {
+        self.rendered.as_ref().and_then(Rendered::error)
+    }
+
+    pub fn fade_in(&self) -> Option<Fade> {
+        self.region.fade_in
+    }
+
+    pub fn fade_out(&self) -> Option<Fade> {
+        self.region.fade_out
+    }
+
+    /// the item's tag for `key`, ignoring case: its own, such as the title
+    /// of the cue sheet track or chapter it plays, or else its file's
+    pub fn tag(&self, key: &str) -> Option<String> {
+        self.tags.iter()
+            .find(|(tag_key, _)| tag_key.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.clone())
+            .or_else(|| self.file.metadata_get(key, false).and_then(|tag| tag.value().ok().map(str::to_owned)))
+    }
+}
+
+/// names a playlist item for as long as it is in its playlist, wherever
+/// edits move it. ids are never reused, so one whose item was removed stays
+/// stale.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ItemId(pub(crate) u64);
+
+/// an `ItemId` whose item is no longer in the playlist, or never was
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StaleItem(pub ItemId);
+
+impl fmt::Display for StaleItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "playlist item {} is not in the playlist", (self.0).0)
+    }
+}
+
+impl error::Error for StaleItem {}
+
+/// why `Playlist::set_ab_loop` failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbLoopError {
+    Stale(StaleItem),
+    /// B is not after A
+    EmptyRange,
+}
+
+impl fmt::Display for AbLoopError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AbLoopError::Stale(ref stale) => stale.fmt(f),
+            AbLoopError::EmptyRange => write!(f, "A-B loop ends before it starts"),
+        }
+    }
+}
+
+impl error::Error for AbLoopError {}
+
+impl From<StaleItem> for AbLoopError {
+    fn from(stale: StaleItem) -> Self {
+        AbLoopError::Stale(stale)
+    }
+}
+
+/// how the level changes through a fade
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FadeShape {
+    Linear,
+    /// rises quickly, then levels off. sounds more even than linear to
+    /// most ears.
+    Logarithmic,
+    /// starts and finishes gently
+    SCurve,
+}
+
+/// a fade into or out of a playlist item
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fade {
+    pub shape: FadeShape,
+    pub length: Duration,
+}
+
+impl Fade {
+    pub fn new(shape: FadeShape, length: Duration) -> Self {
+        Fade { shape, length }
+    }
+}
+
+/// how a file plays as a playlist item
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ItemOptions {
+    /// skip this much of the start of the file
+    pub start: Duration,
+    /// stop this far into the file, rather than at its end
+    pub end: Option<Duration>,
+    /// fade in over the start of the item, from `start`
+    pub fade_in: Option<Fade>,
+    /// fade out over the end of the item, reaching silence at `end`
+    pub fade_out: Option<Fade>,
+    /// see `PlaylistItem::gain`
+    pub gain: f64,
+    /// see `PlaylistItem::peak`
+    pub peak: f64,
+}
+
+impl Default for ItemOptions {
+    fn default() -> Self {
+        ItemOptions {
+            start: Duration::from_secs(0),
+            end: None,
+            fade_in: None,
+            fade_out: None,
+            gain: 1.0,
+            peak: 1.0,
+        }
+    }
+}
+
+/// how one playlist item leads into the next
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Transition {
+    /// straight into the next item, with nothing added or taken away
+    #[default]
+    Gapless,
+    /// fade the end of the item out while the start of the next fades in,
+    /// overlapping them by this long
+    Crossfade(Duration),
+    /// this much silence between the items
+    Silence(Duration),
+}
+
+/// what a playlist does at the end of an item
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RepeatMode {
+    /// go on to the next item, and stop after the last
+    #[default]
+    Off,
+    /// play the same item again, until the mode changes
+    One,
+    /// go on to the next item, and back to the first after the last
+    All,
 }
 
 /// a playlist keeps its sinks full.
-pub struct Playlist {
+///
+/// transitions other than `Gapless`, repeats, A-B loops, and items trimmed
+/// or faded with `ItemOptions`, are handled before the audio reaches
+/// libgroove: the playlist decodes such items itself on a thread of its
+/// own and plays the result in their place.
+pub struct Playlist<'g> {
+    groove: &'g Groove,
     pub(crate) groove_playlist: *mut GroovePlaylist,
-    items: Vec<PlaylistItem>
+    items: Vec<PlaylistItem<'g>>,
+    lineup: Arc<Mutex<Lineup>>,
+    laps: Arc<Mutex<Laps>>,
+    extender: Arc<Extender>,
+    decoders: Arc<Decoders>,
 }
 
-impl Drop for Playlist {
+impl<'g> Drop for Playlist<'g> {
     fn drop(&mut self) {
+        // the rendering threads finish as their items go
         self.clear();
+        self.extender.stop();
+        self.laps.lock().unwrap().close();
         unsafe { groove_playlist_destroy(self.groove_playlist) }
     }
 }
 
-impl Playlist {
-    pub fn new() -> Self {
-        super::init();
-        unsafe {
-            Playlist {
-                groove_playlist: groove_playlist_create(),
-                items: Vec::new()
-            }
+impl<'g> Playlist<'g> {
+    pub fn new(groove: &'g Groove) -> Self {
+        let groove_playlist = unsafe { playlist_create() };
+        let laps = Arc::new(Mutex::new(Laps::new(groove_playlist)));
+        Playlist {
+            groove,
+            groove_playlist,
+            items: Vec::new(),
+            lineup: Arc::new(Mutex::new(Lineup::default())),
+            extender: Arc::new(Extender::new(&laps)),
+            laps,
+            decoders: Arc::default(),
         }
     }
 
@@ -75,60 +291,534 @@ impl Playlist {
         }
     }
 
-    pub fn items(&self) -> &Vec<PlaylistItem> {
-        &self.items
+    /// the items, in the order they play
+    pub fn ids(&self) -> Vec<ItemId> {
+        self.items.iter().map(PlaylistItem::id).collect()
     }
 
-    /// once you add a file to the playlist, you must not destroy it until you first
-    /// remove it from the playlist.
-    /// before: the item to insert before.
-    /// gain: see Groove. use 1.0 for no adjustment.
-    /// peak: see Groove. use 1.0 for no adjustment.
-    /// returns the newly created playlist item.
-    fn _insert(&mut self, file: File, gain: f64, peak: f64, index: Option<usize>) {
-        let before_item = if let Some(index) = index {
-            self.items[index].groove_playlist_item
-        } else {
-            ::std::ptr::null_mut()
-        };
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// the item `id` names, if it is still in the playlist
+    pub fn item(&self, id: ItemId) -> Option<&PlaylistItem<'g>> {
+        self.items.iter().find(|item| item.key == id.0)
+    }
+
+    /// where the item `id` names is in the playlist now
+    pub fn index_of(&self, id: ItemId) -> Option<usize> {
+        self.items.iter().position(|item| item.key == id.0)
+    }
+
+    /// the item a buffer from one of this playlist's sinks came from
+    pub fn item_of(&self, buffer: &DecodedBuffer) -> Option<ItemId> {
+        self.laps.lock().unwrap().key_of(buffer.groove_playlist_item()).map(ItemId)
+    }
+
+    /// find the index of the item a buffer came from
+    pub(crate) fn index_of_groove_item(&self, groove_playlist_item: *mut GroovePlaylistItem) -> Option<usize> {
+        self.laps.lock().unwrap().index_of(groove_playlist_item)
+    }
+
+    // the playlist takes `file` until the item is removed. `index` is where
+    // the new item goes, or None for the end; `options` carries its gain
+    // and peak, which are 1.0 for no adjustment. gives the new item's id.
+    fn _insert(&mut self, file: File<'g>, options: ItemOptions, index: Option<usize>) -> ItemId {
+        let ItemOptions { start, end, fade_in, fade_out, gain, peak } = options;
+        let region = Region { start, end, fade_in, fade_out };
 
-        let groove_playlist_item = unsafe {
-            groove_playlist_insert(
-                self.groove_playlist,
-                file.groove_file,
-                gain,
-                peak,
-                before_item
-            )
+        let key = NEXT_KEY.fetch_add(1, atomic::Ordering::Relaxed);
+        let decoding = self.decoding();
+        let entry_index = index.unwrap_or(self.items.len());
+        let render = {
+            let mut lineup = self.lineup.lock().unwrap();
+            let mut entry = Entry::new(key, file.origin());
+            entry.region = region;
+            entry.rendered = entry.origin.is_some() && (region.altered() || lineup.processing());
+            let render = entry.rendered;
+            lineup.entries.insert(entry_index, entry);
+            render
         };
+        // an item going in after the one being decoded, or where nothing
+        // is, may be decoded straight away
+        let now = decoding.is_none_or(|decoding| entry_index == decoding + 1);
+        let rendered = if render { self.render(key, &file, now) } else { None };
 
-        if groove_playlist_item.is_null() {
-            panic!("out of memory");
-        }
+        {
+            // held, so that the item to insert before stays put
+            let mut laps = self.laps.lock().unwrap();
+            let before_item = match index {
+                Some(index) => self.items[index].groove_playlist_item,
+                None => laps.first_copy(),
+            };
+            let groove_playlist_item = unsafe {
+                groove_playlist_insert(
+                    self.groove_playlist,
+                    rendered.as_ref().map_or(&file, |rendered| &rendered.file).groove_file(),
+                    gain,
+                    peak,
+                    before_item
+                )
+            };
 
-        let playlist_item = PlaylistItem { groove_playlist_item, file };
+            if groove_playlist_item.is_null() {
+                panic!("out of memory");
+            }
 
-        if let Some(index) = index {
-            self.items.insert(index, playlist_item);
-        } else {
-            self.items.push(playlist_item);
+            file.set_in_playlist(true);
+            let playlist_item = PlaylistItem { groove_playlist_item, file, rendered, key, region, tags: Vec::new() };
+
+            if let Some(index) = index {
+                self.items.insert(index, playlist_item);
+            } else {
+                self.items.push(playlist_item);
+            }
+            laps.sources = sources(&self.items);
+        }
+        // the item it went in before follows it now. the one being decoded
+        // keeps going as it was.
+        if let Some(index) = index.filter(|&index| decoding != Some(index)) {
+            let mut lineup = self.lineup.lock().unwrap();
+            let ahead = decoding.is_some_and(|decoding| index > decoding);
+            relink(&mut lineup, index + 1, self.items[index + 1].rendered.as_ref(), ahead);
         }
+        self.update_laps();
+        self.render_after(self.decoding());
+        ItemId(key)
     }
 
-    pub fn append(&mut self, file: File, gain: f64, peak: f64) {
-        self._insert(file, gain, peak, None)
+    pub fn append(&mut self, file: File<'g>, gain: f64, peak: f64) -> ItemId {
+        self._insert(file, ItemOptions { gain, peak, ..ItemOptions::default() }, None)
     }
 
-    pub fn insert(&mut self, file: File, gain: f64, peak: f64, index: usize) {
-        self._insert(file, gain, peak, Some(index))
+    /// like `append`, inserting before the item `before`
+    pub fn insert(&mut self, file: File<'g>, gain: f64, peak: f64, before: ItemId) -> Result<ItemId, StaleItem> {
+        self.insert_before(before, file, ItemOptions { gain, peak, ..ItemOptions::default() })
+    }
+
+    /// append a file which plays as `options` say. trimming is sample
+    /// accurate, and the item's buffers and seeks count from its `start`
+    /// rather than the start of the file. fades apply before the audio
+    /// reaches any sink or encoder; with a crossfade, both apply. files
+    /// opened from a `PcmSource` can't be decoded again, so always play
+    /// whole.
+    pub fn append_with(&mut self, file: File<'g>, options: ItemOptions) -> ItemId {
+        self._insert(file, options, None)
+    }
+
+    /// like `append_with`, inserting before the item `before`
+    pub fn insert_before(&mut self, before: ItemId, file: File<'g>, options: ItemOptions)
+                         -> Result<ItemId, StaleItem> {
+        let index = self.index(before)?;
+        Ok(self._insert(file, options, Some(index)))
+    }
+
+    /// take an item out of the playlist, giving back its file. if it was
+    /// playing, the playlist moves on to the next item.
+    pub fn remove(&mut self, id: ItemId) -> Result<File<'g>, StaleItem> {
+        let index = self.index(id)?;
+        let decoding = self.decoding();
+        {
+            // the item after it follows the one before it now. done before
+            // libgroove moves on to it, if it was playing.
+            let mut lineup = self.lineup.lock().unwrap();
+            lineup.entries.remove(index);
+            let next = index + 1;
+            if next < self.items.len() && decoding != Some(next) {
+                let ahead = decoding.is_some_and(|decoding| next > decoding);
+                relink(&mut lineup, index, self.items[next].rendered.as_ref(), ahead);
+            }
+        }
+
+        let item = {
+            let item = &self.items[index];
+            // the decode thread may be blocked reading from a PcmSource
+            item.file.abort_io();
+            if let Some(ref rendered) = item.rendered {
+                rendered.file.abort_io();
+            }
+            let mut laps = self.laps.lock().unwrap();
+            laps.forget(item.key);
+            unsafe { groove_playlist_remove(self.groove_playlist, item.groove_playlist_item) };
+            let item = self.items.remove(index);
+            laps.sources = sources(&self.items);
+            item
+        };
+        self.update_laps();
+        self.render_after(self.decoding());
+        // what libgroove played in its place closes as the rest is dropped,
+        // now that libgroove has let go of it
+        item.file.set_in_playlist(false);
+        Ok(item.file)
     }
 
     /// remove all playlist items
     pub fn clear(&mut self) {
+        // the decode thread may be blocked reading from a PcmSource
+        for item in &self.items {
+            item.file.abort_io();
+            if let Some(ref rendered) = item.rendered {
+                rendered.file.abort_io();
+            }
+        }
+        {
+            let mut laps = self.laps.lock().unwrap();
+            unsafe { groove_playlist_clear(self.groove_playlist) };
+            laps.cleared();
+            laps.sources.clear();
+        }
+        self.items.clear();
+        self.lineup.lock().unwrap().entries.clear();
+    }
+
+    /// put the items in an order picked by `seed`. the same seed always
+    /// gives the same order for the same number of items.
+    pub fn shuffle(&mut self, seed: u64) {
+        let mut order: Vec<usize> = (0..self.items.len()).collect();
+        let mut state = seed;
+        for index in (1..order.len()).rev() {
+            let other = (split_mix(&mut state) % (index as u64 + 1)) as usize;
+            order.swap(index, other);
+        }
+        self.reorder(order);
+    }
+
+    /// sort the items, keeping those which compare equal in the order they
+    /// were in
+    pub fn sort_by<F>(&mut self, mut compare: F)
+        where F: FnMut(&PlaylistItem<'g>, &PlaylistItem<'g>) -> Ordering
+    {
+        let mut order: Vec<usize> = (0..self.items.len()).collect();
+        order.sort_by(|&a, &b| compare(&self.items[a], &self.items[b]));
+        self.reorder(order);
+    }
+
+    pub fn reverse(&mut self) {
+        let order = (0..self.items.len()).rev().collect();
+        self.reorder(order);
+    }
+
+    /// swap the places of the items `a` and `b`
+    pub fn swap(&mut self, a: ItemId, b: ItemId) -> Result<(), StaleItem> {
+        let (a, b) = (self.index(a)?, self.index(b)?);
+        let mut order: Vec<usize> = (0..self.items.len()).collect();
+        order.swap(a, b);
+        self.reorder(order);
+        Ok(())
+    }
+
+    // put the item at `order[i]` at `i` for every i. the item libgroove is
+    // decoding stays put there, and keeps playing, while what comes after
+    // it changes.
+    fn reorder(&mut self, order: Vec<usize>) {
+        let playing = self.decoding();
+        let prev_keys = |items: &[PlaylistItem]| -> Vec<Option<u64>> {
+            (0..items.len()).map(|index| index.checked_sub(1).map(|prev| items[prev].key)).collect()
+        };
+
+        let old_prev = prev_keys(&self.items);
+        let mut items: Vec<Option<PlaylistItem<'g>>> = self.items.drain(..).map(Some).collect();
+        self.items = order.iter().map(|&index| items[index].take().unwrap()).collect();
+        let new_prev = prev_keys(&self.items);
+        let new_playing = playing.and_then(|playing| order.iter().position(|&index| index == playing));
+
+        // what was rendered to follow one item now follows another. the
+        // playing item keeps going as it was.
+        {
+            let mut lineup = self.lineup.lock().unwrap();
+            let mut entries: Vec<Option<Entry>> = lineup.entries.drain(..).map(Some).collect();
+            lineup.entries = order.iter().map(|&index| entries[index].take().unwrap()).collect();
+            for index in 0..order.len() {
+                if old_prev[order[index]] == new_prev[index] || Some(index) == new_playing {
+                    continue;
+                }
+                let ahead = new_playing.is_some_and(|playing| index > playing);
+                relink(&mut lineup, index, self.items[index].rendered.as_ref(), ahead);
+            }
+        }
+
+        // items which keep their order relative to each other, and to the
+        // playing one, can stay where they are in libgroove's list.
+        // libgroove drops what is queued of an item it removes, so the
+        // fewer that move, the better.
+        let unmoved = |first: usize, last: usize, keep: &dyn Fn(usize) -> bool| -> Vec<usize> {
+            let pairs: Vec<(usize, usize)> = (first..last)
+                .filter(|&index| keep(order[index]))
+                .map(|index| (index, order[index]))
+                .collect();
+            longest_increasing(&pairs)
+        };
+        let staying = match (playing, new_playing) {
+            (Some(playing), Some(new_playing)) => {
+                let mut staying = unmoved(0, new_playing, &|old| old < playing);
+                staying.push(new_playing);
+                staying.extend(unmoved(new_playing + 1, order.len(), &|old| old > playing));
+                staying
+            },
+            _ => unmoved(0, order.len(), &|_| true),
+        };
+
+        {
+            // held, so that the items to insert before stay put
+            let mut laps = self.laps.lock().unwrap();
+            for index in (0..self.items.len()).rev() {
+                if staying.binary_search(&index).is_ok() {
+                    continue;
+                }
+                let next = self.items.get(index + 1).map_or(laps.first_copy(), |item| item.groove_playlist_item);
+                let item = &mut self.items[index];
+                let file = item.rendered.as_ref().map_or(&item.file, |rendered| &rendered.file);
+                unsafe {
+                    let (gain, peak) = ((*item.groove_playlist_item).gain, (*item.groove_playlist_item).peak);
+                    groove_playlist_remove(self.groove_playlist, item.groove_playlist_item);
+                    item.groove_playlist_item = groove_playlist_insert(self.groove_playlist, file.groove_file(),
+                                                                       gain, peak, next);
+                }
+                if item.groove_playlist_item.is_null() {
+                    panic!("out of memory");
+                }
+            }
+            laps.sources = sources(&self.items);
+        }
+        self.update_laps();
+        self.render_after(new_playing);
+    }
+
+    /// how items lead into each other, unless `set_item_transition` says
+    /// otherwise. defaults to `Transition::Gapless`.
+    pub fn transition(&self) -> Transition {
+        self.lineup.lock().unwrap().transition
+    }
+
+    /// set how every item leads into the next. this applies to the items
+    /// libgroove has not started decoding yet; the one it is decoding
+    /// keeps going as it was, unless a transition was already set when it
+    /// was added.
+    pub fn set_transition(&mut self, transition: Transition) {
+        self.lineup.lock().unwrap().transition = transition;
+        self.render_upcoming();
+    }
+
+    /// the transition out of the item `id`, if it has its own
+    pub fn item_transition(&self, id: ItemId) -> Result<Option<Transition>, StaleItem> {
+        let index = self.index(id)?;
+        Ok(self.lineup.lock().unwrap().entries[index].transition)
+    }
+
+    /// give the item `id` its own transition into the next item, or go
+    /// back to the playlist's with None. see `set_transition`.
+    pub fn set_item_transition(&mut self, id: ItemId, transition: Option<Transition>) -> Result<(), StaleItem> {
+        let index = self.index(id)?;
+        self.lineup.lock().unwrap().entries[index].transition = transition;
+        self.render_upcoming();
+        Ok(())
+    }
+
+    /// what the playlist does at the end of an item. defaults to
+    /// `RepeatMode::Off`. like transitions, repeating needs the items
+    /// rendered, so `One` applies to the items libgroove has not started
+    /// decoding yet. with `All`, a transition out of the last item leads
+    /// into the first, except a crossfade, which the first item would have
+    /// to start without.
+    pub fn repeat(&self) -> RepeatMode {
+        self.lineup.lock().unwrap().repeat
+    }
+
+    pub fn set_repeat(&mut self, mode: RepeatMode) {
+        self.lineup.lock().unwrap().repeat = mode;
+        self.render_upcoming();
+        self.laps.lock().unwrap().repeat = mode == RepeatMode::All;
+        self.update_laps();
+    }
+
+    /// the A-B loop of the item `id`, if it has one
+    pub fn ab_loop(&self, id: ItemId) -> Result<Option<(Duration, Duration)>, StaleItem> {
+        let index = self.index(id)?;
+        Ok(self.lineup.lock().unwrap().entries[index].ab_loop)
+    }
+
+    /// play the item `id` from `a` to `b` over and over, with no gap and no
+    /// end of the playlist in between, or with None go on past `b` the
+    /// next time it gets there. times are into the item, so count from its
+    /// `start`. if the item is being decoded, playback jumps to `a`;
+    /// otherwise the loop starts when the item plays up to `b`. fails with
+    /// `EmptyRange` if `b` is not after `a`.
+    pub fn set_ab_loop(&mut self, id: ItemId, ab_loop: Option<(Duration, Duration)>) -> Result<(), AbLoopError> {
+        if let Some((a, b)) = ab_loop {
+            if a >= b {
+                return Err(AbLoopError::EmptyRange);
+            }
+        }
+        let index = self.index(id)?;
+        let key = id.0;
+        let renderable = {
+            let mut lineup = self.lineup.lock().unwrap();
+            let entry = &mut lineup.entries[index];
+            entry.ab_loop = ab_loop;
+            entry.origin.is_some()
+        };
+        let (a, _) = match ab_loop {
+            Some(ab_loop) => ab_loop,
+            None => return Ok(()),
+        };
+
+        let mut decode_head = ptr::null_mut();
+        unsafe { groove_playlist_position(self.groove_playlist, &mut decode_head, ptr::null_mut()) };
+        let playing = self.index_of_groove_item(decode_head) == Some(index);
+        if self.items[index].rendered.is_none() && renderable {
+            {
+                let mut lineup = self.lineup.lock().unwrap();
+                if let Some(entry) = lineup.entries.iter_mut().find(|entry| entry.key == key) {
+                    entry.rendered = true;
+                }
+            }
+            self.switch_to_rendering(index);
+            self.update_laps();
+        }
+        if playing {
+            self.seek(id, a.as_secs_f64())?;
+        }
+        Ok(())
+    }
+
+    // get ready to render an item, or if that can't be done, note that it
+    // plays as it is. `now` for an item libgroove may decode next.
+    fn render(&self, key: u64, file: &File<'g>, now: bool) -> Option<Rendered<'g>> {
+        let rendered = render::start(self.groove, &self.lineup, &self.extender, &self.decoders, key, file, now);
+        if rendered.is_none() {
+            let mut lineup = self.lineup.lock().unwrap();
+            if let Some(entry) = lineup.entries.iter_mut().find(|entry| entry.key == key) {
+                entry.rendered = false;
+            }
+        }
+        rendered
+    }
+
+    // switch the items after the one being decoded over to rendering, now
+    // that something needs them to be
+    fn render_upcoming(&mut self) {
+        let mut decode_head = ptr::null_mut();
+        unsafe { groove_playlist_position(self.groove_playlist, &mut decode_head, ptr::null_mut()) };
+        // at the end of the playlist there's nothing left to switch, and
+        // inserting would start it playing again
+        let first = match self.index_of_groove_item(decode_head) {
+            Some(index) => index + 1,
+            None => return,
+        };
+
+        // marked all at once, so that each sees the others when working
+        // out its crossfades
+        let keys: Vec<u64> = {
+            let mut lineup = self.lineup.lock().unwrap();
+            if !lineup.processing() {
+                return;
+            }
+            self.items[first..].iter()
+                .zip(&mut lineup.entries[first..])
+                .filter(|(item, entry)| item.rendered.is_none() && entry.origin.is_some())
+                .map(|(item, entry)| {
+                    entry.rendered = true;
+                    item.key
+                })
+                .collect()
+        };
+
+        // from the end, so that each goes in before an item already in place
+        for index in (first..self.items.len()).rev() {
+            if keys.contains(&self.items[index].key) {
+                self.switch_to_rendering(index);
+            }
+        }
+        self.update_laps();
+    }
+
+    // have libgroove play the rendered item at `index` in place of its file
+    fn switch_to_rendering(&mut self, index: usize) {
+        let now = self.decoding().is_none_or(|decoding| index == decoding || index == decoding + 1);
+        let rendered = match self.render(self.items[index].key, &self.items[index].file, now) {
+            Some(rendered) => rendered,
+            None => return,
+        };
+        let mut laps = self.laps.lock().unwrap();
+        let next = self.items.get(index + 1).map_or(laps.first_copy(), |item| item.groove_playlist_item);
+        let item = &mut self.items[index];
+        unsafe {
+            let (gain, peak) = ((*item.groove_playlist_item).gain, (*item.groove_playlist_item).peak);
+            groove_playlist_remove(self.groove_playlist, item.groove_playlist_item);
+            item.groove_playlist_item = groove_playlist_insert(self.groove_playlist, rendered.file.groove_file(),
+                                                               gain, peak, next);
+        }
+        if item.groove_playlist_item.is_null() {
+            panic!("out of memory");
+        }
+        item.rendered = Some(rendered);
+        laps.sources = sources(&self.items);
+    }
+
+    // have the rendered item after the one at `index` rendering, for
+    // libgroove to move on to. the others render once it gets to them.
+    fn render_after(&self, index: Option<usize>) {
+        let next = index.and_then(|index| self.items.get(index + 1));
+        if let Some(rendered) = next.and_then(|item| item.rendered.as_ref()) {
+            rendered.start();
+        }
+    }
+
+    // the index of the item libgroove is decoding, unless it is on a lap
+    fn decoding(&self) -> Option<usize> {
+        let mut decode_head = ptr::null_mut();
+        unsafe { groove_playlist_position(self.groove_playlist, &mut decode_head, ptr::null_mut()) };
+        self.items.iter().position(|item| item.groove_playlist_item == decode_head)
+    }
+
+    // bring the laps into line with the items, once `laps.sources` has
+    // caught up with them
+    fn update_laps(&mut self) {
+        self.laps.lock().unwrap().rebuild();
+    }
+
+    /// start decoding the item `id` from `seconds` into it. sinks are
+    /// flushed of any audio decoded before the seek.
+    pub fn seek(&self, id: ItemId, seconds: f64) -> Result<(), StaleItem> {
+        let index = self.index(id)?;
         unsafe {
-            groove_playlist_clear(self.groove_playlist);
-            self.items.clear();
+            groove_playlist_seek(self.groove_playlist, self.items[index].groove_playlist_item, seconds)
         }
+        self.render_after(Some(index));
+        Ok(())
+    }
+
+    // where the item `id` is, or why it isn't anywhere
+    fn index(&self, id: ItemId) -> Result<usize, StaleItem> {
+        self.index_of(id).ok_or(StaleItem(id))
+    }
+
+    /// tell the sinks to carry on playing. playlists start out playing.
+    pub fn play(&self) {
+        unsafe { groove_playlist_play(self.groove_playlist) }
+    }
+
+    /// tell the sinks to stop playing. decoding carries on until they are
+    /// full.
+    pub fn pause(&self) {
+        unsafe { groove_playlist_pause(self.groove_playlist) }
+    }
+
+    pub fn playing(&self) -> bool {
+        unsafe { playlist_playing(self.groove_playlist) }
+    }
+
+    /// events from this playlist as `sink` plays it: items starting and
+    /// finishing, seeks, pauses and the end of the playlist, each timed by
+    /// how much audio `sink` had handed out. item events come as buffers
+    /// are taken from the sink, so arrive as they are played, not as they
+    /// are decoded.
+    pub fn subscribe(&self, sink: &Sink) -> Receiver<Notification> {
+        sink.events.subscribe(self.groove_playlist, self.laps.clone())
     }
 
     pub fn set_fill_mode(&self, mode: FillMode) {
@@ -139,6 +829,273 @@ impl Playlist {
 
         unsafe { groove_playlist_set_fill_mode(self.groove_playlist, mode_int) }
     }
+
+    /// append the files an M3U or M3U8 playlist file lists. relative
+    /// locations are resolved against its directory, and the durations and
+    /// titles of `#EXTINF` lines come back with the entries. each item
+    /// plays from its entry's `start` to its `end`, or to the end of the
+    /// file; its `duration` is only a hint, as playlist files round them
+    /// and often get them wrong. entries which can't be opened are left out and reported, rather
+    /// than failing the load; only failing to read the playlist file itself
+    /// does that.
+    pub fn load_m3u(&mut self, path: &Path) -> io::Result<Loaded> {
+        self.load(path, Format::M3u)
+    }
+
+    /// like `load_m3u`, for a PLS playlist file
+    pub fn load_pls(&mut self, path: &Path) -> io::Result<Loaded> {
+        self.load(path, Format::Pls)
+    }
+
+    /// like `load_m3u`, for an XSPF playlist file
+    pub fn load_xspf(&mut self, path: &Path) -> io::Result<Loaded> {
+        self.load(path, Format::Xspf)
+    }
+
+    /// write the playlist as an extended M3U file, in UTF-8. each item is
+    /// named by its file, relative to the playlist file's directory if
+    /// inside it, with its duration and the artist and title of its `tag`s.
+    /// trimmed items, like cue sheet tracks, say where they start and stop
+    /// with VLC's `start-time` and `stop-time` options. items playing from
+    /// a `PcmSource` have no file to name, so are left out.
+    pub fn save_m3u(&self, path: &Path) -> io::Result<()> {
+        self.save(path, Format::M3u)
+    }
+
+    /// like `save_m3u`, as a PLS playlist file. PLS can only say how long
+    /// an item plays, so items trimmed at the start play from the start of
+    /// their file when loaded back.
+    pub fn save_pls(&self, path: &Path) -> io::Result<()> {
+        self.save(path, Format::Pls)
+    }
+
+    /// like `save_m3u`, as an XSPF playlist file, which has the album too
+    pub fn save_xspf(&self, path: &Path) -> io::Result<()> {
+        self.save(path, Format::Xspf)
+    }
+
+    /// append each track of a cue sheet file as an item playing its part
+    /// of the file the sheet puts it in, resolved against the sheet's
+    /// directory. items have the title and performer of their track as
+    /// their own `tag`s, with the sheet's title as the album. tracks whose
+    /// file can't be opened are left out and reported.
+    ///
+    /// like any trimmed item, each track opens its file for itself, and is
+    /// decoded again as libgroove nears it, with a libgroove playlist and
+    /// sink of the playlist's own. tracks which follow on from each other
+    /// in one file share that decode, which carries on from one into the
+    /// next, so a 20 track album holds its file open 20 times, and once or
+    /// twice more while it plays. append the file whole to play it without
+    /// that cost.
+    pub fn load_cue(&mut self, path: &Path) -> io::Result<Loaded> {
+        let sheet = CueSheet::read(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut loaded = Loaded::default();
+        for track in &sheet.tracks {
+            let entry = cue_entry(&sheet, track, dir.join(&track.file));
+            match File::open(self.groove, &entry.path) {
+                Some(file) => {
+                    let id = self.append_track(file, &sheet, track);
+                    loaded.added.push((id, entry));
+                },
+                None => loaded.unreadable.push(entry),
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// like `load_cue`, for a sheet of tracks all in `file`, such as the
+    /// one `File::cue_sheet` finds embedded in it. the file plays the first
+    /// track, and is opened again for the rest, at the cost `load_cue`
+    /// describes.
+    pub fn append_cue(&mut self, file: File<'g>, sheet: &CueSheet) -> Loaded {
+        let path = file.filename().to_path_buf();
+        let origin = file.origin();
+        let mut file = Some(file);
+        let mut loaded = Loaded::default();
+        for track in &sheet.tracks {
+            let entry = cue_entry(sheet, track, path.clone());
+            match file.take().or_else(|| origin.as_ref().and_then(|origin| origin.open(self.groove))) {
+                Some(file) => {
+                    let id = self.append_track(file, sheet, track);
+                    loaded.added.push((id, entry));
+                },
+                None => loaded.unreadable.push(entry),
+            }
+        }
+        loaded
+    }
+
+    /// append each of `file`'s `chapters` as an item playing that part of
+    /// it, with the chapter's title as its own `tag`. the file plays the
+    /// first chapter, and is opened again for the rest, at the cost
+    /// `load_cue` describes: chapters share a decode as tracks do. the last
+    /// chapter plays to the end of the file. a file without chapters is
+    /// appended whole.
+    pub fn append_chapters(&mut self, file: File<'g>) -> Loaded {
+        let chapters = file.chapters();
+        let path = file.filename().to_path_buf();
+        let origin = file.origin();
+        let mut loaded = Loaded::default();
+        if chapters.is_empty() {
+            let entry = PlaylistEntry { location: path.to_string_lossy().into_owned(), path, ..Default::default() };
+            loaded.added.push((self.append(file, 1.0, 1.0), entry));
+            return loaded;
+        }
+
+        let mut file = Some(file);
+        for (index, chapter) in chapters.iter().enumerate() {
+            let title = Some(chapter.title.clone()).filter(|title| !title.is_empty());
+            let entry = PlaylistEntry {
+                location: path.to_string_lossy().into_owned(),
+                path: path.clone(),
+                title: title.clone(),
+                duration: Some(chapter.end - chapter.start),
+                start: chapter.start,
+                end: Some(chapter.end).filter(|_| index + 1 < chapters.len()),
+                ..Default::default()
+            };
+            match file.take().or_else(|| origin.as_ref().and_then(|origin| origin.open(self.groove))) {
+                Some(file) => {
+                    let options = ItemOptions { start: entry.start, end: entry.end, ..ItemOptions::default() };
+                    let id = self.append_with(file, options);
+                    self.items.last_mut().unwrap().tags = title.map(|title| vec![("title".to_string(), title)])
+                        .unwrap_or_default();
+                    loaded.added.push((id, entry));
+                },
+                None => loaded.unreadable.push(entry),
+            }
+        }
+        loaded
+    }
+
+    fn append_track(&mut self, file: File<'g>, sheet: &CueSheet, track: &CueTrack) -> ItemId {
+        let id = self.append_with(file, ItemOptions { start: track.start, end: track.end, ..ItemOptions::default() });
+        self.items.last_mut().unwrap().tags = sheet.tags(track);
+        id
+    }
+
+    fn load(&mut self, path: &Path, format: Format) -> io::Result<Loaded> {
+        let mut loaded = Loaded::default();
+        for entry in playlist_file::read(path, format)? {
+            match File::open(self.groove, &entry.path) {
+                Some(file) => {
+                    let options = entry_options(&entry);
+                    let id = self.append_with(file, options);
+                    loaded.added.push((id, entry));
+                },
+                None => loaded.unreadable.push(entry),
+            }
+        }
+        Ok(loaded)
+    }
+
+    fn save(&self, path: &Path, format: Format) -> io::Result<()> {
+        let entries: Vec<PlaylistEntry> = self.items.iter().filter_map(entry).collect();
+        playlist_file::write(path, format, &entries)
+    }
+}
+
+// a playlist file entry for a cue sheet track in the file at `path`
+fn cue_entry(sheet: &CueSheet, track: &CueTrack, path: PathBuf) -> PlaylistEntry {
+    PlaylistEntry {
+        location: track.file.clone(),
+        path,
+        title: track.title.clone(),
+        artist: track.performer.clone().or_else(|| sheet.performer.clone()),
+        album: sheet.title.clone(),
+        duration: track.end.map(|end| end.saturating_sub(track.start)),
+        start: track.start,
+        end: track.end,
+    }
+}
+
+// how an item plays the part of its file a playlist file entry gives
+fn entry_options(entry: &PlaylistEntry) -> ItemOptions {
+    ItemOptions { start: entry.start, end: entry.end, ..ItemOptions::default() }
+}
+
+// what a playlist file says about `item`
+fn entry(item: &PlaylistItem) -> Option<PlaylistEntry> {
+    let path = item.file.origin()?.path;
+    let end = item.region.end.or_else(|| {
+        let seconds = item.file.duration();
+        if seconds.is_finite() && seconds > 0.0 { Some(Duration::from_secs_f64(seconds)) } else { None }
+    });
+    Some(PlaylistEntry {
+        location: path.to_string_lossy().into_owned(),
+        path,
+        title: item.tag("title"),
+        artist: item.tag("artist"),
+        album: item.tag("album"),
+        duration: end.map(|end| end.saturating_sub(item.region.start)),
+        start: item.region.start,
+        end: item.region.end,
+    })
+}
+
+// the item at `index` now follows a different item. whatever it rendered
+// to follow the old one is done again if libgroove will play it: if it is
+// `ahead` of the item being decoded, or if the playlist goes round again.
+fn relink(lineup: &mut Lineup, index: usize, rendered: Option<&Rendered>, ahead: bool) {
+    lineup.entries[index].unsettle();
+    if ahead || lineup.repeat == RepeatMode::All {
+        if let Some(rendered) = rendered {
+            rendered.rewind();
+        }
+    }
+}
+
+// what libgroove plays for each item, for the laps
+fn sources(items: &[PlaylistItem]) -> Vec<Source> {
+    items.iter()
+        .map(|item| unsafe {
+            Source {
+                key: item.key,
+                item: item.groove_playlist_item,
+                file: item.rendered.as_ref().map_or(&item.file, |rendered| &rendered.file).groove_file(),
+                gain: (*item.groove_playlist_item).gain,
+                peak: (*item.groove_playlist_item).peak,
+            }
+        })
+        .collect()
+}
+
+// the next number from a splitmix64 generator, which is all a shuffle
+// needs
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// the first of each pair for the longest run of pairs whose seconds
+// increase, in order
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<usize> {
+    // the pair ending the best run of each length found so far
+    let mut ends: Vec<usize> = Vec::new();
+    let mut prev = vec![None; pairs.len()];
+    for (index, &(_, value)) in pairs.iter().enumerate() {
+        let length = ends.partition_point(|&end| pairs[end].1 < value);
+        if length > 0 {
+            prev[index] = Some(ends[length - 1]);
+        }
+        if length == ends.len() {
+            ends.push(index);
+        } else {
+            ends[length] = index;
+        }
+    }
+    let mut run = Vec::new();
+    let mut next = ends.last().cloned();
+    while let Some(index) = next {
+        run.push(pairs[index].0);
+        next = prev[index];
+    }
+    run.reverse();
+    run
 }
 
 #[derive(Clone, Copy, Debug)]
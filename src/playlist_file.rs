@@ -0,0 +1,495 @@
+// reading and writing M3U, PLS and XSPF playlist files. these only list
+// what to play, so reading one gives entries for `Playlist` to open, and
+// writing one names each item's file with what its tags say about it.
+// M3U and PLS files may be Latin-1 rather than UTF-8; they are read as
+// whichever they are, and always written as UTF-8.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use playlist::ItemId;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// M3U or M3U8, extended with `#EXTINF` lines when written
+    M3u,
+    Pls,
+    Xspf,
+}
+
+/// a file listed by a playlist file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistEntry {
+    /// the entry as written in the playlist file. when saving, this is
+    /// worked out from `path`.
+    pub location: String,
+    /// where the file is, with relative locations resolved against the
+    /// directory of the playlist file. URLs other than `file://` ones are
+    /// left as they are, for libav to open.
+    pub path: PathBuf,
+    /// the title the playlist file gives. M3U and PLS files usually give
+    /// "artist - title".
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// how long the playlist file says the entry plays for. only a hint;
+    /// loaded items aren't trimmed to it.
+    pub duration: Option<Duration>,
+    /// how far into the file the entry starts, from VLC's `start-time`
+    /// option in M3U and XSPF files
+    pub start: Duration,
+    /// how far into the file the entry stops, from VLC's `stop-time`
+    /// option
+    pub end: Option<Duration>,
+}
+
+/// what loading a playlist file added to a playlist
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Loaded {
+    /// the items appended, in order, with the entries they came from
+    pub added: Vec<(ItemId, PlaylistEntry)>,
+    /// entries whose file couldn't be opened, which were left out
+    pub unreadable: Vec<PlaylistEntry>,
+}
+
+pub(crate) fn read(path: &Path, format: Format) -> io::Result<Vec<PlaylistEntry>> {
+    let bytes = fs::read(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let entries = match format {
+        Format::M3u => parse_m3u(&text(&bytes)),
+        Format::Pls => parse_pls(&text(&bytes)),
+        Format::Xspf => parse_xspf(&String::from_utf8_lossy(&bytes)),
+    };
+    Ok(entries.into_iter()
+        .map(|(mut entry, uri)| {
+            entry.path = resolve(&entry.location, uri, dir);
+            entry
+        })
+        .collect())
+}
+
+/// write `entries` to `path`, naming each by its `path`
+pub(crate) fn write(path: &Path, format: Format, entries: &[PlaylistEntry]) -> io::Result<()> {
+    let dir = absolute(path.parent().unwrap_or_else(|| Path::new("")));
+    let entries: Vec<PlaylistEntry> = entries.iter()
+        .map(|entry| {
+            let path = relative(&entry.path, &dir);
+            let location = match format {
+                Format::Xspf => uri(&path),
+                Format::M3u | Format::Pls => path.to_string_lossy().into_owned(),
+            };
+            PlaylistEntry { location, ..entry.clone() }
+        })
+        .collect();
+    let text = match format {
+        Format::M3u => write_m3u(&entries),
+        Format::Pls => write_pls(&entries),
+        Format::Xspf => write_xspf(&entries),
+    };
+    fs::write(path, text)
+}
+
+// ---------------------------------------------------------------------------
+// M3U
+
+// entries, and whether their locations are URIs
+fn parse_m3u(text: &str) -> Vec<(PlaylistEntry, bool)> {
+    let mut entries = Vec::new();
+    // what the lines before a location say about it
+    let mut entry = PlaylistEntry::default();
+    for line in text.lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<seconds>[ attributes],<title>
+            let (head, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let seconds = head.split_whitespace().next().and_then(|seconds| seconds.parse().ok());
+            entry.duration = seconds.and_then(seconds_to_duration);
+            entry.title = non_empty(title);
+        } else if let Some(option) = line.strip_prefix("#EXTVLCOPT:") {
+            vlc_option(&mut entry, option);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            entry.location = line.to_owned();
+            entries.push((entry, false));
+            entry = PlaylistEntry::default();
+        }
+    }
+    entries
+}
+
+fn write_m3u(entries: &[PlaylistEntry]) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for entry in entries {
+        let seconds = entry.duration.map_or("-1".to_owned(), seconds);
+        let _ = writeln!(text, "#EXTINF:{},{}", seconds, display_title(entry));
+        for option in vlc_options(entry) {
+            let _ = writeln!(text, "#EXTVLCOPT:{}", option);
+        }
+        let _ = writeln!(text, "{}", entry.location);
+    }
+    text
+}
+
+// ---------------------------------------------------------------------------
+// PLS
+
+fn parse_pls(text: &str) -> Vec<(PlaylistEntry, bool)> {
+    let mut entries: Vec<(usize, PlaylistEntry)> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+        // FileN, TitleN and LengthN, in any order
+        let digits = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let number: usize = match key[digits..].parse() {
+            Ok(number) => number,
+            Err(_) => continue,
+        };
+        let at = match entries.iter().position(|&(n, _)| n == number) {
+            Some(at) => at,
+            None => {
+                entries.push((number, PlaylistEntry::default()));
+                entries.len() - 1
+            },
+        };
+        let entry = &mut entries[at].1;
+        match &key[..digits] {
+            "file" => entry.location = value.to_owned(),
+            "title" => entry.title = non_empty(value),
+            "length" => entry.duration = value.parse().ok().and_then(seconds_to_duration),
+            _ => {},
+        }
+    }
+    entries.sort_by_key(|&(number, _)| number);
+    entries.into_iter()
+        .filter(|(_, entry)| !entry.location.is_empty())
+        .map(|(_, entry)| (entry, false))
+        .collect()
+}
+
+fn write_pls(entries: &[PlaylistEntry]) -> String {
+    let mut text = String::from("[playlist]\n");
+    for (number, entry) in (1..).zip(entries) {
+        let _ = writeln!(text, "File{}={}", number, entry.location);
+        let title = display_title(entry);
+        if !title.is_empty() {
+            let _ = writeln!(text, "Title{}={}", number, title);
+        }
+        let seconds = entry.duration.map_or("-1".to_owned(), seconds);
+        let _ = writeln!(text, "Length{}={}", number, seconds);
+    }
+    let _ = writeln!(text, "NumberOfEntries={}", entries.len());
+    text.push_str("Version=2\n");
+    text
+}
+
+// ---------------------------------------------------------------------------
+// XSPF
+
+fn parse_xspf(xml: &str) -> Vec<(PlaylistEntry, bool)> {
+    elements(xml, "track").into_iter()
+        .filter_map(|track| {
+            let field = |name| elements(track, name).first()
+                .and_then(|raw| non_empty(&xml_text(raw)));
+            let location = field("location")?;
+            // milliseconds
+            let duration = field("duration")
+                .and_then(|millis| millis.parse().ok())
+                .and_then(|millis: f64| seconds_to_duration(millis / 1000.0));
+            let mut entry = PlaylistEntry {
+                location,
+                title: field("title"),
+                artist: field("creator"),
+                album: field("album"),
+                duration,
+                ..PlaylistEntry::default()
+            };
+            for option in elements(track, "vlc:option") {
+                vlc_option(&mut entry, &xml_text(option));
+            }
+            Some((entry, true))
+        })
+        .collect()
+}
+
+fn write_xspf(entries: &[PlaylistEntry]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\" ");
+    xml.push_str("xmlns:vlc=\"http://www.videolan.org/vlc/playlist/ns/0/\">\n  <trackList>\n");
+    for entry in entries {
+        xml.push_str("    <track>\n");
+        let _ = writeln!(xml, "      <location>{}</location>", xml_escape(&entry.location));
+        let fields = [("title", &entry.title), ("creator", &entry.artist), ("album", &entry.album)];
+        for &(name, value) in &fields {
+            if let Some(value) = value {
+                let _ = writeln!(xml, "      <{0}>{1}</{0}>", name, xml_escape(value));
+            }
+        }
+        if let Some(duration) = entry.duration {
+            let _ = writeln!(xml, "      <duration>{}</duration>", duration.as_millis());
+        }
+        let options = vlc_options(entry);
+        if !options.is_empty() {
+            xml.push_str("      <extension application=\"http://www.videolan.org/vlc/playlist/0\">\n");
+            for option in options {
+                let _ = writeln!(xml, "        <vlc:option>{}</vlc:option>", option);
+            }
+            xml.push_str("      </extension>\n");
+        }
+        xml.push_str("    </track>\n");
+    }
+    xml.push_str("  </trackList>\n</playlist>\n");
+    xml
+}
+
+// the contents of each `name` element in `xml`, not looking inside them for
+// more. enough XML for XSPF, whose only namespace prefix in practice is
+// VLC's, which is matched as part of the name.
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(at) = rest.find(&open) {
+        rest = &rest[at + open.len()..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[..end];
+        // another element whose name starts with this one, like trackList
+        if tag.chars().next().is_some_and(|c| !c.is_whitespace() && c != '/') {
+            continue;
+        }
+        rest = &rest[end + 1..];
+        if tag.ends_with('/') {
+            found.push("");
+            continue;
+        }
+        match rest.find(&close) {
+            Some(end) => {
+                found.push(&rest[..end]);
+                rest = &rest[end + close.len()..];
+            },
+            None => break,
+        }
+    }
+    found
+}
+
+fn xml_text(raw: &str) -> String {
+    let raw = raw.trim();
+    if let Some(data) = raw.strip_prefix("<![CDATA[").and_then(|raw| raw.strip_suffix("]]>")) {
+        return data.to_owned();
+    }
+    let mut text = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(at) = rest.find('&') {
+        text.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let character = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            entity => entity.strip_prefix('#')
+                .and_then(|number| match number.strip_prefix('x').or_else(|| number.strip_prefix('X')) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => number.parse().ok(),
+                })
+                .and_then(std::char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                text.push(character);
+                rest = &rest[end + 1..];
+            },
+            // not an entity after all
+            None => {
+                text.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+// ---------------------------------------------------------------------------
+// locations
+
+fn resolve(location: &str, uri: bool, dir: &Path) -> PathBuf {
+    if let Some(rest) = location.strip_prefix("file://") {
+        // file:///path, or file://localhost/path
+        let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+        return PathBuf::from(OsStr::from_bytes(&percent_decode(rest)));
+    }
+    if has_scheme(location) {
+        return PathBuf::from(location);
+    }
+    let path = if uri {
+        PathBuf::from(OsStr::from_bytes(&percent_decode(location)))
+    } else {
+        PathBuf::from(location)
+    };
+    // joining an absolute path replaces the directory
+    dir.join(path)
+}
+
+// a URL like http://, which libav may be able to open
+fn has_scheme(location: &str) -> bool {
+    match location.find("://") {
+        Some(end) => {
+            let scheme = &location[..end];
+            scheme.len() > 1 && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        },
+        None => false,
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_owned();
+    }
+    match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path.to_owned(),
+    }
+}
+
+// `path` relative to `dir` if it's inside it, otherwise absolute
+fn relative(path: &Path, dir: &Path) -> PathBuf {
+    if has_scheme(&path.to_string_lossy()) {
+        return path.to_owned();
+    }
+    let path = absolute(path);
+    match path.strip_prefix(dir) {
+        Ok(inside) => inside.to_owned(),
+        Err(_) => path,
+    }
+}
+
+// `path` as a URI, relative if it is
+fn uri(path: &Path) -> String {
+    if has_scheme(&path.to_string_lossy()) {
+        return path.to_string_lossy().into_owned();
+    }
+    let mut uri = String::from(if path.is_absolute() { "file://" } else { "" });
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            let _ = write!(uri, "%{:02X}", byte);
+        }
+    }
+    uri
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut at = 0;
+    while at < bytes.len() {
+        let escaped = bytes.get(at + 1..at + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) if bytes[at] == b'%' => {
+                decoded.push(byte);
+                at += 3;
+            },
+            _ => {
+                decoded.push(bytes[at]);
+                at += 1;
+            },
+        }
+    }
+    decoded
+}
+
+// ---------------------------------------------------------------------------
+// helpers
+
+// UTF-8 if it is, otherwise Latin-1
+pub(crate) fn text(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_owned(),
+        Err(_) => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+// VLC's options for where an entry starts and stops, in seconds
+fn vlc_option(entry: &mut PlaylistEntry, option: &str) {
+    let (name, value) = match option.split_once('=') {
+        Some((name, value)) => (name.trim(), value.trim().parse().ok().and_then(seconds_to_duration)),
+        None => return,
+    };
+    match (name, value) {
+        ("start-time", Some(start)) => entry.start = start,
+        ("stop-time", end) => entry.end = end,
+        _ => {},
+    }
+}
+
+fn vlc_options(entry: &PlaylistEntry) -> Vec<String> {
+    let mut options = Vec::new();
+    if entry.start > Duration::from_secs(0) {
+        options.push(format!("start-time={}", seconds(entry.start)));
+    }
+    if let Some(end) = entry.end {
+        options.push(format!("stop-time={}", seconds(end)));
+    }
+    options
+}
+
+// whole seconds as such, and otherwise to the millisecond
+fn seconds(duration: Duration) -> String {
+    format!("{}", duration.as_millis() as f64 / 1000.0)
+}
+
+// negative for unknown, in M3U and PLS
+fn seconds_to_duration(seconds: f64) -> Option<Duration> {
+    if seconds.is_finite() && seconds >= 0.0 { Some(Duration::from_secs_f64(seconds)) } else { None }
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() { None } else { Some(text.to_owned()) }
+}
+
+// "artist - title", as M3U and PLS players show it
+fn display_title(entry: &PlaylistEntry) -> String {
+    match (&entry.artist, &entry.title) {
+        (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+        (None, Some(title)) => title.clone(),
+        (Some(artist), None) => artist.clone(),
+        (None, None) => String::new(),
+    }
+}
@@ -0,0 +1,773 @@
+// items which a playlist changes on their way in. libgroove can only play
+// a file as it is, so for these a thread of ours decodes the file again,
+// works on the audio and hands the result to libgroove through a
+// PcmSource. the playlist plays that instead of the file, so every sink and
+// encoder attached to it hears the result. only the item libgroove is
+// decoding and the one after it are rendered at a time.
+
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::io;
+use std::mem;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use audio_format::{AudioFormat, SampleFormat, SampleType};
+use buffer::OwnedAudioBuffer;
+use context::Groove;
+use file::{File, Origin, RangeDecoder};
+use laps::Extender;
+use pcm_source::PcmSource;
+use playlist::{Fade, FadeShape, RepeatMode, Transition};
+
+/// what the rendering threads of a playlist know about its items, in
+/// playlist order. shared with them, so never held while calling into
+/// libgroove, which may be waiting on one of them.
+#[derive(Default)]
+pub(crate) struct Lineup {
+    pub(crate) transition: Transition,
+    pub(crate) repeat: RepeatMode,
+    pub(crate) entries: Vec<Entry>,
+}
+
+pub(crate) struct Entry {
+    pub(crate) key: u64,
+    /// None for files which can't be opened again, so can't be rendered
+    pub(crate) origin: Option<Origin>,
+    pub(crate) rendered: bool,
+    /// replaces the playlist's transition into the next item
+    pub(crate) transition: Option<Transition>,
+    pub(crate) region: Region,
+    /// play from the first time to the second over and over, as times
+    /// into the region
+    pub(crate) ab_loop: Option<(Duration, Duration)>,
+    head: Head,
+    tail: Tail,
+    job: Option<Arc<Job>>,
+}
+
+/// the part of its file an item plays, and how it fades in and out of it
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Region {
+    pub(crate) start: Duration,
+    pub(crate) end: Option<Duration>,
+    pub(crate) fade_in: Option<Fade>,
+    pub(crate) fade_out: Option<Fade>,
+}
+
+impl Region {
+    /// whether playing the region changes the file's audio at all
+    pub(crate) fn altered(&self) -> bool {
+        self.start > Duration::from_secs(0) || self.end.is_some() || self.fade_in.is_some() ||
+            self.fade_out.is_some()
+    }
+}
+
+// whether the item before took the start of this one into its crossfade.
+// settled when this item first renders, and kept, so that seeking back
+// into it gives the same audio.
+#[derive(Clone, Copy, PartialEq)]
+enum Head {
+    Unsettled,
+    Whole,
+    Crossfaded { from: u64, length: Duration },
+}
+
+// whether this item's rendering has ended, crossfading or not. once it
+// has, an item which comes to follow it plays whole, as there is nothing
+// left to mix it into.
+#[derive(Clone, Copy, PartialEq)]
+enum Tail {
+    Pending,
+    Ended,
+}
+
+// what to put at the end of an item
+enum Ending {
+    Cut,
+    Crossfade { origin: Origin, region: Region, length: Duration },
+    Silence(Duration),
+}
+
+impl Entry {
+    /// forget how the item joins the one before, now that it follows
+    /// another
+    pub(crate) fn unsettle(&mut self) {
+        self.head = Head::Unsettled;
+        self.tail = Tail::Pending;
+    }
+
+    pub(crate) fn new(key: u64, origin: Option<Origin>) -> Self {
+        Entry {
+            key,
+            origin,
+            rendered: false,
+            transition: None,
+            region: Region::default(),
+            ab_loop: None,
+            head: Head::Unsettled,
+            tail: Tail::Pending,
+            job: None,
+        }
+    }
+}
+
+impl Lineup {
+    /// whether every item needs rendering
+    pub(crate) fn processing(&self) -> bool {
+        self.transition != Transition::Gapless || self.repeat != RepeatMode::Off ||
+            self.entries.iter()
+                .any(|entry| entry.transition.is_some_and(|transition| transition != Transition::Gapless))
+    }
+
+    fn index_of(&self, key: u64) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.key == key)
+    }
+
+    fn transition_after(&self, index: usize) -> Transition {
+        self.entries[index].transition.unwrap_or(self.transition)
+    }
+
+    // the index of the item after the one at `index`. when the whole
+    // playlist repeats, the first item follows the last.
+    fn next_index(&self, index: usize) -> usize {
+        match self.repeat {
+            RepeatMode::All if index + 1 == self.entries.len() => 0,
+            _ => index + 1,
+        }
+    }
+
+    // what renders the item which plays after `key`
+    fn next_job(&self, key: u64) -> Option<Arc<Job>> {
+        let index = self.index_of(key)?;
+        if self.repeat == RepeatMode::One {
+            return None;
+        }
+        self.entries.get(self.next_index(index))?.job.clone()
+    }
+
+    // how much of the start of an item the item before has taken
+    fn settle_head(&mut self, key: u64) -> Duration {
+        let index = match self.index_of(key) {
+            Some(index) => index,
+            None => return Duration::from_secs(0),
+        };
+        if self.entries[index].head == Head::Unsettled {
+            let crossfade = match index.checked_sub(1) {
+                Some(prev) if self.entries[prev].rendered && self.entries[prev].tail == Tail::Pending => {
+                    match self.transition_after(prev) {
+                        Transition::Crossfade(length) => Some((self.entries[prev].key, length)),
+                        _ => None,
+                    }
+                },
+                _ => None,
+            };
+            self.entries[index].head = match crossfade {
+                Some((from, length)) => Head::Crossfaded { from, length },
+                None => Head::Whole,
+            };
+        }
+        match self.entries[index].head {
+            Head::Crossfaded { length, .. } => length,
+            _ => Duration::from_secs(0),
+        }
+    }
+
+    // the crossfade an item should hold audio back for
+    fn crossfade_after(&self, key: u64) -> Duration {
+        match self.index_of(key).map(|index| self.transition_after(index)) {
+            Some(Transition::Crossfade(length)) => length,
+            _ => Duration::from_secs(0),
+        }
+    }
+
+    fn settle_tail(&mut self, key: u64) -> Ending {
+        let index = match self.index_of(key) {
+            Some(index) => index,
+            None => return Ending::Cut,
+        };
+        let next = self.next_index(index);
+        // the next item may not have got to its start yet, say if it was
+        // only just moved here. it takes what this one leaves it.
+        if let Some(next) = self.entries.get(next).filter(|next| next.rendered && next.head == Head::Unsettled) {
+            let key = next.key;
+            self.settle_head(key);
+        }
+        let next = self.entries.get(next);
+        let ending = match (self.transition_after(index), next) {
+            (Transition::Crossfade(_), Some(next)) => match (next.head, &next.origin) {
+                (Head::Crossfaded { from, length }, Some(origin)) if from == key =>
+                    Ending::Crossfade { origin: origin.clone(), region: next.region, length },
+                _ => Ending::Cut,
+            },
+            (Transition::Silence(length), Some(_)) => Ending::Silence(length),
+            _ => Ending::Cut,
+        };
+        self.entries[index].tail = Tail::Ended;
+        ending
+    }
+}
+
+// why rendering last went wrong, if it did
+type Failure = Arc<Mutex<Option<(io::ErrorKind, String)>>>;
+
+/// what libgroove plays in place of a rendered item
+pub(crate) struct Rendered<'g> {
+    pub(crate) file: File<'g>,
+    job: Arc<Job>,
+}
+
+impl<'g> Drop for Rendered<'g> {
+    // the worker finishes once nothing reads what it renders
+    fn drop(&mut self) {
+        self.file.abort_io();
+        self.job.join();
+    }
+}
+
+impl<'g> Rendered<'g> {
+    /// render the item again from its start, before libgroove gets to it
+    pub(crate) fn rewind(&self) {
+        self.job.source.rewind();
+    }
+
+    /// have the item rendering, if it isn't already, for libgroove to
+    /// move on to
+    pub(crate) fn start(&self) {
+        self.job.start();
+    }
+
+    /// why rendering stopped short, the last time it did
+    pub(crate) fn error(&self) -> Option<io::Error> {
+        self.job.failure.lock().unwrap().as_ref().map(|(kind, message)| io::Error::new(*kind, message.clone()))
+    }
+}
+
+/// get ready to render the item `key`, which plays `file`, and open what
+/// libgroove should play in its place. the audio keeps the file's sample
+/// rate, channels and sample type. rendering starts straight away if
+/// `now`, for an item libgroove may decode next; otherwise when libgroove
+/// first reads it, or it is started. None for files which can't be
+/// opened again.
+pub(crate) fn start<'g>(groove: &'g Groove, lineup: &Arc<Mutex<Lineup>>, extender: &Arc<Extender>,
+                        decoders: &Arc<Decoders>, key: u64, file: &File, now: bool) -> Option<Rendered<'g>> {
+    let origin = file.origin()?;
+    let format = file.audio_format();
+    let sample_type = match format.sample_fmt.sample_type {
+        SampleType::NoType => SampleType::Dbl,
+        sample_type => sample_type,
+    };
+    let format = AudioFormat { sample_fmt: SampleFormat { sample_type, planar: false }, ..format };
+    let source = Arc::new(PcmSource::seekable(format));
+    let job = Arc::new(Job {
+        key,
+        origin,
+        source: source.clone(),
+        failure: Failure::default(),
+        lineup: Arc::downgrade(lineup),
+        extender: extender.clone(),
+        decoders: decoders.clone(),
+        worker: Mutex::new(None),
+    });
+    let demand = Arc::downgrade(&job);
+    source.on_demand(Arc::new(move || {
+        if let Some(job) = demand.upgrade() {
+            job.spawn();
+        }
+    }));
+
+    // libgroove reads the start of the stream while opening it, so either
+    // the worker has to be running first, or the stream has to look empty
+    // until libgroove seeks back to its start
+    if now {
+        job.start();
+    } else {
+        source.finish();
+    }
+    let file = match source.open(groove) {
+        Some(file) => file,
+        None => {
+            // the source was closed, so its worker stops
+            job.join();
+            return None;
+        },
+    };
+    if !now {
+        source.rewind();
+    }
+    if let Some(entry) = lineup.lock().unwrap().entries.iter_mut().find(|entry| entry.key == key) {
+        entry.job = Some(job.clone());
+    }
+    Some(Rendered { file, job })
+}
+
+// renders an item whenever it is needed, on a thread which stops once the
+// item is rendered, or is moved away from where libgroove is decoding
+struct Job {
+    key: u64,
+    origin: Origin,
+    source: Arc<PcmSource>,
+    failure: Failure,
+    lineup: Weak<Mutex<Lineup>>,
+    extender: Arc<Extender>,
+    decoders: Arc<Decoders>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Job {
+    // start a worker, unless one is running
+    fn start(&self) {
+        if self.source.claim() {
+            self.spawn();
+        }
+    }
+
+    // start a worker for the source, which has been claimed for it
+    fn spawn(&self) {
+        let mut worker = self.worker.lock().unwrap();
+        let lineup = match self.lineup.upgrade() {
+            Some(lineup) => lineup,
+            None => return self.source.release(),
+        };
+        if self.source.closed() {
+            return self.source.release();
+        }
+        let new = Worker {
+            lineup,
+            extender: self.extender.clone(),
+            decoders: self.decoders.clone(),
+            key: self.key,
+            origin: self.origin.clone(),
+            source: self.source.clone(),
+            failure: self.failure.clone(),
+            done: false,
+        };
+        // the worker before let go of the source, so is on its way out
+        if let Some(before) = worker.replace(thread::spawn(move || new.run())) {
+            let _ = before.join();
+        }
+    }
+
+    // wait for the worker, once the source is closed
+    fn join(&self) {
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            // a panic was recorded as a failure on its way out
+            let _ = worker.join();
+        }
+    }
+}
+
+struct Worker {
+    lineup: Arc<Mutex<Lineup>>,
+    extender: Arc<Extender>,
+    decoders: Arc<Decoders>,
+    key: u64,
+    origin: Origin,
+    source: Arc<PcmSource>,
+    failure: Failure,
+    // the source has been let go of, for another worker to take
+    done: bool,
+}
+
+impl Drop for Worker {
+    // however the thread ends, libgroove mustn't be left waiting for more
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if thread::panicking() {
+            self.fail(&io::Error::other("render thread panicked"));
+            self.source.finish();
+        }
+        self.source.release();
+    }
+}
+
+impl Worker {
+    fn run(mut self) {
+        let mut restart = self.source.take_restart();
+        loop {
+            // libgroove seeks to the start of an item whenever it moves on
+            // to it, which may mean the playlist has come round to a lap
+            // which needs another after it
+            if restart.is_some() && self.lineup.lock().unwrap().repeat == RepeatMode::All {
+                self.extender.request();
+            }
+            match self.render(restart.unwrap_or(0)) {
+                Ok(()) => {
+                    *self.failure.lock().unwrap() = None;
+                    self.source.finish();
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+                // closed, so nothing will seek
+                Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => return,
+                // what was rendered plays, and the playlist moves on
+                Err(ref err) => {
+                    self.fail(err);
+                    self.source.finish();
+                },
+            }
+            restart = self.source.pass_ended();
+            if restart.is_none() {
+                self.done = true;
+                return;
+            }
+        }
+    }
+
+    fn fail(&self, err: &io::Error) {
+        *self.failure.lock().unwrap() = Some((err.kind(), err.to_string()));
+    }
+
+    // write the item's audio from `from` frames into it
+    fn render(&self, from: u64) -> io::Result<()> {
+        let channel_count = self.source.audio_format().channel_layout.count() as usize;
+        let frames = |time: Duration| self.frames(time);
+
+        let (skip, hold, region) = {
+            let mut lineup = self.lineup.lock().unwrap();
+            let (skip, hold) = (lineup.settle_head(self.key), lineup.crossfade_after(self.key));
+            match lineup.index_of(self.key) {
+                Some(index) => (skip, hold, lineup.entries[index].region),
+                None => return Ok(()),
+            }
+        };
+        // frames in the region, if it ends before the file does
+        let length = region.end.map(|end| frames(end).saturating_sub(frames(region.start)));
+        // the end of the item is held back until we know whether it
+        // crossfades, and so it can fade out
+        let fade_length = region.fade_out.map_or(0, |fade| frames(fade.length));
+        let hold = frames(hold).max(fade_length) * channel_count;
+        // how far into the region rendering is
+        let mut position = frames(skip) + from as usize;
+        let mut held = Vec::new();
+
+        loop {
+            // an A-B loop ahead ends this pass at B
+            let ab_loop = self.ab_loop().filter(|&(_, b)| position < b);
+            let until = ab_loop.map(|(_, b)| b).or(length);
+            let hold = if ab_loop.is_some() { 0 } else { hold };
+            position = self.decode(&region, position, until, &mut held, hold)?;
+            if ab_loop.is_none() {
+                self.start_next();
+            }
+            // rendering runs ahead of playback, so wait until libgroove is
+            // about to reach the end of the pass before deciding what comes
+            // next: the loop may have been let go, or the next item may not
+            // have been added yet
+            self.source.wait_drained()?;
+
+            if ab_loop.is_some() && until == Some(position) {
+                if let Some((a, _)) = self.ab_loop() {
+                    position = a;
+                }
+                continue;
+            }
+
+            if let Some(fade) = region.fade_out {
+                fade_out_frames(&mut held, channel_count, fade.shape, fade_length);
+            }
+            if self.lineup.lock().unwrap().repeat == RepeatMode::One {
+                self.write(&held)?;
+                held.clear();
+                position = 0;
+                continue;
+            }
+            let ending = self.lineup.lock().unwrap().settle_tail(self.key);
+            return self.end(held, ending);
+        }
+    }
+
+    // have the next item rendering while libgroove plays out this one
+    fn start_next(&self) {
+        let next = self.lineup.lock().unwrap().next_job(self.key);
+        if let Some(next) = next {
+            next.start();
+        }
+    }
+
+    fn frames(&self, time: Duration) -> usize {
+        (time.as_secs_f64() * self.source.audio_format().sample_rate as f64).round() as usize
+    }
+
+    // the item's A-B loop, in frames
+    fn ab_loop(&self) -> Option<(usize, usize)> {
+        let ab_loop = {
+            let lineup = self.lineup.lock().unwrap();
+            lineup.entries[lineup.index_of(self.key)?].ab_loop
+        };
+        ab_loop.map(|(a, b)| (self.frames(a), self.frames(b)))
+    }
+
+    // decode `region` from `position` frames into it until `until` or its
+    // end, fading in as it goes. all but the last `hold` samples are
+    // written, and the rest left in `held`. returns the position it
+    // stopped at.
+    fn decode(&self, region: &Region, position: usize, until: Option<usize>, held: &mut Vec<f64>,
+              hold: usize) -> io::Result<usize> {
+        let format = working_format(self.source.audio_format());
+        let channel_count = format.channel_layout.count() as usize;
+        let start = self.frames(region.start) + position;
+        let mut frame = position;
+        let mut decoder = self.decoders.take(&self.origin, start as u64, format)?;
+        while until.is_none_or(|until| frame < until) {
+            let mut samples = match decoder.read() {
+                Some(samples) => samples,
+                None => break,
+            };
+            if let Some(until) = until {
+                let end = samples.len().min((until - frame) * channel_count);
+                decoder.unread(samples.split_off(end));
+            }
+            let begin = held.len();
+            held.append(&mut samples);
+            if let Some(fade) = region.fade_in {
+                fade_in_frames(&mut held[begin..], channel_count, frame, fade.shape, self.frames(fade.length));
+            }
+            frame += (held.len() - begin) / channel_count;
+            // the end of the pass is written once the decoder is parked, so
+            // that the next item finds it if it gets that far first
+            if held.len() > hold && until.is_none_or(|until| frame < until) {
+                let ready: Vec<f64> = held.drain(..held.len() - hold).collect();
+                self.write(&ready)?;
+            }
+        }
+        self.decoders.park(decoder);
+        if held.len() > hold {
+            let ready: Vec<f64> = held.drain(..held.len() - hold).collect();
+            self.write(&ready)?;
+        }
+        Ok(frame)
+    }
+
+    // finish the item's audio with `held`, its last
+    fn end(&self, mut held: Vec<f64>, ending: Ending) -> io::Result<()> {
+        let format = self.source.audio_format();
+        let channel_count = format.channel_layout.count() as usize;
+        let frames = |time: Duration| self.frames(time);
+        match ending {
+            Ending::Cut => self.write(&held),
+            Ending::Silence(length) => {
+                self.write(&held)?;
+                self.write(&vec![0.0; frames(length) * channel_count])
+            },
+            Ending::Crossfade { origin, region, length } => {
+                let length = frames(length);
+                if held.len() > length * channel_count {
+                    let ready: Vec<f64> = held.drain(..held.len() - length * channel_count).collect();
+                    self.write(&ready)?;
+                }
+                // if this item is shorter than the crossfade, or rendering
+                // started part way through it, the overlap starts late
+                let overlap = held.len() / channel_count;
+                let offset = length - overlap;
+                // the next item may itself be shorter than the overlap
+                let start = frames(region.start);
+                let available = region.end.map_or(overlap, |end| frames(end).saturating_sub(start + offset));
+                let mut head = decode_head(&self.decoders, &origin, (start + offset) as u64,
+                                           overlap.min(available), overlap, working_format(format));
+                if let Some(fade) = region.fade_in {
+                    fade_in_frames(&mut head, channel_count, offset, fade.shape, frames(fade.length));
+                }
+                for (frame, (samples, next)) in held.chunks_mut(channel_count)
+                    .zip(head.chunks(channel_count))
+                    .enumerate() {
+                    // equal power, so the level holds up through the middle
+                    let angle = (offset + frame) as f64 / length as f64 * FRAC_PI_2;
+                    for (sample, next) in samples.iter_mut().zip(next) {
+                        *sample = *sample * angle.cos() + next * angle.sin();
+                    }
+                }
+                self.write(&held)
+            },
+        }
+    }
+
+    fn write(&self, samples: &[f64]) -> io::Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let mut buffer = OwnedAudioBuffer::new(self.source.audio_format());
+        buffer.extend_from_f64(samples);
+        self.source.write_bytes(buffer.as_slice_raw())
+    }
+}
+
+// interleaved doubles, which the rendering works in
+fn working_format(format: AudioFormat) -> AudioFormat {
+    AudioFormat { sample_fmt: SampleFormat { sample_type: SampleType::Dbl, planar: false }, ..format }
+}
+
+// `frame_count` frames of the next item's file from `offset`, converted to
+// this item's format, padded with silence to `length` frames
+fn decode_head(decoders: &Decoders, origin: &Origin, offset: u64, frame_count: usize, length: usize,
+               format: AudioFormat) -> Vec<f64> {
+    let channel_count = format.channel_layout.count() as usize;
+    let mut head = Vec::new();
+    if let Ok(mut decoder) = decoders.take(origin, offset, format) {
+        while head.len() < frame_count * channel_count {
+            match decoder.read() {
+                Some(mut samples) => head.append(&mut samples),
+                None => break,
+            }
+        }
+        if head.len() > frame_count * channel_count {
+            decoder.unread(head.split_off(frame_count * channel_count));
+        }
+        decoders.park(decoder);
+    }
+    head.resize(length * channel_count, 0.0);
+    head
+}
+
+/// decoders which items have finished with, kept where they stopped, so
+/// that an item which carries on from there in the same file, like the
+/// next track of a cue sheet or the next chapter, decodes on with one
+/// rather than opening the file again and seeking
+#[derive(Default)]
+pub(crate) struct Decoders {
+    parked: Mutex<Vec<Decoder>>,
+}
+
+// enough for the items being rendered to hand theirs on
+const PARKED: usize = 2;
+
+impl Decoders {
+    // a decoder of `origin` in `format` from `frame`, counted in its sample
+    // rate: one parked there, or one opened anew
+    fn take(&self, origin: &Origin, frame: u64, format: AudioFormat) -> io::Result<Decoder> {
+        let parked = {
+            let mut parked = self.parked.lock().unwrap();
+            parked.iter()
+                .position(|decoder| decoder.origin == *origin && decoder.frame == frame)
+                .map(|index| parked.remove(index))
+        };
+        match parked {
+            Some(decoder) => Ok(decoder),
+            None => Decoder::open(origin, frame, format),
+        }
+    }
+
+    // keep `decoder` for the next item, in place of the one kept longest.
+    // one at the end of its file is no use to anyone.
+    fn park(&self, decoder: Decoder) {
+        if decoder.ended {
+            return;
+        }
+        // dropped once the lock is let go, as closing calls into libgroove
+        let _evicted = {
+            let mut parked = self.parked.lock().unwrap();
+            parked.push(decoder);
+            if parked.len() > PARKED {
+                Some(parked.remove(0))
+            } else {
+                None
+            }
+        };
+    }
+}
+
+// a RangeDecoder with the Groove it borrows, so that it can outlive the
+// worker which opened it
+struct Decoder {
+    // declared first, so it is dropped before the Groove
+    decoder: RangeDecoder<'static>,
+    _groove: Box<Groove>,
+    origin: Origin,
+    channel_count: usize,
+    decoded: OwnedAudioBuffer,
+    // samples read and given back, which are read again first
+    unread: Vec<f64>,
+    // the frame the next samples read start at
+    frame: u64,
+    ended: bool,
+}
+
+// libgroove's objects may be used from any thread, and a decoder is only
+// ever in the hands of one worker, or of the parked ones
+unsafe impl Send for Decoder {}
+
+impl Decoder {
+    fn open(origin: &Origin, frame: u64, format: AudioFormat) -> io::Result<Decoder> {
+        let groove = Box::new(Groove::new().map_err(|err| io::Error::other(err.to_string()))?);
+        // boxed, so it stays put for as long as the decoder, which goes first
+        let borrowed: &'static Groove = unsafe { &*(&*groove as *const Groove) };
+        let decoder = match RangeDecoder::new(borrowed, origin, frame, format) {
+            Some(decoder) => decoder,
+            None => {
+                let message = format!("could not decode {} again", origin.path.display());
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            },
+        };
+        Ok(Decoder {
+            decoder,
+            _groove: groove,
+            origin: origin.clone(),
+            channel_count: format.channel_layout.count() as usize,
+            decoded: OwnedAudioBuffer::new(format),
+            unread: Vec::new(),
+            frame,
+            ended: false,
+        })
+    }
+
+    // the next run of interleaved samples, or None at the end of the file
+    fn read(&mut self) -> Option<Vec<f64>> {
+        let samples = if self.unread.is_empty() {
+            self.decoded.truncate(0);
+            if self.ended || !self.decoder.read(&mut self.decoded) {
+                self.ended = true;
+                return None;
+            }
+            self.decoded.as_slice_f64().to_vec()
+        } else {
+            mem::take(&mut self.unread)
+        };
+        self.frame += (samples.len() / self.channel_count) as u64;
+        Some(samples)
+    }
+
+    // give back the end of what was read, to be read again
+    fn unread(&mut self, mut samples: Vec<f64>) {
+        if samples.is_empty() {
+            return;
+        }
+        self.frame -= (samples.len() / self.channel_count) as u64;
+        samples.append(&mut self.unread);
+        self.unread = samples;
+    }
+}
+
+// the level of a fade `progress` of the way in, from 0 to 1
+fn envelope(shape: FadeShape, progress: f64) -> f64 {
+    match shape {
+        FadeShape::Linear => progress,
+        // as libav's afade has it
+        FadeShape::Logarithmic => (1.0 + 0.2 * progress.log10()).clamp(0.0, 1.0),
+        FadeShape::SCurve => (1.0 - (progress * PI).cos()) / 2.0,
+    }
+}
+
+// fade in frames which start `offset` frames into a fade `length` long
+fn fade_in_frames(samples: &mut [f64], channel_count: usize, offset: usize, shape: FadeShape, length: usize) {
+    for (frame, samples) in samples.chunks_mut(channel_count).enumerate() {
+        let frame = offset + frame;
+        if frame >= length {
+            break;
+        }
+        let level = envelope(shape, frame as f64 / length as f64);
+        samples.iter_mut().for_each(|sample| *sample *= level);
+    }
+}
+
+// fade out the last `length` frames, reaching silence on the last one
+fn fade_out_frames(samples: &mut [f64], channel_count: usize, shape: FadeShape, length: usize) {
+    let frame_count = samples.len() / channel_count;
+    for (frame, samples) in samples.chunks_mut(channel_count).enumerate() {
+        let after = frame_count - 1 - frame;
+        if after < length {
+            let level = envelope(shape, after as f64 / length as f64);
+            samples.iter_mut().for_each(|sample| *sample *= level);
+        }
+    }
+}
@@ -1,23 +1,146 @@
 use c_api::{
+    GroovePlaylistItem,
     GrooveSink,
     groove_sink_attach,
     groove_sink_detach,
-    groove_sink_create,
+    sink_create,
+    sink_set_audio_format,
+    sink_set_disable_resample,
     groove_sink_destroy,
 };
 
-use audio_format::AudioFormat;
-use buffer::DecodedBuffer;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+use audio_format::{AudioFormat, ChannelLayout};
+use buffer::{DecodedBuffer, OwnedAudioBuffer};
+use context::Groove;
+use events::Events;
+use filter::{ChainState, FilterChain};
 use playlist::Playlist;
 
 /// use this to get access to a realtime raw audio buffer
 /// for example you could use it to draw a waveform or other visualization
 /// GroovePlayer uses this internally to get the audio buffer for playback
-pub struct Sink {
+pub struct Sink<'g> {
     pub(crate) groove_sink: *mut GrooveSink,
+    filters: RefCell<Filtering>,
+    /// the sink's callbacks point here, so it is boxed to stay put
+    pub(crate) events: Box<Events>,
+    _groove: PhantomData<&'g Groove>,
+}
+
+#[derive(Default)]
+struct Filtering {
+    chain: FilterChain,
+    /// set up from the first buffer, so it matches the sink's output
+    /// even with resampling disabled
+    running: Option<Running>,
+    /// filtered audio to hand out before asking libgroove for more
+    ready: VecDeque<DecodedBuffer>,
+    /// the end of the playlist is due once the held back audio is out
+    ended: bool,
+}
+
+impl Filtering {
+    // everything held back is from before a seek
+    fn flushed(&mut self) {
+        self.running = None;
+        self.ready.clear();
+        self.ended = false;
+    }
+}
+
+struct Running {
+    /// what libgroove hands out, and what the filters make of it
+    input: AudioFormat,
+    output: AudioFormat,
+    state: ChainState,
+    /// frames gone into the filters
+    frames_in: u64,
+    /// the items whose audio is in the filters, oldest first, with how
+    /// much output each is still owed. the last takes anything over.
+    owed: VecDeque<Owed>,
+}
+
+struct Owed {
+    item: *mut GroovePlaylistItem,
+    /// of the last buffer to go in from the item
+    pos: f64,
+    frames: u64,
+}
+
+impl Running {
+    fn new(chain: &FilterChain, input: AudioFormat) -> Running {
+        let state = ChainState::new(chain, input.sample_rate, input.channel_layout.count() as usize);
+        let channel_layout = if state.channel_count() == input.channel_layout.count() as usize {
+            input.channel_layout
+        } else {
+            ChannelLayout::default(state.channel_count() as i32)
+        };
+        Running {
+            input,
+            output: AudioFormat { channel_layout, ..input },
+            state,
+            frames_in: 0,
+            owed: VecDeque::new(),
+        }
+    }
+
+    // filter `buffer`, giving whatever comes out
+    fn process(&mut self, buffer: &DecodedBuffer) -> Vec<DecodedBuffer> {
+        let item = buffer.groove_playlist_item();
+        let stretch = self.state.stretch();
+        let out = |frames: u64| (frames as f64 * stretch).round() as u64;
+        let before = out(self.frames_in);
+        self.frames_in += buffer.frame_count() as u64;
+        let frames = out(self.frames_in) - before;
+        match self.owed.back_mut() {
+            Some(owed) if owed.item == item => {
+                owed.pos = buffer.pos();
+                owed.frames += frames;
+            },
+            _ => self.owed.push_back(Owed { item, pos: buffer.pos(), frames }),
+        }
+        let samples = self.state.process(buffer.to_f64());
+        self.buffers(&samples)
+    }
+
+    // split filtered samples between the items they came from
+    fn buffers(&mut self, mut samples: &[f64]) -> Vec<DecodedBuffer> {
+        let channel_count = self.state.channel_count();
+        let mut buffers = Vec::new();
+        loop {
+            let last = self.owed.len() == 1;
+            let (item, pos, frames) = match self.owed.front_mut() {
+                Some(owed) => {
+                    let mut frames = samples.len() / channel_count;
+                    if !last {
+                        frames = frames.min(owed.frames as usize);
+                    }
+                    owed.frames = owed.frames.saturating_sub(frames as u64);
+                    (owed.item, owed.pos, frames)
+                },
+                None => break,
+            };
+            let (head, tail) = samples.split_at(frames * channel_count);
+            if !head.is_empty() {
+                let mut buffer = OwnedAudioBuffer::new(self.output);
+                buffer.extend_from_f64(head);
+                buffers.push(DecodedBuffer::filtered(buffer, item, pos));
+            }
+            samples = tail;
+            if last || self.owed[0].frames > 0 {
+                break;
+            }
+            self.owed.pop_front();
+        }
+        buffers
+    }
 }
 
-impl Drop for Sink {
+impl<'g> Drop for Sink<'g> {
     fn drop(&mut self) {
         unsafe {
             if !(*self.groove_sink).playlist.is_null() {
@@ -28,18 +151,24 @@ impl Drop for Sink {
     }
 }
 
-impl Sink {
-    pub fn new() -> Self {
-        super::init();
+impl<'g> Sink<'g> {
+    pub fn new(_groove: &'g Groove) -> Self {
         unsafe {
-            Sink { groove_sink: groove_sink_create() }
+            let sink = Sink {
+                groove_sink: sink_create(),
+                filters: RefCell::new(Filtering::default()),
+                events: Box::default(),
+                _groove: PhantomData,
+            };
+            sink.events.hook(sink.groove_sink);
+            sink
         }
     }
 
     /// set this to the audio format you want the sink to output
     pub fn set_audio_format(&self, format: AudioFormat) {
         unsafe {
-            (*self.groove_sink).audio_format = format.to_groove();
+            sink_set_audio_format(self.groove_sink, format.to_groove());
         }
     }
 
@@ -63,15 +192,70 @@ impl Sink {
     /// returns None on end of playlist, Some<DecodedBuffer> when there is a buffer
     /// blocks the thread until a buffer or end is found
     pub fn buffer_get_blocking(&self) -> Option<DecodedBuffer> {
-        DecodedBuffer::from_sink(self).expect("buffer aborted or not ready")
+        let buffer = self.next_buffer();
+        let playlist = unsafe { (*self.groove_sink).playlist };
+        self.events.observe(playlist, buffer.as_ref());
+        buffer
+    }
+
+    /// filter this sink's output with `chain`, replacing any filters set
+    /// before. every buffer from `buffer_get_blocking` is filtered from
+    /// then on, while other sinks and encoders on the same playlist are not
+    /// affected. filters which change the tempo may hold audio back; it
+    /// still comes out as its own item's, the last of it before the end of
+    /// the playlist, and a seek drops it. a pan to another channel count changes the buffers' channel layout
+    /// from the one set with `set_audio_format`.
+    pub fn set_filters(&self, chain: FilterChain) {
+        *self.filters.borrow_mut() = Filtering { chain, ..Filtering::default() };
+    }
 
+    // the next buffer from libgroove, through the filters if there are any
+    fn next_buffer(&self) -> Option<DecodedBuffer> {
+        let mut filtering = self.filters.borrow_mut();
+        loop {
+            if self.events.take_flush() {
+                filtering.flushed();
+            }
+            if let Some(buffer) = filtering.ready.pop_front() {
+                return Some(buffer);
+            }
+            if filtering.ended {
+                filtering.ended = false;
+                return None;
+            }
+            let buffer = DecodedBuffer::from_sink(self).expect("buffer aborted or not ready");
+            // a seek while waiting, so this buffer is from after it
+            if self.events.take_flush() {
+                filtering.flushed();
+            }
+            if filtering.chain.is_empty() {
+                return buffer;
+            }
+            let buffer = match buffer {
+                Some(buffer) => buffer,
+                None => {
+                    // anything added to the playlist later starts afresh
+                    let mut running = filtering.running.take()?;
+                    let samples = running.state.flush();
+                    filtering.ready.extend(running.buffers(&samples));
+                    filtering.ended = true;
+                    continue;
+                },
+            };
+            let format = buffer.audio_format();
+            if filtering.running.as_ref().is_none_or(|running| !running.input.same_layout(&format)) {
+                filtering.running = Some(Running::new(&filtering.chain, format));
+            }
+            let buffers = filtering.running.as_mut().unwrap().process(&buffer);
+            filtering.ready.extend(buffers);
+        }
     }
 
     /// Set this flag to ignore audio_format. If you set this flag, the
     /// buffers you pull from this sink could have any audio format.
     pub fn disable_resample(&self, disabled: bool) {
         unsafe {
-            (*self.groove_sink).disable_resample = if disabled { 1 } else { 0 }
+            sink_set_disable_resample(self.groove_sink, disabled)
         }
     }
 }
@@ -0,0 +1,272 @@
+extern crate libc;
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use audio_format::AudioFormat;
+use buffer::EncodedBuffer;
+use chapter::Chapter;
+use context::Groove;
+use encoder::{Encoder, MetadataMode};
+use file::File;
+use playlist::Playlist;
+
+// how often a cancellable transcode which is waiting for the encoder looks
+// at the cancel flag
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+// tells apart the temporary files of saves running at the same time
+static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+
+/// settings for `transcode`. `Default` gives you libgroove's encoder
+/// defaults and copies metadata from the first input.
+#[derive(Default)]
+pub struct TranscodeOptions<'a> {
+    /// see `Encoder::set_format_short_name`
+    pub format_short_name: Option<&'a str>,
+    /// see `Encoder::set_codec_short_name`
+    pub codec_short_name: Option<&'a str>,
+    /// see `Encoder::set_mime_type`
+    pub mime_type: Option<&'a str>,
+    /// see `Encoder::set_bit_rate`
+    pub bit_rate: Option<i32>,
+    /// see `Encoder::set_target_audio_format`. when transcoding a single
+    /// input this defaults to the audio format of that input.
+    pub target_audio_format: Option<AudioFormat>,
+    /// which tags end up in the output. with `PerItem`, each input is
+    /// encoded as a stream of its own, chained together in the output. to
+    /// give each input an output file of its own instead, call `transcode`
+    /// once per input.
+    pub metadata: MetadataMode,
+    /// chapters to write into the output once it is encoded, such as one
+    /// for each input when joining the parts of an audiobook. see
+    /// `File::set_chapters` for the formats which can carry them, such as
+    /// M4B and MKA.
+    pub chapters: Option<&'a [Chapter]>,
+    /// called every time a buffer of encoded audio is written
+    pub progress: Option<&'a mut dyn FnMut(&Progress)>,
+    /// set this to true from any thread to stop transcoding. the output
+    /// file is left untouched.
+    pub cancel: Option<&'a AtomicBool>,
+}
+
+/// how far along a `transcode` is
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    /// index into the inputs of the file currently being encoded
+    pub input_index: usize,
+    /// seconds of audio encoded so far, across all inputs
+    pub position: f64,
+    /// sum of `File::duration` of all inputs. like that function, this can
+    /// be inaccurate.
+    pub duration: f64,
+}
+
+impl Progress {
+    /// position / duration, clamped to 0.0 - 1.0
+    pub fn fraction(&self) -> f64 {
+        if self.duration > 0.0 {
+            (self.position / self.duration).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TranscodeError {
+    /// no input files were given
+    NoInputs,
+    /// libgroove could not open this input
+    Open(PathBuf),
+    /// setting encoder metadata failed with this libgroove error code
+    Metadata(i32),
+    /// attaching the encoder failed with this libgroove error code, usually
+    /// because no format or codec matched the hints
+    Attach(i32),
+    /// writing chapters into the output failed with this error code, which
+    /// is `-ENOSYS` if its format can't carry them
+    Chapters(i32),
+    /// writing the output failed
+    Io(io::Error),
+    /// the cancel flag was set
+    Cancelled,
+}
+
+impl fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TranscodeError::NoInputs => write!(f, "no input files"),
+            TranscodeError::Open(ref path) => write!(f, "unable to open {}", path.display()),
+            TranscodeError::Metadata(code) => write!(f, "unable to set metadata (error {})", code),
+            TranscodeError::Attach(code) => write!(f, "unable to attach encoder (error {})", code),
+            TranscodeError::Chapters(code) => write!(f, "unable to write chapters (error {})", code),
+            TranscodeError::Io(ref err) => write!(f, "unable to write output: {}", err),
+            TranscodeError::Cancelled => write!(f, "transcode cancelled"),
+        }
+    }
+}
+
+impl error::Error for TranscodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            TranscodeError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TranscodeError {
+    fn from(err: io::Error) -> Self {
+        TranscodeError::Io(err)
+    }
+}
+
+/// encode one or more files, back to back, into a single output file.
+/// the output is written to a temporary file next to `output` and renamed
+/// into place once encoding finishes, so `output` is never left half
+/// written - not on error, and not when cancelled.
+pub fn transcode(groove: &Groove, inputs: &[&Path], output: &Path,
+                 mut options: TranscodeOptions) -> Result<(), TranscodeError> {
+    if inputs.is_empty() {
+        return Err(TranscodeError::NoInputs);
+    }
+
+    let mut files = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        match File::open(groove, input) {
+            Some(file) => files.push(file),
+            None => return Err(TranscodeError::Open(input.to_path_buf())),
+        }
+    }
+    let durations: Vec<f64> = files.iter().map(|file| file.duration()).collect();
+
+    // declared before the encoder so the encoder is dropped, and detached,
+    // first
+    let mut playlist = Playlist::new(groove);
+    let mut encoder = Encoder::new(groove);
+    if let Some(format) = options.format_short_name {
+        encoder.set_format_short_name(format);
+    }
+    if let Some(codec) = options.codec_short_name {
+        encoder.set_codec_short_name(codec);
+    }
+    if let Some(mime_type) = options.mime_type {
+        encoder.set_mime_type(mime_type);
+    }
+    if let Some(bit_rate) = options.bit_rate {
+        encoder.set_bit_rate(bit_rate);
+    }
+    // the temporary file's name is useless as a hint, so give the real one
+    if let Some(hint) = output.to_str() {
+        encoder.set_filename(hint);
+    }
+    match options.target_audio_format {
+        Some(format) => encoder.set_target_audio_format(format),
+        None if files.len() == 1 => encoder.set_target_audio_format(files[0].audio_format()),
+        None => {},
+    }
+    encoder.set_metadata_mode(options.metadata);
+
+    // each segment ends with a format trailer. in PerItem mode every file
+    // is its own segment so that it gets its own header.
+    let segments = if options.metadata == MetadataMode::PerItem {
+        files.into_iter().map(|file| vec![file]).collect()
+    } else {
+        vec![files]
+    };
+
+    let temp_path = temp_path_for(output);
+    let result = encode_to(segments, &mut playlist, &encoder, &durations, &temp_path, &mut options)
+        .and_then(|()| match options.chapters {
+            Some(chapters) => write_chapters(groove, &temp_path, chapters),
+            None => Ok(()),
+        })
+        .and_then(|()| fs::rename(&temp_path, output).map_err(TranscodeError::Io));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn encode_to<'g>(segments: Vec<Vec<File<'g>>>, playlist: &mut Playlist<'g>, encoder: &Encoder,
+             durations: &[f64], path: &Path, options: &mut TranscodeOptions) -> Result<(), TranscodeError> {
+    let mut out = fs::File::create(path)?;
+    encoder.attach(playlist).map_err(TranscodeError::Attach)?;
+
+    let mut progress = Progress {
+        input_index: 0,
+        position: 0.0,
+        duration: durations.iter().sum(),
+    };
+
+    let mut segment_start = 0;
+    for segment in segments {
+        let segment_len = segment.len();
+        encoder.tag_stream(&segment[0]).map_err(TranscodeError::Metadata)?;
+        for file in segment {
+            playlist.append(file, 1.0, 1.0);
+        }
+
+        while let Some(buffer) = next_buffer(encoder, options.cancel)? {
+            out.write_all(buffer.as_vec())?;
+
+            // format headers and trailers don't belong to any item
+            if let Some(index) = playlist.index_of_groove_item(buffer.groove_playlist_item()) {
+                let index = segment_start + index;
+                progress.input_index = index;
+                progress.position = durations[..index].iter().sum::<f64>() + buffer.pos();
+                if let Some(ref mut callback) = options.progress {
+                    callback(&progress);
+                }
+            }
+        }
+
+        playlist.clear();
+        segment_start += segment_len;
+    }
+
+    out.sync_all()?;
+    Ok(())
+}
+
+// rewrite the encoded output at `path` to hold `chapters`
+fn write_chapters(groove: &Groove, path: &Path, chapters: &[Chapter]) -> Result<(), TranscodeError> {
+    let file = File::open(groove, path).ok_or(TranscodeError::Chapters(-libc::EIO))?;
+    file.set_chapters(chapters.to_vec()).map_err(TranscodeError::Chapters)?;
+    file.save().map_err(TranscodeError::Chapters)
+}
+
+// the encoder's next buffer, giving up as soon as `cancel` is set rather
+// than when a buffer next arrives
+fn next_buffer(encoder: &Encoder, cancel: Option<&AtomicBool>) -> Result<Option<EncodedBuffer>, TranscodeError> {
+    let cancel = match cancel {
+        Some(cancel) => cancel,
+        None => return Ok(encoder.buffer_get_blocking()),
+    };
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            return Err(TranscodeError::Cancelled);
+        }
+        match EncodedBuffer::from_encoder(encoder, false) {
+            Ok(buffer) => return Ok(buffer),
+            Err(()) => thread::sleep(CANCEL_POLL),
+        }
+    }
+}
+
+/// a path next to `output` to write to before renaming over it, which no
+/// other thread or process is using
+pub(crate) fn temp_path_for(output: &Path) -> PathBuf {
+    let file_name = output.file_name().map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let index = NEXT_TEMP.fetch_add(1, Ordering::SeqCst);
+    output.with_file_name(format!(".{}.{}.{}.part", file_name, process::id(), index))
+}
@@ -0,0 +1,237 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use audio_format::{AudioFormat, ChannelLayout, SampleType};
+use buffer::{DecodedBuffer, OwnedAudioBuffer};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+// the tail shared by the subformat GUIDs of KSDATAFORMAT_SUBTYPE_PCM and
+// KSDATAFORMAT_SUBTYPE_IEEE_FLOAT
+const SUBFORMAT_GUID_TAIL: [u8; 12] = [
+    0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+// size of the ds64 chunk body without a table, reserved up front as a JUNK
+// chunk in case the file grows past 4 GiB
+const DS64_SIZE: u32 = 28;
+
+/// writes a WAV file from decoded audio without going through libgroove's
+/// encoder. call `finish` when done to fill in the sizes in the header;
+/// if you drop the writer instead, this is attempted but errors are
+/// ignored.
+///
+/// buffers in any sample format can be written; planar audio is
+/// interleaved on the way out. files which outgrow the 4 GiB limit of
+/// RIFF are written as RF64.
+pub struct WavWriter<W: Write + Seek> {
+    inner: Option<W>,
+    format: AudioFormat,
+    /// position of the start of the header within `inner`
+    start: u64,
+    header_len: u64,
+    data_len: u64,
+    frame_count: u64,
+    scratch: Vec<u8>,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// write a header for audio in `format` to `inner`, starting at its
+    /// current position
+    pub fn new(mut inner: W, format: AudioFormat) -> io::Result<Self> {
+        let start = inner.stream_position()?;
+        let header = header(format, 0, 0, true);
+        inner.write_all(&header)?;
+        Ok(WavWriter {
+            inner: Some(inner),
+            format,
+            start,
+            header_len: header.len() as u64,
+            data_len: 0,
+            frame_count: 0,
+            scratch: Vec::new(),
+        })
+    }
+
+    pub fn audio_format(&self) -> AudioFormat {
+        self.format
+    }
+
+    /// number of frames written so far
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// append the samples of a buffer pulled from a Sink.
+    /// fails with `InvalidInput` if the buffer is not in the writer's
+    /// audio format.
+    pub fn write_decoded(&mut self, buffer: &DecodedBuffer) -> io::Result<()> {
+        self.check_format(&buffer.audio_format())?;
+        let planes: Vec<&[u8]> = (0..self.format.plane_count())
+            .map(|index| buffer.plane_as_slice_raw(index))
+            .collect();
+        self.write_planes(&planes, buffer.frame_count())
+    }
+
+    /// append the samples of an owned buffer.
+    /// fails with `InvalidInput` if the buffer is not in the writer's
+    /// audio format.
+    pub fn write_owned(&mut self, buffer: &OwnedAudioBuffer) -> io::Result<()> {
+        self.check_format(&buffer.audio_format())?;
+        let planes: Vec<&[u8]> = (0..self.format.plane_count())
+            .map(|index| buffer.plane_as_slice_raw(index))
+            .collect();
+        self.write_planes(&planes, buffer.frame_count())
+    }
+
+    /// fill in the header and return the underlying writer, positioned at
+    /// the end of the WAV data
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_sizes()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn check_format(&self, format: &AudioFormat) -> io::Result<()> {
+        if format.same_layout(&self.format) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "buffer audio format does not match"))
+        }
+    }
+
+    fn write_planes(&mut self, planes: &[&[u8]], frame_count: usize) -> io::Result<()> {
+        let sample_size = self.format.sample_fmt.bytes_per_sample() as usize;
+        let channel_count = self.format.channel_layout.count() as usize;
+        let len = frame_count * channel_count * sample_size;
+
+        self.scratch.clear();
+        if planes.len() == 1 {
+            self.scratch.extend_from_slice(&planes[0][..len]);
+        } else {
+            self.scratch.reserve(len);
+            for frame in 0..frame_count {
+                let offset = frame * sample_size;
+                for plane in planes {
+                    self.scratch.extend_from_slice(&plane[offset..offset + sample_size]);
+                }
+            }
+        }
+        // WAV is little endian; samples in memory are native endian
+        if cfg!(target_endian = "big") && sample_size > 1 {
+            for sample in self.scratch.chunks_mut(sample_size) {
+                sample.reverse();
+            }
+        }
+
+        self.inner.as_mut().unwrap().write_all(&self.scratch)?;
+        self.data_len += len as u64;
+        self.frame_count += frame_count as u64;
+        Ok(())
+    }
+
+    fn write_sizes(&mut self) -> io::Result<()> {
+        let inner = self.inner.as_mut().unwrap();
+        // chunks must be an even number of bytes long
+        if self.data_len % 2 == 1 {
+            inner.write_all(&[0])?;
+        }
+        let end = inner.stream_position()?;
+        let riff_len = end - self.start - 8;
+
+        inner.seek(SeekFrom::Start(self.start))?;
+        if riff_len <= u64::from(u32::MAX) {
+            let header = header(self.format, riff_len as u32, self.data_len as u32, true);
+            inner.write_all(&header)?;
+        } else {
+            let mut header = header(self.format, u32::MAX, u32::MAX, true);
+            header[0..4].copy_from_slice(b"RF64");
+            let mut ds64 = Vec::with_capacity(8 + DS64_SIZE as usize);
+            ds64.extend_from_slice(b"ds64");
+            ds64.extend_from_slice(&DS64_SIZE.to_le_bytes());
+            ds64.extend_from_slice(&riff_len.to_le_bytes());
+            ds64.extend_from_slice(&self.data_len.to_le_bytes());
+            ds64.extend_from_slice(&self.frame_count.to_le_bytes());
+            ds64.extend_from_slice(&0u32.to_le_bytes());
+            header[12..12 + ds64.len()].copy_from_slice(&ds64);
+            inner.write_all(&header)?;
+        }
+        debug_assert_eq!(inner.stream_position()?, self.start + self.header_len);
+
+        inner.seek(SeekFrom::Start(end))?;
+        inner.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_sizes();
+        }
+    }
+}
+
+/// header of a WAV stream whose length is not known up front, for readers
+/// which can cope with that
+pub(crate) fn streaming_header(format: AudioFormat) -> Vec<u8> {
+    header(format, u32::MAX, u32::MAX, false)
+}
+
+/// everything up to and including the data chunk header. `junk` reserves
+/// room to turn the file into RF64 later.
+fn header(format: AudioFormat, riff_len: u32, data_len: u32, junk: bool) -> Vec<u8> {
+    let channel_count = format.channel_layout.count() as u16;
+    let bytes_per_sample = format.sample_fmt.bytes_per_sample() as u16;
+    let bits_per_sample = bytes_per_sample * 8;
+    let block_align = channel_count * bytes_per_sample;
+    let format_tag = match format.sample_fmt.sample_type {
+        SampleType::Flt | SampleType::Dbl => WAVE_FORMAT_IEEE_FLOAT,
+        _ => WAVE_FORMAT_PCM,
+    };
+    // the plain header can't describe a channel mask or more than 16 bit
+    // integer samples
+    let extensible = channel_count > 2 ||
+        (format_tag == WAVE_FORMAT_PCM && bits_per_sample > 16) ||
+        format.channel_layout.mask() != ChannelLayout::default(channel_count as i32).mask();
+
+    let mut header = Vec::with_capacity(80);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&riff_len.to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+
+    if junk {
+        header.extend_from_slice(b"JUNK");
+        header.extend_from_slice(&DS64_SIZE.to_le_bytes());
+        header.extend_from_slice(&[0; DS64_SIZE as usize]);
+    }
+
+    header.extend_from_slice(b"fmt ");
+    let fmt_len: u32 = if extensible {
+        40
+    } else if format_tag == WAVE_FORMAT_PCM {
+        16
+    } else {
+        18
+    };
+    header.extend_from_slice(&fmt_len.to_le_bytes());
+    let tag = if extensible { WAVE_FORMAT_EXTENSIBLE } else { format_tag };
+    header.extend_from_slice(&tag.to_le_bytes());
+    header.extend_from_slice(&channel_count.to_le_bytes());
+    header.extend_from_slice(&(format.sample_rate as u32).to_le_bytes());
+    header.extend_from_slice(&(format.sample_rate as u32 * u32::from(block_align)).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&bits_per_sample.to_le_bytes());
+    if extensible {
+        header.extend_from_slice(&22u16.to_le_bytes());
+        header.extend_from_slice(&bits_per_sample.to_le_bytes());
+        header.extend_from_slice(&(format.channel_layout.mask() as u32).to_le_bytes());
+        header.extend_from_slice(&u32::from(format_tag).to_le_bytes());
+        header.extend_from_slice(&SUBFORMAT_GUID_TAIL);
+    } else if fmt_len == 18 {
+        header.extend_from_slice(&0u16.to_le_bytes());
+    }
+
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
@@ -0,0 +1,240 @@
+extern crate groove;
+
+mod common;
+
+use std::fs;
+use std::time::Duration;
+
+use common::{Container, Encoding, Track};
+use groove::{Chapter, File, ItemId, Playlist, TranscodeError, TranscodeOptions};
+
+const AUDIO: &[u8] = b"\xff\xfb\x90\x64 not really MPEG audio, but it must come out the same";
+
+fn chapter(start: u64, end: u64, title: &str) -> Chapter {
+    Chapter { start: Duration::from_millis(start), end: Duration::from_millis(end), title: title.to_string() }
+}
+
+// an ID3v2.3 frame
+fn frame(id: &str, body: &[u8]) -> Vec<u8> {
+    let mut frame = id.as_bytes().to_vec();
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(body);
+    frame
+}
+
+// a CHAP frame running from `start` to `end` milliseconds, titled by `title`
+// as the body of a TIT2 frame
+fn chap(id: &str, start: u32, end: u32, title: Option<&[u8]>) -> Vec<u8> {
+    let mut body = id.as_bytes().to_vec();
+    body.push(0);
+    for value in &[start, end, u32::MAX, u32::MAX] {
+        body.extend_from_slice(&value.to_be_bytes());
+    }
+    if let Some(title) = title {
+        body.extend(frame("TIT2", title));
+    }
+    frame("CHAP", &body)
+}
+
+// an MP3 which the mock plays for a second, with `frames` in an ID3v2.3 tag
+// at its start
+fn mp3(frames: &[Vec<u8>]) -> Container {
+    let body: Vec<u8> = frames.concat();
+    let size = body.len() as u32;
+    let mut bytes = b"ID3\x03\x00\x00".to_vec();
+    bytes.extend_from_slice(&[(size >> 21) as u8 & 0x7f, (size >> 14) as u8 & 0x7f, (size >> 7) as u8 & 0x7f,
+                              size as u8 & 0x7f]);
+    bytes.extend(body);
+    bytes.extend_from_slice(AUDIO);
+    Container::new("fixture.mp3", &bytes)
+}
+
+// a version 0 `chpl` box, in a `udta` box, of chapters starting at these
+// milliseconds
+fn nero(chapters: &[(u64, &str)]) -> Vec<u8> {
+    let mut chpl = vec![0, 0, 0, 0, chapters.len() as u8];
+    for &(start, title) in chapters {
+        chpl.extend_from_slice(&(start * 10_000).to_be_bytes());
+        chpl.push(title.len() as u8);
+        chpl.extend_from_slice(title.as_bytes());
+    }
+    common::mp4_box(b"udta", &common::mp4_box(b"chpl", &chpl))
+}
+
+// three seconds of mono audio, with Nero chapters
+fn m4b() -> Container {
+    let udta = nero(&[(1000, "Middle"), (0, "Intro"), (2500, "")]);
+    common::mp4(&[Track::audio("mp4a", 44100, 1)], 3.0, &udta, AUDIO, false)
+}
+
+// a Matroska ChapterAtom, hidden if `hidden`
+fn atom(start: u64, end: Option<u64>, title: Option<&str>, hidden: bool) -> Vec<u8> {
+    let mut atom = common::ebml(0x73C4, &[1]);
+    atom.extend(common::ebml(0x91, &(start * 1_000_000).to_be_bytes()));
+    if let Some(end) = end {
+        atom.extend(common::ebml(0x92, &(end * 1_000_000).to_be_bytes()));
+    }
+    if hidden {
+        atom.extend(common::ebml(0x98, &[1]));
+    }
+    if let Some(title) = title {
+        atom.extend(common::ebml(0x80, &common::ebml(0x85, title.as_bytes())));
+    }
+    common::ebml(0xB6, &atom)
+}
+
+// three seconds of Matroska audio, with `chapters` ahead of it
+fn mka(chapters: &[u8]) -> Container {
+    let mut children = common::matroska_tracks(&[Track::audio("A_AAC", 48000, 2)], 3.0);
+    children.extend_from_slice(chapters);
+    children.extend(common::ebml(0x1F43B675, &common::ebml(0xA3, AUDIO)));
+    common::matroska(&children)
+}
+
+#[test]
+fn id3_chapters_are_read_in_order() {
+    let groove = common::groove();
+    // UTF-16 with a byte order mark
+    let mut middle = vec![1, 0xff, 0xfe];
+    middle.extend("Middle".encode_utf16().flat_map(|unit| unit.to_le_bytes().to_vec()));
+    let fixture = mp3(&[
+        chap("ch1", 300, 750, Some(&middle)),
+        chap("ch0", 0, 300, Some(b"\x00Intro")),
+        chap("ch2", 750, 1000, None),
+    ]);
+
+    let file = File::open(&groove, &fixture.path).unwrap();
+    assert_eq!(file.chapters(), vec![chapter(0, 300, "Intro"), chapter(300, 750, "Middle"), chapter(750, 1000, "")]);
+
+    let plain = common::noise_wav(1, 4410);
+    assert!(File::open(&groove, &plain.path).unwrap().chapters().is_empty());
+}
+
+#[test]
+fn nero_chapters_run_up_to_the_next() {
+    let groove = common::groove();
+    let fixture = m4b();
+    let file = File::open(&groove, &fixture.path).unwrap();
+    assert_eq!(file.chapters(), vec![chapter(0, 1000, "Intro"), chapter(1000, 2500, "Middle"),
+                                     chapter(2500, 3000, "")]);
+
+    let plain = common::mp4(&[Track::audio("mp4a", 44100, 1)], 3.0, &[], AUDIO, false);
+    assert!(File::open(&groove, &plain.path).unwrap().chapters().is_empty());
+}
+
+#[test]
+fn matroska_chapters_come_from_the_default_edition() {
+    let groove = common::groove();
+    let first = common::ebml(0x45B9, &atom(2000, None, Some("Ignored"), false));
+    let mut default = common::ebml(0x45DB, &[1]);
+    default.extend(atom(1200, None, Some("Second"), false));
+    default.extend(atom(0, Some(1200), Some("First"), false));
+    default.extend(atom(500, Some(600), Some("Hidden"), true));
+    default.extend(atom(2000, Some(2500), None, false));
+    let mut chapters = first;
+    chapters.extend(common::ebml(0x45B9, &default));
+    let fixture = mka(&common::ebml(0x1043A770, &chapters));
+
+    let file = File::open(&groove, &fixture.path).unwrap();
+    assert_eq!(file.chapters(), vec![chapter(0, 1200, "First"), chapter(1200, 2000, "Second"),
+                                     chapter(2000, 2500, "")]);
+    assert!(File::open(&groove, &mka(&[]).path).unwrap().chapters().is_empty());
+}
+
+#[test]
+fn mp4_chapters_are_saved_without_breaking_chunk_offsets() {
+    let groove = common::groove();
+    for &moov_last in &[false, true] {
+        let fixture = common::mp4(&[Track::audio("mp4a", 44100, 1)], 3.0, &nero(&[(0, "Old")]), AUDIO, moov_last);
+        let file = File::open(&groove, &fixture.path).unwrap();
+        let chapters = vec![chapter(0, 1500, "Part One"), chapter(1500, 3000, "Part Two")];
+        file.set_chapters(chapters.clone()).unwrap();
+        assert!(file.is_dirty());
+        assert_eq!(file.chapters(), chapters);
+        file.save().unwrap();
+        assert!(!file.is_dirty());
+
+        let saved = File::open(&groove, &fixture.path).unwrap();
+        assert_eq!(saved.chapters(), chapters);
+        let bytes = fs::read(&fixture.path).unwrap();
+        let moov = common::find_box(&bytes, &[b"moov"]).unwrap();
+        let stco = common::find_box(moov, &[b"trak", b"mdia", b"minf", b"stbl", b"stco"]).unwrap();
+        let offset = u32::from_be_bytes([stco[8], stco[9], stco[10], stco[11]]) as usize;
+        assert_eq!(&bytes[offset..offset + AUDIO.len()], AUDIO);
+
+        saved.set_chapters(Vec::new()).unwrap();
+        saved.save().unwrap();
+        assert!(File::open(&groove, &fixture.path).unwrap().chapters().is_empty());
+    }
+}
+
+#[test]
+fn matroska_chapters_are_saved_with_their_ends() {
+    let groove = common::groove();
+    let fixture = mka(&common::ebml(0x1043A770, &common::ebml(0x45B9, &atom(0, None, Some("Old"), false))));
+    let file = File::open(&groove, &fixture.path).unwrap();
+    let chapters = vec![chapter(0, 1000, "One"), chapter(1000, 2000, ""), chapter(2500, 3000, "Three")];
+    file.set_chapters(chapters.clone()).unwrap();
+    file.save().unwrap();
+
+    // found through a new SeekHead, as they now follow the Cluster
+    let saved = File::open(&groove, &fixture.path).unwrap();
+    assert_eq!(saved.chapters(), chapters);
+    assert_eq!(saved.audio_streams().len(), 1);
+
+    saved.set_chapters(Vec::new()).unwrap();
+    saved.save().unwrap();
+    assert!(File::open(&groove, &fixture.path).unwrap().chapters().is_empty());
+}
+
+#[test]
+fn chapters_cannot_be_set_in_other_formats() {
+    let groove = common::groove();
+    let fixture = common::noise_wav(2, 100);
+    let file = common::open(&groove, &fixture);
+    assert_eq!(file.set_chapters(vec![chapter(0, 1, "")]), Err(-38));
+
+    let many: Vec<Chapter> = (0..256).map(|start| chapter(start, start + 1, "")).collect();
+    let m4b = m4b();
+    assert_eq!(File::open(&groove, &m4b.path).unwrap().set_chapters(many), Err(-22));
+
+    // transcoding writes them into the output, which the mock only makes WAV
+    let output = common::temp_path("chaptered.wav");
+    let result = groove::transcode(&groove, &[&fixture.path], &output, TranscodeOptions {
+        chapters: Some(&[chapter(0, 1, "")]),
+        ..Default::default()
+    });
+    match result {
+        Err(TranscodeError::Chapters(-38)) => {},
+        other => panic!("expected a chapters error, got {:?}", other),
+    }
+    assert!(!common::exists(&output));
+}
+
+#[test]
+fn chapters_play_as_items_of_their_own() {
+    let groove = common::groove();
+    let book = m4b();
+    let plain = common::noise_wav(3, 4410);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    let loaded = playlist.append_chapters(File::open(&groove, &book.path).unwrap());
+    assert!(loaded.unreadable.is_empty());
+    assert_eq!(loaded.added[1].1.duration, Some(Duration::from_millis(1500)));
+    // a file without chapters goes in whole
+    let whole = playlist.append_chapters(File::open(&groove, &plain.path).unwrap());
+    assert_eq!(whole.added.len(), 1);
+
+    let title = |id| playlist.item(id).unwrap().tag("title");
+    let titles: Vec<Option<String>> = playlist.ids().into_iter().map(title).collect();
+    assert_eq!(titles, vec![Some("Intro".to_string()), Some("Middle".to_string()), None, None]);
+
+    let (played, samples) = common::play_items(&sink, &playlist);
+    let ids: Vec<ItemId> = loaded.added.iter().chain(&whole.added).map(|&(id, _)| id).collect();
+    assert_eq!(played, vec![(ids[0], 44100), (ids[1], 66150), (ids[2], 22050), (ids[3], 4410)]);
+    // the mock decodes the MP4 as a constant
+    let expected: Vec<f64> = vec![0.1; 44100 * 3].into_iter().chain(plain.samples.iter().cloned()).collect();
+    common::assert_samples_eq(&samples, &expected, Encoding::F32.step());
+}
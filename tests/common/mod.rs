@@ -0,0 +1,521 @@
+// fixtures and helpers shared by the integration tests. fixtures are WAV
+// files written to the temp directory when a test runs, so the tests work
+// against libgroove itself as well as the `mock` feature. MP4 and Matroska
+// fixtures hold tracks but no audio, which only the mock plays.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use groove::{
+    AudioFormat,
+    ChannelLayout,
+    DecodedBuffer,
+    File,
+    Groove,
+    ItemId,
+    OwnedAudioBuffer,
+    Playlist,
+    SampleFormat,
+    SampleType,
+    Sink,
+};
+
+static NEXT_FIXTURE: AtomicUsize = AtomicUsize::new(0);
+
+pub fn groove() -> Groove {
+    Groove::new().expect("error initializing libgroove")
+}
+
+/// how samples are stored in a fixture
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    U8,
+    S16,
+    S24,
+    S32,
+    F32,
+    F64,
+}
+
+pub const ENCODINGS: [Encoding; 6] = [
+    Encoding::U8,
+    Encoding::S16,
+    Encoding::S24,
+    Encoding::S32,
+    Encoding::F32,
+    Encoding::F64,
+];
+
+impl Encoding {
+    fn bits(self) -> u16 {
+        match self {
+            Encoding::U8 => 8,
+            Encoding::S16 => 16,
+            Encoding::S24 => 24,
+            Encoding::S32 | Encoding::F32 => 32,
+            Encoding::F64 => 64,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        self == Encoding::F32 || self == Encoding::F64
+    }
+
+    /// the value a sample actually has once stored
+    pub fn quantize(self, value: f64) -> f64 {
+        let value = value.clamp(-1.0, 1.0);
+        match self {
+            Encoding::F32 => f64::from(value as f32),
+            Encoding::F64 => value,
+            _ => {
+                let scale = f64::from(1u32 << (self.bits() - 1));
+                (value * scale).round().clamp(-scale, scale - 1.0) / scale
+            },
+        }
+    }
+
+    /// the largest difference between a value and its stored form
+    pub fn step(self) -> f64 {
+        if self.is_float() { 1e-7 } else { 1.0 / f64::from(1u32 << (self.bits() - 1)) }
+    }
+
+    /// the sample type libav decodes this encoding to
+    pub fn decoded_type(self) -> SampleType {
+        match self {
+            Encoding::U8 => SampleType::U8,
+            Encoding::S16 => SampleType::S16,
+            Encoding::S24 | Encoding::S32 => SampleType::S32,
+            Encoding::F32 => SampleType::Flt,
+            Encoding::F64 => SampleType::Dbl,
+        }
+    }
+
+    fn encode(self, value: f64, out: &mut Vec<u8>) {
+        let scaled = |bits: u16| {
+            let scale = f64::from(1u32 << (bits - 1));
+            (value * scale).round().clamp(-scale, scale - 1.0) as i32
+        };
+        match self {
+            Encoding::U8 => out.push((scaled(8) + 128) as u8),
+            Encoding::S16 => out.extend_from_slice(&(scaled(16) as i16).to_le_bytes()),
+            Encoding::S24 => out.extend_from_slice(&scaled(24).to_le_bytes()[..3]),
+            Encoding::S32 => out.extend_from_slice(&scaled(32).to_le_bytes()),
+            Encoding::F32 => out.extend_from_slice(&(value as f32).to_le_bytes()),
+            Encoding::F64 => out.extend_from_slice(&value.to_le_bytes()),
+        }
+    }
+}
+
+/// interleaved samples of a sine wave. each channel gets its own frequency,
+/// a multiple of `frequency`, so that swapped channels are noticed.
+pub fn sine(frequency: f64, sample_rate: u32, channels: usize, frames: usize) -> Vec<f64> {
+    let mut samples = Vec::with_capacity(frames * channels);
+    for frame in 0..frames {
+        let t = frame as f64 / f64::from(sample_rate);
+        for channel in 0..channels {
+            samples.push(0.5 * (2.0 * PI * frequency * (channel + 1) as f64 * t).sin());
+        }
+    }
+    samples
+}
+
+/// interleaved white noise, the same for a given seed
+pub fn noise(seed: u64, channels: usize, frames: usize) -> Vec<f64> {
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    (0..frames * channels)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 11) as f64 / (1u64 << 53) as f64) * 1.6 - 0.8
+        })
+        .collect()
+}
+
+/// `frames` of mono noise at 44.1kHz, stored as 32-bit floats
+pub fn noise_wav(seed: u64, frames: usize) -> Fixture {
+    wav(Encoding::F32, 44100, 1, &noise(seed, 1, frames), &[])
+}
+
+/// a file in the temp directory, removed when dropped
+pub struct Fixture {
+    pub path: PathBuf,
+    pub encoding: Encoding,
+    pub sample_rate: u32,
+    pub channels: usize,
+    /// the interleaved samples as stored in the file
+    pub samples: Vec<f64>,
+}
+
+impl Fixture {
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.frames() as f64 / f64::from(self.sample_rate)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// a fresh path in the temp directory, for output files
+pub fn temp_path(name: &str) -> PathBuf {
+    let index = NEXT_FIXTURE.fetch_add(1, Ordering::SeqCst);
+    env::temp_dir().join(format!("groove-test-{}-{}-{}", process::id(), index, name))
+}
+
+/// write interleaved `samples` as a WAV file, with `tags` as LIST INFO
+/// metadata keyed by chunk id, for example `INAM` for the title
+pub fn wav(encoding: Encoding, sample_rate: u32, channels: usize, samples: &[f64],
+           tags: &[(&str, &str)]) -> Fixture {
+    let path = temp_path("fixture.wav");
+    let samples: Vec<f64> = samples.iter().map(|&value| encoding.quantize(value)).collect();
+    fs::write(&path, wav_bytes(encoding, sample_rate, channels, &samples, tags)).unwrap();
+    Fixture { path, encoding, sample_rate, channels, samples }
+}
+
+fn wav_bytes(encoding: Encoding, sample_rate: u32, channels: usize, samples: &[f64],
+             tags: &[(&str, &str)]) -> Vec<u8> {
+    let block_align = channels as u16 * encoding.bits() / 8;
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&(if encoding.is_float() { 3u16 } else { 1u16 }).to_le_bytes());
+    fmt.extend_from_slice(&(channels as u16).to_le_bytes());
+    fmt.extend_from_slice(&sample_rate.to_le_bytes());
+    fmt.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+    fmt.extend_from_slice(&block_align.to_le_bytes());
+    fmt.extend_from_slice(&encoding.bits().to_le_bytes());
+
+    let mut data = Vec::new();
+    for &value in samples {
+        encoding.encode(value, &mut data);
+    }
+
+    let mut body = b"WAVE".to_vec();
+    chunk(&mut body, b"fmt ", &fmt);
+    if !tags.is_empty() {
+        let mut info = b"INFO".to_vec();
+        for &(id, value) in tags {
+            let mut value = value.as_bytes().to_vec();
+            value.push(0);
+            chunk(&mut info, id.as_bytes(), &value);
+        }
+        chunk(&mut body, b"LIST", &info);
+    }
+    chunk(&mut body, b"data", &data);
+
+    let mut out = Vec::new();
+    chunk(&mut out, b"RIFF", &body);
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, id: &[u8], body: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// a file in the temp directory with no audio in it, removed when dropped
+pub struct Container {
+    pub path: PathBuf,
+}
+
+impl Drop for Container {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Container {
+    pub fn new(name: &str, bytes: &[u8]) -> Container {
+        let path = temp_path(name);
+        fs::write(&path, bytes).unwrap();
+        Container { path }
+    }
+}
+
+/// a track of an `mp4` or `matroska` fixture
+#[derive(Clone, Copy)]
+pub struct Track {
+    pub audio: bool,
+    /// a fourcc for MP4, a codec id for Matroska
+    pub codec: &'static str,
+    pub language: Option<&'static str>,
+    /// Matroska only
+    pub name: Option<&'static str>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// enabled, for MP4
+    pub default: bool,
+}
+
+impl Track {
+    pub fn audio(codec: &'static str, sample_rate: u32, channels: u16) -> Track {
+        Track { audio: true, codec, language: None, name: None, sample_rate, channels, default: true }
+    }
+
+    pub fn video(codec: &'static str) -> Track {
+        Track { audio: false, codec, language: None, name: None, sample_rate: 0, channels: 0, default: true }
+    }
+}
+
+pub fn mp4_box(kind: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+// the moov box of an MP4 file `seconds` long, holding `tracks`, each with
+// one chunk at `chunk_offset`, and then `extra`, which is more boxes
+fn moov(tracks: &[Track], seconds: f64, extra: &[u8], chunk_offset: u32) -> Vec<u8> {
+    let mut mvhd = vec![0u8; 100];
+    mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+    mvhd[16..20].copy_from_slice(&((seconds * 1000.0).round() as u32).to_be_bytes());
+    let mut payload = mp4_box(b"mvhd", &mvhd);
+    for track in tracks {
+        let mut tkhd = vec![0u8; 84];
+        tkhd[3] = if track.default { 1 } else { 0 };
+        let mut mdhd = vec![0u8; 24];
+        let language = track.language.unwrap_or("und").as_bytes();
+        let packed = language.iter().fold(0u16, |packed, &c| (packed << 5) | u16::from(c - 0x60));
+        mdhd[20..22].copy_from_slice(&packed.to_be_bytes());
+        let mut hdlr = vec![0u8; 24];
+        hdlr[8..12].copy_from_slice(if track.audio { b"soun" } else { b"vide" });
+
+        let mut entry = vec![0u8; 28];
+        entry[16..18].copy_from_slice(&track.channels.to_be_bytes());
+        entry[24..28].copy_from_slice(&(track.sample_rate << 16).to_be_bytes());
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend_from_slice(&mp4_box(track.codec.as_bytes(), &entry));
+        let mut stco = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stco.extend_from_slice(&chunk_offset.to_be_bytes());
+
+        let mut stbl = mp4_box(b"stsd", &stsd);
+        stbl.extend_from_slice(&mp4_box(b"stco", &stco));
+        let mut mdia = mp4_box(b"mdhd", &mdhd);
+        mdia.extend_from_slice(&mp4_box(b"hdlr", &hdlr));
+        mdia.extend_from_slice(&mp4_box(b"minf", &mp4_box(b"stbl", &stbl)));
+        let mut trak = mp4_box(b"tkhd", &tkhd);
+        trak.extend_from_slice(&mp4_box(b"mdia", &mdia));
+        payload.extend_from_slice(&mp4_box(b"trak", &trak));
+    }
+    payload.extend_from_slice(extra);
+    mp4_box(b"moov", &payload)
+}
+
+/// an MP4 file `seconds` long holding `tracks`, then `extra` boxes in its
+/// moov box, and `media` in its mdat box, which every track's one chunk
+/// points at. the moov box comes first, or after the mdat if `moov_last`.
+pub fn mp4(tracks: &[Track], seconds: f64, extra: &[u8], media: &[u8], moov_last: bool) -> Container {
+    let ftyp = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A mp42isom");
+    let mdat = mp4_box(b"mdat", media);
+    let moov_len = moov(tracks, seconds, extra, 0).len();
+    let mut bytes = ftyp.clone();
+    if moov_last {
+        bytes.extend_from_slice(&mdat);
+        bytes.extend_from_slice(&moov(tracks, seconds, extra, ftyp.len() as u32 + 8));
+    } else {
+        bytes.extend_from_slice(&moov(tracks, seconds, extra, (ftyp.len() + moov_len) as u32 + 8));
+        bytes.extend_from_slice(&mdat);
+    }
+    Container::new("fixture.m4a", &bytes)
+}
+
+/// the payload of the first box at the end of `path` in `data`, which is
+/// a box payload or a whole file
+pub fn find_box<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let mut data = data;
+    for kind in path {
+        let mut rest = data;
+        data = loop {
+            if rest.len() < 8 {
+                return None;
+            }
+            let size = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            if size < 8 || size > rest.len() {
+                return None;
+            }
+            if rest[4..8] == kind[..] {
+                break &rest[8..size];
+            }
+            rest = &rest[size..];
+        };
+    }
+    Some(data)
+}
+
+/// a Matroska element, with its size written in 8 bytes
+pub fn ebml(id: u32, payload: &[u8]) -> Vec<u8> {
+    let id_bytes = id.to_be_bytes();
+    let skip = id_bytes.iter().position(|&byte| byte != 0).unwrap_or(3);
+    let mut out = id_bytes[skip..].to_vec();
+    out.push(0x01);
+    out.extend_from_slice(&(payload.len() as u64).to_be_bytes()[1..]);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// the Info and Tracks elements of a Matroska segment `seconds` long
+pub fn matroska_tracks(tracks: &[Track], seconds: f64) -> Vec<u8> {
+    let mut info = ebml(0x2AD7B1, &1_000_000u32.to_be_bytes());
+    info.extend_from_slice(&ebml(0x4489, &(seconds * 1000.0).to_bits().to_be_bytes()));
+    let mut entries = Vec::new();
+    for (index, track) in tracks.iter().enumerate() {
+        let mut entry = ebml(0xD7, &[index as u8 + 1]);
+        entry.extend_from_slice(&ebml(0x83, &[if track.audio { 2 } else { 1 }]));
+        entry.extend_from_slice(&ebml(0x86, track.codec.as_bytes()));
+        entry.extend_from_slice(&ebml(0x88, &[track.default as u8]));
+        if let Some(language) = track.language {
+            entry.extend_from_slice(&ebml(0x22B59C, language.as_bytes()));
+        }
+        if let Some(name) = track.name {
+            entry.extend_from_slice(&ebml(0x536E, name.as_bytes()));
+        }
+        if track.audio {
+            let mut audio = ebml(0xB5, &f64::from(track.sample_rate).to_bits().to_be_bytes());
+            audio.extend_from_slice(&ebml(0x9F, &[track.channels as u8]));
+            entry.extend_from_slice(&ebml(0xE1, &audio));
+        }
+        entries.extend_from_slice(&ebml(0xAE, &entry));
+    }
+    let mut out = ebml(0x1549A966, &info);
+    out.extend_from_slice(&ebml(0x1654AE6B, &entries));
+    out
+}
+
+/// a Matroska file whose segment holds `children`
+pub fn matroska(children: &[u8]) -> Container {
+    let mut bytes = ebml(0x1A45DFA3, &ebml(0x4282, b"matroska"));
+    bytes.extend_from_slice(&ebml(0x18538067, children));
+    Container::new("fixture.mka", &bytes)
+}
+
+pub fn open<'g>(groove: &'g Groove, fixture: &Fixture) -> File<'g> {
+    File::open(groove, &fixture.path).expect("could not open fixture")
+}
+
+pub fn audio_format(sample_rate: u32, channels: usize, sample_type: SampleType, planar: bool) -> AudioFormat {
+    AudioFormat {
+        sample_rate: sample_rate as i32,
+        channel_layout: if channels == 1 { ChannelLayout::LayoutMono } else { ChannelLayout::LayoutStereo },
+        sample_fmt: SampleFormat { sample_type, planar },
+    }
+}
+
+/// mono doubles at 44.1kHz, which `noise_wav` fixtures play back exactly
+pub fn mono() -> AudioFormat {
+    audio_format(44100, 1, SampleType::Dbl, false)
+}
+
+/// a sink attached to `playlist`, asking for `mono()` buffers
+pub fn listen<'g>(groove: &'g Groove, playlist: &Playlist<'g>) -> Sink<'g> {
+    let sink = Sink::new(groove);
+    sink.set_audio_format(mono());
+    sink.attach(playlist).unwrap();
+    sink
+}
+
+/// the largest difference between a value and its stored form in buffers
+/// of this sample type
+pub fn sample_step(sample_type: SampleType) -> f64 {
+    match sample_type {
+        SampleType::U8 => 1.0 / 128.0,
+        SampleType::S16 => 1.0 / 32768.0,
+        SampleType::S32 => 1.0 / 2147483648.0,
+        SampleType::Flt => 1e-7,
+        _ => 1e-15,
+    }
+}
+
+/// the samples of one frame of a decoded buffer, as floats
+fn decoded_frame(buffer: &DecodedBuffer, frame: usize, channels: usize, out: &mut Vec<f64>) {
+    let format = buffer.audio_format();
+    for channel in 0..channels {
+        let index = if format.sample_fmt.planar { frame } else { frame * channels + channel };
+        let value = if format.sample_fmt.planar {
+            let channel = channel as u32;
+            match format.sample_fmt.sample_type {
+                SampleType::U8 => (f64::from(buffer.channel_as_slice_u8(channel)[index]) - 128.0) / 128.0,
+                SampleType::S16 => f64::from(buffer.channel_as_slice_i16(channel)[index]) / 32768.0,
+                SampleType::S32 => f64::from(buffer.channel_as_slice_i32(channel)[index]) / 2147483648.0,
+                SampleType::Flt => f64::from(buffer.channel_as_slice_f32(channel)[index]),
+                _ => buffer.channel_as_slice_f64(channel)[index],
+            }
+        } else {
+            match format.sample_fmt.sample_type {
+                SampleType::U8 => (f64::from(buffer.as_slice_u8()[index]) - 128.0) / 128.0,
+                SampleType::S16 => f64::from(buffer.as_slice_i16()[index]) / 32768.0,
+                SampleType::S32 => f64::from(buffer.as_slice_i32()[index]) / 2147483648.0,
+                SampleType::Flt => f64::from(buffer.as_slice_f32()[index]),
+                _ => buffer.as_slice_f64()[index],
+            }
+        };
+        out.push(value);
+    }
+}
+
+/// every frame the sink delivers until the end of the playlist, as
+/// interleaved floats, checking each buffer is in `format`
+pub fn collect(sink: &Sink, format: AudioFormat) -> Vec<f64> {
+    let channels = format.channel_layout.count() as usize;
+    let mut samples = Vec::new();
+    while let Some(buffer) = sink.buffer_get_blocking() {
+        let buffer_format = buffer.audio_format();
+        assert_eq!(buffer_format.sample_rate, format.sample_rate);
+        assert_eq!(buffer_format.channel_layout.mask(), format.channel_layout.mask());
+        assert_eq!(buffer_format.sample_fmt.sample_type, format.sample_fmt.sample_type);
+        assert_eq!(buffer_format.sample_fmt.planar, format.sample_fmt.planar);
+        for frame in 0..buffer.frame_count() {
+            decoded_frame(&buffer, frame, channels, &mut samples);
+        }
+    }
+    samples
+}
+
+/// play the playlist to its end through a `listen` sink, giving how many
+/// frames each item played in turn, and every sample
+pub fn play_items(sink: &Sink, playlist: &Playlist) -> (Vec<(ItemId, usize)>, Vec<f64>) {
+    let mut samples = Vec::new();
+    let mut played: Vec<(ItemId, usize)> = Vec::new();
+    while let Some(buffer) = sink.buffer_get_blocking() {
+        let id = playlist.item_of(&buffer).expect("a buffer from no item");
+        match played.last_mut() {
+            Some((last, frames)) if *last == id => *frames += buffer.frame_count(),
+            _ => played.push((id, buffer.frame_count())),
+        }
+        samples.extend_from_slice(buffer.as_slice_f64());
+    }
+    (played, samples)
+}
+
+/// the samples of an owned buffer as interleaved floats
+pub fn owned_samples(buffer: &OwnedAudioBuffer) -> Vec<f64> {
+    let format = buffer.audio_format();
+    assert!(!format.sample_fmt.planar && format.sample_fmt.sample_type == SampleType::Dbl);
+    buffer.as_slice_f64().to_vec()
+}
+
+/// assert two runs of samples match to within `tolerance`
+pub fn assert_samples_eq(actual: &[f64], expected: &[f64], tolerance: f64) {
+    assert_eq!(actual.len(), expected.len(), "sample counts differ");
+    for (index, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!((a - e).abs() <= tolerance,
+                "sample {} is {}, expected {} (tolerance {})", index, a, e, tolerance);
+    }
+}
+
+pub fn exists(path: &Path) -> bool {
+    fs::metadata(path).is_ok()
+}
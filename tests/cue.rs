@@ -0,0 +1,122 @@
+extern crate groove;
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use common::{Encoding, Fixture};
+use groove::{CueError, CueSheet, Encoder, File, ItemId, Playlist};
+
+const SHEET: &str = r#"REM GENRE Ambient
+PERFORMER "The Band"
+TITLE "An Album"
+FILE "album.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "First"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Second"
+    PERFORMER "A Guest"
+    INDEX 00 00:01:00
+    INDEX 01 00:01:15
+  TRACK 03 AUDIO
+    TITLE "Third"
+    INDEX 01 00:02:00
+"#;
+
+// three seconds of noise as album.wav, with the sheet beside it
+fn album() -> (Fixture, PathBuf) {
+    let mut fixture = common::wav(Encoding::F32, 44100, 1, &common::noise(1, 1, 44100 * 3), &[]);
+    let dir = common::temp_path("album");
+    fs::create_dir_all(&dir).unwrap();
+    fs::rename(&fixture.path, dir.join("album.wav")).unwrap();
+    fixture.path = dir.join("album.wav");
+    fs::write(dir.join("album.cue"), SHEET).unwrap();
+    (fixture, dir.join("album.cue"))
+}
+
+#[test]
+fn sheets_give_each_track_its_part_of_the_file() {
+    let sheet = CueSheet::parse(SHEET).unwrap();
+    assert_eq!((sheet.title.as_deref(), sheet.performer.as_deref()), (Some("An Album"), Some("The Band")));
+    let tracks: Vec<_> = sheet.tracks.iter()
+        .map(|track| (track.number, track.file.as_str(), track.title.as_deref(), track.performer.as_deref()))
+        .collect();
+    assert_eq!(tracks, vec![
+        (1, "album.wav", Some("First"), None),
+        (2, "album.wav", Some("Second"), Some("A Guest")),
+        (3, "album.wav", Some("Third"), None),
+    ]);
+    // from INDEX 01 to the next track's
+    let ms = Duration::from_millis;
+    let parts: Vec<_> = sheet.tracks.iter().map(|track| (track.start, track.end)).collect();
+    assert_eq!(parts, vec![(ms(0), Some(ms(1200))), (ms(1200), Some(ms(2000))), (ms(2000), None)]);
+
+    // tracks in different files end with their file
+    let sheet = CueSheet::parse("FILE a.flac WAVE\nTRACK 1 AUDIO\nINDEX 01 01:02:03\nFILE b.flac WAVE\n\
+                                 TRACK 2 AUDIO\nINDEX 01 00:00:00\n").unwrap();
+    assert_eq!(sheet.tracks[0].start, Duration::from_secs(62) + Duration::from_nanos(40_000_000));
+    assert_eq!((sheet.tracks[0].end, sheet.tracks[1].file.as_str()), (None, "b.flac"));
+
+    assert_eq!(CueSheet::parse("FILE a.flac WAVE\nTRACK 1 AUDIO\nINDEX 01 00:00:80\n"), Err(CueError::Line(3)));
+    assert_eq!(CueSheet::parse("FILE a.flac WAVE\nTRACK 7 AUDIO\nINDEX 00 00:00:00\n"), Err(CueError::NoStart(7)));
+}
+
+#[test]
+fn tracks_play_as_items_of_their_own() {
+    let groove = common::groove();
+    let (album, cue) = album();
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    let loaded = playlist.load_cue(&cue).unwrap();
+    assert!(loaded.unreadable.is_empty());
+    let ids: Vec<ItemId> = loaded.added.iter().map(|&(id, _)| id).collect();
+    assert_eq!(loaded.added[1].1.duration, Some(Duration::from_millis(800)));
+
+    let tag = |index: usize, key| playlist.item(ids[index]).unwrap().tag(key);
+    assert_eq!(tag(1, "title").as_deref(), Some("Second"));
+    assert_eq!(tag(1, "artist").as_deref(), Some("A Guest"));
+    assert_eq!(tag(2, "ARTIST").as_deref(), Some("The Band"));
+    assert_eq!(tag(2, "album").as_deref(), Some("An Album"));
+
+    // no buffer runs from one track into the next
+    let (played, samples) = common::play_items(&sink, &playlist);
+    assert_eq!(played, vec![(ids[0], 52920), (ids[1], 35280), (ids[2], 44100)]);
+    common::assert_samples_eq(&samples, &album.samples, Encoding::F32.step());
+}
+
+#[test]
+fn embedded_sheets_split_the_file_they_are_in() {
+    let groove = common::groove();
+    let (album, _) = album();
+
+    let file = File::open(&groove, &album.path).unwrap();
+    assert_eq!(file.cue_sheet(), None);
+    file.metadata_set("CUESHEET", SHEET, false).unwrap();
+    let sheet = file.cue_sheet().unwrap();
+
+    let mut playlist = Playlist::new(&groove);
+    let loaded = playlist.append_cue(file, &sheet);
+    assert_eq!(loaded.added.len(), 3);
+    playlist.remove(loaded.added[0].0).unwrap();
+
+    let mut encoder = Encoder::new(&groove);
+    encoder.set_format_short_name("wav");
+    encoder.set_codec_short_name("pcm_f64le");
+    encoder.set_target_audio_format(common::mono());
+    encoder.attach(&playlist).unwrap();
+    let mut bytes = Vec::new();
+    while let Some(buffer) = encoder.buffer_get_blocking() {
+        bytes.extend_from_slice(buffer.as_vec());
+    }
+    encoder.detach();
+    let path = common::temp_path("tracks.wav");
+    fs::write(&path, bytes).unwrap();
+    let encoded = File::open(&groove, &path).unwrap().decode_all(common::mono()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    common::assert_samples_eq(&common::owned_samples(&encoded), &album.samples[52920..], Encoding::F32.step());
+}
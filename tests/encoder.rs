@@ -0,0 +1,304 @@
+extern crate groove;
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use common::Encoding;
+use groove::{AudioFormat, Encoder, File, Groove, MetadataMode, Playlist, Progress, SampleType, TranscodeError,
+             TranscodeOptions};
+
+// encode `inputs` back to back into a WAV file with `codec`, returning its path
+fn encode(groove: &Groove, inputs: &[&Path], codec: &str, format: AudioFormat,
+          tags: &[(&str, &str)]) -> PathBuf {
+    encode_with(groove, inputs, codec, format, |encoder| {
+        for &(key, value) in tags {
+            encoder.metadata_set(key, value, false).unwrap();
+        }
+    })
+}
+
+// like `encode`, with `setup` choosing the encoder's tags
+fn encode_with<F: FnOnce(&Encoder)>(groove: &Groove, inputs: &[&Path], codec: &str, format: AudioFormat,
+                                    setup: F) -> PathBuf {
+    let mut playlist = Playlist::new(groove);
+    let mut encoder = Encoder::new(groove);
+    encoder.set_format_short_name("wav");
+    encoder.set_codec_short_name(codec);
+    encoder.set_target_audio_format(format);
+    setup(&encoder);
+    encoder.attach(&playlist).expect("error attaching encoder");
+    for input in inputs {
+        playlist.append(File::open(groove, input).unwrap(), 1.0, 1.0);
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(buffer) = encoder.buffer_get_blocking() {
+        bytes.extend_from_slice(buffer.as_vec());
+    }
+    encoder.detach();
+
+    let path = common::temp_path("encoded.wav");
+    fs::write(&path, bytes).unwrap();
+    path
+}
+
+fn decode(groove: &Groove, path: &Path, sample_rate: u32, channels: usize) -> Vec<f64> {
+    let file = File::open(groove, path).expect("could not open encoder output");
+    let decoded = file.decode_all(common::audio_format(sample_rate, channels, SampleType::Dbl, false)).unwrap();
+    common::owned_samples(&decoded)
+}
+
+#[test]
+fn output_decodes_back_to_the_input() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::F64, 44100, 2, &common::noise(21, 2, 20000), &[]);
+    let codecs = [
+        ("pcm_u8", SampleType::U8, Encoding::U8),
+        ("pcm_s16le", SampleType::S16, Encoding::S16),
+        ("pcm_s32le", SampleType::S32, Encoding::S32),
+        ("pcm_f32le", SampleType::Flt, Encoding::F32),
+        ("pcm_f64le", SampleType::Dbl, Encoding::F64),
+    ];
+    for &(codec, sample_type, encoding) in &codecs {
+        let path = encode(&groove, &[&fixture.path], codec, common::audio_format(44100, 2, sample_type, false), &[]);
+        let samples = decode(&groove, &path, 44100, 2);
+        fs::remove_file(&path).unwrap();
+        common::assert_samples_eq(&samples, &fixture.samples, encoding.step());
+    }
+}
+
+#[test]
+fn output_joins_inputs_and_converts_format() {
+    let groove = common::groove();
+    let a = common::wav(Encoding::S16, 22050, 1, &common::sine(100.0, 22050, 1, 22050), &[]);
+    let b = common::wav(Encoding::S16, 22050, 1, &common::noise(4, 1, 11025), &[]);
+    let format = common::audio_format(22050, 1, SampleType::Flt, false);
+    let path = encode(&groove, &[&a.path, &b.path], "pcm_f32le", format, &[]);
+
+    let samples = decode(&groove, &path, 22050, 1);
+    fs::remove_file(&path).unwrap();
+    let expected: Vec<f64> = a.samples.iter().chain(&b.samples).cloned().collect();
+    common::assert_samples_eq(&samples, &expected, Encoding::F32.step());
+}
+
+#[test]
+fn output_carries_metadata() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::sine(440.0, 44100, 2, 4410), &[]);
+    let path = encode(&groove, &[&fixture.path], "pcm_s16le", common::audio_format(44100, 2, SampleType::S16, false),
+                      &[("title", "Encoded"), ("artist", "Someone")]);
+
+    let file = File::open(&groove, &path).unwrap();
+    assert_eq!(file.metadata_get("title", false).unwrap().value().unwrap(), "Encoded");
+    assert_eq!(file.metadata_get("artist", false).unwrap().value().unwrap(), "Someone");
+    drop(file);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn tags_are_copied_from_files() {
+    let groove = common::groove();
+    let tags = [("INAM", "A Title"), ("IART", "Someone"), ("IGNR", "Noise")];
+    let fixture = common::wav(Encoding::S16, 44100, 1, &common::noise(27, 1, 4410), &tags);
+    let file = File::open(&groove, &fixture.path).unwrap();
+    let format = common::audio_format(44100, 1, SampleType::S16, false);
+    let tag = |path: &Path, key: &str| {
+        let file = File::open(&groove, path).unwrap();
+        file.metadata_get(key, false).map(|tag| tag.value().unwrap().to_string())
+    };
+
+    let path = encode_with(&groove, &[&fixture.path], "pcm_s16le", format, |encoder| {
+        encoder.copy_metadata_from(&file).unwrap();
+    });
+    assert_eq!(tag(&path, "title").as_deref(), Some("A Title"));
+    assert_eq!(tag(&path, "artist").as_deref(), Some("Someone"));
+    assert_eq!(tag(&path, "genre").as_deref(), Some("Noise"));
+    fs::remove_file(&path).unwrap();
+
+    // renamed, rewritten and skipped
+    let path = encode_with(&groove, &[&fixture.path], "pcm_s16le", format, |encoder| {
+        encoder.copy_metadata_from_with(&file, |key, value| match key {
+            "title" => Some(("album".to_string(), value.to_string())),
+            "artist" => Some((key.to_string(), value.to_uppercase())),
+            _ => None,
+        }).unwrap();
+    });
+    assert_eq!(tag(&path, "title"), None);
+    assert_eq!(tag(&path, "album").as_deref(), Some("A Title"));
+    assert_eq!(tag(&path, "artist").as_deref(), Some("SOMEONE"));
+    assert_eq!(tag(&path, "genre"), None);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn metadata_modes_choose_the_files_streams_are_tagged_from() {
+    let groove = common::groove();
+    let a = common::wav(Encoding::S16, 44100, 1, &common::noise(28, 1, 2000), &[("INAM", "First")]);
+    let b = common::wav(Encoding::S16, 44100, 1, &common::noise(29, 1, 2000), &[("INAM", "Second")]);
+    let files = [File::open(&groove, &a.path).unwrap(), File::open(&groove, &b.path).unwrap()];
+    let format = common::audio_format(44100, 1, SampleType::S16, false);
+
+    // the tags of a stream tagged for `a`, and then one tagged for `b`
+    let tags = |mode: MetadataMode| {
+        let path = encode_with(&groove, &[&a.path], "pcm_s16le", format, |encoder| {
+            encoder.metadata_set("comment", "by hand", false).unwrap();
+            encoder.set_metadata_mode(mode);
+            for file in &files {
+                encoder.tag_stream(file).unwrap();
+            }
+        });
+        let file = File::open(&groove, &path).unwrap();
+        let tag = |key| file.metadata_get(key, false).map(|tag| tag.value().unwrap().to_string());
+        let tags = (tag("title"), tag("comment"));
+        drop(file);
+        fs::remove_file(&path).unwrap();
+        tags
+    };
+    assert_eq!(tags(MetadataMode::FirstItem), (Some("First".to_string()), None));
+    assert_eq!(tags(MetadataMode::PerItem), (Some("Second".to_string()), None));
+    assert_eq!(tags(MetadataMode::None), (None, None));
+}
+
+#[test]
+fn transcode_tags_the_output_from_the_first_input_or_not_at_all() {
+    let groove = common::groove();
+    let a = common::wav(Encoding::S16, 44100, 1, &common::noise(30, 1, 2000), &[("INAM", "First")]);
+    let b = common::wav(Encoding::S16, 44100, 1, &common::noise(31, 1, 2000), &[("INAM", "Second")]);
+    for &(mode, title) in &[(MetadataMode::FirstItem, Some("First")), (MetadataMode::None, None)] {
+        let output = common::temp_path("tagged.wav");
+        groove::transcode(&groove, &[&a.path, &b.path], &output, TranscodeOptions {
+            metadata: mode,
+            ..wav_options()
+        }).unwrap();
+        let file = File::open(&groove, &output).unwrap();
+        assert_eq!(file.metadata_get("title", false).map(|tag| tag.value().unwrap().to_string()).as_deref(), title);
+        drop(file);
+        fs::remove_file(&output).unwrap();
+    }
+}
+
+#[test]
+fn transcode_writes_a_decodable_file() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 48000, 2, &common::noise(8, 2, 9600), &[("INAM", "Source")]);
+    let output = common::temp_path("transcoded.wav");
+    groove::transcode(&groove, &[&fixture.path], &output, TranscodeOptions {
+        format_short_name: Some("wav"),
+        codec_short_name: Some("pcm_s16le"),
+        ..TranscodeOptions::default()
+    }).expect("transcode failed");
+
+    let samples = decode(&groove, &output, 48000, 2);
+    common::assert_samples_eq(&samples, &fixture.samples, Encoding::S16.step());
+    let file = File::open(&groove, &output).unwrap();
+    assert_eq!(file.metadata_get("title", false).unwrap().value().unwrap(), "Source");
+    drop(file);
+    fs::remove_file(&output).unwrap();
+    assert!(!common::exists(&output));
+}
+
+// options writing 16 bit WAV
+fn wav_options<'a>() -> TranscodeOptions<'a> {
+    TranscodeOptions {
+        format_short_name: Some("wav"),
+        codec_short_name: Some("pcm_s16le"),
+        target_audio_format: Some(common::audio_format(44100, 1, SampleType::S16, false)),
+        ..TranscodeOptions::default()
+    }
+}
+
+// whether anything is left of transcodes to `output` but `output` itself
+fn temp_files_left(output: &Path) -> bool {
+    let prefix = format!(".{}.", output.file_name().unwrap().to_string_lossy());
+    fs::read_dir(output.parent().unwrap()).unwrap()
+        .any(|entry| entry.unwrap().file_name().to_string_lossy().starts_with(&prefix))
+}
+
+#[test]
+fn transcode_joins_inputs_and_reports_progress() {
+    let groove = common::groove();
+    let a = common::wav(Encoding::S16, 44100, 1, &common::noise(22, 1, 30000), &[]);
+    let b = common::wav(Encoding::S16, 44100, 1, &common::noise(23, 1, 15000), &[]);
+    let output = common::temp_path("joined.wav");
+
+    let mut reports: Vec<Progress> = Vec::new();
+    let mut report = |progress: &Progress| reports.push(*progress);
+    groove::transcode(&groove, &[&a.path, &b.path], &output, TranscodeOptions {
+        progress: Some(&mut report),
+        ..wav_options()
+    }).unwrap();
+
+    let expected: Vec<f64> = a.samples.iter().chain(&b.samples).cloned().collect();
+    common::assert_samples_eq(&decode(&groove, &output, 44100, 1), &expected, Encoding::S16.step());
+    fs::remove_file(&output).unwrap();
+
+    let duration = a.duration() + b.duration();
+    assert!(reports.iter().all(|progress| (progress.duration - duration).abs() < 1e-3));
+    assert!(reports.windows(2).all(|pair| pair[0].position <= pair[1].position));
+    assert!(reports.windows(2).all(|pair| pair[0].input_index <= pair[1].input_index));
+    assert_eq!(reports[0].input_index, 0);
+    let last = reports.last().unwrap();
+    assert_eq!(last.input_index, 1);
+    // the last buffer starts short of the end by at most its own length
+    assert!(last.fraction() > 0.9 && last.fraction() <= 1.0);
+}
+
+#[test]
+fn cancelled_transcodes_leave_the_output_alone() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 1, &common::noise(24, 1, 44100), &[]);
+    let output = common::temp_path("cancelled.wav");
+    fs::write(&output, b"before").unwrap();
+
+    // from within the transcode
+    let cancel = AtomicBool::new(false);
+    let mut report = |_: &Progress| cancel.store(true, Ordering::SeqCst);
+    let result = groove::transcode(&groove, &[&fixture.path], &output, TranscodeOptions {
+        progress: Some(&mut report),
+        cancel: Some(&cancel),
+        ..wav_options()
+    });
+    assert!(matches!(result, Err(TranscodeError::Cancelled)));
+
+    // and before any audio is encoded
+    let result = groove::transcode(&groove, &[&fixture.path], &output, TranscodeOptions {
+        cancel: Some(&AtomicBool::new(true)),
+        ..wav_options()
+    });
+    assert!(matches!(result, Err(TranscodeError::Cancelled)));
+
+    assert_eq!(fs::read(&output).unwrap(), b"before");
+    assert!(!temp_files_left(&output));
+    fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn per_input_metadata_gives_each_input_its_own_stream() {
+    let groove = common::groove();
+    let a = common::wav(Encoding::S16, 44100, 1, &common::noise(25, 1, 4000), &[("INAM", "First")]);
+    let b = common::wav(Encoding::S16, 44100, 1, &common::noise(26, 1, 3000), &[("INAM", "Second")]);
+    let output = common::temp_path("per-input.wav");
+    groove::transcode(&groove, &[&a.path, &b.path], &output, TranscodeOptions {
+        metadata: MetadataMode::PerItem,
+        ..wav_options()
+    }).unwrap();
+
+    // two WAV streams, one after the other. the encoder can't go back to
+    // fill in their lengths, so look for the second header.
+    let bytes = fs::read(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    let second = 4 + bytes[4..].windows(4).position(|window| window == b"RIFF").unwrap();
+    let parts = [(&bytes[..second], &a, "First"), (&bytes[second..], &b, "Second")];
+    for &(part, fixture, title) in &parts {
+        let path = common::temp_path("part.wav");
+        fs::write(&path, part).unwrap();
+        let file = File::open(&groove, &path).unwrap();
+        assert_eq!(file.metadata_get("title", false).unwrap().value().unwrap(), title);
+        drop(file);
+        common::assert_samples_eq(&decode(&groove, &path, 44100, 1), &fixture.samples, Encoding::S16.step());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+extern crate groove;
+
+mod common;
+
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use groove::{Notification, Playlist, PlaylistEvent, RepeatMode};
+use groove::PlaylistEvent::*;
+
+fn at(event: PlaylistEvent, frames: usize) -> Notification {
+    Notification { event, timestamp: Duration::from_secs_f64(frames as f64 / 44100.0) }
+}
+
+fn received(events: &Receiver<Notification>) -> Vec<Notification> {
+    events.try_iter().collect()
+}
+
+fn assert_events_eq(actual: &[Notification], expected: &[Notification]) {
+    let kinds = |notifications: &[Notification]| notifications.iter().map(|n| n.event).collect::<Vec<_>>();
+    assert_eq!(kinds(actual), kinds(expected));
+    for (actual, expected) in actual.iter().zip(expected) {
+        let difference = actual.timestamp.as_secs_f64() - expected.timestamp.as_secs_f64();
+        assert!(difference.abs() < 1e-6, "{:?} at {:?}, expected {:?}", actual.event, actual.timestamp,
+                expected.timestamp);
+    }
+}
+
+#[test]
+fn items_start_and_finish_as_the_sink_plays_them() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(1, 3000), common::noise_wav(2, 2000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    let events = playlist.subscribe(&sink);
+    let a = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let b = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+
+    // nothing is seen until the sink hands audio out
+    assert!(received(&events).is_empty());
+    sink.buffer_get_blocking().unwrap();
+    assert_events_eq(&received(&events), &[at(ItemStarted(a), 0)]);
+
+    common::collect(&sink, common::mono());
+    assert_events_eq(&received(&events), &[
+        at(ItemFinished(a), 3000),
+        at(ItemStarted(b), 3000),
+        at(ItemFinished(b), 5000),
+        at(EndOfPlaylist, 5000),
+    ]);
+}
+
+#[test]
+fn seeks_and_pauses_are_timed_by_the_sink() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(3, 20000), common::noise_wav(4, 10000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    let a = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let b = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    let events = playlist.subscribe(&sink);
+
+    let played = sink.buffer_get_blocking().unwrap().frame_count();
+    playlist.pause();
+    assert!(!playlist.playing());
+    playlist.play();
+    assert!(playlist.playing());
+    playlist.seek(b, 0.1).unwrap();
+    // the seek is heard with the first buffer after it
+    assert_events_eq(&received(&events), &[at(ItemStarted(a), 0), at(Paused, played), at(Played, played)]);
+
+    let first = sink.buffer_get_blocking().unwrap();
+    assert!((first.pos() - 0.1).abs() < 1e-6);
+    assert_events_eq(&received(&events), &[
+        at(Seeked, played),
+        at(ItemFinished(a), played),
+        at(ItemStarted(b), played),
+    ]);
+    let rest = common::collect(&sink, common::mono()).len();
+    assert_events_eq(&received(&events), &[
+        at(ItemFinished(b), played + first.frame_count() + rest),
+        at(EndOfPlaylist, played + first.frame_count() + rest),
+    ]);
+}
+
+#[test]
+fn removed_items_finish_as_themselves() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(8, 20000), common::noise_wav(9, 2000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    let events = playlist.subscribe(&sink);
+    let a = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let b = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+
+    sink.buffer_get_blocking().unwrap();
+    playlist.remove(a).unwrap();
+    common::collect(&sink, common::mono());
+    let kinds: Vec<PlaylistEvent> = received(&events).iter().map(|notification| notification.event).collect();
+    assert_eq!(kinds, vec![ItemStarted(a), ItemFinished(a), ItemStarted(b), ItemFinished(b), EndOfPlaylist]);
+}
+
+#[test]
+fn each_lap_starts_the_items_again() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(5, 2000), common::noise_wav(6, 1000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    let events = playlist.subscribe(&sink);
+    playlist.set_repeat(RepeatMode::All);
+    let ids = [
+        playlist.append(common::open(&groove, &a), 1.0, 1.0),
+        playlist.append(common::open(&groove, &b), 1.0, 1.0),
+    ];
+
+    let mut frames = 0;
+    while frames < 3000 * 3 {
+        frames += sink.buffer_get_blocking().unwrap().frame_count();
+    }
+    let started: Vec<Notification> = received(&events).into_iter()
+        .filter(|notification| matches!(notification.event, ItemStarted(_)))
+        .collect();
+    let expected: Vec<Notification> = (0..6)
+        .map(|lap| at(ItemStarted(ids[lap % 2]), lap / 2 * 3000 + lap % 2 * 2000))
+        .collect();
+    assert_events_eq(&started[..6], &expected);
+}
+
+#[test]
+fn events_follow_the_sink_subscribed_to() {
+    let groove = common::groove();
+    let a = common::noise_wav(7, 2000);
+
+    let mut playlist = Playlist::new(&groove);
+    let id = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let (fast, slow) = (common::listen(&groove, &playlist), common::listen(&groove, &playlist));
+    let (fast_events, slow_events) = (playlist.subscribe(&fast), playlist.subscribe(&slow));
+
+    common::collect(&fast, common::mono());
+    assert_eq!(received(&fast_events).len(), 3);
+    assert!(received(&slow_events).is_empty());
+    common::collect(&slow, common::mono());
+    assert_events_eq(&received(&slow_events), &[
+        at(ItemStarted(id), 0),
+        at(ItemFinished(id), 2000),
+        at(EndOfPlaylist, 2000),
+    ]);
+}
@@ -0,0 +1,233 @@
+extern crate groove;
+
+mod common;
+
+use std::time::Duration;
+
+use common::{Encoding, Track, ENCODINGS};
+use groove::{File, ItemOptions, Playlist, SampleType};
+
+#[test]
+fn open_reports_format_and_duration() {
+    let groove = common::groove();
+    for &encoding in &ENCODINGS {
+        for &(sample_rate, channels) in &[(22050, 1), (44100, 2), (48000, 2)] {
+            let fixture = common::wav(encoding, sample_rate, channels,
+                                      &common::sine(440.0, sample_rate, channels, 12345), &[]);
+            let file = File::open(&groove, &fixture.path).expect("could not open fixture");
+            let format = file.audio_format();
+            assert_eq!(format.sample_rate, sample_rate as i32);
+            assert_eq!(format.channel_layout.count(), channels as i32);
+            assert_eq!(format.sample_fmt.sample_type, encoding.decoded_type(), "{:?}", encoding);
+            assert!(!format.sample_fmt.planar);
+            assert!((file.duration() - fixture.duration()).abs() < 1.0 / f64::from(sample_rate),
+                    "duration {} of {:?}, expected {}", file.duration(), encoding, fixture.duration());
+            assert_eq!(file.filename(), fixture.path.as_path());
+        }
+    }
+}
+
+#[test]
+fn info_describes_the_codec_and_streams() {
+    let groove = common::groove();
+    let codecs = [
+        (Encoding::U8, "pcm_u8", 8),
+        (Encoding::S16, "pcm_s16le", 16),
+        (Encoding::S24, "pcm_s24le", 24),
+        (Encoding::S32, "pcm_s32le", 32),
+        (Encoding::F32, "pcm_f32le", 32),
+        (Encoding::F64, "pcm_f64le", 64),
+    ];
+    for &(encoding, codec_name, bits) in &codecs {
+        let fixture = common::wav(encoding, 48000, 2, &common::sine(440.0, 48000, 2, 4800), &[]);
+        let file = File::open(&groove, &fixture.path).unwrap();
+        let info = file.info();
+        assert!(info.is_format("wav"), "{:?}", info.format_names);
+        assert_eq!(info.codec_name.as_deref(), Some(codec_name));
+        assert!(info.codec_long_name.as_ref().is_some_and(|name| name.starts_with("PCM ")), "{:?}", info);
+        assert_eq!(info.stream_count, 1);
+        assert_eq!(info.stream_index, Some(0));
+        assert_eq!(info.bits_per_raw_sample, Some(bits));
+        assert_eq!(info.bit_rate, Some(48000 * 2 * u64::from(bits)));
+        assert_eq!(info.lossless, Some(true));
+        let streams = file.audio_streams();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].index, Some(0));
+    }
+}
+
+#[test]
+fn audio_streams_lists_the_tracks_of_mp4_files() {
+    let groove = common::groove();
+    let mut commentary = Track::audio("mp4a", 22050, 1);
+    commentary.language = Some("fra");
+    commentary.default = false;
+    let mut main = Track::audio("mp4a", 48000, 2);
+    main.language = Some("eng");
+    let fixture = common::mp4(&[Track::video("avc1"), main, commentary], 0.5, &[], &[], false);
+    let file = File::open(&groove, &fixture.path).unwrap();
+
+    let streams = file.audio_streams();
+    assert_eq!(streams.iter().map(|stream| stream.index).collect::<Vec<_>>(), [Some(1), Some(2)]);
+    assert_eq!(streams[0].codec.as_deref(), Some("mp4a"));
+    assert_eq!(streams[0].language.as_deref(), Some("eng"));
+    assert_eq!((streams[0].sample_rate, streams[0].channel_count), (Some(48000), Some(2)));
+    assert!(streams[0].default);
+    assert_eq!(streams[1].language.as_deref(), Some("fra"));
+    assert_eq!((streams[1].sample_rate, streams[1].channel_count), (Some(22050), Some(1)));
+    assert!(!streams[1].default);
+
+    let info = file.info();
+    assert!(info.is_format("mp4"), "{:?}", info.format_names);
+    assert_eq!(info.stream_count, 3);
+    assert_eq!(info.stream_index, Some(1));
+}
+
+#[test]
+fn audio_streams_lists_the_tracks_of_matroska_files() {
+    let groove = common::groove();
+    let mut main = Track::audio("A_OPUS", 48000, 2);
+    main.name = Some("Stereo");
+    let mut commentary = Track::audio("A_AAC", 44100, 1);
+    commentary.language = Some("und");
+    commentary.name = Some("Director's commentary");
+    commentary.default = false;
+    let fixture = common::matroska(&common::matroska_tracks(&[main, Track::video("V_VP9"), commentary], 0.5));
+    let file = File::open(&groove, &fixture.path).unwrap();
+
+    let streams = file.audio_streams();
+    assert_eq!(streams.iter().map(|stream| stream.index).collect::<Vec<_>>(), [Some(0), Some(2)]);
+    assert_eq!(streams[0].codec.as_deref(), Some("A_OPUS"));
+    // Matroska's default language
+    assert_eq!(streams[0].language.as_deref(), Some("eng"));
+    assert_eq!(streams[0].name.as_deref(), Some("Stereo"));
+    assert!(streams[0].default);
+    assert_eq!(streams[1].codec.as_deref(), Some("A_AAC"));
+    assert_eq!(streams[1].language, None);
+    assert_eq!(streams[1].name.as_deref(), Some("Director's commentary"));
+    assert_eq!((streams[1].sample_rate, streams[1].channel_count), (Some(44100), Some(1)));
+    assert!(!streams[1].default);
+    assert_eq!(file.info().stream_count, 3);
+}
+
+#[test]
+fn select_audio_stream_changes_what_plays() {
+    let groove = common::groove();
+    let tracks = [Track::video("avc1"), Track::audio("mp4a", 48000, 2), Track::audio("mp4a", 22050, 1)];
+    let fixture = common::mp4(&tracks, 0.5, &[], &[], false);
+    let mut file = File::open(&groove, &fixture.path).unwrap();
+    assert_eq!(file.audio_format().sample_rate, 48000);
+
+    // neither the video nor a stream which isn't there can be chosen
+    assert!(file.select_audio_stream(0).is_err());
+    assert!(file.select_audio_stream(3).is_err());
+    assert_eq!(file.info().stream_index, Some(1));
+
+    file.select_audio_stream(2).unwrap();
+    let format = file.audio_format();
+    assert_eq!((format.sample_rate, format.channel_layout.count()), (22050, 1));
+    assert_eq!(file.info().stream_index, Some(2));
+
+    // decoding apart from a playlist opens the file again, with the same
+    // stream. the mock plays 0.3 for the container's third stream.
+    let decoded = file.decode_all(common::audio_format(22050, 1, SampleType::Dbl, false)).unwrap();
+    assert_eq!(decoded.frame_count(), 11025);
+    assert!(common::owned_samples(&decoded).iter().all(|&sample| (sample - 0.3).abs() < 1e-6));
+
+    // as does rendering a region of it
+    let mut playlist = Playlist::new(&groove);
+    playlist.append_with(file, ItemOptions { start: Duration::from_millis(100), ..ItemOptions::default() });
+    let sink = common::listen(&groove, &playlist);
+    let samples = common::collect(&sink, common::mono());
+    assert_eq!(samples.len(), 17640);
+    assert!(samples.iter().all(|&sample| (sample - 0.3).abs() < 1e-6), "{:?}", &samples[..10]);
+}
+
+#[test]
+fn open_missing_or_invalid_file_fails() {
+    let groove = common::groove();
+    let path = common::temp_path("missing.wav");
+    assert!(File::open(&groove, &path).is_none());
+
+    std::fs::write(&path, b"not a wav file at all, just some text").unwrap();
+    assert!(File::open(&groove, &path).is_none());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn decode_all_is_sample_accurate() {
+    let groove = common::groove();
+    for &encoding in &ENCODINGS {
+        let fixture = common::wav(encoding, 44100, 2, &common::noise(7, 2, 5000), &[]);
+        let file = File::open(&groove, &fixture.path).unwrap();
+        let decoded = file.decode_all(common::audio_format(44100, 2, SampleType::Dbl, false)).unwrap();
+        assert_eq!(decoded.frame_count(), fixture.frames());
+        common::assert_samples_eq(&common::owned_samples(&decoded), &fixture.samples, 1e-9);
+    }
+}
+
+#[test]
+fn decode_range_is_sample_accurate() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(8, 2, 20000), &[]);
+    let file = File::open(&groove, &fixture.path).unwrap();
+    let format = common::audio_format(44100, 2, SampleType::Dbl, false);
+
+    let decoded = file.decode_range(Duration::from_millis(100), Duration::from_millis(250), format).unwrap();
+    assert_eq!(decoded.frame_count(), 6615);
+    common::assert_samples_eq(&common::owned_samples(&decoded), &fixture.samples[4410 * 2..11025 * 2], 1e-9);
+
+    // a range past the end stops with the file
+    let decoded = file.decode_range(Duration::from_millis(400), Duration::from_secs(1), format).unwrap();
+    assert_eq!(decoded.frame_count(), 20000 - 17640);
+    common::assert_samples_eq(&common::owned_samples(&decoded), &fixture.samples[17640 * 2..], 1e-9);
+}
+
+#[test]
+fn metadata_get_and_iterate() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::sine(440.0, 44100, 2, 100),
+                              &[("INAM", "A Title"), ("IART", "An Artist")]);
+    let file = File::open(&groove, &fixture.path).unwrap();
+
+    let title = file.metadata_get("title", false).expect("no title");
+    assert_eq!(title.value().unwrap(), "A Title");
+    assert_eq!(file.metadata_get("TITLE", false).unwrap().value().unwrap(), "A Title");
+    assert!(file.metadata_get("TITLE", true).is_none());
+    assert!(file.metadata_get("album", false).is_none());
+
+    let mut tags: Vec<(String, String)> = file.metadata_iter()
+        .map(|tag| (tag.key().unwrap().to_string(), tag.value().unwrap().to_string()))
+        .collect();
+    tags.sort();
+    assert_eq!(tags, vec![
+        ("artist".to_string(), "An Artist".to_string()),
+        ("title".to_string(), "A Title".to_string()),
+    ]);
+}
+
+#[test]
+fn metadata_round_trips_through_save() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(3, 2, 3000),
+                              &[("INAM", "Old Title"), ("IART", "An Artist")]);
+    {
+        let file = File::open(&groove, &fixture.path).unwrap();
+        assert!(!file.is_dirty());
+        file.metadata_set("title", "New Title", false).unwrap();
+        file.metadata_set("album", "An Album", false).unwrap();
+        file.metadata_delete("artist", false).unwrap();
+        assert!(file.is_dirty());
+        file.save().expect("save failed");
+        assert!(!file.is_dirty());
+    }
+
+    let file = File::open(&groove, &fixture.path).unwrap();
+    assert_eq!(file.metadata_get("title", false).unwrap().value().unwrap(), "New Title");
+    assert_eq!(file.metadata_get("album", false).unwrap().value().unwrap(), "An Album");
+    assert!(file.metadata_get("artist", false).is_none());
+
+    // saving must leave the audio alone
+    let decoded = file.decode_all(common::audio_format(44100, 2, SampleType::Dbl, false)).unwrap();
+    common::assert_samples_eq(&common::owned_samples(&decoded), &fixture.samples, 1e-9);
+}
@@ -0,0 +1,218 @@
+extern crate groove;
+
+mod common;
+
+use common::{Encoding, Fixture};
+use groove::{AudioFormat, File, Filter, FilterChain, FilterError, Groove, Playlist, SampleType, Sink};
+
+fn dbl(channels: usize) -> AudioFormat {
+    common::audio_format(44100, channels, SampleType::Dbl, false)
+}
+
+// play `fixture` through a sink filtered by `chain`, which gives
+// `channels` channels
+fn filtered_to(groove: &Groove, fixture: &Fixture, chain: FilterChain, channels: usize) -> Vec<f64> {
+    let mut playlist = Playlist::new(groove);
+    let sink = Sink::new(groove);
+    sink.set_audio_format(dbl(fixture.channels));
+    sink.set_filters(chain);
+    sink.attach(&playlist).unwrap();
+    playlist.append(File::open(groove, &fixture.path).unwrap(), 1.0, 1.0);
+    common::collect(&sink, dbl(channels))
+}
+
+fn filtered(groove: &Groove, fixture: &Fixture, chain: FilterChain) -> Vec<f64> {
+    filtered_to(groove, fixture, chain, fixture.channels)
+}
+
+fn sine(frequency: f64, channels: usize) -> Fixture {
+    common::wav(Encoding::F32, 44100, channels, &common::sine(frequency, 44100, channels, 44100), &[])
+}
+
+// root mean square level, skipping the first tenth while filters settle
+fn rms(samples: &[f64]) -> f64 {
+    let settled = &samples[samples.len() / 10..];
+    (settled.iter().map(|sample| sample * sample).sum::<f64>() / settled.len() as f64).sqrt()
+}
+
+#[test]
+fn empty_chain_changes_nothing() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(1, 2, 10000), &[]);
+    common::assert_samples_eq(&filtered(&groove, &fixture, FilterChain::new()), &fixture.samples, 1e-9);
+}
+
+#[test]
+fn volume_scales() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(2, 2, 10000), &[]);
+    let expected: Vec<f64> = fixture.samples.iter().map(|sample| sample * 0.5).collect();
+    common::assert_samples_eq(&filtered(&groove, &fixture, FilterChain::new().volume_db(-6.0206)), &expected, 1e-4);
+}
+
+#[test]
+fn lowpass_and_highpass_split_the_spectrum() {
+    let groove = common::groove();
+    let (low, high) = (sine(100.0, 1), sine(8000.0, 1));
+    let level = rms(&low.samples);
+
+    assert!(rms(&filtered(&groove, &low, FilterChain::new().lowpass(1000.0))) > level * 0.95);
+    assert!(rms(&filtered(&groove, &high, FilterChain::new().lowpass(1000.0))) < level * 0.05);
+    assert!(rms(&filtered(&groove, &high, FilterChain::new().highpass(1000.0))) > level * 0.95);
+    assert!(rms(&filtered(&groove, &low, FilterChain::new().highpass(1000.0))) < level * 0.05);
+}
+
+#[test]
+fn equalizer_boosts_its_band_only() {
+    let groove = common::groove();
+    let (band, outside) = (sine(1000.0, 1), sine(100.0, 1));
+    let chain = FilterChain::new().equalizer(1000.0, 2.0, 6.0);
+
+    let boost = rms(&filtered(&groove, &band, chain.clone())) / rms(&band.samples);
+    assert!((boost - 2.0).abs() < 0.05, "boost {}", boost);
+    let other = rms(&filtered(&groove, &outside, chain)) / rms(&outside.samples);
+    assert!((other - 1.0).abs() < 0.1, "gain outside the band {}", other);
+}
+
+#[test]
+fn tempo_changes_length_not_pitch() {
+    let groove = common::groove();
+    let fixture = sine(441.0, 2);
+    for &factor in &[0.5, 0.8, 1.25, 2.0] {
+        let samples = filtered(&groove, &fixture, FilterChain::new().tempo(factor).unwrap());
+        let frames = samples.len() / 2;
+        assert_eq!(frames, (fixture.frames() as f64 / factor).round() as usize);
+
+        // the left channel still crosses zero 441 times a second
+        let left: Vec<f64> = samples.iter().step_by(2).cloned().collect();
+        let settled = &left[frames / 10..frames * 9 / 10];
+        let crossings = settled.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+        let expected = 441.0 * settled.len() as f64 / 44100.0;
+        assert!((crossings as f64 - expected).abs() < expected * 0.05,
+                "tempo {}: {} crossings, expected {}", factor, crossings, expected);
+    }
+}
+
+#[test]
+fn pan_mixes_channels() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(3, 2, 5000), &[]);
+    let samples = filtered(&groove, &fixture, FilterChain::new().pan(&[&[0.0, 1.0], &[0.5, 0.5]]).unwrap());
+    let expected: Vec<f64> = fixture.samples.chunks(2)
+        .flat_map(|frame| vec![frame[1], (frame[0] + frame[1]) / 2.0])
+        .collect();
+    common::assert_samples_eq(&samples, &expected, 1e-9);
+}
+
+#[test]
+fn pan_changes_the_channel_count() {
+    let groove = common::groove();
+    let stereo = common::wav(Encoding::S16, 44100, 2, &common::noise(5, 2, 5000), &[]);
+    let samples = filtered_to(&groove, &stereo, FilterChain::new().pan(&[&[0.5, 0.5]]).unwrap(), 1);
+    let expected: Vec<f64> = stereo.samples.chunks(2).map(|frame| (frame[0] + frame[1]) / 2.0).collect();
+    common::assert_samples_eq(&samples, &expected, 1e-9);
+
+    // and the filters after the pan run on the new channels
+    let mono = common::wav(Encoding::S16, 44100, 1, &common::noise(6, 1, 5000), &[]);
+    let chain = FilterChain::new().pan(&[&[1.0], &[-1.0]]).unwrap().volume(0.5);
+    let samples = filtered_to(&groove, &mono, chain, 2);
+    let expected: Vec<f64> = mono.samples.iter().flat_map(|&sample| vec![sample * 0.5, sample * -0.5]).collect();
+    common::assert_samples_eq(&samples, &expected, 1e-9);
+}
+
+#[test]
+fn out_of_range_filters_are_refused() {
+    assert_eq!(FilterChain::new().pan(&[&[1.0], &[1.0], &[1.0]]), Err(FilterError::PanChannels(3)));
+    assert_eq!(FilterChain::new().pan(&[]), Err(FilterError::PanChannels(0)));
+    assert_eq!(FilterChain::new().tempo(4.0), Err(FilterError::TempoFactor(4.0)));
+    assert_eq!(FilterChain::new().push(Filter::Tempo(0.25)), Err(FilterError::TempoFactor(0.25)));
+    let chain = FilterChain::new().volume(0.5).tempo(2.0).unwrap();
+    assert_eq!(chain.filters(), &[Filter::Volume(0.5), Filter::Tempo(2.0)]);
+}
+
+#[test]
+fn filtered_buffers_keep_their_items() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(7, 4000), common::noise_wav(8, 6000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    sink.set_filters(FilterChain::new().tempo(2.0).unwrap());
+    let a_id = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let b_id = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    // what the tempo change holds back of the first item still comes out
+    // as the first item's
+    let (played, samples) = common::play_items(&sink, &playlist);
+    assert_eq!(samples.len(), 5000);
+    assert_eq!(played, vec![(a_id, 2000), (b_id, 3000)]);
+}
+
+#[test]
+fn seeking_starts_the_filters_afresh() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 1, &common::noise(9, 1, 44100), &[]);
+    let chain = FilterChain::new().echo(1.0, 0.5, &[(0.05, 0.5)]).tempo(1.25).unwrap();
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(dbl(1));
+    sink.set_filters(chain.clone());
+    sink.attach(&playlist).unwrap();
+    let id = playlist.append(File::open(&groove, &fixture.path).unwrap(), 1.0, 1.0);
+    assert!(sink.buffer_get_blocking().is_some());
+    playlist.seek(id, 0.0).unwrap();
+
+    // nothing held back from before the seek, no echo of it
+    let samples = common::collect(&sink, dbl(1));
+    common::assert_samples_eq(&samples, &filtered(&groove, &fixture, chain), 1e-9);
+}
+
+#[test]
+fn echo_repeats_after_the_delay() {
+    let groove = common::groove();
+    let mut click = vec![0.0; 4410];
+    click[10] = 0.8;
+    let fixture = common::wav(Encoding::F32, 44100, 1, &click, &[]);
+    let samples = filtered(&groove, &fixture, FilterChain::new().echo(1.0, 0.5, &[(0.05, 0.5)]));
+    assert!((samples[10] - 0.4).abs() < 1e-6);
+    assert!((samples[10 + 2205] - 0.2).abs() < 1e-6);
+    let total: f64 = samples.iter().map(|sample| sample.abs()).sum();
+    assert!((total - 0.6).abs() < 1e-6);
+}
+
+#[test]
+fn compand_compresses_loud_audio() {
+    let groove = common::groove();
+    let loud = sine(200.0, 1);
+    let soft: Vec<f64> = loud.samples.iter().map(|sample| sample * 0.01).collect();
+    let quiet = common::wav(Encoding::F32, 44100, 1, &soft, &[]);
+    let chain = FilterChain::new().compand(0.005, 0.1, &[(-90.0, -90.0), (-20.0, -20.0), (0.0, -10.0)]);
+
+    // -9 dB peaks come down by about 4.5 dB
+    let ratio = rms(&filtered(&groove, &loud, chain.clone())) / rms(&loud.samples);
+    assert!(ratio > 0.45 && ratio < 0.75, "loud ratio {}", ratio);
+    // -49 dB is below the knee and left alone
+    let ratio = rms(&filtered(&groove, &quiet, chain)) / rms(&quiet.samples);
+    assert!((ratio - 1.0).abs() < 0.05, "quiet ratio {}", ratio);
+}
+
+#[test]
+fn filters_apply_to_one_sink_only() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(4, 2, 8000), &[]);
+
+    let mut playlist = Playlist::new(&groove);
+    let clean = Sink::new(&groove);
+    let quiet = Sink::new(&groove);
+    for sink in &[&clean, &quiet] {
+        sink.set_audio_format(dbl(2));
+        sink.attach(&playlist).unwrap();
+    }
+    quiet.set_filters(FilterChain::new().volume(0.25));
+    playlist.append(File::open(&groove, &fixture.path).unwrap(), 1.0, 1.0);
+
+    let quiet_samples = common::collect(&quiet, dbl(2));
+    let expected: Vec<f64> = fixture.samples.iter().map(|sample| sample * 0.25).collect();
+    common::assert_samples_eq(&quiet_samples, &expected, 1e-9);
+    common::assert_samples_eq(&common::collect(&clean, dbl(2)), &fixture.samples, 1e-9);
+}
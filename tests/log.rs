@@ -0,0 +1,61 @@
+// what libav logs about each file, which the mock logs on demand
+
+#![cfg(all(feature = "mock", feature = "log"))]
+
+extern crate groove;
+
+mod common;
+
+use common::Encoding;
+use groove::mock::{self, Fault};
+use groove::{File, Log, Playlist};
+
+fn describe(file: &File) -> Vec<(Log, String, String)> {
+    file.log_messages().into_iter().map(|message| (message.level, message.target, message.message)).collect()
+}
+
+#[test]
+fn open_logged_says_why_a_file_did_not_open() {
+    let groove = common::groove();
+    let path = common::temp_path("missing.wav");
+    let messages = match File::open_logged(&groove, &path) {
+        Err(messages) => messages,
+        Ok(_) => panic!("opened a missing file"),
+    };
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].level, Log::Error);
+    assert_eq!(messages[0].target, "groove::libav");
+    assert_eq!(messages[0].message, format!("unable to open {}", path.display()));
+
+    mock::inject_fault(Fault::Open);
+    let fixture = common::noise_wav(1, 100);
+    assert!(File::open_logged(&groove, &fixture.path).is_err());
+    let file = File::open_logged(&groove, &fixture.path).unwrap();
+    assert!(file.log_messages().is_empty());
+}
+
+#[test]
+fn read_errors_are_kept_by_the_file_which_hit_them() {
+    let groove = common::groove();
+    let format = common::mono();
+    let a = common::wav(Encoding::F32, 44100, 1, &common::noise(1, 1, 10000), &[]);
+    let b = common::wav(Encoding::F32, 44100, 1, &common::noise(2, 1, 10000), &[]);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    mock::inject_fault(Fault::ReadError { after_frames: 4000 });
+    let a_id = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let b_id = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    assert_eq!(common::collect(&sink, format).len(), 14000);
+
+    let error = (Log::Error, "groove::libav".to_string(), "error reading frames".to_string());
+    assert_eq!(describe(playlist.item(a_id).unwrap().file()), vec![error]);
+    assert!(describe(playlist.item(b_id).unwrap().file()).is_empty());
+
+    // a file opened after one which logged is not given its messages, even
+    // if its contexts take the same addresses
+    playlist.clear();
+    let c_id = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    assert_eq!(common::collect(&sink, format).len(), 10000);
+    assert!(describe(playlist.item(c_id).unwrap().file()).is_empty());
+}
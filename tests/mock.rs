@@ -0,0 +1,199 @@
+// failures only the mock can produce on demand, and leaks only it can see
+
+#![cfg(feature = "mock")]
+
+extern crate groove;
+
+mod common;
+
+use std::time::Duration;
+
+use common::{Encoding, Track};
+use groove::mock::{self, Fault};
+use groove::{Chapter, CueSheet, Encoder, Error, File, Groove, ItemOptions, Playlist, SampleType, Sink};
+
+#[test]
+fn init_failure() {
+    // other tests may hold a Groove, in which case init does not run again
+    mock::inject_fault(Fault::Init(-12));
+    match Groove::new() {
+        Err(Error::Init(code)) => assert_eq!(code, -12),
+        Ok(_) => {},
+        Err(err) => panic!("unexpected error: {}", err),
+    }
+    mock::clear_faults();
+}
+
+#[test]
+fn open_failure() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(1, 2, 100), &[]);
+    mock::inject_fault(Fault::Open);
+    assert!(File::open(&groove, &fixture.path).is_none());
+    assert!(File::open(&groove, &fixture.path).is_some());
+}
+
+#[test]
+fn attach_failure() {
+    let groove = common::groove();
+    let playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    let encoder = Encoder::new(&groove);
+    mock::inject_fault(Fault::Attach(-5));
+    mock::inject_fault(Fault::Attach(-12));
+    assert_eq!(sink.attach(&playlist), Err(-5));
+    assert_eq!(encoder.attach(&playlist), Err(-12));
+    assert_eq!(sink.attach(&playlist), Ok(()));
+}
+
+#[test]
+fn metadata_and_save_failures() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(1, 2, 100), &[("INAM", "Title")]);
+    let file = File::open(&groove, &fixture.path).unwrap();
+
+    mock::inject_fault(Fault::MetadataSet(-22));
+    assert_eq!(file.metadata_set("title", "New", false), Err(-22));
+    assert!(!file.is_dirty());
+
+    file.metadata_set("title", "New", false).unwrap();
+    mock::inject_fault(Fault::Save(-28));
+    assert_eq!(file.save(), Err(-28));
+    assert!(file.is_dirty());
+    drop(file);
+
+    let file = File::open(&groove, &fixture.path).unwrap();
+    assert_eq!(file.metadata_get("title", false).unwrap().value().unwrap(), "Title");
+}
+
+#[test]
+fn read_error_ends_the_item_early() {
+    let groove = common::groove();
+    let a = common::wav(Encoding::S16, 44100, 1, &common::noise(2, 1, 10000), &[]);
+    let b = common::wav(Encoding::S16, 44100, 1, &common::noise(3, 1, 3000), &[]);
+    let format = common::audio_format(44100, 1, SampleType::Dbl, false);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(format);
+    sink.attach(&playlist).unwrap();
+    mock::inject_fault(Fault::ReadError { after_frames: 4000 });
+    playlist.append(File::open(&groove, &a.path).unwrap(), 1.0, 1.0);
+    playlist.append(File::open(&groove, &b.path).unwrap(), 1.0, 1.0);
+
+    // the playlist carries on with the next item
+    let expected: Vec<f64> = a.samples[..4000].iter().chain(&b.samples).cloned().collect();
+    common::assert_samples_eq(&common::collect(&sink, format), &expected, 1e-9);
+}
+
+#[test]
+fn buffer_get_after_the_end_does_not_block() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(1, 2, 100), &[]);
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.attach(&playlist).unwrap();
+    playlist.append(File::open(&groove, &fixture.path).unwrap(), 1.0, 1.0);
+    while sink.buffer_get_blocking().is_some() {}
+    assert!(sink.buffer_get_blocking().is_none());
+}
+
+#[test]
+fn saving_closes_the_handles_it_replaces() {
+    let groove = common::groove();
+    let fixture = common::mp4(&[Track::audio("mp4a", 44100, 1)], 3.0, &[], b"audio", false);
+    // Nero chapters run to the end of the file
+    let chapters = |title: &str| vec![Chapter { start: Duration::from_secs(0), end: Duration::from_secs(3),
+                                                title: title.to_string() }];
+    let file = File::open(&groove, &fixture.path).unwrap();
+    for title in &["One", "Two", "Three"] {
+        file.set_chapters(chapters(title)).unwrap();
+        file.save().unwrap();
+        assert_eq!(mock::open_files(&fixture.path), 1);
+    }
+
+    // kept while a playlist may be decoding from them, until it lets go
+    let mut playlist = Playlist::new(&groove);
+    let id = playlist.append(file, 1.0, 1.0);
+    let file = playlist.item(id).unwrap().file();
+    file.set_chapters(chapters("Four")).unwrap();
+    file.save().unwrap();
+    assert_eq!(mock::open_files(&fixture.path), 2);
+    let file = playlist.remove(id).unwrap();
+    assert_eq!(mock::open_files(&fixture.path), 1);
+    assert_eq!(file.chapters(), chapters("Four"));
+    drop(file);
+    assert_eq!(mock::open_files(&fixture.path), 0);
+}
+
+#[test]
+fn only_the_items_about_to_play_are_rendered() {
+    let groove = common::groove();
+    // longer than the second a rendered item keeps ready
+    let fixture = common::noise_wav(10, 4 * 44100);
+    let mut playlist = Playlist::new(&groove);
+    let options = ItemOptions { start: Duration::from_millis(500), ..ItemOptions::default() };
+    for _ in 0..6 {
+        playlist.append_with(common::open(&groove, &fixture), options);
+    }
+
+    // each item holds its own file open, and the one being rendered and
+    // the next a second
+    let sink = common::listen(&groove, &playlist);
+    let mut samples = Vec::new();
+    while let Some(buffer) = sink.buffer_get_blocking() {
+        assert!(mock::open_files(&fixture.path) <= 6 + 2);
+        samples.extend_from_slice(buffer.as_slice_f64());
+    }
+    let expected: Vec<f64> = fixture.samples[22050..].iter().cycle().take(6 * (4 * 44100 - 22050)).cloned().collect();
+    common::assert_samples_eq(&samples, &expected, 1e-7);
+    drop(sink);
+    drop(playlist);
+    assert_eq!(mock::open_files(&fixture.path), 0);
+}
+
+#[test]
+fn cue_tracks_share_a_decode() {
+    let groove = common::groove();
+    let fixture = common::noise_wav(11, 5 * 44100);
+    let mut text = "FILE \"album.wav\" WAVE\n".to_string();
+    for track in 0..5 {
+        text += &format!("TRACK {:02} AUDIO\nINDEX 01 00:{:02}:00\n", track + 1, track);
+    }
+    let sheet = CueSheet::parse(&text).unwrap();
+
+    let mut playlist = Playlist::new(&groove);
+    let loaded = playlist.append_cue(common::open(&groove, &fixture), &sheet);
+    assert_eq!(loaded.added.len(), 5);
+    let sink = common::listen(&groove, &playlist);
+    let (_, samples) = common::play_items(&sink, &playlist);
+    common::assert_samples_eq(&samples, &fixture.samples, 1e-7);
+    // a file for each track, and a decode for the first two, which start
+    // together. the rest carry on from the track before.
+    assert!(mock::times_opened(&fixture.path) <= 5 + 2);
+}
+
+#[test]
+fn chapters_share_a_decode() {
+    let groove = common::groove();
+    let fixture = common::mp4(&[Track::audio("mp4a", 44100, 1)], 5.0, &[], b"audio", false);
+    let file = File::open(&groove, &fixture.path).unwrap();
+    let chapters = (0..5).map(|start| Chapter {
+        start: Duration::from_secs(start),
+        end: Duration::from_secs(start + 1),
+        title: String::new(),
+    });
+    file.set_chapters(chapters.collect()).unwrap();
+    file.save().unwrap();
+    let opened = mock::times_opened(&fixture.path);
+
+    let mut playlist = Playlist::new(&groove);
+    let loaded = playlist.append_chapters(file);
+    assert_eq!(loaded.added.len(), 5);
+    let sink = common::listen(&groove, &playlist);
+    let (played, _) = common::play_items(&sink, &playlist);
+    assert_eq!(played.iter().map(|&(_, frames)| frames).collect::<Vec<usize>>(), vec![44100; 5]);
+    // a file for each chapter after the first, and a decode for the first
+    // two, which start together. the rest carry on from the one before.
+    assert!(mock::times_opened(&fixture.path) - opened <= 4 + 2);
+}
@@ -0,0 +1,71 @@
+extern crate groove;
+
+mod common;
+
+use std::io;
+use std::path::Path;
+use std::thread;
+
+use groove::{PcmSource, Playlist, SampleType, Sink};
+
+#[test]
+fn written_samples_play_through_a_playlist() {
+    let groove = common::groove();
+    let samples = common::noise(30, 1, 20000);
+    let source = PcmSource::new(common::mono());
+    // small enough for the queue to fill and drain many times over
+    source.set_capacity(3000);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    let played = thread::scope(|scope| {
+        scope.spawn(|| {
+            for chunk in samples.chunks(999) {
+                source.write_interleaved(chunk).unwrap();
+            }
+            source.finish();
+        });
+        playlist.append(source.open(&groove).unwrap(), 1.0, 1.0);
+        common::collect(&sink, common::mono())
+    });
+    common::assert_samples_eq(&played, &samples, 1e-9);
+}
+
+#[test]
+fn planar_writes_play_interleaved() {
+    let groove = common::groove();
+    let (left, right) = (common::noise(31, 1, 3000), common::noise(32, 1, 3000));
+    let source = PcmSource::new(common::audio_format(44100, 2, SampleType::Dbl, true));
+    source.write_planar(&[&left[..], &right[..]]).unwrap();
+    source.finish();
+
+    let mut playlist = Playlist::new(&groove);
+    let format = common::audio_format(44100, 2, SampleType::Dbl, false);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(format);
+    sink.attach(&playlist).unwrap();
+    playlist.append(source.open(&groove).unwrap(), 1.0, 1.0);
+
+    let expected: Vec<f64> = left.iter().zip(&right).flat_map(|(&l, &r)| vec![l, r]).collect();
+    common::assert_samples_eq(&common::collect(&sink, format), &expected, 1e-9);
+}
+
+#[test]
+fn sources_open_once_and_have_no_filename() {
+    let groove = common::groove();
+    let source = PcmSource::new(common::mono());
+    source.write_interleaved(&[0.0f64; 100]).unwrap();
+    let file = source.open(&groove).unwrap();
+    assert_eq!(file.filename(), Path::new(""));
+    assert!(source.open(&groove).is_none());
+}
+
+#[test]
+fn writes_fail_once_the_file_is_closed() {
+    let groove = common::groove();
+    let source = PcmSource::new(common::mono());
+    source.write_interleaved(&[0.0f64; 100]).unwrap();
+    drop(source.open(&groove).unwrap());
+    let err = source.write_interleaved(&[0.0f64; 100]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
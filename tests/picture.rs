@@ -0,0 +1,380 @@
+extern crate groove;
+
+mod common;
+
+use std::fs;
+use std::slice;
+
+use common::{Container, Track};
+use groove::{File, Picture, PictureType};
+
+const AUDIO: &[u8] = b"\xff\xfb\x90\x64 not really MPEG audio, but it must come out the same";
+
+fn picture(picture_type: PictureType, mime_type: &str, description: &str, data: &[u8]) -> Picture {
+    Picture {
+        picture_type,
+        mime_type: mime_type.to_string(),
+        description: description.to_string(),
+        data: data.to_vec(),
+    }
+}
+
+fn assert_pictures(actual: &[Picture], expected: &[Picture]) {
+    let describe = |pictures: &[Picture]| -> Vec<(PictureType, String, String, Vec<u8>)> {
+        pictures.iter()
+            .map(|picture| (picture.picture_type, picture.mime_type.clone(), picture.description.clone(),
+                            picture.data.clone()))
+            .collect()
+    };
+    assert_eq!(describe(actual), describe(expected));
+}
+
+fn syncsafe(size: usize) -> [u8; 4] {
+    [(size >> 21) as u8 & 0x7f, (size >> 14) as u8 & 0x7f, (size >> 7) as u8 & 0x7f, size as u8 & 0x7f]
+}
+
+// an ID3v2 frame, whose size is syncsafe from version 4
+fn id3_frame(major_version: u8, id: &str, body: &[u8]) -> Vec<u8> {
+    let mut frame = id.as_bytes().to_vec();
+    if major_version >= 4 {
+        frame.extend_from_slice(&syncsafe(body.len()));
+    } else {
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    }
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(body);
+    frame
+}
+
+fn apic(major_version: u8, picture: &Picture) -> Vec<u8> {
+    let mut body = vec![0];
+    body.extend_from_slice(picture.mime_type.as_bytes());
+    body.push(0);
+    body.push(3);
+    body.extend_from_slice(picture.description.as_bytes());
+    body.push(0);
+    body.extend_from_slice(&picture.data);
+    id3_frame(major_version, "APIC", &body)
+}
+
+// an ID3v2 tag holding a title and `pictures`, then some audio
+fn mp3(major_version: u8, pictures: &[Picture]) -> Container {
+    let mut frames = id3_frame(major_version, "TIT2", b"\x00Song");
+    for picture in pictures {
+        frames.extend(apic(major_version, picture));
+    }
+    let mut bytes = vec![b'I', b'D', b'3', major_version, 0, 0];
+    bytes.extend_from_slice(&syncsafe(frames.len()));
+    bytes.extend(frames);
+    bytes.extend_from_slice(AUDIO);
+    Container::new("fixture.mp3", &bytes)
+}
+
+fn flac_block(kind: u8, last: bool, data: &[u8]) -> Vec<u8> {
+    let mut block = vec![kind | if last { 0x80 } else { 0 }];
+    block.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+    block.extend_from_slice(data);
+    block
+}
+
+fn flac_picture(picture: &Picture) -> Vec<u8> {
+    let mut data = 3u32.to_be_bytes().to_vec();
+    for text in &[&picture.mime_type, &picture.description] {
+        data.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        data.extend_from_slice(text.as_bytes());
+    }
+    data.extend_from_slice(&[0; 16]);
+    data.extend_from_slice(&(picture.data.len() as u32).to_be_bytes());
+    data.extend_from_slice(&picture.data);
+    flac_block(6, false, &data)
+}
+
+// a second of 44.1kHz 16-bit stereo FLAC with a title and `pictures`
+fn flac(pictures: &[Picture]) -> Container {
+    let mut info = vec![0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0, 0x0a, 0xc4, 0x42, 0xf0];
+    info.extend_from_slice(&44100u32.to_be_bytes());
+    info.extend_from_slice(&[0; 16]);
+    let mut comments = 4u32.to_le_bytes().to_vec();
+    comments.extend_from_slice(b"test");
+    comments.extend_from_slice(&1u32.to_le_bytes());
+    comments.extend_from_slice(&10u32.to_le_bytes());
+    comments.extend_from_slice(b"TITLE=Song");
+
+    let mut bytes = b"fLaC".to_vec();
+    bytes.extend(flac_block(0, false, &info));
+    for picture in pictures {
+        bytes.extend(flac_picture(picture));
+    }
+    bytes.extend(flac_block(4, true, &comments));
+    bytes.extend_from_slice(b"\xff\xf8 not really FLAC frames");
+    Container::new("fixture.flac", &bytes)
+}
+
+fn contains(data: &[u8], needle: &[u8]) -> bool {
+    data.windows(needle.len()).any(|window| window == needle)
+}
+
+#[test]
+fn mp3_pictures_are_saved() {
+    let groove = common::groove();
+    let front = picture(PictureType::FrontCover, "image/jpeg", "", b"old front");
+    for &major_version in &[3, 4] {
+        let fixture = mp3(major_version, slice::from_ref(&front));
+        let file = File::open(&groove, &fixture.path).unwrap();
+        assert_pictures(&file.pictures(), slice::from_ref(&front));
+
+        let new_front = picture(PictureType::FrontCover, "image/png", "", b"new front");
+        let back = picture(PictureType::BackCover, "image/jpeg", "the back", b"back");
+        file.set_picture(back.clone()).unwrap();
+        file.set_picture(new_front.clone()).unwrap();
+        assert_pictures(&file.pictures(), &[back.clone(), new_front.clone()]);
+        file.save().unwrap();
+
+        let saved = File::open(&groove, &fixture.path).unwrap();
+        assert_pictures(&saved.pictures(), &[back.clone(), new_front.clone()]);
+        let bytes = fs::read(&fixture.path).unwrap();
+        assert_eq!(bytes[3], major_version);
+        assert!(contains(&bytes, b"TIT2"));
+        assert!(bytes.ends_with(AUDIO));
+
+        saved.remove_pictures().unwrap();
+        saved.save().unwrap();
+        assert!(File::open(&groove, &fixture.path).unwrap().pictures().is_empty());
+        assert!(fs::read(&fixture.path).unwrap().ends_with(AUDIO));
+    }
+}
+
+#[test]
+fn flac_pictures_are_saved() {
+    let groove = common::groove();
+    let front = picture(PictureType::FrontCover, "image/jpeg", "front", b"front");
+    let fixture = flac(slice::from_ref(&front));
+    let file = File::open(&groove, &fixture.path).unwrap();
+    assert_pictures(&file.pictures(), slice::from_ref(&front));
+
+    let artist = picture(PictureType::LeadArtist, "image/png", "the singer", b"artist");
+    file.set_picture(artist.clone()).unwrap();
+    file.save().unwrap();
+
+    let saved = File::open(&groove, &fixture.path).unwrap();
+    assert_pictures(&saved.pictures(), &[front, artist]);
+    assert_eq!(saved.audio_format().sample_rate, 44100);
+    let bytes = fs::read(&fixture.path).unwrap();
+    assert!(contains(&bytes, b"TITLE=Song"));
+    assert!(bytes.ends_with(b"\xff\xf8 not really FLAC frames"));
+}
+
+// the media each track's chunk offset points at
+fn chunks(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let moov = common::find_box(bytes, &[b"moov"]).unwrap();
+    let mut chunks = Vec::new();
+    let mut rest = moov;
+    while let Some(trak) = common::find_box(rest, &[b"trak"]) {
+        let stco = common::find_box(trak, &[b"mdia", b"minf", b"stbl", b"stco"]).unwrap();
+        let offset = u32::from_be_bytes([stco[8], stco[9], stco[10], stco[11]]) as usize;
+        chunks.push(bytes[offset..offset + AUDIO.len()].to_vec());
+        let trak_end = trak.as_ptr() as usize - rest.as_ptr() as usize + trak.len();
+        rest = &rest[trak_end..];
+    }
+    chunks
+}
+
+#[test]
+fn mp4_pictures_are_saved_without_breaking_chunk_offsets() {
+    let groove = common::groove();
+    let mut data = 1u32.to_be_bytes().to_vec();
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(b"Song");
+    let ilst = common::mp4_box(b"ilst", &common::mp4_box(b"\xa9nam", &common::mp4_box(b"data", &data)));
+    let mut meta = vec![0; 4];
+    meta.extend(common::mp4_box(b"hdlr", b"\0\0\0\0\0\0\0\0mdirappl\0\0\0\0\0\0\0\0\0"));
+    meta.extend(ilst);
+    let udta = common::mp4_box(b"udta", &common::mp4_box(b"meta", &meta));
+
+    for &moov_last in &[false, true] {
+        let tracks = [Track::audio("mp4a", 44100, 2), Track::audio("mp4a", 48000, 1)];
+        let fixture = common::mp4(&tracks, 1.0, &udta, AUDIO, moov_last);
+        let file = File::open(&groove, &fixture.path).unwrap();
+        assert!(file.pictures().is_empty());
+
+        file.set_picture(picture(PictureType::FrontCover, "image/jpeg", "", b"front")).unwrap();
+        file.set_picture(picture(PictureType::BackCover, "image/png", "lost", b"back")).unwrap();
+        file.save().unwrap();
+
+        // iTunes-style cover art has no picture types or descriptions
+        let saved = File::open(&groove, &fixture.path).unwrap();
+        assert_pictures(&saved.pictures(), &[
+            picture(PictureType::FrontCover, "image/jpeg", "", b"front"),
+            picture(PictureType::FrontCover, "image/png", "", b"back"),
+        ]);
+        assert_eq!(saved.audio_streams().len(), 2);
+        let bytes = fs::read(&fixture.path).unwrap();
+        assert_eq!(chunks(&bytes), vec![AUDIO.to_vec(), AUDIO.to_vec()]);
+        let moov = common::find_box(&bytes, &[b"moov"]).unwrap();
+        let meta = common::find_box(moov, &[b"udta", b"meta"]).unwrap();
+        assert!(common::find_box(&meta[4..], &[b"ilst", b"\xa9nam"]).is_some());
+
+        saved.remove_pictures().unwrap();
+        saved.save().unwrap();
+        assert!(File::open(&groove, &fixture.path).unwrap().pictures().is_empty());
+        assert_eq!(chunks(&fs::read(&fixture.path).unwrap()), vec![AUDIO.to_vec(), AUDIO.to_vec()]);
+    }
+}
+
+// the id, offset and payload of each EBML element in `data`
+fn elements(data: &[u8]) -> Vec<(u32, usize, &[u8])> {
+    let mut elements = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let id_len = data[offset].leading_zeros() as usize + 1;
+        let id = data[offset..offset + id_len].iter().fold(0, |id, &byte| (id << 8) | u32::from(byte));
+        let size_len = data[offset + id_len].leading_zeros() as usize + 1;
+        let size_bytes = &data[offset + id_len..offset + id_len + size_len];
+        let first = u64::from(size_bytes[0] & (0xffu16 >> size_len) as u8);
+        let size = size_bytes[1..].iter().fold(first, |size, &byte| (size << 8) | u64::from(byte)) as usize;
+        let start = offset + id_len + size_len;
+        elements.push((id, offset, &data[start..start + size]));
+        offset = start + size;
+    }
+    elements
+}
+
+fn child(data: &[u8], id: u32) -> Option<&[u8]> {
+    elements(data).into_iter().find(|&(element_id, _, _)| element_id == id).map(|(_, _, payload)| payload)
+}
+
+fn uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, &byte| (value << 8) | u64::from(byte))
+}
+
+fn attached_file(name: &str, mime_type: &str, data: &[u8]) -> Vec<u8> {
+    let mut attached_file = common::ebml(0x466E, name.as_bytes());
+    attached_file.extend(common::ebml(0x4660, mime_type.as_bytes()));
+    attached_file.extend(common::ebml(0x465C, data));
+    attached_file.extend(common::ebml(0x46AE, &[1]));
+    common::ebml(0x61A7, &attached_file)
+}
+
+// the children of a segment: a seek head, then Info, Tracks, a cluster,
+// cues and attachments
+fn segment_children() -> Vec<u8> {
+    let tracks = common::matroska_tracks(&[Track::audio("A_VORBIS", 44100, 2)], 1.0);
+    let mut cluster = common::ebml(0xE7, &[0]);
+    cluster.extend(common::ebml(0xA3, AUDIO));
+    let cluster = common::ebml(0x1F43B675, &cluster);
+    let mut attachments = attached_file("font.ttf", "application/x-truetype-font", b"font");
+    attachments.extend(attached_file("cover.jpg", "image/jpeg", b"old front"));
+    let attachments = common::ebml(0x1941A469, &attachments);
+
+    // every size and position takes 8 bytes, so the layout is known up front
+    let cues = |cluster_position: u64| {
+        let mut positions = common::ebml(0xF7, &[1]);
+        positions.extend(common::ebml(0xF1, &cluster_position.to_be_bytes()));
+        let mut cue_point = common::ebml(0xB3, &[0]);
+        cue_point.extend(common::ebml(0xB7, &positions));
+        common::ebml(0x1C53BB6B, &common::ebml(0xBB, &cue_point))
+    };
+    let seek_head = |seeks: &[(u32, u64)]| {
+        let mut payload = Vec::new();
+        for &(id, position) in seeks {
+            let mut seek = common::ebml(0x53AB, &id.to_be_bytes());
+            seek.extend(common::ebml(0x53AC, &position.to_be_bytes()));
+            payload.extend(common::ebml(0x4DBB, &seek));
+        }
+        common::ebml(0x114D9B74, &payload)
+    };
+    let ids = [0x1549A966, 0x1654AE6B, 0x1C53BB6B, 0x1941A469];
+    let seek_head_len = seek_head(&ids.iter().map(|&id| (id, 0)).collect::<Vec<_>>()).len() as u64;
+    let tracks_offset = elements(&tracks)[1].1 as u64;
+    let cluster_position = seek_head_len + tracks.len() as u64;
+    let cues_position = cluster_position + cluster.len() as u64;
+    let attachments_position = cues_position + cues(0).len() as u64;
+    let mut children = seek_head(&[(ids[0], seek_head_len), (ids[1], seek_head_len + tracks_offset),
+                                   (ids[2], cues_position), (ids[3], attachments_position)]);
+    children.extend(tracks);
+    children.extend(cluster);
+    children.extend(cues(cluster_position));
+    children.extend(attachments);
+    children
+}
+
+// check that what the seek head and cues point at is still there
+fn check_segment(bytes: &[u8]) -> Vec<String> {
+    let segment = child(bytes, 0x18538067).unwrap();
+    let at = |position: u64| elements(&segment[position as usize..])[0];
+
+    let seek_head = child(segment, 0x114D9B74).unwrap();
+    let mut seeks = Vec::new();
+    for (_, _, seek) in elements(seek_head) {
+        let id = uint(child(seek, 0x53AB).unwrap()) as u32;
+        assert_eq!(at(uint(child(seek, 0x53AC).unwrap())).0, id);
+        seeks.push(id);
+    }
+    assert!(seeks.contains(&0x1C53BB6B));
+    assert!(seeks.contains(&0x1941A469));
+
+    let cue_point = child(child(segment, 0x1C53BB6B).unwrap(), 0xBB).unwrap();
+    let cluster_position = uint(child(child(cue_point, 0xB7).unwrap(), 0xF1).unwrap());
+    let (id, _, cluster) = at(cluster_position);
+    assert_eq!(id, 0x1F43B675);
+    assert_eq!(child(cluster, 0xA3), Some(AUDIO));
+
+    let attachments = child(segment, 0x1941A469).unwrap();
+    elements(attachments).iter()
+        .map(|&(_, _, attached_file)| String::from_utf8(child(attached_file, 0x466E).unwrap().to_vec()).unwrap())
+        .collect()
+}
+
+#[test]
+fn matroska_pictures_are_saved_without_breaking_seeks_and_cues() {
+    let groove = common::groove();
+    let fixture = common::matroska(&segment_children());
+    assert_eq!(check_segment(&fs::read(&fixture.path).unwrap()), vec!["font.ttf", "cover.jpg"]);
+    let file = File::open(&groove, &fixture.path).unwrap();
+    assert_pictures(&file.pictures(), &[picture(PictureType::FrontCover, "image/jpeg", "", b"old front")]);
+
+    let front = picture(PictureType::FrontCover, "image/png", "new", b"new front");
+    let artist = picture(PictureType::Artist, "image/jpeg", "", b"artist");
+    file.set_picture(front.clone()).unwrap();
+    file.set_picture(artist).unwrap();
+    file.save().unwrap();
+
+    // Matroska only tells the front cover from other pictures
+    let saved = File::open(&groove, &fixture.path).unwrap();
+    assert_pictures(&saved.pictures(), &[front, picture(PictureType::Other, "image/jpeg", "", b"artist")]);
+    assert_eq!(saved.audio_streams().len(), 1);
+    let names = check_segment(&fs::read(&fixture.path).unwrap());
+    assert_eq!(names[0], "font.ttf");
+    assert_eq!(names.len(), 3);
+
+    saved.remove_pictures().unwrap();
+    saved.save().unwrap();
+    assert!(File::open(&groove, &fixture.path).unwrap().pictures().is_empty());
+    assert_eq!(check_segment(&fs::read(&fixture.path).unwrap()), vec!["font.ttf"]);
+}
+
+#[test]
+fn saving_metadata_after_pictures_keeps_them() {
+    let groove = common::groove();
+    let fixture = mp3(3, &[]);
+    let front = picture(PictureType::FrontCover, "image/jpeg", "", b"front");
+    let file = File::open(&groove, &fixture.path).unwrap();
+    file.set_picture(front.clone()).unwrap();
+    file.save().unwrap();
+
+    // libgroove saves by copying from the file it opened
+    file.metadata_set("title", "Renamed", false).unwrap();
+    file.save().unwrap();
+    assert_pictures(&file.pictures(), slice::from_ref(&front));
+    assert_pictures(&File::open(&groove, &fixture.path).unwrap().pictures(), &[front]);
+}
+
+#[test]
+fn pictures_cannot_be_set_in_other_formats() {
+    let groove = common::groove();
+    let fixture = common::noise_wav(1, 100);
+    let file = common::open(&groove, &fixture);
+    assert_eq!(file.set_picture(picture(PictureType::FrontCover, "image/png", "", b"front")), Err(-38));
+    assert_eq!(file.remove_pictures(), Err(-38));
+    assert_eq!(file.save(), Ok(()));
+}
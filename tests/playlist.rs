@@ -0,0 +1,391 @@
+extern crate groove;
+
+mod common;
+
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use common::{Encoding, Fixture};
+use groove::{Fade, FadeShape, ItemId, ItemOptions, Playlist, SampleType, Sink, StaleItem};
+
+// a short mono file holding one constant level, so the order files play
+// in can be read back from the samples
+fn level(level: f64, frames: usize) -> Fixture {
+    common::wav(Encoding::S16, 44100, 1, &vec![level; frames], &[])
+}
+
+// the files of the items, in order
+fn filenames(playlist: &Playlist) -> Vec<PathBuf> {
+    playlist.ids().iter().map(|&id| playlist.item(id).unwrap().file().filename().to_path_buf()).collect()
+}
+
+fn played(sink: &Sink) -> Vec<f64> {
+    common::collect(sink, common::audio_format(44100, 1, SampleType::Dbl, false))
+}
+
+fn expected(parts: &[(&Fixture, f64)]) -> Vec<f64> {
+    parts.iter()
+        .flat_map(|&(fixture, gain)| fixture.samples.iter().map(move |sample| sample * gain))
+        .collect()
+}
+
+#[test]
+fn insert_orders_items() {
+    let groove = common::groove();
+    let (a, b, c, d) = (level(0.1, 1500), level(0.2, 2500), level(-0.3, 700), level(0.4, 3000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    sink.attach(&playlist).unwrap();
+
+    // decoding starts with the first item added, so later ones go after it
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let d_id = playlist.append(common::open(&groove, &d), 1.0, 1.0);
+    let c_id = playlist.insert(common::open(&groove, &c), 1.0, 1.0, d_id).unwrap();
+    playlist.insert(common::open(&groove, &b), 1.0, 1.0, c_id).unwrap();
+
+    assert_eq!(filenames(&playlist), vec![a.path.clone(), b.path.clone(), c.path.clone(), d.path.clone()]);
+
+    common::assert_samples_eq(&played(&sink), &expected(&[(&a, 1.0), (&b, 1.0), (&c, 1.0), (&d, 1.0)]),
+                              1e-9);
+}
+
+#[test]
+fn item_gain_scales_samples() {
+    let groove = common::groove();
+    let (a, b) = (level(0.5, 2000), level(-0.25, 2000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    sink.attach(&playlist).unwrap();
+    let a_id = playlist.append(common::open(&groove, &a), 0.5, 1.0);
+    let b_id = playlist.append(common::open(&groove, &b), 2.0, 1.0);
+
+    assert_eq!(playlist.item(a_id).unwrap().gain(), 0.5);
+    assert_eq!(playlist.item(b_id).unwrap().gain(), 2.0);
+    common::assert_samples_eq(&played(&sink), &expected(&[(&a, 0.5), (&b, 2.0)]), 1e-6);
+}
+
+#[test]
+fn clear_removes_everything() {
+    let groove = common::groove();
+    let (a, b, c) = (level(0.1, 1000), level(0.2, 1000), level(0.3, 1200));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    sink.attach(&playlist).unwrap();
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    playlist.append(common::open(&groove, &b), 1.0, 1.0);
+
+    playlist.clear();
+    assert!(playlist.is_empty());
+
+    playlist.append(common::open(&groove, &c), 1.0, 1.0);
+    assert_eq!(playlist.len(), 1);
+    common::assert_samples_eq(&played(&sink), &expected(&[(&c, 1.0)]), 1e-9);
+}
+
+#[test]
+fn seek_starts_from_the_given_item_and_time() {
+    let groove = common::groove();
+    let a = level(0.1, 4410);
+    let b = common::wav(Encoding::S16, 44100, 1, &common::noise(11, 1, 44100), &[]);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let id = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    playlist.seek(id, 0.5).unwrap();
+    sink.attach(&playlist).unwrap();
+
+    // the seek lands on a frame near the time asked for
+    let samples = played(&sink);
+    let skipped = b.samples.len() - samples.len();
+    assert!(skipped.abs_diff(22050) < 1024, "seek skipped {} frames", skipped);
+    common::assert_samples_eq(&samples, &b.samples[skipped..], 1e-9);
+}
+
+#[test]
+fn trimmed_items_play_their_region_only() {
+    let groove = common::groove();
+    let a = common::wav(Encoding::S16, 44100, 1, &common::noise(12, 1, 20000), &[]);
+    let b = level(0.2, 3000);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    sink.attach(&playlist).unwrap();
+    let id = playlist.append_with(common::open(&groove, &a), ItemOptions {
+        start: Duration::from_millis(100),
+        end: Some(Duration::from_millis(300)),
+        gain: 0.5,
+        ..ItemOptions::default()
+    });
+    playlist.append_with(common::open(&groove, &b), ItemOptions {
+        start: Duration::from_millis(50),
+        ..ItemOptions::default()
+    });
+    assert_eq!(playlist.item(id).unwrap().start(), Duration::from_millis(100));
+    assert_eq!(playlist.item(id).unwrap().end(), Some(Duration::from_millis(300)));
+
+    // positions count from the start of the region
+    let first = sink.buffer_get_blocking().unwrap();
+    assert_eq!(first.pos(), 0.0);
+    let mut samples = first.as_slice_f64().to_vec();
+    samples.extend(played(&sink));
+
+    let mut expected: Vec<f64> = a.samples[4410..13230].iter().map(|sample| sample * 0.5).collect();
+    expected.extend_from_slice(&b.samples[2205..]);
+    common::assert_samples_eq(&samples, &expected, 1e-6);
+}
+
+#[test]
+fn seek_in_a_trimmed_item_counts_from_its_start() {
+    let groove = common::groove();
+    let a = common::wav(Encoding::S16, 44100, 1, &common::noise(13, 1, 30000), &[]);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    let id = playlist.append_with(common::open(&groove, &a), ItemOptions {
+        start: Duration::from_millis(200),
+        end: Some(Duration::from_millis(500)),
+        ..ItemOptions::default()
+    });
+    playlist.seek(id, 0.1).unwrap();
+    sink.attach(&playlist).unwrap();
+
+    common::assert_samples_eq(&played(&sink), &a.samples[8820 + 4410..22050], 1e-9);
+}
+
+#[test]
+fn fades_shape_the_start_and_end_of_an_item() {
+    let groove = common::groove();
+    let (a, b) = (level(0.5, 10000), level(0.25, 1000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    sink.attach(&playlist).unwrap();
+    playlist.append_with(common::open(&groove, &a), ItemOptions {
+        fade_in: Some(Fade::new(FadeShape::Linear, Duration::from_millis(100))),
+        fade_out: Some(Fade::new(FadeShape::SCurve, Duration::from_millis(50))),
+        ..ItemOptions::default()
+    });
+    playlist.append(common::open(&groove, &b), 1.0, 1.0);
+
+    let mut expected: Vec<f64> = (0..10000).map(|frame| {
+        let fade_in = (frame as f64 / 4410.0).min(1.0);
+        let after = (9999 - frame) as f64;
+        let fade_out = if after < 2205.0 { (1.0 - (after / 2205.0 * PI).cos()) / 2.0 } else { 1.0 };
+        0.5 * fade_in * fade_out
+    }).collect();
+    expected.extend_from_slice(&b.samples);
+    // rendering keeps the file's sample format
+    common::assert_samples_eq(&played(&sink), &expected, Encoding::S16.step());
+}
+
+#[test]
+fn logarithmic_fade_rises_quickly() {
+    let groove = common::groove();
+    let a = level(0.5, 8820);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    sink.attach(&playlist).unwrap();
+    playlist.append_with(common::open(&groove, &a), ItemOptions {
+        fade_in: Some(Fade::new(FadeShape::Logarithmic, Duration::from_millis(100))),
+        ..ItemOptions::default()
+    });
+
+    // a tenth of the way in, the level is already most of the way up
+    let samples = played(&sink);
+    assert_eq!(samples[0], 0.0);
+    assert!((samples[441] - 0.5 * 0.8).abs() < Encoding::S16.step(), "level {}", samples[441]);
+    assert_eq!(samples[4410], 0.5);
+}
+
+#[test]
+fn shuffle_is_reproducible() {
+    let groove = common::groove();
+    let fixtures: Vec<Fixture> = (1..7).map(|index| level(index as f64 / 10.0, 500 + index * 100)).collect();
+    let shuffled = |seed: u64| {
+        let mut playlist = Playlist::new(&groove);
+        for fixture in &fixtures {
+            playlist.append(common::open(&groove, fixture), 1.0, 1.0);
+        }
+        let sink = Sink::new(&groove);
+        sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+        playlist.shuffle(seed);
+        let order: Vec<usize> = filenames(&playlist).iter()
+            .map(|filename| fixtures.iter().position(|fixture| fixture.path == *filename).unwrap())
+            .collect();
+
+        // the first item was being decoded, so stays first in libgroove
+        // wherever it lands in the playlist
+        sink.attach(&playlist).unwrap();
+        let first = order.iter().position(|&index| index == 0).unwrap();
+        let parts: Vec<(&Fixture, f64)> = order[first..].iter().map(|&index| (&fixtures[index], 1.0)).collect();
+        common::assert_samples_eq(&played(&sink), &expected(&parts), 1e-9);
+        order
+    };
+
+    let order = shuffled(42);
+    assert_eq!(shuffled(42), order);
+    assert_ne!(shuffled(43), order);
+    let mut sorted = order.clone();
+    sorted.sort();
+    assert_eq!(sorted, (0..6).collect::<Vec<_>>());
+}
+
+#[test]
+fn sort_by_uses_item_metadata() {
+    let groove = common::groove();
+    let title = |level_value: f64, title: &str| {
+        common::wav(Encoding::S16, 44100, 1, &[level_value; 800], &[("INAM", title)])
+    };
+    let (c, a, b) = (title(0.3, "c"), title(0.1, "a"), title(0.2, "b"));
+
+    let mut playlist = Playlist::new(&groove);
+    for fixture in &[&c, &a, &b] {
+        playlist.append(common::open(&groove, fixture), 1.0, 1.0);
+    }
+    playlist.sort_by(|x, y| {
+        let title = |item: &groove::PlaylistItem| item.file().metadata_get("title", false)
+            .map(|tag| tag.value().unwrap().to_string());
+        title(x).cmp(&title(y))
+    });
+    assert_eq!(filenames(&playlist), vec![a.path.clone(), b.path.clone(), c.path.clone()]);
+
+    // c was being decoded, so plays on into what now follows it: nothing
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    sink.attach(&playlist).unwrap();
+    common::assert_samples_eq(&played(&sink), &expected(&[(&c, 1.0)]), 1e-9);
+    playlist.seek(playlist.ids()[0], 0.0).unwrap();
+    common::assert_samples_eq(&played(&sink), &expected(&[(&a, 1.0), (&b, 1.0), (&c, 1.0)]), 1e-9);
+}
+
+#[test]
+fn reordering_while_playing_keeps_the_current_item() {
+    let groove = common::groove();
+    let (a, b, c, d) = (level(0.1, 3000), level(0.2, 1000), level(0.3, 1500), level(0.4, 2000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    sink.attach(&playlist).unwrap();
+    let ids: Vec<ItemId> = [&a, &b, &c, &d].iter()
+        .map(|fixture| playlist.append(common::open(&groove, fixture), 1.0, 1.0))
+        .collect();
+    let mut samples = sink.buffer_get_blocking().unwrap().as_slice_f64().to_vec();
+
+    playlist.swap(ids[1], ids[3]).unwrap();
+    playlist.reverse();
+    playlist.swap(ids[0], ids[1]).unwrap();
+    // a, d, c, b reversed is b, c, d, a, and a swapped with b is a, c, d, b
+    assert_eq!(filenames(&playlist), vec![a.path.clone(), c.path.clone(), d.path.clone(), b.path.clone()]);
+
+    samples.extend(played(&sink));
+    common::assert_samples_eq(&samples, &expected(&[(&a, 1.0), (&c, 1.0), (&d, 1.0), (&b, 1.0)]), 1e-9);
+}
+
+#[test]
+fn item_ids_follow_their_items() {
+    let groove = common::groove();
+    let (a, b, c, d) = (level(0.1, 500), level(0.2, 500), level(0.3, 500), level(0.4, 500));
+
+    let mut playlist = Playlist::new(&groove);
+    let ids = [
+        playlist.append(common::open(&groove, &a), 1.0, 1.0),
+        playlist.append(common::open(&groove, &b), 1.0, 1.0),
+        playlist.append(common::open(&groove, &c), 1.0, 1.0),
+    ];
+    let d_id = playlist.insert_before(ids[1], common::open(&groove, &d), ItemOptions::default()).unwrap();
+    playlist.reverse();
+
+    // c, b, d, a
+    assert_eq!(playlist.index_of(ids[1]), Some(1));
+    assert_eq!(playlist.index_of(d_id), Some(2));
+    assert_eq!(playlist.item(ids[2]).unwrap().file().filename(), c.path.as_path());
+    assert_eq!(playlist.ids()[3], ids[0]);
+
+    let file = playlist.remove(ids[1]).unwrap();
+    assert_eq!(file.filename(), b.path.as_path());
+    assert_eq!(playlist.index_of(d_id), Some(1));
+
+    // gone, and never mistaken for another item
+    assert!(playlist.item(ids[1]).is_none());
+    assert_eq!(playlist.index_of(ids[1]), None);
+    assert_eq!(playlist.remove(ids[1]).err(), Some(StaleItem(ids[1])));
+    assert_eq!(playlist.seek(ids[1], 0.0), Err(StaleItem(ids[1])));
+    assert_eq!(playlist.swap(ids[0], ids[1]), Err(StaleItem(ids[1])));
+    assert_eq!(playlist.ab_loop(ids[1]), Err(StaleItem(ids[1])));
+    assert_eq!(playlist.insert_before(ids[1], file, ItemOptions::default()), Err(StaleItem(ids[1])));
+    let mut other = Playlist::new(&groove);
+    other.append(common::open(&groove, &a), 1.0, 1.0);
+    assert_eq!(other.index_of(ids[0]), None);
+    assert_eq!(playlist.len(), 3);
+}
+
+#[test]
+fn removing_the_playing_item_moves_on() {
+    let groove = common::groove();
+    let (a, b, c) = (level(0.1, 5000), level(0.2, 1500), level(0.3, 1500));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 1, SampleType::Dbl, false));
+    sink.attach(&playlist).unwrap();
+    let ids = [
+        playlist.append(common::open(&groove, &a), 1.0, 1.0),
+        playlist.append(common::open(&groove, &b), 1.0, 1.0),
+        playlist.append(common::open(&groove, &c), 1.0, 1.0),
+    ];
+
+    let first = sink.buffer_get_blocking().unwrap();
+    assert_eq!(playlist.item_of(&first), Some(ids[0]));
+    playlist.remove(ids[2]).unwrap();
+    playlist.remove(ids[0]).unwrap();
+
+    // what was left of a goes with it
+    let mut samples = Vec::new();
+    while let Some(buffer) = sink.buffer_get_blocking() {
+        assert_eq!(playlist.item_of(&buffer), Some(ids[1]));
+        samples.extend_from_slice(buffer.as_slice_f64());
+    }
+    common::assert_samples_eq(&samples, &b.samples, 1e-9);
+}
+
+#[test]
+fn items_which_fail_to_render_report_why() {
+    let groove = common::groove();
+    let (a, b) = (level(0.1, 20000), level(0.2, 1500));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::mono());
+    let id = playlist.append_with(common::open(&groove, &a), ItemOptions {
+        start: Duration::from_millis(100),
+        ..ItemOptions::default()
+    });
+    playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    assert!(playlist.item(id).unwrap().render_error().is_none());
+
+    // the item is decoded again from wherever playback seeks to
+    fs::remove_file(&a.path).unwrap();
+    playlist.seek(id, 0.05).unwrap();
+    sink.attach(&playlist).unwrap();
+    let samples = played(&sink);
+    assert!(samples.ends_with(&b.samples));
+    let err = playlist.item(id).unwrap().render_error().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
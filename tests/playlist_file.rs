@@ -0,0 +1,183 @@
+extern crate groove;
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use common::Encoding;
+use groove::{File, ItemOptions, Playlist, PlaylistEntry};
+
+// a directory of its own for a playlist file and what it lists
+fn directory() -> PathBuf {
+    let dir = common::temp_path("playlist");
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    dir
+}
+
+// a second of tagged noise at `path`
+fn track(path: &Path, seed: u64, tags: &[(&str, &str)]) {
+    let fixture = common::wav(Encoding::S16, 44100, 1, &common::noise(seed, 1, 44100), tags);
+    fs::rename(&fixture.path, path).unwrap();
+}
+
+fn locations(entries: &[PlaylistEntry]) -> Vec<&str> {
+    entries.iter().map(|entry| entry.location.as_str()).collect()
+}
+
+#[test]
+fn m3u_entries_resolve_against_the_playlist_directory() {
+    let groove = common::groove();
+    let dir = directory();
+    track(&dir.join("sub/a.wav"), 1, &[]);
+    let elsewhere = common::temp_path("elsewhere.wav");
+    track(&elsewhere, 2, &[]);
+
+    let mut text = b"#EXTM3U\n# a comment\n#EXTINF:123,Someone - Caf\xe9\nsub/a.wav\n".to_vec();
+    text.extend_from_slice(b"#EXTINF:-1,Missing\r\nmissing.wav\r\n\n");
+    text.extend_from_slice(elsewhere.to_str().unwrap().as_bytes());
+    fs::write(dir.join("list.m3u"), text).unwrap();
+
+    let mut playlist = Playlist::new(&groove);
+    let loaded = playlist.load_m3u(&dir.join("list.m3u")).unwrap();
+    let added: Vec<PlaylistEntry> = loaded.added.iter().map(|(_, entry)| entry.clone()).collect();
+    assert_eq!(locations(&added), vec!["sub/a.wav", elsewhere.to_str().unwrap()]);
+    // Latin-1, since it isn't UTF-8
+    assert_eq!(added[0].title.as_ref().unwrap(), "Someone - Café");
+    assert_eq!(added[0].duration, Some(Duration::from_secs(123)));
+    assert_eq!(added[1].duration, None);
+    assert_eq!(locations(&loaded.unreadable), vec!["missing.wav"]);
+    assert_eq!(loaded.unreadable[0].path, dir.join("missing.wav"));
+
+    let ids = playlist.ids();
+    assert_eq!(ids, loaded.added.iter().map(|&(id, _)| id).collect::<Vec<_>>());
+    assert_eq!(playlist.item(ids[0]).unwrap().file().filename(), dir.join("sub/a.wav"));
+    assert_eq!(playlist.item(ids[1]).unwrap().file().filename(), elsewhere);
+}
+
+#[test]
+fn pls_and_xspf_entries() {
+    let groove = common::groove();
+    let dir = directory();
+    track(&dir.join("sub/a b.wav"), 3, &[]);
+    track(&dir.join("c.wav"), 4, &[]);
+
+    fs::write(dir.join("list.pls"), format!(
+        "[playlist]\nfile2=c.wav\nTitle1=First\nFile1={}\nLength1=61\nLength2=-1\nNumberOfEntries=2\nVersion=2\n",
+        dir.join("sub/a b.wav").display(),
+    )).unwrap();
+    let mut playlist = Playlist::new(&groove);
+    let loaded = playlist.load_pls(&dir.join("list.pls")).unwrap();
+    assert_eq!(loaded.added.len(), 2);
+    assert_eq!(loaded.added[0].1.title.as_ref().unwrap(), "First");
+    assert_eq!(loaded.added[0].1.duration, Some(Duration::from_secs(61)));
+    assert_eq!(loaded.added[1].1.path, dir.join("c.wav"));
+    assert_eq!(loaded.added[1].1.duration, None);
+
+    fs::write(dir.join("list.xspf"), format!(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\"><trackList>\n",
+        "<track><location>file://{}/sub/a%20b.wav</location><title>Fish &amp; Chips</title>",
+        "<creator>Someone</creator><duration>1500</duration></track>\n",
+        "<track><title>Gone</title><location>gone.flac</location></track>\n",
+        "<track><location>c.wav</location></track>\n",
+        "</trackList></playlist>\n"), dir.display())).unwrap();
+    let mut playlist = Playlist::new(&groove);
+    let loaded = playlist.load_xspf(&dir.join("list.xspf")).unwrap();
+    let first = &loaded.added[0].1;
+    assert_eq!(first.path, dir.join("sub/a b.wav"));
+    assert_eq!(first.title.as_ref().unwrap(), "Fish & Chips");
+    assert_eq!(first.artist.as_ref().unwrap(), "Someone");
+    assert_eq!(first.duration, Some(Duration::from_millis(1500)));
+    assert_eq!(loaded.added[1].1.path, dir.join("c.wav"));
+    assert_eq!(locations(&loaded.unreadable), vec!["gone.flac"]);
+    assert_eq!(playlist.len(), 2);
+}
+
+#[test]
+fn saved_playlists_load_back() {
+    let groove = common::groove();
+    let dir = directory();
+    track(&dir.join("sub/a b.wav"), 5, &[("INAM", "Title"), ("IART", "Artist"), ("IPRD", "Album")]);
+    let elsewhere = common::temp_path("elsewhere.wav");
+    track(&elsewhere, 6, &[("INAM", "Other")]);
+
+    let mut playlist = Playlist::new(&groove);
+    playlist.append(File::open(&groove, &dir.join("sub/a b.wav")).unwrap(), 1.0, 1.0);
+    playlist.append_with(File::open(&groove, &elsewhere).unwrap(), ItemOptions {
+        start: Duration::from_millis(250),
+        end: Some(Duration::from_millis(750)),
+        ..ItemOptions::default()
+    });
+
+    playlist.save_m3u(&dir.join("list.m3u8")).unwrap();
+    let text = fs::read_to_string(dir.join("list.m3u8")).unwrap();
+    assert!(text.starts_with("#EXTM3U\n#EXTINF:1,Artist - Title\nsub/a b.wav\n"), "{}", text);
+    playlist.save_pls(&dir.join("list.pls")).unwrap();
+    playlist.save_xspf(&dir.join("list.xspf")).unwrap();
+    let xml = fs::read_to_string(dir.join("list.xspf")).unwrap();
+    assert!(xml.contains("<location>sub/a%20b.wav</location>"), "{}", xml);
+
+    let paths = vec![dir.join("sub/a b.wav"), elsewhere.clone()];
+    let mut loaded = Playlist::new(&groove);
+    let ms = Duration::from_millis;
+    for added in &[
+        loaded.load_m3u(&dir.join("list.m3u8")).unwrap(),
+        loaded.load_pls(&dir.join("list.pls")).unwrap(),
+    ] {
+        let entries: Vec<&PlaylistEntry> = added.added.iter().map(|(_, entry)| entry).collect();
+        assert_eq!(entries.iter().map(|entry| entry.path.clone()).collect::<Vec<_>>(), paths);
+        assert_eq!(entries[0].title.as_ref().unwrap(), "Artist - Title");
+        assert_eq!(entries[1].title.as_ref().unwrap(), "Other");
+        assert_eq!(entries[0].duration, Some(Duration::from_secs(1)));
+        assert_eq!(entries[1].duration, Some(ms(500)));
+    }
+
+    let added = loaded.load_xspf(&dir.join("list.xspf")).unwrap().added;
+    let (first, second) = (&added[0].1, &added[1].1);
+    assert_eq!((&first.path, &second.path), (&paths[0], &paths[1]));
+    assert_eq!((first.title.as_deref(), first.artist.as_deref(), first.album.as_deref()),
+               (Some("Title"), Some("Artist"), Some("Album")));
+    assert_eq!((second.title.as_deref(), second.artist.as_deref()), (Some("Other"), None));
+    assert_eq!(first.duration, Some(Duration::from_secs(1)));
+    assert_eq!(second.duration, Some(ms(500)));
+
+    // M3U and XSPF say where the trimmed item starts and stops; PLS can't
+    let regions: Vec<(Duration, Option<Duration>)> = loaded.ids().into_iter()
+        .map(|id| loaded.item(id).unwrap())
+        .map(|item| (item.start(), item.end()))
+        .collect();
+    let whole = (ms(0), None);
+    assert_eq!(regions, vec![whole, (ms(250), Some(ms(750))), whole, whole, whole, (ms(250), Some(ms(750)))]);
+}
+
+#[test]
+fn durations_do_not_trim_entries() {
+    let groove = common::groove();
+    let dir = directory();
+    let long = common::wav(Encoding::S16, 44100, 1, &common::noise(7, 1, 44100 * 3), &[]);
+    fs::rename(&long.path, dir.join("long.wav")).unwrap();
+
+    fs::write(dir.join("list.m3u"), concat!(
+        "#EXTINF:1,Short\nlong.wav\n",
+        "#EXTINF:2.5,Rounded\nlong.wav\n",
+        "#EXTVLCOPT:start-time=0.5\n#EXTINF:1.25,Offset\nlong.wav\n",
+        "#EXTINF:1,Stopped\n#EXTVLCOPT:start-time=1\n#EXTVLCOPT:stop-time=1.5\nlong.wav\n",
+    )).unwrap();
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    let loaded = playlist.load_m3u(&dir.join("list.m3u")).unwrap();
+    let ms = Duration::from_millis;
+    let regions: Vec<(Duration, Option<Duration>)> = loaded.added.iter()
+        .map(|&(id, _)| playlist.item(id).unwrap())
+        .map(|item| (item.start(), item.end()))
+        .collect();
+    assert_eq!(regions, vec![(ms(0), None), (ms(0), None), (ms(500), None), (ms(1000), Some(ms(1500)))]);
+    // the durations are still there as hints
+    assert_eq!(loaded.added[0].1.duration, Some(ms(1000)));
+
+    let (played, _) = common::play_items(&sink, &playlist);
+    let frames: Vec<usize> = played.iter().map(|&(_, frames)| frames).collect();
+    assert_eq!(frames, vec![44100 * 3, 44100 * 3, 110250, 22050]);
+}
@@ -0,0 +1,152 @@
+extern crate groove;
+
+mod common;
+
+use std::time::Duration;
+
+use common::Encoding;
+use groove::{AbLoopError, Playlist, RepeatMode, Sink, StaleItem};
+
+// whole buffers from the sink until it has played at least `frames`
+// frames, which a repeating playlist never runs out of
+fn take(sink: &Sink, frames: usize) -> Vec<f64> {
+    let mut samples = Vec::new();
+    while samples.len() < frames {
+        let buffer = sink.buffer_get_blocking().expect("the playlist ended");
+        samples.extend_from_slice(buffer.as_slice_f64());
+    }
+    samples
+}
+
+fn cycle(parts: &[&[f64]], frames: usize) -> Vec<f64> {
+    parts.iter().cycle().flat_map(|part| part.iter().cloned()).take(frames).collect()
+}
+
+#[test]
+fn repeat_one_plays_the_item_again() {
+    let groove = common::groove();
+    let a = common::noise_wav(1, 3000);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    playlist.set_repeat(RepeatMode::One);
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    assert_eq!(playlist.repeat(), RepeatMode::One);
+
+    let samples = take(&sink, 10000);
+    common::assert_samples_eq(&samples, &cycle(&[&a.samples], samples.len()), Encoding::F32.step());
+}
+
+#[test]
+fn repeat_all_goes_back_to_the_first_item() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(2, 3000), common::noise_wav(3, 2000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    playlist.set_repeat(RepeatMode::All);
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    playlist.append(common::open(&groove, &b), 1.0, 1.0);
+
+    // well past the laps laid out up front
+    let samples = take(&sink, 5000 * 6 + 1000);
+    common::assert_samples_eq(&samples, &cycle(&[&a.samples, &b.samples], samples.len()), Encoding::F32.step());
+}
+
+#[test]
+fn turning_repeat_off_lets_the_playlist_end() {
+    let groove = common::groove();
+    let a = common::noise_wav(4, 2000);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    playlist.set_repeat(RepeatMode::One);
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let mut samples = take(&sink, 5000);
+    playlist.set_repeat(RepeatMode::Off);
+
+    // however many more times it had already been rendered
+    samples.extend(common::collect(&sink, common::mono()));
+    assert_eq!(samples.len() % 2000, 0);
+    common::assert_samples_eq(&samples, &cycle(&[&a.samples], samples.len()), Encoding::F32.step());
+}
+
+#[test]
+fn ab_loop_repeats_part_of_an_item() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(5, 1000), common::noise_wav(6, 20000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let id = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    let region = (Duration::from_millis(100), Duration::from_millis(200));
+    playlist.set_ab_loop(id, Some(region)).unwrap();
+    assert_eq!(playlist.ab_loop(id), Ok(Some(region)));
+
+    // the item plays up to B, then goes back to A
+    let mut expected = a.samples.clone();
+    expected.extend_from_slice(&b.samples[..8820]);
+    expected.extend(cycle(&[&b.samples[4410..8820]], 4410 * 3));
+    let mut samples = take(&sink, expected.len());
+    common::assert_samples_eq(&samples[..expected.len()], &expected, Encoding::F32.step());
+
+    // once let go, the next time round carries on to the end
+    playlist.set_ab_loop(id, None).unwrap();
+    samples.extend(common::collect(&sink, common::mono()));
+    let tail = &samples[samples.len() - (20000 - 8820)..];
+    common::assert_samples_eq(tail, &b.samples[8820..], Encoding::F32.step());
+    assert_eq!((samples.len() - a.samples.len() - 20000) % 4410, 0);
+}
+
+#[test]
+fn ab_loop_on_the_playing_item_jumps_to_a() {
+    let groove = common::groove();
+    let a = common::noise_wav(7, 20000);
+
+    let mut playlist = Playlist::new(&groove);
+    let id = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    playlist.set_ab_loop(id, Some((Duration::from_millis(50), Duration::from_millis(100)))).unwrap();
+    let sink = common::listen(&groove, &playlist);
+
+    let first = sink.buffer_get_blocking().unwrap();
+    assert!((first.pos() - 0.05).abs() < 1e-6, "first buffer at {}", first.pos());
+    let mut samples = first.as_slice_f64().to_vec();
+    samples.extend(take(&sink, 2205 * 4 - samples.len()));
+    common::assert_samples_eq(&samples, &cycle(&[&a.samples[2205..4410]], samples.len()), Encoding::F32.step());
+}
+
+#[test]
+fn empty_ab_loops_are_refused() {
+    let groove = common::groove();
+    let a = common::noise_wav(11, 4410);
+
+    let mut playlist = Playlist::new(&groove);
+    let id = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let at = Duration::from_millis(50);
+    assert_eq!(playlist.set_ab_loop(id, Some((at, at))), Err(AbLoopError::EmptyRange));
+    assert_eq!(playlist.set_ab_loop(id, Some((at * 2, at))), Err(AbLoopError::EmptyRange));
+    assert_eq!(playlist.ab_loop(id), Ok(None));
+    playlist.remove(id).unwrap();
+    assert_eq!(playlist.set_ab_loop(id, None), Err(AbLoopError::Stale(StaleItem(id))));
+}
+
+#[test]
+fn laps_follow_a_reordered_playlist() {
+    let groove = common::groove();
+    let (a, b, c) = (common::noise_wav(8, 2000), common::noise_wav(9, 1500), common::noise_wav(10, 1000));
+
+    let mut playlist = Playlist::new(&groove);
+    playlist.set_repeat(RepeatMode::All);
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    playlist.append(common::open(&groove, &c), 1.0, 1.0);
+    playlist.reverse();
+    let sink = common::listen(&groove, &playlist);
+
+    // a was being decoded, so plays first and ends the lap it started
+    let mut expected = a.samples.clone();
+    expected.extend(cycle(&[&c.samples, &b.samples, &a.samples], 4500 * 3));
+    let samples = take(&sink, expected.len());
+    common::assert_samples_eq(&samples[..expected.len()], &expected, Encoding::F32.step());
+}
@@ -0,0 +1,152 @@
+extern crate groove;
+
+mod common;
+
+use std::f64::consts::PI;
+
+use common::{Encoding, Fixture};
+use groove::{File, Groove, Playlist, SampleType, Sink};
+
+const SAMPLE_TYPES: [SampleType; 5] = [
+    SampleType::U8,
+    SampleType::S16,
+    SampleType::S32,
+    SampleType::Flt,
+    SampleType::Dbl,
+];
+
+fn play(groove: &Groove, fixture: &Fixture, sample_rate: u32, channels: usize,
+        sample_type: SampleType, planar: bool) -> Vec<f64> {
+    let format = common::audio_format(sample_rate, channels, sample_type, planar);
+    let mut playlist = Playlist::new(groove);
+    let sink = Sink::new(groove);
+    sink.set_audio_format(format);
+    sink.attach(&playlist).expect("error attaching sink");
+    playlist.append(File::open(groove, &fixture.path).unwrap(), 1.0, 1.0);
+    common::collect(&sink, format)
+}
+
+#[test]
+fn converts_to_every_sample_format() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::sine(440.0, 44100, 2, 10000), &[]);
+    for &sample_type in &SAMPLE_TYPES {
+        for &planar in &[false, true] {
+            let samples = play(&groove, &fixture, 44100, 2, sample_type, planar);
+            // a rounding step either side, as libav truncates some conversions
+            let tolerance = common::sample_step(sample_type) + fixture.encoding.step();
+            common::assert_samples_eq(&samples, &fixture.samples, tolerance);
+        }
+    }
+}
+
+#[test]
+fn decodes_every_encoding_exactly() {
+    let groove = common::groove();
+    for &encoding in &common::ENCODINGS {
+        let fixture = common::wav(encoding, 48000, 2, &common::noise(5, 2, 4000), &[]);
+        let samples = play(&groove, &fixture, 48000, 2, SampleType::Dbl, true);
+        common::assert_samples_eq(&samples, &fixture.samples, 1e-9);
+    }
+}
+
+#[test]
+fn maps_mono_to_stereo() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::F32, 44100, 1, &common::sine(300.0, 44100, 1, 5000), &[]);
+    let samples = play(&groove, &fixture, 44100, 2, SampleType::Flt, false);
+    assert_eq!(samples.len(), fixture.samples.len() * 2);
+    for (frame, &expected) in fixture.samples.iter().enumerate() {
+        // an upmix may lower the level, but both sides must match
+        let (left, right) = (samples[frame * 2], samples[frame * 2 + 1]);
+        assert!((left - right).abs() < 1e-6);
+        assert!(left.abs() <= expected.abs() + 1e-6);
+    }
+    let peak = samples.iter().fold(0.0f64, |peak, sample| peak.max(sample.abs()));
+    assert!(peak > 0.3, "peak {} too low", peak);
+}
+
+#[test]
+fn resamples() {
+    let groove = common::groove();
+    let frequency = 220.0;
+    for &(from, to) in &[(48000, 44100), (22050, 44100), (44100, 8000)] {
+        let fixture = common::wav(Encoding::F32, from, 1, &common::sine(frequency, from, 1, from as usize), &[]);
+        for &sample_type in &SAMPLE_TYPES {
+            let samples = play(&groove, &fixture, to, 1, sample_type, false);
+            let expected_frames = to as f64 * fixture.duration();
+            assert!((samples.len() as f64 - expected_frames).abs() <= expected_frames * 0.01,
+                    "{} -> {}: {} frames, expected {}", from, to, samples.len(), expected_frames);
+
+            // away from the edges, where resamplers may fade in and out,
+            // every sample stays close to the sine wave
+            let margin = (to / 100) as usize;
+            for (frame, &sample) in samples.iter().enumerate().take(samples.len() - margin).skip(margin) {
+                let expected = 0.5 * (2.0 * PI * frequency * frame as f64 / f64::from(to)).sin();
+                assert!((sample - expected).abs() < 0.02 + common::sample_step(sample_type),
+                        "{} -> {} as {:?}: frame {} is {}, expected {}",
+                        from, to, sample_type, frame, sample, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn disable_resample_passes_the_file_format_through() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 22050, 1, &common::noise(9, 1, 3000), &[]);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(common::audio_format(44100, 2, SampleType::Flt, true));
+    sink.disable_resample(true);
+    sink.attach(&playlist).unwrap();
+    playlist.append(File::open(&groove, &fixture.path).unwrap(), 1.0, 1.0);
+
+    let samples = common::collect(&sink, common::audio_format(22050, 1, SampleType::S16, false));
+    common::assert_samples_eq(&samples, &fixture.samples, 1e-9);
+}
+
+#[test]
+fn detached_sink_gets_nothing_more() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(1, 2, 44100), &[]);
+    let format = common::audio_format(44100, 2, SampleType::S16, false);
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = Sink::new(&groove);
+    sink.set_audio_format(format);
+    sink.attach(&playlist).unwrap();
+    let id = playlist.append(File::open(&groove, &fixture.path).unwrap(), 1.0, 1.0);
+    assert!(sink.buffer_get_blocking().is_some());
+
+    sink.detach();
+    sink.attach(&playlist).unwrap();
+    playlist.seek(id, 0.0).unwrap();
+    let samples = common::collect(&sink, format);
+    common::assert_samples_eq(&samples, &fixture.samples, 1e-9);
+}
+
+#[test]
+fn raw_slices_hold_every_byte() {
+    let groove = common::groove();
+    let fixture = common::wav(Encoding::S16, 44100, 2, &common::noise(6, 2, 1000), &[]);
+    for &planar in &[false, true] {
+        let format = common::audio_format(44100, 2, SampleType::S16, planar);
+        let mut playlist = Playlist::new(&groove);
+        let sink = Sink::new(&groove);
+        sink.set_audio_format(format);
+        sink.attach(&playlist).expect("error attaching sink");
+        playlist.append(File::open(&groove, &fixture.path).unwrap(), 1.0, 1.0);
+
+        let buffer = sink.buffer_get_blocking().unwrap();
+        let native = |samples: &[i16]| -> Vec<u8> { samples.iter().flat_map(|sample| sample.to_ne_bytes()).collect() };
+        if planar {
+            assert_eq!(buffer.channel_as_slice_raw(1).len(), buffer.frame_count() * 2);
+            assert_eq!(buffer.channel_as_slice_raw(1), &native(buffer.channel_as_slice_i16(1))[..]);
+        } else {
+            assert_eq!(buffer.as_slice_raw().len(), buffer.frame_count() * 4);
+            assert_eq!(buffer.as_slice_raw(), &native(buffer.as_slice_i16())[..]);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+extern crate groove;
+
+mod common;
+
+use std::f64::consts::FRAC_PI_2;
+use std::fs;
+use std::time::Duration;
+
+use common::Encoding;
+use groove::{Encoder, File, ItemOptions, Playlist, Transition};
+
+// `a` fading out over its last `length` frames while `b` fades in
+fn crossfaded(a: &[f64], b: &[f64], length: usize) -> Vec<f64> {
+    let overlap = a.len() - length;
+    let mut samples = a[..overlap].to_vec();
+    for frame in 0..length {
+        let angle = frame as f64 / length as f64 * FRAC_PI_2;
+        samples.push(a[overlap + frame] * angle.cos() + b[frame] * angle.sin());
+    }
+    samples.extend_from_slice(&b[length..]);
+    samples
+}
+
+#[test]
+fn crossfade_overlaps_items() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(1, 8000), common::noise_wav(2, 6000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    playlist.set_transition(Transition::Crossfade(Duration::from_millis(50)));
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    playlist.append(common::open(&groove, &b), 1.0, 1.0);
+
+    common::assert_samples_eq(&common::collect(&sink, common::mono()), &crossfaded(&a.samples, &b.samples, 2205),
+                              Encoding::F32.step());
+}
+
+#[test]
+fn silence_separates_items() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(3, 3000), common::noise_wav(4, 3000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    playlist.set_transition(Transition::Silence(Duration::from_millis(10)));
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    playlist.append(common::open(&groove, &b), 1.0, 1.0);
+
+    // nothing follows the last item
+    let expected: Vec<f64> = a.samples.iter().chain(&[0.0; 441]).chain(&b.samples).cloned().collect();
+    common::assert_samples_eq(&common::collect(&sink, common::mono()), &expected, Encoding::F32.step());
+}
+
+#[test]
+fn item_transition_overrides_the_playlist() {
+    let groove = common::groove();
+    let (a, b, c) = (common::noise_wav(5, 2000), common::noise_wav(6, 2000), common::noise_wav(7, 2000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    playlist.set_transition(Transition::Silence(Duration::from_millis(10)));
+    let a_id = playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let b_id = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    playlist.append(common::open(&groove, &c), 1.0, 1.0);
+    playlist.set_item_transition(b_id, Some(Transition::Gapless)).unwrap();
+    assert_eq!(playlist.item_transition(a_id), Ok(None));
+    assert_eq!(playlist.item_transition(b_id), Ok(Some(Transition::Gapless)));
+
+    let expected: Vec<f64> = a.samples.iter().chain(&[0.0; 441]).chain(&b.samples).chain(&c.samples)
+        .cloned().collect();
+    common::assert_samples_eq(&common::collect(&sink, common::mono()), &expected, Encoding::F32.step());
+}
+
+#[test]
+fn new_transition_applies_to_items_not_started() {
+    let groove = common::groove();
+    let (a, b, c) = (common::noise_wav(8, 3000), common::noise_wav(9, 4000), common::noise_wav(10, 3000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    playlist.append(common::open(&groove, &c), 1.0, 1.0);
+    // the first item is already being decoded, so it goes straight on
+    playlist.set_transition(Transition::Crossfade(Duration::from_millis(20)));
+    assert_eq!(playlist.transition(), Transition::Crossfade(Duration::from_millis(20)));
+
+    let mut expected = a.samples.clone();
+    expected.extend(crossfaded(&b.samples, &c.samples, 882));
+    common::assert_samples_eq(&common::collect(&sink, common::mono()), &expected, Encoding::F32.step());
+}
+
+#[test]
+fn encoder_hears_the_crossfade() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(11, 5000), common::noise_wav(12, 5000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    let mut encoder = Encoder::new(&groove);
+    encoder.set_format_short_name("wav");
+    encoder.set_codec_short_name("pcm_f64le");
+    encoder.set_target_audio_format(common::mono());
+    encoder.attach(&playlist).unwrap();
+    playlist.set_transition(Transition::Crossfade(Duration::from_millis(30)));
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    playlist.append(common::open(&groove, &b), 1.0, 1.0);
+
+    let played = common::collect(&sink, common::mono());
+    let mut bytes = Vec::new();
+    while let Some(buffer) = encoder.buffer_get_blocking() {
+        bytes.extend_from_slice(buffer.as_vec());
+    }
+    encoder.detach();
+    let path = common::temp_path("crossfade.wav");
+    fs::write(&path, bytes).unwrap();
+    let encoded = File::open(&groove, &path).unwrap().decode_all(common::mono()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    common::assert_samples_eq(&played, &crossfaded(&a.samples, &b.samples, 1323), Encoding::F32.step());
+    common::assert_samples_eq(&common::owned_samples(&encoded), &played, 1e-12);
+}
+
+#[test]
+fn seeking_into_an_item_after_a_crossfade() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(13, 4000), common::noise_wav(14, 20000));
+
+    let mut playlist = Playlist::new(&groove);
+    playlist.set_transition(Transition::Crossfade(Duration::from_millis(50)));
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let id = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    playlist.seek(id, 0.1).unwrap();
+    let sink = common::listen(&groove, &playlist);
+
+    // the item's own time starts after the part mixed into the crossfade
+    let first = sink.buffer_get_blocking().unwrap();
+    assert!((first.pos() - 0.1).abs() < 1e-6, "first buffer at {}", first.pos());
+    let mut samples = first.as_slice_f64().to_vec();
+    samples.extend(common::collect(&sink, common::mono()));
+    common::assert_samples_eq(&samples, &b.samples[2205 + 4410..], Encoding::F32.step());
+}
+
+#[test]
+fn crossfade_starts_from_the_next_items_start() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(15, 6000), common::noise_wav(16, 10000));
+
+    let mut playlist = Playlist::new(&groove);
+    let sink = common::listen(&groove, &playlist);
+    playlist.set_transition(Transition::Crossfade(Duration::from_millis(20)));
+    playlist.append_with(common::open(&groove, &a), ItemOptions {
+        end: Some(Duration::from_millis(100)),
+        ..ItemOptions::default()
+    });
+    playlist.append_with(common::open(&groove, &b), ItemOptions {
+        start: Duration::from_millis(50),
+        ..ItemOptions::default()
+    });
+
+    let expected = crossfaded(&a.samples[..4410], &b.samples[2205..], 882);
+    common::assert_samples_eq(&common::collect(&sink, common::mono()), &expected, Encoding::F32.step());
+}
+
+#[test]
+fn reordering_crossfades_the_new_neighbours() {
+    let groove = common::groove();
+    let (a, b, c) = (common::noise_wav(17, 4000), common::noise_wav(18, 4000), common::noise_wav(19, 4000));
+
+    let mut playlist = Playlist::new(&groove);
+    playlist.set_transition(Transition::Crossfade(Duration::from_millis(20)));
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let b_id = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    let c_id = playlist.append(common::open(&groove, &c), 1.0, 1.0);
+    playlist.swap(b_id, c_id).unwrap();
+    let sink = common::listen(&groove, &playlist);
+
+    let expected = crossfaded(&crossfaded(&a.samples, &c.samples, 882), &b.samples, 882);
+    common::assert_samples_eq(&common::collect(&sink, common::mono()), &expected, Encoding::F32.step());
+}
+
+#[test]
+fn removing_and_inserting_crossfade_the_new_neighbours() {
+    let groove = common::groove();
+    let (a, b) = (common::noise_wav(20, 4000), common::noise_wav(21, 4000));
+    let (c, d) = (common::noise_wav(22, 4000), common::noise_wav(23, 4000));
+
+    let mut playlist = Playlist::new(&groove);
+    playlist.set_transition(Transition::Crossfade(Duration::from_millis(20)));
+    playlist.append(common::open(&groove, &a), 1.0, 1.0);
+    let b_id = playlist.append(common::open(&groove, &b), 1.0, 1.0);
+    let c_id = playlist.append(common::open(&groove, &c), 1.0, 1.0);
+    playlist.remove(b_id).unwrap();
+    playlist.insert_before(c_id, common::open(&groove, &d), ItemOptions::default()).unwrap();
+    let sink = common::listen(&groove, &playlist);
+
+    let expected = crossfaded(&crossfaded(&a.samples, &d.samples, 882), &c.samples, 882);
+    common::assert_samples_eq(&common::collect(&sink, common::mono()), &expected, Encoding::F32.step());
+}
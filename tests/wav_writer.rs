@@ -0,0 +1,169 @@
+extern crate groove;
+
+mod common;
+
+use std::io::{self, Cursor, Seek, SeekFrom, Write};
+
+use groove::{AudioFormat, OwnedAudioBuffer, SampleType, WavWriter};
+
+// the GUID of KSDATAFORMAT_SUBTYPE_PCM or _IEEE_FLOAT after its first
+// four bytes, which hold the format tag
+const GUID_TAIL: [u8; 12] = [0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(body);
+    chunk
+}
+
+// the fmt chunk fields every layout starts with
+fn fmt_fields(tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+    let block_align = channels * bits / 8;
+    let mut fields = Vec::new();
+    fields.extend_from_slice(&tag.to_le_bytes());
+    fields.extend_from_slice(&channels.to_le_bytes());
+    fields.extend_from_slice(&sample_rate.to_le_bytes());
+    fields.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+    fields.extend_from_slice(&block_align.to_le_bytes());
+    fields.extend_from_slice(&bits.to_le_bytes());
+    fields
+}
+
+// what the writer puts out for `data_len` bytes of silence after `fmt`:
+// RIFF, the JUNK chunk reserving room for ds64, then fmt and data
+fn wav_bytes(fmt: &[u8], data_len: usize) -> Vec<u8> {
+    let mut body = b"WAVE".to_vec();
+    body.extend(chunk(b"JUNK", &[0; 28]));
+    body.extend(chunk(b"fmt ", fmt));
+    body.extend(chunk(b"data", &vec![0; data_len]));
+    if data_len % 2 == 1 {
+        body.push(0);
+    }
+    chunk(b"RIFF", &body)
+}
+
+fn write_silence(format: AudioFormat, frames: usize) -> Vec<u8> {
+    let mut buffer = OwnedAudioBuffer::new(format);
+    buffer.extend_silence(frames);
+    let mut writer = WavWriter::new(Cursor::new(Vec::new()), format).unwrap();
+    writer.write_owned(&buffer).unwrap();
+    assert_eq!(writer.frame_count(), frames as u64);
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn writes_plain_pcm_headers() {
+    let format = common::audio_format(44100, 2, SampleType::S16, false);
+    let fmt = fmt_fields(1, 2, 44100, 16);
+    assert_eq!(write_silence(format, 10), wav_bytes(&fmt, 40));
+}
+
+#[test]
+fn writes_float_headers_with_an_empty_extension() {
+    let format = common::audio_format(48000, 1, SampleType::Flt, false);
+    let mut fmt = fmt_fields(3, 1, 48000, 32);
+    fmt.extend_from_slice(&0u16.to_le_bytes());
+    assert_eq!(write_silence(format, 5), wav_bytes(&fmt, 20));
+}
+
+#[test]
+fn writes_extensible_headers_for_wide_integer_samples() {
+    let format = common::audio_format(44100, 2, SampleType::S32, true);
+    let mut fmt = fmt_fields(0xfffe, 2, 44100, 32);
+    fmt.extend_from_slice(&22u16.to_le_bytes());
+    fmt.extend_from_slice(&32u16.to_le_bytes());
+    // front left and right
+    fmt.extend_from_slice(&3u32.to_le_bytes());
+    fmt.extend_from_slice(&1u32.to_le_bytes());
+    fmt.extend_from_slice(&GUID_TAIL);
+    assert_eq!(write_silence(format, 3), wav_bytes(&fmt, 24));
+}
+
+#[test]
+fn pads_odd_length_data() {
+    let format = common::audio_format(8000, 1, SampleType::U8, false);
+    let bytes = write_silence(format, 3);
+    let mut expected = wav_bytes(&fmt_fields(1, 1, 8000, 8), 3);
+    // unsigned silence, before the pad byte
+    let len = expected.len();
+    expected[len - 4..len - 1].copy_from_slice(&[0x80; 3]);
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn leaves_what_came_before_the_header() {
+    let format = common::audio_format(44100, 2, SampleType::S16, false);
+    let mut cursor = Cursor::new(b"before".to_vec());
+    cursor.seek(SeekFrom::End(0)).unwrap();
+    let mut buffer = OwnedAudioBuffer::new(format);
+    buffer.extend_silence(10);
+    let mut writer = WavWriter::new(cursor, format).unwrap();
+    writer.write_owned(&buffer).unwrap();
+
+    let mut expected = b"before".to_vec();
+    expected.extend(wav_bytes(&fmt_fields(1, 2, 44100, 16), 40));
+    assert_eq!(writer.finish().unwrap().into_inner(), expected);
+}
+
+// keeps the header, and counts each byte of audio written after it as a
+// MiB, standing in for a file past 4 GiB without writing one
+struct Inflated {
+    header: Cursor<Vec<u8>>,
+    header_len: u64,
+    position: u64,
+}
+
+impl Write for Inflated {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.position < self.header_len {
+            self.header.set_position(self.position);
+            self.header.write_all(buf)?;
+            self.position += buf.len() as u64;
+        } else {
+            self.position += buf.len() as u64 * 1024 * 1024;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Inflated {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(position) => position,
+            SeekFrom::Current(offset) => (self.position as i64 + offset) as u64,
+            SeekFrom::End(_) => unimplemented!(),
+        };
+        Ok(self.position)
+    }
+}
+
+#[test]
+fn turns_the_junk_chunk_into_ds64_past_4_gib() {
+    let format = common::audio_format(44100, 2, SampleType::S16, false);
+    let header_len = wav_bytes(&fmt_fields(1, 2, 44100, 16), 0).len() as u64;
+    let inflated = Inflated { header: Cursor::new(Vec::new()), header_len, position: 0 };
+    let mut buffer = OwnedAudioBuffer::new(format);
+    buffer.extend_silence(2048);
+    let mut writer = WavWriter::new(inflated, format).unwrap();
+    writer.write_owned(&buffer).unwrap();
+    let inflated = writer.finish().unwrap();
+
+    let end = header_len + 8192 * 1024 * 1024;
+    let header = inflated.header.into_inner();
+    assert_eq!(&header[0..4], b"RF64");
+    assert_eq!(&header[4..8], &u32::MAX.to_le_bytes());
+    let mut ds64 = (end - 8).to_le_bytes().to_vec();
+    // the sizes written, as opposed to how far the writer went
+    ds64.extend_from_slice(&8192u64.to_le_bytes());
+    ds64.extend_from_slice(&2048u64.to_le_bytes());
+    ds64.extend_from_slice(&0u32.to_le_bytes());
+    assert_eq!(&header[12..48], &chunk(b"ds64", &ds64)[..]);
+    // both sizes are left to ds64
+    assert_eq!(&header[header.len() - 8..header.len() - 4], b"data");
+    assert_eq!(&header[header.len() - 4..], &u32::MAX.to_le_bytes());
+}